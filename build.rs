@@ -0,0 +1,5 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/admin.proto")
+        .expect("Failed to compile protobuf definitions");
+}
@@ -0,0 +1,131 @@
+use eyre::{eyre, Context, Result};
+use tracing::info;
+
+use crate::{audit_log::AuditKind, commands, reload_config, UserError};
+
+/// Handle `~admin <subcommand>`. The registry only lets the configured owner run it.
+pub(crate) async fn handle_admin_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let response = match ctx.parts.next().map(|s| s.to_lowercase()).as_deref() {
+        Some("reload") => {
+            reload_config(ctx.state, ctx.client).wrap_err("Failed to reload config")?;
+
+            "Reloaded config".to_string()
+        }
+        Some("pause") => {
+            if ctx.state.pause.set(true) {
+                info!("Paused");
+                "Paused, I won't create or deliver reminders until resumed".to_string()
+            } else {
+                "I'm already paused".to_string()
+            }
+        }
+        Some("resume") => {
+            if ctx.state.pause.set(false) {
+                info!("Resumed");
+                "Resumed, held back reminders are delivered now".to_string()
+            } else {
+                "I'm not paused".to_string()
+            }
+        }
+        Some("stats") => {
+            let counts = ctx.state.parse_failures.counts();
+            if counts.is_empty() {
+                "No reminders failed to parse since I started".to_string()
+            } else {
+                format!(
+                    "Parse failures since I started: {}",
+                    counts
+                        .iter()
+                        .map(|(category, count)| format!("{} {}", category.name(), count))
+                        .intersperse(", ".to_string())
+                        .collect::<String>()
+                )
+            }
+        }
+        Some("usage") => {
+            let channel = ctx
+                .parts
+                .next()
+                .map(|channel| channel.trim_start_matches('#').to_lowercase());
+            let counts = ctx.state.usage.counts(channel.as_deref());
+            let list = |counts: &[(&str, u64)]| {
+                counts
+                    .iter()
+                    .map(|(name, count)| format!("{} {}", name, count))
+                    .intersperse(", ".to_string())
+                    .collect::<String>()
+            };
+
+            match channel {
+                _ if counts.is_empty() => "No commands were used since I started".to_string(),
+                Some(channel) => format!(
+                    "Command usage since I started in #{}: {}",
+                    channel,
+                    list(&counts)
+                ),
+                None => format!(
+                    "Command usage since I started: {}; busiest channels: {}",
+                    list(&counts),
+                    list(&ctx.state.usage.channels())
+                ),
+            }
+        }
+        Some("channels") => format!(
+            "Joined channels: {}",
+            ctx.state
+                .channels
+                .iter()
+                .map(|channel| channel.as_str())
+                .intersperse(", ")
+                .collect::<String>()
+        ),
+        Some("purge") => {
+            let user = match ctx.parts.next() {
+                Some(user) => user.trim_start_matches('@').to_lowercase(),
+                None => return Err(eyre!(UserError("Missing user".to_string()))),
+            };
+
+            let messages = {
+                let mut store = ctx.state.store.lock().await;
+                let messages = store.remove_user(&user);
+                store.save().wrap_err("Failed to save store")?;
+                messages
+            };
+            for message in &messages {
+                ctx.state
+                    .audit
+                    .record(AuditKind::Cancelled, message)
+                    .wrap_err("Failed to write audit log")?;
+            }
+            info!("Purged {} messages of {}", messages.len(), user);
+
+            format!("Purged {} reminders of {}", messages.len(), user)
+        }
+        Some("say") => {
+            let channel = match ctx.parts.next() {
+                Some(channel) => channel.trim_start_matches('#').to_lowercase(),
+                None => return Err(eyre!(UserError("Missing channel".to_string()))),
+            };
+            let text = ctx.parts.by_ref().intersperse(" ").collect::<String>();
+            if text.is_empty() {
+                return Err(eyre!(UserError("Message is empty".to_string())));
+            }
+
+            ctx.client
+                .say(channel.clone(), text)
+                .await
+                .wrap_err("Failed to send message")?;
+
+            format!("Sent message to #{}", channel)
+        }
+        _ => {
+            return Err(eyre!(UserError(
+                "Usage: admin reload | channels | stats | purge <user> | say <channel> <text> | \
+                 pause | resume"
+                    .to_string()
+            )))
+        }
+    };
+
+    ctx.reply(response).await
+}
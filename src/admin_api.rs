@@ -0,0 +1,720 @@
+//! HTTP endpoints to fix reminders on the running bot, protected by a token from the config.
+//!
+//! Requests are handed to the message loop, so they go through the same store and timers as the
+//! chat commands:
+//!
+//! - `GET /reminders[?recipient=<login>]` lists the pending reminders
+//! - `POST /reminders` creates one from `{"author", "channel", "recipient", "text", "at"?}`
+//! - `PATCH /reminders/<id>` changes any of `{"recipient", "text", "at"}`
+//! - `DELETE /reminders/<id>` cancels one
+//! - `PUT /channels/<channel>` and `DELETE /channels/<channel>` join and part a channel until the
+//!   config is reloaded
+//! - `GET /stats` counts reminders, timers, the commands used in each channel and the reminders
+//!   that failed to parse
+//! - `GET /metrics` has the same numbers and the delivery latency of the last day in the
+//!   Prometheus text format
+//!
+//! `at` is an RFC 3339 timestamp, reminders without one are delivered when their recipient types
+//! next. Changed reminders get a new id, so a timer of the old version can't deliver them.
+
+use std::{collections::BTreeMap, convert::Infallible, net::SocketAddr, sync::Arc};
+
+use eyre::{Context, Result};
+use hyper::{
+    header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Method, StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, info};
+
+use crate::{
+    audit_log::AuditKind,
+    delivery_stats, join_channels,
+    message::{Activation, Message},
+    metrics::{self, Metrics},
+    queue_messages, sanitize, Client, State, STATS_WINDOW,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AdminApiConfig {
+    pub listen: SocketAddr,
+    /// Expected as `Authorization: Bearer <token>`.
+    pub token: String,
+}
+
+/// An operation for the message loop and where to send its outcome.
+#[derive(Debug)]
+pub struct Request {
+    pub operation: Operation,
+    pub respond: oneshot::Sender<Result<Response, ApiError>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Operation {
+    List { recipient: Option<String> },
+    Create(Draft),
+    Update { id: String, changes: Changes },
+    Delete { id: String },
+    Join { channel: String },
+    Part { channel: String },
+    Stats,
+    Metrics,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct Draft {
+    pub author: String,
+    pub channel: String,
+    pub recipient: String,
+    pub text: String,
+    #[serde(default)]
+    pub at: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+pub struct Changes {
+    #[serde(default)]
+    pub recipient: Option<String>,
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum Response {
+    Reminders(Vec<Reminder>),
+    Reminder(Reminder),
+    Deleted {
+        deleted: String,
+    },
+    Channels {
+        channels: Vec<String>,
+    },
+    Stats(Stats),
+    /// Sent as is, in the Prometheus text format.
+    #[serde(skip)]
+    Metrics(String),
+}
+
+/// A reminder as the API shows it.
+#[derive(Debug, Serialize)]
+pub struct Reminder {
+    pub id: String,
+    pub author: String,
+    pub recipient: String,
+    pub channel: String,
+    pub text: String,
+    pub created: String,
+    /// Unset if the reminder is delivered when its recipient types next.
+    pub at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub pending: usize,
+    pub timed: usize,
+    pub active_timers: usize,
+    pub channels: Vec<String>,
+    pub paused: bool,
+    /// Uses of each command since the start, keyed by channel. Unknown commands count as
+    /// `unknown`.
+    pub usage: BTreeMap<String, BTreeMap<String, u64>>,
+    /// Reminders that failed to parse since the start, keyed by why.
+    pub parse_failures: BTreeMap<String, u64>,
+}
+
+impl From<&Message> for Reminder {
+    fn from(message: &Message) -> Self {
+        Self {
+            id: message.id().to_string(),
+            author: message.author().to_string(),
+            recipient: message.recipient().to_string(),
+            channel: message.channel().to_string(),
+            text: message.text().to_string(),
+            created: format_timestamp(message.created()),
+            at: match message.activation() {
+                Activation::Fixed(at) => Some(format_timestamp(*at)),
+                _ => None,
+            },
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("missing or wrong token")]
+    Unauthorized,
+
+    #[error("not found")]
+    NotFound,
+
+    #[error("method not allowed")]
+    MethodNotAllowed,
+
+    #[error("{0}")]
+    BadRequest(String),
+
+    #[error("the bot is shutting down")]
+    Unavailable,
+
+    #[error("something went wrong, see the log")]
+    Internal,
+}
+
+impl ApiError {
+    fn status(&self) -> StatusCode {
+        match self {
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::NotFound => StatusCode::NOT_FOUND,
+            ApiError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<eyre::Report> for ApiError {
+    fn from(err: eyre::Report) -> Self {
+        error!("{:?}", err.wrap_err("Failed to handle admin API request"));
+        ApiError::Internal
+    }
+}
+
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Serve the API until it fails, handing requests to `requests`.
+pub async fn serve(config: AdminApiConfig, requests: mpsc::Sender<Request>) -> Result<()> {
+    let token = Arc::new(config.token);
+    let make_service = make_service_fn(move |_| {
+        let token = token.clone();
+        let requests = requests.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle(request, token.clone(), requests.clone())
+            }))
+        }
+    });
+
+    info!("Serving the admin API on {}", config.listen);
+    hyper::Server::try_bind(&config.listen)
+        .wrap_err("Failed to bind admin API")?
+        .serve(make_service)
+        .await
+        .wrap_err("Failed to serve admin API")
+}
+
+async fn handle(
+    request: hyper::Request<Body>,
+    token: Arc<String>,
+    requests: mpsc::Sender<Request>,
+) -> Result<hyper::Response<Body>, Infallible> {
+    let (status, body, content_type) = match respond(request, &token, &requests).await {
+        Ok(Response::Metrics(text)) => (StatusCode::OK, Ok(text), METRICS_CONTENT_TYPE),
+        Ok(response) => (
+            StatusCode::OK,
+            serde_json::to_string(&response),
+            "application/json",
+        ),
+        Err(err) => (
+            err.status(),
+            serde_json::to_string(&serde_json::json!({ "error": err.to_string() })),
+            "application/json",
+        ),
+    };
+    let mut response = hyper::Response::new(Body::from(body.unwrap_or_default()));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+
+    Ok(response)
+}
+
+/// Check the token of `request` and run its operation in the message loop.
+async fn respond(
+    request: hyper::Request<Body>,
+    token: &str,
+    requests: &mpsc::Sender<Request>,
+) -> Result<Response, ApiError> {
+    let header = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if !authorized(header, token) {
+        return Err(ApiError::Unauthorized);
+    }
+
+    let (parts, body) = request.into_parts();
+    let body = hyper::body::to_bytes(body)
+        .await
+        .map_err(|_| ApiError::BadRequest("failed to read body".to_string()))?;
+    let operation = route(&parts.method, parts.uri.path(), parts.uri.query(), &body)?;
+
+    let (respond, response) = oneshot::channel();
+    requests
+        .send(Request { operation, respond })
+        .await
+        .map_err(|_| ApiError::Unavailable)?;
+    response.await.map_err(|_| ApiError::Unavailable)?
+}
+
+/// Whether `header` carries `token`, compared in constant time.
+pub(crate) fn authorized(header: Option<&str>, token: &str) -> bool {
+    let given = match header.and_then(|header| header.strip_prefix("Bearer ")) {
+        Some(given) => given.as_bytes(),
+        None => return false,
+    };
+
+    !token.is_empty()
+        && given.len() == token.len()
+        && given
+            .iter()
+            .zip(token.as_bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn route(
+    method: &Method,
+    path: &str,
+    query: Option<&str>,
+    body: &[u8],
+) -> Result<Operation, ApiError> {
+    let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+    match (method, segments.as_slice()) {
+        (&Method::GET, ["reminders"]) => Ok(Operation::List {
+            recipient: query
+                .unwrap_or_default()
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("recipient="))
+                .map(percent_decode)
+                .transpose()?
+                .map(|recipient| recipient.to_lowercase()),
+        }),
+        (&Method::POST, ["reminders"]) => Ok(Operation::Create(parse(body)?)),
+        (&Method::PATCH, ["reminders", id]) => Ok(Operation::Update {
+            id: id.to_string(),
+            changes: parse(body)?,
+        }),
+        (&Method::DELETE, ["reminders", id]) => Ok(Operation::Delete { id: id.to_string() }),
+        (&Method::PUT, ["channels", channel]) => Ok(Operation::Join {
+            channel: channel.to_string(),
+        }),
+        (&Method::DELETE, ["channels", channel]) => Ok(Operation::Part {
+            channel: channel.to_string(),
+        }),
+        (&Method::GET, ["stats"]) => Ok(Operation::Stats),
+        (&Method::GET, ["metrics"]) => Ok(Operation::Metrics),
+        (_, ["reminders"])
+        | (_, ["reminders", _])
+        | (_, ["channels", _])
+        | (_, ["stats"])
+        | (_, ["metrics"]) => Err(ApiError::MethodNotAllowed),
+        _ => Err(ApiError::NotFound),
+    }
+}
+
+/// Decode a query value, where `+` is a space and `%XX` a byte in hex.
+fn percent_decode(value: &str) -> Result<String, ApiError> {
+    let invalid = || ApiError::BadRequest(format!("{:?} is not a valid query value", value));
+
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = rest.get(..2).ok_or_else(invalid)?;
+                let hex = std::str::from_utf8(hex).map_err(|_| invalid())?;
+                bytes.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+                rest = &rest[2..];
+            }
+            _ => bytes.push(byte),
+        }
+    }
+
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+fn parse<T: DeserializeOwned>(body: &[u8]) -> Result<T, ApiError> {
+    serde_json::from_slice(body).map_err(|err| ApiError::BadRequest(err.to_string()))
+}
+
+/// Run the operation of `request` and send back the outcome.
+pub(crate) async fn handle_request(state: &mut State, client: &Client, request: Request) {
+    let result = apply(state, client, request.operation).await;
+
+    // the client might have hung up already
+    let _ = request.respond.send(result);
+}
+
+async fn apply(
+    state: &mut State,
+    client: &Client,
+    operation: Operation,
+) -> Result<Response, ApiError> {
+    match operation {
+        Operation::List { recipient } => {
+            let store = state.store.lock().await;
+            let mut messages = match &recipient {
+                Some(recipient) => store.get_by_recipient(recipient),
+                None => store.get_all().into_iter().collect(),
+            };
+            messages.sort_by_key(|message| message.created());
+
+            Ok(Response::Reminders(
+                messages.into_iter().map(Reminder::from).collect(),
+            ))
+        }
+        Operation::Create(draft) => {
+            if !state.channels.contains(&draft.channel) {
+                return Err(ApiError::BadRequest(format!(
+                    "not in channel {}",
+                    draft.channel
+                )));
+            }
+            let activation = parse_activation(draft.at.as_deref())?;
+            let message = Message::new(
+                state
+                    .config
+                    .id_scheme
+                    .generate()
+                    .wrap_err("Failed to generate id")?,
+                activation,
+                login(&draft.author)?,
+                draft.channel,
+                login(&draft.recipient)?,
+                text(&draft.text)?,
+            );
+            info!("Creating message {} through the admin API", message.id());
+
+            let response = save(state, client, message.clone()).await?;
+            state
+                .audit
+                .record(AuditKind::Created, &message)
+                .wrap_err("Failed to write audit log")?;
+
+            Ok(response)
+        }
+        Operation::Update { id, changes } => {
+            let old = {
+                let store = state.store.lock().await;
+                store.get_by_id(&id).cloned().ok_or(ApiError::NotFound)?
+            };
+
+            let mut message = old.clone().with_id(
+                state
+                    .config
+                    .id_scheme
+                    .generate()
+                    .wrap_err("Failed to generate id")?,
+            );
+            if let Some(recipient) = &changes.recipient {
+                message = message.with_recipient(login(recipient)?);
+            }
+            if let Some(text) = &changes.text {
+                message = message.with_text(self::text(text)?);
+            }
+            if let Some(at) = &changes.at {
+                message = message.with_activation(parse_activation(Some(at))?);
+            }
+            info!(
+                "Replacing message {} with {} through the admin API",
+                old.id(),
+                message.id()
+            );
+
+            state.store.lock().await.remove(&old);
+            let response = save(state, client, message.clone()).await?;
+            state
+                .audit
+                .record(AuditKind::Edited, &old)
+                .wrap_err("Failed to write audit log")?;
+            state
+                .audit
+                .record(AuditKind::Created, &message)
+                .wrap_err("Failed to write audit log")?;
+
+            Ok(response)
+        }
+        Operation::Delete { id } => {
+            let message = {
+                let mut store = state.store.lock().await;
+                let message = store.take(&id).ok_or(ApiError::NotFound)?;
+                store.save().wrap_err("Failed to save store")?;
+                message
+            };
+            info!("Cancelled message {} through the admin API", id);
+            state
+                .audit
+                .record(AuditKind::Cancelled, &message)
+                .wrap_err("Failed to write audit log")?;
+
+            Ok(Response::Deleted { deleted: id })
+        }
+        Operation::Join { channel } => {
+            let channel = login(channel.trim_start_matches('#'))?;
+            if state.channels.insert(channel.clone()) {
+                info!("Joining {} through the admin API", channel);
+                tokio::spawn(join_channels(
+                    client.clone(),
+                    state.joins.clone(),
+                    vec![channel],
+                ));
+            }
+
+            Ok(channels(state))
+        }
+        Operation::Part { channel } => {
+            let channel = login(channel.trim_start_matches('#'))?;
+            if state.channels.remove(&channel) {
+                info!("Parting {} through the admin API", channel);
+                client.part(channel);
+            }
+
+            Ok(channels(state))
+        }
+        Operation::Stats => {
+            let (pending, timed) = count_pending(state).await;
+
+            Ok(Response::Stats(Stats {
+                pending,
+                timed,
+                active_timers: state.timers.len(),
+                channels: state.channels.iter().cloned().collect(),
+                paused: state.pause.is_paused(),
+                usage: state.usage.by_channel().clone(),
+                parse_failures: state
+                    .parse_failures
+                    .counts()
+                    .into_iter()
+                    .map(|(category, count)| (category.name().to_string(), count))
+                    .collect(),
+            }))
+        }
+        Operation::Metrics => {
+            let (pending, timed) = count_pending(state).await;
+            let (timed_latency, untimed_latency) = delivery_stats::delivered_since(
+                &state.audit,
+                OffsetDateTime::now_utc() - STATS_WINDOW,
+            )?;
+
+            let mut metrics = Metrics::default();
+            metrics.gauge("remindme_pending", "Pending reminders", pending as f64);
+            metrics.gauge(
+                "remindme_pending_timed",
+                "Pending reminders delivered at a fixed time",
+                timed as f64,
+            );
+            metrics.gauge(
+                "remindme_active_timers",
+                "Reminders waiting on a timer",
+                state.timers.len() as f64,
+            );
+            metrics.gauge(
+                "remindme_paused",
+                "Whether deliveries are paused",
+                if state.pause.is_paused() { 1.0 } else { 0.0 },
+            );
+            let mut latency =
+                metrics::latency_samples(&vec![("activation", "timed".to_string())], timed_latency);
+            latency.extend(metrics::latency_samples(
+                &vec![("activation", "next_message".to_string())],
+                untimed_latency,
+            ));
+            metrics.family(
+                "remindme_delivery_latency_seconds",
+                "gauge",
+                "How long reminders delivered in the last day waited after becoming due",
+                &latency,
+            );
+            let usage = state
+                .usage
+                .by_channel()
+                .iter()
+                .flat_map(|(channel, commands)| {
+                    commands.iter().map(move |(command, count)| {
+                        (
+                            vec![("channel", channel.clone()), ("command", command.clone())],
+                            *count as f64,
+                        )
+                    })
+                })
+                .collect::<Vec<_>>();
+            metrics.family(
+                "remindme_command_uses_total",
+                "counter",
+                "Uses of each command in each channel since the start",
+                &usage,
+            );
+            let parse_failures = state
+                .parse_failures
+                .counts()
+                .into_iter()
+                .map(|(category, count)| {
+                    (
+                        vec![("category", category.name().to_string())],
+                        count as f64,
+                    )
+                })
+                .collect::<Vec<_>>();
+            metrics.family(
+                "remindme_parse_failures_total",
+                "counter",
+                "Reminders that failed to parse since the start, by why",
+                &parse_failures,
+            );
+
+            Ok(Response::Metrics(metrics.into_text()))
+        }
+    }
+}
+
+/// Count the pending reminders and the timed ones among them.
+async fn count_pending(state: &State) -> (usize, usize) {
+    let store = state.store.lock().await;
+    let timed = store
+        .get_all()
+        .into_iter()
+        .filter(|message| matches!(message.activation(), Activation::Fixed(_)))
+        .count();
+
+    (store.len(), timed)
+}
+
+fn channels(state: &State) -> Response {
+    Response::Channels {
+        channels: state.channels.iter().cloned().collect(),
+    }
+}
+
+/// Store `message` and queue it if it is timed.
+async fn save(state: &State, client: &Client, message: Message) -> Result<Response, ApiError> {
+    {
+        let mut store = state.store.lock().await;
+        store.insert(message.clone());
+        store.save().wrap_err("Failed to save store")?;
+    }
+    queue_messages(state, client, &[message.clone()]).await;
+
+    Ok(Response::Reminder(Reminder::from(&message)))
+}
+
+fn parse_activation(at: Option<&str>) -> Result<Activation, ApiError> {
+    let at = match at {
+        Some(at) => at,
+        None => return Ok(Activation::OnNextMessage),
+    };
+    let at = OffsetDateTime::parse(at, &Rfc3339)
+        .map_err(|_| ApiError::BadRequest(format!("{:?} is not an RFC 3339 timestamp", at)))?;
+    if at <= OffsetDateTime::now_utc() {
+        return Err(ApiError::BadRequest(
+            "at has to be in the future".to_string(),
+        ));
+    }
+
+    Ok(Activation::Fixed(at))
+}
+
+fn login(name: &str) -> Result<String, ApiError> {
+    let login = name.trim_start_matches('@').to_lowercase();
+    if login.is_empty() || !login.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(ApiError::BadRequest(format!("{:?} is not a login", name)));
+    }
+
+    Ok(login)
+}
+
+fn text(text: &str) -> Result<String, ApiError> {
+    let text = sanitize::sanitize(text);
+    if text.is_empty() {
+        return Err(ApiError::BadRequest("text is empty".to_string()));
+    }
+
+    Ok(text)
+}
+
+fn format_timestamp(at: OffsetDateTime) -> String {
+    at.format(&Rfc3339).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_is_required() {
+        assert!(authorized(Some("Bearer secret"), "secret"));
+        assert!(!authorized(Some("Bearer secreT"), "secret"));
+        assert!(!authorized(Some("Bearer secret2"), "secret"));
+        assert!(!authorized(Some("secret"), "secret"));
+        assert!(!authorized(None, "secret"));
+        assert!(!authorized(Some("Bearer "), ""));
+    }
+
+    #[test]
+    fn routes() {
+        assert_eq!(
+            Operation::List {
+                recipient: Some("bob".to_string())
+            },
+            route(&Method::GET, "/reminders", Some("recipient=Bob"), b"").unwrap()
+        );
+        assert_eq!(
+            Operation::List {
+                recipient: Some("bob_1".to_string())
+            },
+            route(&Method::GET, "/reminders", Some("recipient=bob%5F1"), b"").unwrap()
+        );
+        assert!(matches!(
+            route(&Method::GET, "/reminders", Some("recipient=bob%5"), b""),
+            Err(ApiError::BadRequest(_))
+        ));
+        assert_eq!(
+            Operation::Delete {
+                id: "abc".to_string()
+            },
+            route(&Method::DELETE, "/reminders/abc", None, b"").unwrap()
+        );
+        assert_eq!(
+            Operation::Update {
+                id: "abc".to_string(),
+                changes: Changes {
+                    text: Some("new".to_string()),
+                    ..Changes::default()
+                }
+            },
+            route(&Method::PATCH, "/reminders/abc", None, br#"{"text":"new"}"#).unwrap()
+        );
+        assert!(matches!(
+            route(&Method::POST, "/reminders", None, b"{}"),
+            Err(ApiError::BadRequest(_))
+        ));
+        assert!(matches!(
+            route(&Method::PUT, "/reminders", None, b""),
+            Err(ApiError::MethodNotAllowed)
+        ));
+        assert_eq!(
+            Operation::Join {
+                channel: "channel".to_string()
+            },
+            route(&Method::PUT, "/channels/channel", None, b"").unwrap()
+        );
+        assert_eq!(
+            Operation::Metrics,
+            route(&Method::GET, "/metrics", None, b"").unwrap()
+        );
+        assert!(matches!(
+            route(&Method::GET, "/", None, b""),
+            Err(ApiError::NotFound)
+        ));
+    }
+}
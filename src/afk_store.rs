@@ -0,0 +1,41 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::ron_store::RonStore;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AfkStatus {
+    pub since: OffsetDateTime,
+    pub channel: String,
+    pub reason: String,
+}
+
+/// Users that announced they are away, keyed by login.
+#[derive(Debug, Clone)]
+pub struct AfkStore {
+    data: RonStore<HashMap<String, AfkStatus>>,
+}
+
+impl AfkStore {
+    pub fn from_path(path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            data: RonStore::from_path(path, "afk store")?,
+        })
+    }
+
+    pub fn set(&mut self, login: &str, status: AfkStatus) {
+        self.data.write().insert(login.to_string(), status);
+    }
+
+    /// Remove and return the status of `login` if they are afk.
+    pub fn pop(&mut self, login: &str) -> Option<AfkStatus> {
+        self.data.write().remove(login)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.data.save()
+    }
+}
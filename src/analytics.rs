@@ -0,0 +1,132 @@
+//! Usage of the bot in a channel for `~analytics`, from the audit log and the pending reminders.
+//!
+//! The audit log has no event for creating a reminder, so a reminder counts as created in the
+//! window if it is pending or has any event and was created after the window started.
+
+use std::collections::HashMap;
+
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    audit_log::{AuditEvent, AuditKind},
+    message::Message,
+};
+
+/// How many authors are listed.
+const TOP_AUTHORS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelUsage {
+    pub created: usize,
+    pub delivered: usize,
+    /// Authors of the most reminders created, most active first.
+    pub top_authors: Vec<(String, usize)>,
+    /// Average time between a reminder becoming due and its delivery.
+    pub average_delay: Option<Duration>,
+}
+
+impl ChannelUsage {
+    /// Summarize the reminders written in `channel` since `since`.
+    pub fn new(
+        channel: &str,
+        since: OffsetDateTime,
+        events: &[AuditEvent],
+        pending: &[&Message],
+    ) -> Self {
+        let events = events
+            .iter()
+            .filter(|event| event.channel == channel)
+            .collect::<Vec<_>>();
+
+        // ids to authors, so reminders with several events count once
+        let mut created = HashMap::new();
+        for event in events.iter().filter(|event| event.created >= since) {
+            created.insert(event.id.as_str(), event.author.as_str());
+        }
+        for message in pending
+            .iter()
+            .filter(|message| message.channel() == channel && message.created() >= since)
+        {
+            created.insert(message.id(), message.author());
+        }
+
+        let mut authors = HashMap::<&str, usize>::new();
+        for author in created.values() {
+            *authors.entry(*author).or_default() += 1;
+        }
+        let mut top_authors = authors
+            .into_iter()
+            .map(|(author, count)| (author.to_string(), count))
+            .collect::<Vec<_>>();
+        top_authors.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top_authors.truncate(TOP_AUTHORS);
+
+        let delays = events
+            .iter()
+            .filter(|event| event.kind == AuditKind::Delivered && event.at >= since)
+            .map(|event| event.latency())
+            .collect::<Vec<_>>();
+        let average_delay = (!delays.is_empty()).then(|| {
+            delays
+                .iter()
+                .fold(Duration::ZERO, |sum, delay| sum + *delay)
+                / delays.len() as u32
+        });
+
+        Self {
+            created: created.len(),
+            delivered: delays.len(),
+            top_authors,
+            average_delay,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Activation;
+
+    fn message(id: &str, author: &str, channel: &str) -> Message {
+        Message::new(
+            id.to_string(),
+            Activation::OnNextMessage,
+            author.to_string(),
+            channel.to_string(),
+            "bob".to_string(),
+            "text".to_string(),
+        )
+    }
+
+    fn delivered(message: &Message, delay: Duration) -> AuditEvent {
+        let mut event = AuditEvent::new(AuditKind::Delivered, message);
+        event.at = event.created + delay;
+        event
+    }
+
+    #[test]
+    fn counts_reminders_of_the_channel() {
+        let now = OffsetDateTime::now_utc();
+        let a = message("a", "alice", "channel");
+        let b = message("b", "alice", "channel");
+        let c = message("c", "carol", "channel");
+        let other = message("d", "dave", "other");
+
+        let events = vec![
+            delivered(&a, Duration::minutes(10)),
+            AuditEvent::new(AuditKind::Cancelled, &b),
+            delivered(&other, Duration::minutes(1)),
+        ];
+        let usage = ChannelUsage::new("channel", now - Duration::days(7), &events, &[&c, &other]);
+
+        assert_eq!(
+            ChannelUsage {
+                created: 3,
+                delivered: 1,
+                top_authors: vec![("alice".to_string(), 2), ("carol".to_string(), 1)],
+                average_delay: Some(Duration::minutes(10)),
+            },
+            usage
+        );
+    }
+}
@@ -0,0 +1,204 @@
+//! Delivered reminders kept for good, one JSON object per line, so `~history` can go back further
+//! than the last few deliveries and operators can look into what was sent when users report abuse.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use eyre::{Context, Result};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::message::{stored::StoredMessage, Message};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct Entry {
+    delivered_at: OffsetDateTime,
+    /// Where the reminder was delivered, which is not necessarily where it was written.
+    channel: String,
+    message: StoredMessage,
+}
+
+/// A reminder from the archive.
+#[derive(Debug, Clone)]
+pub struct Archived {
+    pub delivered_at: OffsetDateTime,
+    pub channel: String,
+    pub message: Message,
+}
+
+impl From<&Archived> for Entry {
+    fn from(archived: &Archived) -> Self {
+        Self {
+            delivered_at: archived.delivered_at,
+            channel: archived.channel.clone(),
+            message: (&archived.message).into(),
+        }
+    }
+}
+
+impl From<Entry> for Archived {
+    fn from(entry: Entry) -> Self {
+        Self {
+            delivered_at: entry.delivered_at,
+            channel: entry.channel,
+            message: entry.message.into(),
+        }
+    }
+}
+
+impl Archived {
+    /// Whether `login` wrote or received the reminder.
+    pub fn involves(&self, login: &str) -> bool {
+        self.message.author() == login || self.message.recipient() == login
+    }
+}
+
+/// Clones write to the same file one at a time, so rewriting it in [`Archive::forget`] can't
+/// lose what is appended meanwhile.
+#[derive(Debug, Clone)]
+pub struct Archive {
+    path: PathBuf,
+    writer: Arc<Mutex<()>>,
+}
+
+impl Archive {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            writer: Arc::default(),
+        }
+    }
+
+    /// Append `message`, delivered in `channel` at `delivered_at`.
+    pub fn record(
+        &self,
+        message: &Message,
+        channel: &str,
+        delivered_at: OffsetDateTime,
+    ) -> Result<()> {
+        let mut line = serde_json::to_string(&Entry {
+            delivered_at,
+            channel: channel.to_string(),
+            message: message.into(),
+        })
+        .wrap_err("Failed to serialize archive entry")?;
+        line.push('\n');
+
+        let _writer = self.writer.lock().unwrap();
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .wrap_err("Failed to open archive")?
+            .write_all(line.as_bytes())
+            .wrap_err("Failed to write archive")
+    }
+
+    /// Everything in the archive, oldest first.
+    pub fn all(&self) -> Result<Vec<Archived>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err).wrap_err("Failed to open archive"),
+        };
+
+        BufReader::new(file)
+            .lines()
+            .map(|line| {
+                let line = line.wrap_err("Failed to read archive")?;
+                serde_json::from_str::<Entry>(&line)
+                    .map(Archived::from)
+                    .wrap_err("Failed to deserialize archive entry")
+            })
+            .collect()
+    }
+
+    /// The reminders delivered to `login`, newest first.
+    pub fn delivered_to(&self, login: &str) -> Result<Vec<Archived>> {
+        Ok(self
+            .all()?
+            .into_iter()
+            .rev()
+            .filter(|archived| archived.message.recipient() == login)
+            .collect())
+    }
+
+    /// Remove everything `login` wrote or received.
+    pub fn forget(&self, login: &str) -> Result<()> {
+        let _writer = self.writer.lock().unwrap();
+        let all = self.all()?;
+        if !all.iter().any(|archived| archived.involves(login)) {
+            return Ok(());
+        }
+
+        let mut data = String::new();
+        for archived in all.iter().filter(|archived| !archived.involves(login)) {
+            data.push_str(
+                &serde_json::to_string(&Entry::from(archived))
+                    .wrap_err("Failed to serialize archive entry")?,
+            );
+            data.push('\n');
+        }
+
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, data).wrap_err("Failed to write archive")?;
+        fs::rename(&tmp, &self.path).wrap_err("Failed to replace archive")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::message::Activation;
+
+    fn message(id: &str, author: &str, recipient: &str) -> Message {
+        Message::new(
+            id.to_string(),
+            Activation::OnNextMessage,
+            author.to_string(),
+            "channel".to_string(),
+            recipient.to_string(),
+            "text".to_string(),
+        )
+    }
+
+    #[test]
+    fn records_and_forgets() {
+        let path = env::temp_dir().join(format!("remindme-archive-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let archive = Archive::new(path.clone());
+        let now = OffsetDateTime::UNIX_EPOCH;
+
+        archive
+            .record(&message("a", "alice", "bob"), "channel", now)
+            .unwrap();
+        archive
+            .record(&message("b", "carol", "bob"), "other", now)
+            .unwrap();
+        archive
+            .record(&message("c", "bob", "alice"), "channel", now)
+            .unwrap();
+
+        let delivered = archive.delivered_to("bob").unwrap();
+        assert_eq!(
+            vec!["b", "a"],
+            delivered
+                .iter()
+                .map(|archived| archived.message.id())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!("other", delivered[0].channel);
+
+        archive.forget("alice").unwrap();
+        assert_eq!(1, archive.all().unwrap().len());
+        assert_eq!("b", archive.all().unwrap()[0].message.id());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+};
+
+use eyre::{Context, Result};
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+use crate::message::{Activation, Message};
+
+#[derive(Debug, Copy, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub enum AuditKind {
+    Delivered,
+    Cancelled,
+    /// A cancelled reminder was brought back with `~undo`.
+    Restored,
+    /// A delivered reminder was scheduled again with `~snoozeall`.
+    Snoozed,
+    /// A pending reminder was given to another recipient with `~transfer`.
+    Transferred,
+    /// An undelivered reminder was removed by the retention policy.
+    Expired,
+    /// A reminder was created through the admin API.
+    Created,
+    /// A reminder was replaced through the admin API, recorded with the old version. The new
+    /// version is recorded as created.
+    Edited,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuditEvent {
+    pub kind: AuditKind,
+    pub at: OffsetDateTime,
+    pub id: String,
+    pub author: String,
+    pub recipient: String,
+    pub channel: String,
+    pub created: OffsetDateTime,
+    /// When a timed reminder was due.
+    #[serde(default)]
+    pub due: Option<OffsetDateTime>,
+    /// The channel the reminder was written in, if it is known. It was delivered in `channel`.
+    #[serde(default)]
+    pub written_in: Option<String>,
+    pub text_hash: String,
+}
+
+impl AuditEvent {
+    pub fn new(kind: AuditKind, message: &Message) -> Self {
+        Self {
+            kind,
+            at: OffsetDateTime::now_utc(),
+            id: message.id().to_string(),
+            author: message.author().to_string(),
+            recipient: message.recipient().to_string(),
+            channel: message.channel().to_string(),
+            created: message.created(),
+            due: match message.activation() {
+                Activation::Fixed(_) => Some(message.due()),
+                Activation::OnNextMessage
+                | Activation::OnRaid
+                | Activation::OnOffline
+                | Activation::Never => None,
+            },
+            written_in: message.origin().map(|origin| origin.channel.clone()),
+            text_hash: text_hash(message.text()),
+        }
+    }
+
+    /// How long the reminder waited between becoming due and this event.
+    pub fn latency(&self) -> Duration {
+        self.at - self.due.unwrap_or(self.created)
+    }
+}
+
+/// An append-only log of what happened to reminders, one RON entry per line.
+///
+/// Only a hash of the reminder text is kept so the log can confirm a reported text without
+/// storing it.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+    retention: Duration,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf, retention: Duration) -> Self {
+        Self { path, retention }
+    }
+
+    pub fn append(&self, event: &AuditEvent) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .wrap_err("Failed to open audit log")?;
+        let line = ron::ser::to_string(event).wrap_err("Failed to serialize audit event")?;
+
+        writeln!(file, "{}", line).wrap_err("Failed to write audit log")
+    }
+
+    pub fn record(&self, kind: AuditKind, message: &Message) -> Result<()> {
+        self.append(&AuditEvent::new(kind, message))
+    }
+
+    /// Read every event since `since`, skipping lines that can't be parsed.
+    pub fn events_since(&self, since: OffsetDateTime) -> Result<Vec<AuditEvent>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let file = File::open(&self.path).wrap_err("Failed to open audit log")?;
+
+        let mut events = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.wrap_err("Failed to read audit log")?;
+
+            match ron::de::from_str::<AuditEvent>(&line) {
+                Ok(event) if event.at >= since => events.push(event),
+                _ => {}
+            }
+        }
+
+        Ok(events)
+    }
+
+    /// Drop every event older than the retention period. Returns the number of removed events.
+    pub fn prune(&self) -> Result<usize> {
+        if !self.path.exists() {
+            return Ok(0);
+        }
+
+        let cutoff = OffsetDateTime::now_utc() - self.retention;
+        let file = File::open(&self.path).wrap_err("Failed to open audit log")?;
+
+        let mut kept = Vec::new();
+        let mut removed = 0;
+        for line in BufReader::new(file).lines() {
+            let line = line.wrap_err("Failed to read audit log")?;
+
+            match ron::de::from_str::<AuditEvent>(&line) {
+                Ok(event) if event.at < cutoff => removed += 1,
+                // keep lines we can't parse so nothing is lost silently
+                _ => kept.push(line),
+            }
+        }
+
+        if removed > 0 {
+            let mut file = File::create(&self.path).wrap_err("Failed to open audit log")?;
+            for line in kept {
+                writeln!(file, "{}", line).wrap_err("Failed to write audit log")?;
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+/// 64-bit FNV-1a hash of `text` as hex. Stable across builds, unlike `DefaultHasher`.
+fn text_hash(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_hash_is_fnv1a() {
+        assert_eq!("cbf29ce484222325", text_hash(""));
+        assert_eq!("af63dc4c8601ec8c", text_hash("a"));
+    }
+}
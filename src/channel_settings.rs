@@ -0,0 +1,296 @@
+use std::{collections::HashMap, path::PathBuf, str::FromStr};
+
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::DeliveryStyle, delivery_format::DeliveryFormat, duration_parser::Language,
+    limits::Tier, ron_store::RonStore,
+};
+
+/// Settings moderators changed in chat. Unset settings fall back to the config.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct ChannelSettings {
+    pub duration_precision: Option<usize>,
+    pub anti_ping: Option<bool>,
+    pub scoped: Option<bool>,
+    pub language: Option<Language>,
+    pub format: Option<DeliveryFormat>,
+    pub moderator_limits: Option<Tier>,
+    pub broadcaster_limits: Option<Tier>,
+}
+
+/// A setting that can be changed with `~set`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Key {
+    /// How many units of elapsed time deliveries show.
+    Precision,
+    /// Whether author names in deliveries are kept from pinging.
+    AntiPing,
+    /// Whether reminders are only delivered in this channel by default.
+    Scoped,
+    /// The language durations are typed in.
+    Language,
+    /// How several reminders delivered at once are laid out.
+    Format,
+    /// How far the caps and cooldowns apply to moderators.
+    ModeratorLimits,
+    /// How far the caps and cooldowns apply to the broadcaster.
+    BroadcasterLimits,
+}
+
+impl Key {
+    pub const ALL: &'static [Key] = &[
+        Key::Precision,
+        Key::AntiPing,
+        Key::Scoped,
+        Key::Language,
+        Key::Format,
+        Key::ModeratorLimits,
+        Key::BroadcasterLimits,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Key::Precision => "precision",
+            Key::AntiPing => "antiping",
+            Key::Scoped => "scoped",
+            Key::Language => "language",
+            Key::Format => "format",
+            Key::ModeratorLimits => "modlimits",
+            Key::BroadcasterLimits => "broadcasterlimits",
+        }
+    }
+
+    /// Whether only the broadcaster may change the setting, since moderators could lift their
+    /// own limits otherwise.
+    pub fn broadcaster_only(self) -> bool {
+        matches!(self, Key::ModeratorLimits | Key::BroadcasterLimits)
+    }
+}
+
+impl FromStr for Key {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Key::ALL
+            .iter()
+            .copied()
+            .find(|key| key.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::unknown_key(s, Key::ALL.iter().map(|key| key.name())))
+    }
+}
+
+/// Why a setting of a channel or user couldn't be changed.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("unknown setting '{key}', try one of {known}")]
+    UnknownKey { key: String, known: String },
+
+    #[error("couldn't understand '{value}' for {key}, expected {expected}")]
+    InvalidValue {
+        key: &'static str,
+        value: String,
+        expected: &'static str,
+    },
+}
+
+impl Error {
+    /// `key` isn't one of the `known` setting names.
+    pub fn unknown_key<'a>(key: &str, known: impl Iterator<Item = &'a str>) -> Self {
+        Error::UnknownKey {
+            key: key.to_string(),
+            known: known.intersperse(", ").collect(),
+        }
+    }
+}
+
+impl ChannelSettings {
+    /// Change `key` to `value`, or back to the config with `default`.
+    pub fn set(&mut self, key: Key, value: &str) -> Result<(), Error> {
+        let reset = value.eq_ignore_ascii_case("default");
+        let invalid = |expected| Error::InvalidValue {
+            key: key.name(),
+            value: value.to_string(),
+            expected,
+        };
+
+        match key {
+            Key::Precision if reset => self.duration_precision = None,
+            Key::Precision => {
+                self.duration_precision = match value.parse() {
+                    Ok(precision @ 1..=5) => Some(precision),
+                    _ => return Err(invalid("a number from 1 to 5")),
+                }
+            }
+            Key::AntiPing if reset => self.anti_ping = None,
+            Key::AntiPing => {
+                self.anti_ping = Some(parse_bool(value).ok_or_else(|| invalid("on or off"))?)
+            }
+            Key::Scoped if reset => self.scoped = None,
+            Key::Scoped => {
+                self.scoped = Some(parse_bool(value).ok_or_else(|| invalid("on or off"))?)
+            }
+            Key::Language if reset => self.language = None,
+            Key::Language => {
+                self.language = Some(value.parse().map_err(|_| invalid("en, de or fr"))?)
+            }
+            Key::Format if reset => self.format = None,
+            Key::Format => {
+                self.format = Some(
+                    value
+                        .parse()
+                        .map_err(|_| invalid("grouped, compact or verbose"))?,
+                )
+            }
+            Key::ModeratorLimits if reset => self.moderator_limits = None,
+            Key::ModeratorLimits => {
+                self.moderator_limits = Some(
+                    value
+                        .parse()
+                        .map_err(|_| invalid("normal, raised or unlimited"))?,
+                )
+            }
+            Key::BroadcasterLimits if reset => self.broadcaster_limits = None,
+            Key::BroadcasterLimits => {
+                self.broadcaster_limits = Some(
+                    value
+                        .parse()
+                        .map_err(|_| invalid("normal, raised or unlimited"))?,
+                )
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The value of `key`, if it is set.
+    pub fn get(&self, key: Key) -> Option<String> {
+        match key {
+            Key::Precision => self
+                .duration_precision
+                .map(|precision| precision.to_string()),
+            Key::AntiPing => self.anti_ping.map(on_off),
+            Key::Scoped => self.scoped.map(on_off),
+            Key::Language => self.language.map(|language| language.code().to_string()),
+            Key::Format => self.format.map(|format| format.name().to_string()),
+            Key::ModeratorLimits => self.moderator_limits.map(|tier| tier.name().to_string()),
+            Key::BroadcasterLimits => self.broadcaster_limits.map(|tier| tier.name().to_string()),
+        }
+    }
+
+    /// Apply the settings to `style` from the config.
+    pub fn apply(&self, mut style: DeliveryStyle) -> DeliveryStyle {
+        if let Some(precision) = self.duration_precision {
+            style.precision = precision;
+        }
+        if let Some(anti_ping) = self.anti_ping {
+            style.anti_ping = anti_ping;
+        }
+        if let Some(format) = self.format {
+            style.format = format;
+        }
+
+        style
+    }
+}
+
+pub(crate) fn on_off(value: bool) -> String {
+    let value = if value { "on" } else { "off" };
+
+    value.to_string()
+}
+
+pub(crate) fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" => Some(true),
+        "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// The settings of every channel that changed one, keyed by channel.
+#[derive(Debug, Clone)]
+pub struct ChannelSettingsStore {
+    data: RonStore<HashMap<String, ChannelSettings>>,
+}
+
+impl ChannelSettingsStore {
+    pub fn from_path(path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            data: RonStore::from_path(path, "channel settings store")?,
+        })
+    }
+
+    pub fn get(&self, channel: &str) -> ChannelSettings {
+        self.data.read().get(channel).cloned().unwrap_or_default()
+    }
+
+    /// Change `key` of `channel` to `value`.
+    pub fn set(&self, channel: &str, key: Key, value: &str) -> Result<(), Error> {
+        let mut data = self.data.write();
+
+        let settings = data.entry(channel.to_string()).or_default();
+        let result = settings.set(key, value);
+        if *settings == ChannelSettings::default() {
+            data.remove(channel);
+        }
+
+        result
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.data.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_validates_values() {
+        let mut settings = ChannelSettings::default();
+
+        settings.set(Key::Precision, "3").unwrap();
+        assert_eq!(Some("3".to_string()), settings.get(Key::Precision));
+        assert!(settings.set(Key::Precision, "0").is_err());
+        assert!(settings.set(Key::AntiPing, "maybe").is_err());
+
+        settings.set(Key::Language, "de").unwrap();
+        assert_eq!(Some("de".to_string()), settings.get(Key::Language));
+        assert!(settings.set(Key::Language, "klingon").is_err());
+
+        settings.set(Key::Precision, "default").unwrap();
+        assert_eq!(None, settings.get(Key::Precision));
+    }
+
+    #[test]
+    fn settings_override_the_config() {
+        let mut settings = ChannelSettings::default();
+        settings.set(Key::AntiPing, "on").unwrap();
+
+        let style = DeliveryStyle {
+            max_message_bytes: 500,
+            precision: 2,
+            preview_chars: 100,
+            anti_ping: false,
+            batch_window: time::Duration::seconds(5),
+            format: DeliveryFormat::Grouped,
+            missed: false,
+        };
+
+        assert!(settings.apply(style).anti_ping);
+        assert_eq!(2, settings.apply(style).precision);
+
+        settings.set(Key::Format, "compact").unwrap();
+        assert_eq!(DeliveryFormat::Compact, settings.apply(style).format);
+    }
+
+    #[test]
+    fn parse_keys() {
+        assert_eq!(Key::AntiPing, "AntiPing".parse::<Key>().unwrap());
+        assert!("prefix".parse::<Key>().is_err());
+    }
+}
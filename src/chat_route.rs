@@ -0,0 +1,135 @@
+//! Chat connections `twitch_irc` can't make itself: to another server than Twitch, e.g. a local
+//! mock server, or through a [proxy](crate::proxy). They go through [`RoutedTransport`] instead
+//! of one of its transports.
+
+use std::{fmt, io, sync::Arc};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use either::Either;
+use futures::{future, stream::FusedStream, Sink, SinkExt, StreamExt, TryStreamExt};
+use once_cell::sync::OnceCell;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_rustls::{rustls::ClientConfig, webpki::DNSNameRef, TlsConnector};
+use tokio_util::codec::{BytesCodec, FramedRead, FramedWrite, LinesCodec};
+use twitch_irc::{
+    message::{IRCMessage, IRCParseError},
+    transport::Transport,
+};
+
+use crate::proxy::Proxy;
+
+pub const TWITCH_HOST: &str = "irc.chat.twitch.tv";
+pub const TLS_PORT: u16 = 6697;
+pub const PLAIN_PORT: u16 = 6667;
+
+/// The route chat connections take. `twitch_irc` creates transports without arguments, so it is
+/// set once at startup.
+static CHAT_ROUTE: OnceCell<Route> = OnceCell::new();
+
+/// Where chat connections go and how.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub host: String,
+    pub port: u16,
+    /// Whether IRC runs over TLS.
+    pub tls: bool,
+    pub proxy: Option<Proxy>,
+}
+
+impl Route {
+    /// Send chat connections along `route`. Only the first call has an effect.
+    pub fn use_for_chat(route: Route) {
+        let _ = CHAT_ROUTE.set(route);
+    }
+
+    async fn connect(&self) -> io::Result<TcpStream> {
+        match &self.proxy {
+            Some(proxy) => proxy.connect(&self.host, self.port).await,
+            None => TcpStream::connect((self.host.as_str(), self.port)).await,
+        }
+    }
+}
+
+type Incoming = Box<
+    dyn FusedStream<Item = Result<IRCMessage, Either<io::Error, IRCParseError>>>
+        + Unpin
+        + Send
+        + Sync,
+>;
+type Outgoing = Box<dyn Sink<IRCMessage, Error = io::Error> + Unpin + Send + Sync>;
+
+/// IRC along the route passed to [`Route::use_for_chat`].
+pub struct RoutedTransport {
+    incoming: Incoming,
+    outgoing: Outgoing,
+}
+
+impl fmt::Debug for RoutedTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RoutedTransport").finish_non_exhaustive()
+    }
+}
+
+impl RoutedTransport {
+    fn from_stream<S>(stream: S) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Send + Sync + 'static,
+    {
+        let (read, write) = tokio::io::split(stream);
+
+        let incoming = FramedRead::new(read, LinesCodec::new())
+            .map_err(|err| Either::Left(io::Error::new(io::ErrorKind::Other, err)))
+            .and_then(|line| future::ready(IRCMessage::parse(&line).map_err(Either::Right)))
+            .fuse();
+        let outgoing = FramedWrite::new(write, BytesCodec::new()).with(|message: IRCMessage| {
+            future::ready(Ok::<_, io::Error>(Bytes::from(format!(
+                "{}\r\n",
+                message.as_raw_irc()
+            ))))
+        });
+
+        Self {
+            incoming: Box::new(incoming),
+            outgoing: Box::new(outgoing),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for RoutedTransport {
+    type ConnectError = io::Error;
+    type IncomingError = io::Error;
+    type OutgoingError = io::Error;
+    type Incoming = Incoming;
+    type Outgoing = Outgoing;
+
+    async fn new() -> Result<Self, Self::ConnectError> {
+        let route = CHAT_ROUTE.get().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::Other, "no route is configured for chat")
+        })?;
+        let stream = route.connect().await?;
+        if !route.tls {
+            return Ok(Self::from_stream(stream));
+        }
+
+        let mut config = ClientConfig::new();
+        config
+            .root_store
+            .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+        let domain = DNSNameRef::try_from_ascii_str(&route.host)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let stream = TlsConnector::from(Arc::new(config))
+            .connect(domain, stream)
+            .await?;
+
+        Ok(Self::from_stream(stream))
+    }
+
+    fn split(self) -> (Self::Incoming, Self::Outgoing) {
+        (self.incoming, self.outgoing)
+    }
+}
@@ -0,0 +1,138 @@
+//! Split long replies into chat messages Twitch accepts.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Split `text` into chunks of at most `max_bytes` bytes.
+///
+/// Chunks end between whitespace delimited words so emote codes like `FeelsDankMan` still
+/// render. Words longer than a whole chunk are split between grapheme clusters, so combining
+/// sequences and emoji are never torn apart.
+pub fn split(text: &str, max_bytes: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+
+    for word in text.split_inclusive(char::is_whitespace) {
+        if !chunk.is_empty() && chunk.len() + word.trim_end().len() > max_bytes {
+            push_chunk(&mut chunks, &mut chunk);
+        }
+
+        if word.trim_end().len() <= max_bytes {
+            chunk.push_str(word);
+            continue;
+        }
+
+        for grapheme in word.graphemes(true) {
+            if !chunk.is_empty() && chunk.len() + grapheme.len() > max_bytes {
+                push_chunk(&mut chunks, &mut chunk);
+            }
+            chunk.push_str(grapheme);
+        }
+    }
+    push_chunk(&mut chunks, &mut chunk);
+
+    chunks
+}
+
+/// Shorten `text` to at most `max_chars` characters.
+pub fn preview(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+
+    let mut preview = text
+        .chars()
+        .take(max_chars.saturating_sub(1))
+        .collect::<String>();
+    preview.push('…');
+    preview
+}
+
+/// Pack `entries` into pages of at most `max_bytes`, joined by `separator`. Entries are only
+/// split if they don't fit on a page by themselves.
+pub fn paginate(entries: &[String], separator: &str, max_bytes: usize) -> Vec<String> {
+    let mut pages = Vec::new();
+    let mut page = String::new();
+
+    for entry in entries {
+        if !page.is_empty() && page.len() + separator.len() + entry.len() > max_bytes {
+            pages.push(std::mem::take(&mut page));
+        }
+
+        if entry.len() > max_bytes {
+            pages.extend(split(entry, max_bytes));
+            continue;
+        }
+
+        if !page.is_empty() {
+            page.push_str(separator);
+        }
+        page.push_str(entry);
+    }
+    if !page.is_empty() {
+        pages.push(page);
+    }
+
+    pages
+}
+
+/// Move `chunk` into `chunks` without the whitespace it ends in.
+fn push_chunk(chunks: &mut Vec<String>, chunk: &mut String) {
+    let trimmed = chunk.trim_end();
+    if !trimmed.is_empty() {
+        chunks.push(trimmed.to_string());
+    }
+    chunk.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_text_is_one_chunk() {
+        assert_eq!(vec!["hello".to_string()], split("hello", 10));
+        assert!(split("", 10).is_empty());
+    }
+
+    #[test]
+    fn words_stay_whole() {
+        assert_eq!(
+            vec!["hi", "FeelsDankMan", "bye"],
+            split("hi FeelsDankMan bye", 13)
+        );
+        assert_eq!(vec!["a b", "c"], split("a b c", 4));
+    }
+
+    #[test]
+    fn limit_counts_bytes() {
+        // 'ä' takes two bytes
+        assert_eq!(vec!["ää", "ä"], split("äää", 4));
+    }
+
+    #[test]
+    fn pages_keep_entries_whole() {
+        let entries = ["aaa", "bbb", "ccc", "dddddddddd"].map(String::from);
+
+        assert_eq!(
+            vec!["aaa | bbb", "ccc", "ddddddddd", "d"],
+            paginate(&entries, " | ", 9)
+        );
+        assert!(paginate(&[], " | ", 9).is_empty());
+    }
+
+    #[test]
+    fn graphemes_stay_whole() {
+        // family emoji made of four people joined by zero width joiners
+        let family = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}\u{200d}\u{1f466}";
+        let text = format!("a{}", family);
+
+        assert_eq!(vec!["a".to_string(), family.to_string()], split(&text, 10));
+    }
+
+    #[test]
+    fn previews_end_in_an_ellipsis() {
+        assert_eq!("hello", preview("hello", 5));
+        assert_eq!("hel…", preview("hello", 4));
+        assert_eq!("…", preview("hello", 0));
+    }
+}
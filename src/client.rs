@@ -0,0 +1,309 @@
+//! The chat client over the transport chosen in the config. `twitch_irc` makes the transport
+//! part of the client type, so every transport gets a variant.
+//!
+//! Chat messages are sent one at a time through a [`SendQueue`], so they can skip ahead by
+//! priority while staying within the [rate limits](crate::rate_limits) of the account. They may
+//! be sent over another connection than the one reading chat, see
+//! [`connections`](crate::connections).
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use eyre::{eyre, Context, Result};
+use serde::Deserialize;
+use tokio::{
+    sync::{mpsc::UnboundedReceiver, oneshot},
+    time::timeout,
+};
+use tracing::error;
+use twitch_irc::{
+    login::StaticLoginCredentials, message::ServerMessage, ClientConfig, PlainTCPTransport,
+    SecureTCPTransport, SecureWSTransport, TwitchIRCClient,
+};
+
+#[cfg(test)]
+use crate::harness::MockClient;
+use crate::{
+    chat_route::RoutedTransport,
+    rate_limits::{Limiter, Verification},
+    send_queue::{Priority, SendQueue},
+};
+
+/// How long sending one chat message may take before it counts as failed.
+const SEND_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How the client connects to Twitch.
+#[derive(Debug, Copy, Clone, Deserialize, PartialEq, Eq)]
+pub enum Transport {
+    /// IRC over TLS.
+    Tcp,
+    /// IRC over a secure WebSocket, for networks that block the IRC port.
+    WebSocket,
+    /// IRC without encryption, e.g. to inspect the traffic through a local proxy or to talk to a
+    /// mock server.
+    PlainTcp,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Transport::Tcp
+    }
+}
+
+#[derive(Clone)]
+pub(crate) enum Connection {
+    Tcp(TwitchIRCClient<SecureTCPTransport, StaticLoginCredentials>),
+    WebSocket(TwitchIRCClient<SecureWSTransport, StaticLoginCredentials>),
+    PlainTcp(TwitchIRCClient<PlainTCPTransport, StaticLoginCredentials>),
+    Routed(TwitchIRCClient<RoutedTransport, StaticLoginCredentials>),
+    #[cfg(test)]
+    Mock(MockClient),
+}
+
+/// Run `$call` on the inner client, whatever its transport.
+macro_rules! dispatch {
+    ($client:expr, $inner:ident => $call:expr) => {
+        match $client {
+            Connection::Tcp($inner) => $call,
+            Connection::WebSocket($inner) => $call,
+            Connection::PlainTcp($inner) => $call,
+            Connection::Routed($inner) => $call,
+            #[cfg(test)]
+            Connection::Mock($inner) => $call,
+        }
+    };
+}
+
+impl Connection {
+    /// Connect with `transport`, or along the route passed to
+    /// [`Route::use_for_chat`](crate::chat_route::Route::use_for_chat) if `routed`.
+    pub fn open(
+        transport: Transport,
+        routed: bool,
+        config: ClientConfig<StaticLoginCredentials>,
+    ) -> (UnboundedReceiver<ServerMessage>, Self) {
+        if routed {
+            let (incoming, client) = TwitchIRCClient::new(config);
+            (incoming, Connection::Routed(client))
+        } else {
+            match transport {
+                Transport::Tcp => {
+                    let (incoming, client) = TwitchIRCClient::new(config);
+                    (incoming, Connection::Tcp(client))
+                }
+                Transport::WebSocket => {
+                    let (incoming, client) = TwitchIRCClient::new(config);
+                    (incoming, Connection::WebSocket(client))
+                }
+                Transport::PlainTcp => {
+                    let (incoming, client) = TwitchIRCClient::new(config);
+                    (incoming, Connection::PlainTcp(client))
+                }
+            }
+        }
+    }
+
+    async fn send(&self, outgoing: Outgoing) -> Result<()> {
+        let Outgoing {
+            channel_login,
+            message,
+            reply_to,
+            raw,
+        } = outgoing;
+
+        if raw {
+            dispatch!(self, client => Ok(client.privmsg(channel_login, message).await?))
+        } else {
+            dispatch!(self, client => {
+                Ok(client
+                    .say_in_response(channel_login, message, reply_to)
+                    .await?)
+            })
+        }
+    }
+}
+
+/// A chat message waiting in the [`SendQueue`].
+struct Outgoing {
+    channel_login: String,
+    message: String,
+    reply_to: Option<String>,
+    /// Sent as is, without keeping it from running chat commands.
+    raw: bool,
+}
+
+/// Send the queued messages in order of their priority until the process exits. A message that
+/// can't be sent within [`SEND_TIMEOUT`] fails, so one stuck channel doesn't hold up the others.
+async fn send_queued(
+    connection: Connection,
+    queue: Arc<SendQueue<Queued>>,
+    mut limiter: Option<Limiter>,
+) {
+    loop {
+        let (outgoing, sent) = queue.pop().await;
+        if let Some(limiter) = &mut limiter {
+            limiter.acquire().await;
+        }
+        let channel_login = outgoing.channel_login.clone();
+        let result = match timeout(SEND_TIMEOUT, connection.send(outgoing)).await {
+            Ok(result) => result,
+            Err(_) => Err(eyre!(
+                "Sending to {} took longer than {:?}",
+                channel_login,
+                SEND_TIMEOUT
+            )),
+        };
+        // nobody waits for the message anymore if the receiver was dropped
+        let _ = sent.send(result);
+    }
+}
+
+/// A queued message and where to report whether it was sent.
+type Queued = (Outgoing, oneshot::Sender<Result<()>>);
+
+#[derive(Clone)]
+pub(crate) struct Client {
+    /// Joins the channels and reads their chat.
+    read: Connection,
+    queue: Arc<SendQueue<Queued>>,
+    verification: Option<Verification>,
+    /// Replies queued but not sent yet.
+    unsent_replies: Arc<AtomicUsize>,
+}
+
+impl Client {
+    /// Read chat over `read` and send over `send`, they may be the same connection. Messages and
+    /// joins are paced for an account with `verification`, only joins if it isn't known.
+    pub fn new(read: Connection, send: Connection, verification: Option<Verification>) -> Self {
+        let queue = Arc::new(SendQueue::default());
+        tokio::spawn(send_queued(
+            send,
+            queue.clone(),
+            verification.map(|verification| Limiter::new(verification.messages())),
+        ));
+
+        Self {
+            read,
+            queue,
+            verification,
+            unsent_replies: Arc::default(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn mock(mock: MockClient) -> Self {
+        let connection = Connection::Mock(mock);
+        // tests send more than an ordinary account may
+        Self::new(connection.clone(), connection, Some(Verification::Verified))
+    }
+
+    /// The delay between two joins Twitch accepts.
+    pub fn join_interval(&self) -> std::time::Duration {
+        self.verification
+            .unwrap_or(Verification::Normal)
+            .joins()
+            .interval()
+    }
+
+    pub async fn connect(&self) {
+        dispatch!(&self.read, client => client.connect().await)
+    }
+
+    pub fn join(&self, channel_login: String) {
+        dispatch!(&self.read, client => client.join(channel_login))
+    }
+
+    pub fn part(&self, channel_login: String) {
+        dispatch!(&self.read, client => client.part(channel_login))
+    }
+
+    /// Queue a chat message and wait until it was sent.
+    async fn send(&self, priority: Priority, outgoing: Outgoing) -> Result<()> {
+        self.queue_message(priority, outgoing).await
+    }
+
+    /// Queue a chat message, the returned future resolves once it was sent.
+    fn queue_message(
+        &self,
+        priority: Priority,
+        outgoing: Outgoing,
+    ) -> impl std::future::Future<Output = Result<()>> {
+        let (sent, result) = oneshot::channel();
+        self.queue.push(priority, (outgoing, sent));
+
+        async move {
+            result
+                .await
+                .map_err(|_| eyre!("The send queue was shut down"))?
+        }
+    }
+
+    pub async fn say(&self, channel_login: String, message: String) -> Result<()> {
+        self.say_with_priority(Priority::Ack, channel_login, message, None)
+            .await
+    }
+
+    /// Reply to a command. Returns once the reply is queued, so the handler doesn't wait for the
+    /// rate limit and hold up the chat of every channel. Replies that fail to send are logged.
+    pub async fn say_in_response(
+        &self,
+        channel_login: String,
+        message: String,
+        reply_to: Option<String>,
+    ) -> Result<()> {
+        let outgoing = Outgoing {
+            channel_login,
+            message,
+            reply_to,
+            raw: false,
+        };
+        let sent = self.queue_message(Priority::Ack, outgoing);
+
+        let unsent_replies = self.unsent_replies.clone();
+        unsent_replies.fetch_add(1, Ordering::SeqCst);
+        tokio::spawn(async move {
+            if let Err(err) = sent.await.wrap_err("Failed to send reply") {
+                error!("{:?}", err);
+            }
+            unsent_replies.fetch_sub(1, Ordering::SeqCst);
+        });
+
+        Ok(())
+    }
+
+    /// Wait until every reply queued so far was sent or failed to.
+    #[cfg(test)]
+    pub async fn replies_sent(&self) {
+        while self.unsent_replies.load(Ordering::SeqCst) > 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+    }
+
+    pub async fn say_with_priority(
+        &self,
+        priority: Priority,
+        channel_login: String,
+        message: String,
+        reply_to: Option<String>,
+    ) -> Result<()> {
+        let outgoing = Outgoing {
+            channel_login,
+            message,
+            reply_to,
+            raw: false,
+        };
+        self.send(priority, outgoing).await
+    }
+
+    pub async fn privmsg(&self, channel_login: String, message: String) -> Result<()> {
+        let outgoing = Outgoing {
+            channel_login,
+            message,
+            reply_to: None,
+            raw: true,
+        };
+        self.send(Priority::Ack, outgoing).await
+    }
+}
@@ -0,0 +1,46 @@
+//! Waiting for points in wall-clock time.
+//!
+//! Tokio sleeps on a monotonic clock, which may stop while the machine is suspended and doesn't
+//! follow changes to the system time, so a long sleep can end well before or after the deadline
+//! it was computed for. Waits are cut into short sleeps and checked against the wall clock after
+//! each one instead.
+
+use std::time::Duration;
+
+use time::OffsetDateTime;
+use tokio::time::sleep;
+
+/// Longest single sleep while waiting for a deadline.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// Wait until the wall clock reaches `deadline`, returning right away if it already passed.
+pub async fn sleep_until(deadline: OffsetDateTime) {
+    loop {
+        let remaining = deadline - OffsetDateTime::now_utc();
+        if !remaining.is_positive() {
+            return;
+        }
+
+        sleep(
+            Duration::try_from(remaining).map_or(MAX_SLEEP, |remaining| remaining.min(MAX_SLEEP)),
+        )
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn waits_for_the_wall_clock() {
+        let start = OffsetDateTime::now_utc();
+
+        sleep_until(start - time::Duration::days(1)).await;
+        assert!(OffsetDateTime::now_utc() - start < time::Duration::seconds(1));
+
+        let deadline = start + time::Duration::milliseconds(50);
+        sleep_until(deadline).await;
+        assert!(OffsetDateTime::now_utc() >= deadline);
+    }
+}
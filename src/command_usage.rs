@@ -0,0 +1,86 @@
+//! How often each command was used in each channel since the bot started, for `~admin usage` and
+//! the `stats` endpoint of the admin API, so operators see which features matter and notice when
+//! a channel suddenly uses a lot more of them.
+
+use std::collections::BTreeMap;
+
+/// What unknown commands are counted as.
+pub const UNKNOWN: &str = "unknown";
+
+#[derive(Debug, Default)]
+pub struct CommandUsage {
+    /// Uses of each command, keyed by channel.
+    counts: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+impl CommandUsage {
+    /// Count a use of `command` in `channel`. Unknown commands are counted as [`UNKNOWN`].
+    pub fn record(&mut self, channel: &str, command: &str) {
+        *self
+            .counts
+            .entry(channel.to_string())
+            .or_default()
+            .entry(command.to_string())
+            .or_default() += 1;
+    }
+
+    /// Uses of each command in `channel`, or in every channel, most used first.
+    pub fn counts(&self, channel: Option<&str>) -> Vec<(&str, u64)> {
+        let mut counts = BTreeMap::<&str, u64>::new();
+        for (_, commands) in self
+            .counts
+            .iter()
+            .filter(|(name, _)| channel.map_or(true, |channel| channel == name.as_str()))
+        {
+            for (command, count) in commands {
+                *counts.entry(command.as_str()).or_default() += count;
+            }
+        }
+
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort_by(|a, b| b.1.cmp(&a.1));
+        counts
+    }
+
+    /// Uses of any command in each channel, busiest first.
+    pub fn channels(&self) -> Vec<(&str, u64)> {
+        let mut channels = self
+            .counts
+            .iter()
+            .map(|(channel, commands)| (channel.as_str(), commands.values().sum()))
+            .collect::<Vec<_>>();
+        channels.sort_by(|a, b| b.1.cmp(&a.1));
+        channels
+    }
+
+    /// Uses of each command, keyed by channel.
+    pub fn by_channel(&self) -> &BTreeMap<String, BTreeMap<String, u64>> {
+        &self.counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_per_channel() {
+        let mut usage = CommandUsage::default();
+        usage.record("forsen", "tell");
+        usage.record("forsen", "tell");
+        usage.record("forsen", UNKNOWN);
+        usage.record("xqc", "cancel");
+        usage.record("xqc", "tell");
+
+        assert_eq!(
+            vec![("tell", 2), (UNKNOWN, 1)],
+            usage.counts(Some("forsen"))
+        );
+        assert_eq!(
+            vec![("tell", 3), ("cancel", 1), (UNKNOWN, 1)],
+            usage.counts(None)
+        );
+        assert!(usage.counts(Some("other")).is_empty());
+        assert_eq!(vec![("forsen", 3), ("xqc", 2)], usage.channels());
+    }
+}
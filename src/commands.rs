@@ -0,0 +1,329 @@
+//! The command framework: a registry of chat commands with their metadata, and the middleware
+//! every invocation passes before its handler runs.
+
+use std::{collections::HashMap, future::Future, pin::Pin, str::SplitWhitespace};
+
+use eyre::{eyre, Context as _, Result};
+use time::{Duration, OffsetDateTime};
+use tracing::{info_span, Instrument};
+use twitch_irc::message::PrivmsgMessage;
+
+use crate::{humanize, limits::Tier, permissions::Role, Client, State, UserError, PREFIX};
+
+pub(crate) type CommandFuture<'a> = Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+
+/// Runs a command. The words after the command name are left in [`Context::parts`].
+pub(crate) type Handler = for<'a> fn(&'a mut Context<'a>) -> CommandFuture<'a>;
+
+/// Runs before the handler of a command. An error stops the invocation and is shown to the user
+/// if it is a [`UserError`].
+pub(crate) type Middleware = fn(&Command, &mut Context<'_>) -> Result<()>;
+
+/// Run in order before every command.
+pub(crate) const MIDDLEWARE: &[Middleware] = &[check_role, check_paused, check_cooldown];
+
+/// Everything a handler needs to know about an invocation.
+pub(crate) struct Context<'a> {
+    pub state: &'a mut State,
+    pub client: &'a Client,
+    pub privmsg: &'a PrivmsgMessage,
+    pub parts: SplitWhitespace<'a>,
+}
+
+impl Context<'_> {
+    /// Reply to the message that invoked the command.
+    pub async fn reply(&self, text: String) -> Result<()> {
+        self.client
+            .say_in_response(
+                self.privmsg.channel_login.clone(),
+                text,
+                Some(self.privmsg.channel_id.clone()),
+            )
+            .await
+            .wrap_err("Failed to send reply")
+    }
+
+    /// The role of the sender in the channel of the invocation.
+    pub fn role(&self) -> Role {
+        Role::of(self.privmsg, &self.state.config)
+    }
+
+    /// How far the cooldowns apply to the sender in the channel of the invocation.
+    pub fn tier(&self) -> Tier {
+        self.state.limit_tier(self.privmsg)
+    }
+
+    /// How far the caps apply to the sender.
+    pub fn cap_tier(&self) -> Tier {
+        self.state.cap_tier(self.privmsg)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct Command {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    /// The least role allowed to run the command.
+    pub role: Role,
+    /// How long a user has to wait before running the command again.
+    pub cooldown: Duration,
+    /// Arguments of the command, shown by `~help <command>`.
+    pub usage: &'static str,
+    /// Whether the command runs while the bot is paused with `~admin pause`.
+    pub while_paused: bool,
+    pub handler: Handler,
+}
+
+impl Command {
+    pub fn new(name: &'static str, usage: &'static str, handler: Handler) -> Self {
+        Self {
+            name,
+            aliases: &[],
+            role: Role::Everyone,
+            cooldown: Duration::ZERO,
+            usage,
+            while_paused: false,
+            handler,
+        }
+    }
+
+    pub fn with_aliases(mut self, aliases: &'static [&'static str]) -> Self {
+        self.aliases = aliases;
+        self
+    }
+
+    pub fn with_role(mut self, role: Role) -> Self {
+        self.role = role;
+        self
+    }
+
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    pub fn available_while_paused(mut self) -> Self {
+        self.while_paused = true;
+        self
+    }
+
+    /// How to invoke the command, e.g. `~afk [reason]`.
+    pub fn help(&self) -> String {
+        let mut help = format!("{}{}", PREFIX, self.name);
+        if !self.usage.is_empty() {
+            help = format!("{} {}", help, self.usage);
+        }
+        if !self.aliases.is_empty() {
+            help = format!("{} (also {})", help, self.aliases.join(", "));
+        }
+
+        help
+    }
+}
+
+/// Every command the bot understands.
+pub(crate) struct Registry {
+    commands: Vec<Command>,
+}
+
+impl Registry {
+    pub fn new(commands: Vec<Command>) -> Self {
+        Self { commands }
+    }
+
+    /// Look up a command by its name or one of its aliases.
+    pub fn find(&self, name: &str) -> Option<&Command> {
+        self.commands
+            .iter()
+            .find(|command| command.name == name || command.aliases.contains(&name))
+    }
+
+    /// The commands `ctx` is allowed to run, in registration order.
+    pub fn available(&self, ctx: &Context<'_>) -> Vec<&Command> {
+        let role = ctx.role();
+
+        self.commands
+            .iter()
+            .filter(|command| role >= command.role)
+            .collect()
+    }
+}
+
+/// Run `command` after the [`MIDDLEWARE`], in a `command` span so everything it logs can be told
+/// apart by command.
+pub(crate) async fn dispatch(command: Command, mut ctx: Context<'_>) -> Result<()> {
+    let span = info_span!("command", command = command.name);
+
+    async move {
+        for middleware in MIDDLEWARE {
+            middleware(&command, &mut ctx)?;
+        }
+
+        (command.handler)(&mut ctx)
+            .await
+            .wrap_err_with(|| format!("Failed to handle {} command", command.name))
+    }
+    .instrument(span)
+    .await
+}
+
+/// When each user last ran each command, for the commands with a cooldown.
+#[derive(Debug, Default)]
+pub(crate) struct Cooldowns {
+    last_use: HashMap<(String, &'static str), OffsetDateTime>,
+}
+
+impl Cooldowns {
+    /// Record that `login` runs `command` at `now`. Returns how long they have to wait instead
+    /// if the command is still on `cooldown`, which may be shorter than the one of `command`.
+    pub fn try_use(
+        &mut self,
+        login: &str,
+        command: &Command,
+        cooldown: Duration,
+        now: OffsetDateTime,
+    ) -> Result<(), Duration> {
+        if command.cooldown <= Duration::ZERO {
+            return Ok(());
+        }
+
+        let key = (login.to_string(), command.name);
+        if let Some(last) = self.last_use.get(&key) {
+            let remaining = *last + cooldown - now;
+            if remaining.is_positive() {
+                return Err(remaining);
+            }
+        }
+
+        self.last_use
+            .retain(|(_, name), last| *name != command.name || *last + command.cooldown > now);
+        self.last_use.insert(key, now);
+
+        Ok(())
+    }
+}
+
+fn check_role(command: &Command, ctx: &mut Context<'_>) -> Result<()> {
+    if ctx.role() >= command.role {
+        return Ok(());
+    }
+
+    Err(eyre!(UserError(format!(
+        "{}{} is only available to {}",
+        PREFIX,
+        command.name,
+        command.role.describe()
+    ))))
+}
+
+fn check_paused(command: &Command, ctx: &mut Context<'_>) -> Result<()> {
+    if command.while_paused || !ctx.state.pause.is_paused() {
+        return Ok(());
+    }
+
+    Err(eyre!(UserError(
+        "I'm paused for maintenance, try again later".to_string()
+    )))
+}
+
+fn check_cooldown(command: &Command, ctx: &mut Context<'_>) -> Result<()> {
+    let login = &ctx.privmsg.sender.login;
+    let cooldown = ctx.tier().cooldown(command.cooldown);
+
+    match ctx
+        .state
+        .cooldowns
+        .try_use(login, command, cooldown, OffsetDateTime::now_utc())
+    {
+        Ok(()) => Ok(()),
+        Err(remaining) => Err(eyre!(UserError(format!(
+            "{}{} is on cooldown, try again {}",
+            PREFIX,
+            command.name,
+            humanize::until(remaining)
+        )))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noop<'a>(_: &'a mut Context<'a>) -> CommandFuture<'a> {
+        Box::pin(async { Ok(()) })
+    }
+
+    #[test]
+    fn find_by_name_or_alias() {
+        let registry = Registry::new(vec![
+            Command::new("timezone", "<offset>", noop).with_aliases(&["tz"]),
+            Command::new("help", "", noop),
+        ]);
+
+        assert_eq!("timezone", registry.find("tz").unwrap().name);
+        assert_eq!("help", registry.find("help").unwrap().name);
+        assert!(registry.find("unknown").is_none());
+    }
+
+    #[test]
+    fn help_shows_usage_and_aliases() {
+        let command = Command::new("timezone", "<offset>", noop).with_aliases(&["tz"]);
+
+        assert_eq!("~timezone <offset> (also tz)", command.help());
+        assert_eq!("~help", Command::new("help", "", noop).help());
+    }
+
+    #[test]
+    fn cooldown_per_user() {
+        let command = Command::new("stats", "", noop).with_cooldown(Duration::seconds(10));
+        let now = OffsetDateTime::UNIX_EPOCH;
+        let cooldown = command.cooldown;
+        let mut cooldowns = Cooldowns::default();
+
+        assert_eq!(Ok(()), cooldowns.try_use("alice", &command, cooldown, now));
+        assert_eq!(
+            Err(Duration::seconds(5)),
+            cooldowns.try_use("alice", &command, cooldown, now + Duration::seconds(5))
+        );
+        assert_eq!(
+            Ok(()),
+            cooldowns.try_use("bob", &command, cooldown, now + Duration::seconds(5))
+        );
+        assert_eq!(
+            Ok(()),
+            cooldowns.try_use("alice", &command, cooldown, now + Duration::seconds(10))
+        );
+    }
+
+    #[test]
+    fn shorter_cooldown_for_raised_tiers() {
+        let command = Command::new("stats", "", noop).with_cooldown(Duration::seconds(10));
+        let now = OffsetDateTime::UNIX_EPOCH;
+        let mut cooldowns = Cooldowns::default();
+        let cooldown = Tier::Raised.cooldown(command.cooldown);
+
+        assert_eq!(Ok(()), cooldowns.try_use("mod", &command, cooldown, now));
+        assert_eq!(
+            Ok(()),
+            cooldowns.try_use("mod", &command, cooldown, now + Duration::seconds(2))
+        );
+        assert_eq!(
+            Ok(()),
+            cooldowns.try_use(
+                "broadcaster",
+                &command,
+                Tier::Unlimited.cooldown(command.cooldown),
+                now
+            )
+        );
+        assert_eq!(
+            Ok(()),
+            cooldowns.try_use(
+                "broadcaster",
+                &command,
+                Tier::Unlimited.cooldown(command.cooldown),
+                now
+            )
+        );
+    }
+}
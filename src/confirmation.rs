@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+use time::{Duration, OffsetDateTime};
+
+/// A destructive command that has to be confirmed before it runs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Remove every reminder the user wrote.
+    CancelAll,
+    /// Remove everything the bot knows about the user.
+    ForgetMe,
+}
+
+/// Destructive commands waiting for the user to confirm them, keyed by login.
+///
+/// Each user has at most one pending action. Requesting another one replaces it.
+#[derive(Debug, Clone)]
+pub struct Confirmations {
+    ttl: Duration,
+    pending: HashMap<String, (Action, OffsetDateTime)>,
+}
+
+impl Confirmations {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn request(&mut self, login: &str, action: Action) {
+        let now = OffsetDateTime::now_utc();
+        let ttl = self.ttl;
+        self.pending.retain(|_, (_, at)| now - *at < ttl);
+
+        self.pending.insert(login.to_string(), (action, now));
+    }
+
+    /// Take the pending action of `login` unless it expired.
+    pub fn confirm(&mut self, login: &str) -> Option<Action> {
+        let (action, at) = self.pending.remove(login)?;
+
+        (OffsetDateTime::now_utc() - at < self.ttl).then(|| action)
+    }
+
+    /// Drop the pending action of `login`. Returns whether there was one.
+    pub fn abort(&mut self, login: &str) -> bool {
+        self.confirm(login).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confirm_once() {
+        let mut confirmations = Confirmations::new(Duration::minutes(1));
+        confirmations.request("alice", Action::CancelAll);
+        confirmations.request("alice", Action::ForgetMe);
+
+        assert_eq!(None, confirmations.confirm("bob"));
+        assert_eq!(Some(Action::ForgetMe), confirmations.confirm("alice"));
+        assert_eq!(None, confirmations.confirm("alice"));
+    }
+
+    #[test]
+    fn expired_actions_are_not_confirmed() {
+        let mut confirmations = Confirmations::new(Duration::ZERO);
+        confirmations.request("alice", Action::CancelAll);
+
+        assert!(!confirmations.abort("alice"));
+        assert_eq!(None, confirmations.confirm("alice"));
+    }
+}
@@ -0,0 +1,133 @@
+//! The chat connections of the bot. Chat can be read as someone else than the account sending,
+//! e.g. anonymously, so a verified bot account only needs to send.
+//!
+//! The sending account is `TWITCH_LOGIN` with `TWITCH_TOKEN`. A separate reading account is
+//! `TWITCH_READ_LOGIN` with `TWITCH_READ_TOKEN`.
+
+use std::env;
+
+use eyre::{Context, Result};
+use serde::Deserialize;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tracing::{trace, warn};
+use twitch_irc::{login::StaticLoginCredentials, message::ServerMessage, ClientConfig};
+
+use crate::{
+    client::{Client, Connection},
+    config::IrcConfig,
+    proxy::Proxy,
+    token,
+};
+
+/// Scopes the reading account needs and what for.
+const READ_SCOPES: &[(&str, &str)] = &[("chat:read", "reading chat")];
+
+/// Who the bot reads chat as.
+#[derive(Debug, Copy, Clone, Deserialize, PartialEq, Eq)]
+pub enum ReadIdentity {
+    /// The sending account, over the same connections.
+    Sender,
+    /// Nobody, Twitch allows reading chat without logging in.
+    Anonymous,
+    /// `TWITCH_READ_LOGIN` with `TWITCH_READ_TOKEN`.
+    Login,
+}
+
+impl Default for ReadIdentity {
+    fn default() -> Self {
+        ReadIdentity::Sender
+    }
+}
+
+/// The credentials to read chat with, `None` to read with the sending account. A separate account
+/// is checked like the sending one.
+pub async fn read_credentials(
+    identity: ReadIdentity,
+    proxy: Option<&Proxy>,
+) -> Result<Option<StaticLoginCredentials>> {
+    match identity {
+        ReadIdentity::Sender => Ok(None),
+        ReadIdentity::Anonymous => Ok(Some(StaticLoginCredentials::anonymous())),
+        ReadIdentity::Login => {
+            let login =
+                env::var("TWITCH_READ_LOGIN").wrap_err("Failed to get TWITCH_READ_LOGIN")?;
+            let token =
+                env::var("TWITCH_READ_TOKEN").wrap_err("Failed to get TWITCH_READ_TOKEN")?;
+            token::verify(&token, &login, READ_SCOPES, proxy)
+                .await
+                .wrap_err("TWITCH_READ_TOKEN can't be used")?;
+
+            Ok(Some(StaticLoginCredentials::new(login, Some(token))))
+        }
+    }
+}
+
+/// Open the connections to chat, reading as `read` if set and sending as `send`, along the
+/// [route](crate::chat_route) set at startup if `routed`. Messages of the
+/// sending connection only end up in the returned receiver if they are notices, so a failed login
+/// is still noticed, or whispers, since those go to the sending account.
+///
+/// Also returns the login chat is read as, which Twitch reports the bot's joins and parts under.
+/// Anonymous logins look like `justinfan12345`.
+pub fn open(
+    irc: &IrcConfig,
+    routed: bool,
+    send: StaticLoginCredentials,
+    read: Option<StaticLoginCredentials>,
+) -> (UnboundedReceiver<ServerMessage>, Client, String) {
+    let config = |credentials: StaticLoginCredentials| {
+        let mut config = ClientConfig::new_simple(credentials);
+        irc.apply(&mut config);
+        config
+    };
+
+    let read_login = read
+        .as_ref()
+        .unwrap_or(&send)
+        .credentials
+        .login
+        .to_lowercase();
+    let (sent, send_connection) = Connection::open(irc.transport(), routed, config(send));
+    let read = match read {
+        Some(read) => read,
+        None => {
+            let client = Client::new(send_connection.clone(), send_connection, irc.verification);
+            return (sent, client, read_login);
+        }
+    };
+    let (read, read_connection) = Connection::open(irc.transport(), routed, config(read));
+
+    let (incoming_sender, incoming) = mpsc::unbounded_channel();
+    tokio::spawn(forward(read, incoming_sender.clone(), |_| true));
+    tokio::spawn(forward(sent, incoming_sender, |message| {
+        matches!(
+            message,
+            ServerMessage::Notice(_) | ServerMessage::Whisper(_)
+        )
+    }));
+
+    (
+        incoming,
+        Client::new(read_connection, send_connection, irc.verification),
+        read_login,
+    )
+}
+
+/// Pass the messages `keep` accepts from `from` on to `to`, until either is closed.
+async fn forward(
+    mut from: UnboundedReceiver<ServerMessage>,
+    to: UnboundedSender<ServerMessage>,
+    keep: fn(&ServerMessage) -> bool,
+) {
+    while let Some(message) = from.recv().await {
+        if !keep(&message) {
+            trace!("Dropping {:?}", message);
+            continue;
+        }
+
+        if to.send(message).is_err() {
+            warn!("Stopped forwarding chat messages, nobody reads them anymore");
+            return;
+        }
+    }
+}
@@ -0,0 +1,352 @@
+use time::{Duration, OffsetDateTime, Time, Weekday};
+
+use crate::time_zone::Zone;
+
+const MINUTES_PER_DAY: i64 = 24 * 60;
+
+/// Parse a time of day like `20:00` or `8:05` into hour and minute.
+pub fn parse_time_of_day(s: &str) -> Option<(u8, u8)> {
+    let (hour, minute) = s.split_once(':')?;
+    if minute.len() != 2 {
+        return None;
+    }
+
+    let hour = hour.parse::<u8>().ok().filter(|hour| *hour < 24)?;
+    let minute = minute.parse::<u8>().ok().filter(|minute| *minute < 60)?;
+
+    Some((hour, minute))
+}
+
+/// Parse a time of day like `parse_time_of_day` does, also accepting `noon`, `midnight` and
+/// 12-hour times like `9am` or `9:30pm`.
+pub fn parse_clock_time(s: &str) -> Option<(u8, u8)> {
+    match s.to_lowercase().as_str() {
+        "noon" => Some((12, 0)),
+        "midnight" => Some((0, 0)),
+        s => parse_time_of_day(s).or_else(|| parse_12_hour_time(s)),
+    }
+}
+
+fn parse_12_hour_time(s: &str) -> Option<(u8, u8)> {
+    let (s, afternoon) = match (s.strip_suffix("am"), s.strip_suffix("pm")) {
+        (Some(s), _) => (s, false),
+        (_, Some(s)) => (s, true),
+        _ => return None,
+    };
+    let (hour, minute) = match s.split_once(':') {
+        Some((hour, minute)) if minute.len() == 2 => (hour, minute.parse::<u8>().ok()?),
+        Some(_) => return None,
+        None => (s, 0),
+    };
+    let hour = hour
+        .parse::<u8>()
+        .ok()
+        .filter(|hour| (1..=12).contains(hour))?;
+    if minute >= 60 {
+        return None;
+    }
+
+    Some((hour % 12 + if afternoon { 12 } else { 0 }, minute))
+}
+
+fn parse_weekday(s: &str) -> Option<Weekday> {
+    match s.to_lowercase().as_str() {
+        "monday" | "mon" => Some(Weekday::Monday),
+        "tuesday" | "tue" => Some(Weekday::Tuesday),
+        "wednesday" | "wed" => Some(Weekday::Wednesday),
+        "thursday" | "thu" => Some(Weekday::Thursday),
+        "friday" | "fri" => Some(Weekday::Friday),
+        "saturday" | "sat" => Some(Weekday::Saturday),
+        "sunday" | "sun" => Some(Weekday::Sunday),
+        _ => None,
+    }
+}
+
+/// Which week a [`WeekdayTime`] refers to. Weeks start on Monday.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Week {
+    /// The first occurrence that hasn't passed yet, e.g. `monday 9am`.
+    Upcoming,
+    /// The current week, e.g. `this sunday 20:00`.
+    This,
+    /// The week after the current one, e.g. `next monday 9am`.
+    Next,
+}
+
+/// A weekday with a time of day like `next monday 9am` or `sunday 20:00`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WeekdayTime {
+    pub week: Week,
+    pub weekday: Weekday,
+    pub time: (u8, u8),
+}
+
+impl WeekdayTime {
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut words = s.split_whitespace().peekable();
+
+        let week = match words.peek().map(|word| word.to_lowercase()).as_deref() {
+            Some("this") => Week::This,
+            Some("next") => Week::Next,
+            _ => Week::Upcoming,
+        };
+        if week != Week::Upcoming {
+            words.next();
+        }
+        let weekday = parse_weekday(words.next()?)?;
+        // allow `monday at 9am` and `monday 9 am`
+        let time = words
+            .filter(|word| !word.eq_ignore_ascii_case("at"))
+            .collect::<String>();
+        let time = parse_clock_time(&time)?;
+
+        Some(Self {
+            week,
+            weekday,
+            time,
+        })
+    }
+
+    /// Get the time this refers to as seen from `now` in `zone`, or `None` if it has already
+    /// passed.
+    pub fn resolve(&self, now: OffsetDateTime, zone: &Zone) -> Option<OffsetDateTime> {
+        let local = zone.wall_clock(now);
+        let today = i64::from(local.weekday().number_days_from_monday());
+        let target = i64::from(self.weekday.number_days_from_monday());
+        let (hour, minute) = self.time;
+
+        let on = |days: i64| {
+            zone.instant(
+                local.replace_time(Time::MIDNIGHT)
+                    + Duration::days(days)
+                    + Duration::hours(hour.into())
+                    + Duration::minutes(minute.into()),
+            )
+        };
+
+        let at = match self.week {
+            Week::Upcoming => {
+                let days = (target - today).rem_euclid(7);
+                let at = on(days);
+                if at <= now {
+                    on(days + 7)
+                } else {
+                    at
+                }
+            }
+            Week::This => on(target - today),
+            Week::Next => on(target - today + 7),
+        };
+
+        Some(at).filter(|at| *at > now)
+    }
+}
+
+/// Get the first time after `now` at which the clock reads `hour:minute` in UTC.
+pub fn next_occurrence(now: OffsetDateTime, (hour, minute): (u8, u8)) -> OffsetDateTime {
+    let current = now
+        .unix_timestamp()
+        .div_euclid(60)
+        .rem_euclid(MINUTES_PER_DAY);
+    let target = i64::from(hour) * 60 + i64::from(minute);
+
+    let mut remaining = (target - current).rem_euclid(MINUTES_PER_DAY);
+    if remaining == 0 {
+        remaining = MINUTES_PER_DAY;
+    }
+
+    now + Duration::minutes(remaining) - Duration::seconds(now.second().into())
+}
+
+/// Get the first time after `now` at which the clocks in `zone` read `hour:minute`, honoring
+/// changes to and from daylight saving time in between.
+pub fn next_local_occurrence(now: OffsetDateTime, time: (u8, u8), zone: &Zone) -> OffsetDateTime {
+    let wall_clock = next_occurrence(zone.wall_clock(now), time);
+
+    let at = zone.instant(wall_clock);
+    if at <= now {
+        // the clocks were set back in between, so the time is still to come tomorrow
+        zone.instant(wall_clock + Duration::days(1))
+    } else {
+        at
+    }
+}
+
+/// Parse a UTC offset like `UTC`, `+2`, `-05:30` or `UTC+1` into minutes.
+pub fn parse_utc_offset(s: &str) -> Option<i64> {
+    let s = s.trim_start_matches("UTC").trim_start_matches("utc");
+    if s.is_empty() {
+        return Some(0);
+    }
+
+    let (sign, s) = if let Some(rest) = s.strip_prefix('+') {
+        (1, rest)
+    } else {
+        (-1, s.strip_prefix('-')?)
+    };
+    let (hours, minutes) = match s.split_once(':') {
+        Some((hours, minutes)) => (hours, minutes.parse::<i64>().ok()?),
+        None => (s, 0),
+    };
+    let hours = hours.parse::<i64>().ok()?;
+
+    if hours > 14 || minutes >= 60 {
+        return None;
+    }
+
+    Some(sign * (hours * 60 + minutes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: i64, minute: i64) -> OffsetDateTime {
+        OffsetDateTime::UNIX_EPOCH + Duration::hours(hour) + Duration::minutes(minute)
+    }
+
+    #[test]
+    fn parse_valid_times() {
+        assert_eq!(Some((20, 0)), parse_time_of_day("20:00"));
+        assert_eq!(Some((8, 5)), parse_time_of_day("8:05"));
+    }
+
+    #[test]
+    fn parse_invalid_times() {
+        assert_eq!(None, parse_time_of_day("24:00"));
+        assert_eq!(None, parse_time_of_day("12:60"));
+        assert_eq!(None, parse_time_of_day("12:5"));
+        assert_eq!(None, parse_time_of_day("noon"));
+    }
+
+    #[test]
+    fn parse_clock_times() {
+        assert_eq!(Some((12, 0)), parse_clock_time("noon"));
+        assert_eq!(Some((0, 0)), parse_clock_time("Midnight"));
+        assert_eq!(Some((18, 30)), parse_clock_time("18:30"));
+        assert_eq!(None, parse_clock_time("teatime"));
+    }
+
+    #[test]
+    fn parse_12_hour_clock_times() {
+        assert_eq!(Some((9, 0)), parse_clock_time("9am"));
+        assert_eq!(Some((21, 30)), parse_clock_time("9:30PM"));
+        assert_eq!(Some((0, 0)), parse_clock_time("12am"));
+        assert_eq!(Some((12, 15)), parse_clock_time("12:15pm"));
+        assert_eq!(None, parse_clock_time("13pm"));
+        assert_eq!(None, parse_clock_time("0am"));
+    }
+
+    #[test]
+    fn parse_weekday_times() {
+        assert_eq!(
+            Some(WeekdayTime {
+                week: Week::Next,
+                weekday: Weekday::Monday,
+                time: (9, 0)
+            }),
+            WeekdayTime::parse("next Monday 9am")
+        );
+        assert_eq!(
+            Some(WeekdayTime {
+                week: Week::Upcoming,
+                weekday: Weekday::Sunday,
+                time: (20, 0)
+            }),
+            WeekdayTime::parse("sunday at 20:00")
+        );
+        assert_eq!(
+            Some((9, 0)),
+            WeekdayTime::parse("this fri 9 am").map(|parsed| parsed.time)
+        );
+        assert_eq!(None, WeekdayTime::parse("next monday"));
+        assert_eq!(None, WeekdayTime::parse("someday 9am"));
+    }
+
+    #[test]
+    fn resolve_weekday_times_around_week_boundary() {
+        // the epoch was a thursday, so this is sunday 18:00
+        let sunday = at(3 * 24 + 18, 0);
+        let resolve = |s: &str| {
+            WeekdayTime::parse(s)
+                .unwrap()
+                .resolve(sunday, &Zone::default())
+        };
+
+        assert_eq!(Some(at(4 * 24 + 9, 0)), resolve("monday 9am"));
+        assert_eq!(Some(at(4 * 24 + 9, 0)), resolve("next monday 9am"));
+        assert_eq!(None, resolve("this monday 9am"));
+        assert_eq!(Some(at(3 * 24 + 20, 0)), resolve("this sunday 20:00"));
+        assert_eq!(Some(at(3 * 24 + 20, 0)), resolve("sunday 20:00"));
+        assert_eq!(Some(at(10 * 24 + 17, 0)), resolve("sunday 17:00"));
+        assert_eq!(Some(at(10 * 24 + 20, 0)), resolve("next sunday 20:00"));
+    }
+
+    #[test]
+    fn resolve_weekday_times_with_offset() {
+        // sunday 23:30 in UTC is already monday 00:30 at UTC+1
+        let now = at(3 * 24 + 23, 30);
+        let resolve = |s: &str| {
+            WeekdayTime::parse(s)
+                .unwrap()
+                .resolve(now, &Zone::Fixed(60))
+        };
+
+        assert_eq!(Some(at(4 * 24 + 8, 0)), resolve("this monday 9am"));
+        assert_eq!(Some(at(11 * 24 + 8, 0)), resolve("next monday 9am"));
+        assert_eq!(Some(at(10 * 24 + 19, 0)), resolve("this sunday 20:00"));
+    }
+
+    #[test]
+    fn parse_offsets() {
+        assert_eq!(Some(0), parse_utc_offset("UTC"));
+        assert_eq!(Some(120), parse_utc_offset("+2"));
+        assert_eq!(Some(60), parse_utc_offset("UTC+1"));
+        assert_eq!(Some(-330), parse_utc_offset("-05:30"));
+        assert_eq!(None, parse_utc_offset("+15"));
+        assert_eq!(None, parse_utc_offset("Europe/Berlin"));
+    }
+
+    #[test]
+    fn next_occurrence_wraps_to_tomorrow() {
+        assert_eq!(at(20, 0), next_occurrence(at(18, 30), (20, 0)));
+        assert_eq!(at(24 + 8, 0), next_occurrence(at(18, 30), (8, 0)));
+        assert_eq!(at(24 + 18, 30), next_occurrence(at(18, 30), (18, 30)));
+    }
+
+    #[test]
+    fn next_local_occurrence_uses_offset() {
+        // 19:30 at UTC+1, so noon is tomorrow
+        assert_eq!(
+            at(24 + 11, 0),
+            next_local_occurrence(at(18, 30), (12, 0), &Zone::Fixed(60))
+        );
+        // 13:30 at UTC-5, so 18:30 is still today
+        assert_eq!(
+            at(23, 30),
+            next_local_occurrence(at(18, 30), (18, 30), &Zone::Fixed(-300))
+        );
+    }
+
+    #[test]
+    fn next_local_occurrence_across_daylight_saving_time() {
+        let berlin = Zone::named("Europe/Berlin").unwrap();
+        let at = |timestamp| OffsetDateTime::from_unix_timestamp(timestamp).unwrap();
+
+        // from 2021-03-27 12:00 UTC, 09:00 the next day is already summer time
+        assert_eq!(
+            at(1_616_914_800),
+            next_local_occurrence(at(1_616_846_400), (9, 0), &berlin)
+        );
+        // from 2021-10-30 12:00 UTC, 09:00 the next day is winter time again
+        assert_eq!(
+            at(1_635_667_200),
+            next_local_occurrence(at(1_635_595_200), (9, 0), &berlin)
+        );
+        // a fixed offset doesn't know about the change
+        assert_eq!(
+            at(1_616_914_800 - 3_600),
+            next_local_occurrence(at(1_616_846_400), (9, 0), &Zone::Fixed(60))
+        );
+    }
+}
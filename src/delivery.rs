@@ -0,0 +1,217 @@
+//! Where reminders waiting for their recipient to type may be delivered.
+//!
+//! A reminder is either scoped to the channel it was created in (`here:true`) or delivered in
+//! whichever joined channel its recipient types next (`anywhere:true`). Reminders with
+//! `onjoin:true` are also delivered when their recipient joins such a channel.
+//!
+//! Timed reminders for the same recipient that are due within a few seconds of each other are
+//! delivered together by the task of the earliest one. Those that became due while the bot was
+//! offline are delivered together when it starts, one digest per recipient and channel.
+
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    channel_settings::ChannelSettings,
+    config::Config,
+    message::{Activation, Kind, Message},
+};
+
+/// Whether reminders created in `channel` are scoped to it unless their author says otherwise.
+pub fn scoped_by_default(config: &Config, settings: &ChannelSettings, channel: &str) -> bool {
+    settings
+        .scoped
+        .unwrap_or_else(|| !config.deliver_anywhere || config.scoped_channels.contains(channel))
+}
+
+/// Whether `message` should be delivered when its recipient types in `channel`.
+pub fn deliverable_in(message: &Message, channel: &str) -> bool {
+    message.activation() == &Activation::OnNextMessage
+        && (!message.here() || message.channel() == channel)
+}
+
+/// Whether `message` should be delivered when its recipient joins `channel`, before they type.
+pub fn deliverable_on_join(message: &Message, channel: &str) -> bool {
+    message.on_join() && deliverable_in(message, channel)
+}
+
+/// The timed reminders among `pending` that are delivered together with `message`: those for the
+/// same recipient in the same channel that follow it in a chain of reminders each due at most
+/// `window` after the one before. `None` if `message` is delivered together with an earlier
+/// reminder instead.
+pub fn batch<'a>(
+    message: &Message,
+    pending: &[&'a Message],
+    window: Duration,
+) -> Option<Vec<&'a Message>> {
+    let key = match batch_key(message) {
+        Some(key) => key,
+        None => return Some(Vec::new()),
+    };
+
+    let mut others = pending
+        .iter()
+        .copied()
+        .filter(|other| {
+            other.recipient() == message.recipient() && other.channel() == message.channel()
+        })
+        .filter_map(|other| Some((batch_key(other)?, other)))
+        .filter(|(other_key, _)| *other_key != key)
+        .collect::<Vec<_>>();
+    others.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let later = others.partition_point(|(other_key, _)| *other_key < key);
+    if matches!(others[..later].last(), Some((before, _)) if key.0 - before.0 <= window) {
+        return None;
+    }
+
+    let mut batch = Vec::new();
+    let mut last = key.0;
+    for (other_key, other) in &others[later..] {
+        if other_key.0 - last > window {
+            break;
+        }
+        last = other_key.0;
+        batch.push(*other);
+    }
+
+    Some(batch)
+}
+
+/// Group the timed reminders among `pending` that were due before `before` by recipient and
+/// channel, in the order the groups first appear.
+pub fn missed<'a>(pending: &[&'a Message], before: OffsetDateTime) -> Vec<Vec<&'a Message>> {
+    let mut groups: Vec<Vec<&Message>> = Vec::new();
+    for message in pending {
+        match batch_key(message) {
+            Some((at, _)) if at < before => {}
+            _ => continue,
+        }
+
+        match groups.iter_mut().find(|group| {
+            group[0].recipient() == message.recipient() && group[0].channel() == message.channel()
+        }) {
+            Some(group) => group.push(message),
+            None => groups.push(vec![message]),
+        }
+    }
+
+    groups
+}
+
+/// Orders timed reminders by when they are due, ties broken by id.
+fn batch_key(message: &Message) -> Option<(OffsetDateTime, &str)> {
+    match message.activation() {
+        Activation::Fixed(at) if message.kind() == Kind::Reminder => Some((*at, message.id())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(here: bool) -> Message {
+        Message::new(
+            "id".to_string(),
+            Activation::OnNextMessage,
+            "alice".to_string(),
+            "origin".to_string(),
+            "bob".to_string(),
+            "text".to_string(),
+        )
+        .with_here(here)
+    }
+
+    #[test]
+    fn scoped_messages_stay_in_their_channel() {
+        assert!(deliverable_in(&message(true), "origin"));
+        assert!(!deliverable_in(&message(true), "other"));
+        assert!(deliverable_in(&message(false), "other"));
+    }
+
+    #[test]
+    fn on_join_is_opt_in() {
+        assert!(!deliverable_on_join(&message(false), "origin"));
+        assert!(deliverable_on_join(
+            &message(false).with_on_join(true),
+            "origin"
+        ));
+        assert!(!deliverable_on_join(
+            &message(true).with_on_join(true),
+            "other"
+        ));
+    }
+
+    #[test]
+    fn scoped_unless_configured_otherwise() {
+        let mut config = Config::default();
+        let mut settings = ChannelSettings::default();
+        assert!(scoped_by_default(&config, &settings, "channel"));
+
+        config.deliver_anywhere = true;
+        assert!(!scoped_by_default(&config, &settings, "channel"));
+
+        config.scoped_channels.insert("channel".to_string());
+        assert!(scoped_by_default(&config, &settings, "channel"));
+
+        settings.scoped = Some(false);
+        assert!(!scoped_by_default(&config, &settings, "channel"));
+    }
+
+    #[test]
+    fn batches_reminders_due_together() {
+        let now = OffsetDateTime::now_utc();
+        let timed = |id: &str, recipient: &str, seconds: i64| {
+            Message::new(
+                id.to_string(),
+                Activation::Fixed(now + Duration::seconds(seconds)),
+                "alice".to_string(),
+                "origin".to_string(),
+                recipient.to_string(),
+                "text".to_string(),
+            )
+        };
+        let first = timed("a", "bob", 0);
+        let second = timed("b", "bob", 3);
+        let third = timed("e", "bob", 7);
+        let late = timed("c", "bob", 30);
+        let other = timed("d", "carol", 1);
+        let pending = [&first, &second, &third, &late, &other];
+        let window = Duration::seconds(5);
+
+        let ids = |batch: Option<Vec<&Message>>| {
+            batch.map(|batch| batch.iter().map(|message| message.id()).collect::<Vec<_>>())
+        };
+        assert_eq!(Some(vec!["b", "e"]), ids(batch(&first, &pending, window)));
+        assert_eq!(None, ids(batch(&second, &pending, window)));
+        assert_eq!(None, ids(batch(&third, &pending, window)));
+        assert_eq!(Some(vec![]), ids(batch(&late, &pending, window)));
+        assert_eq!(Some(vec![]), ids(batch(&other, &pending, window)));
+    }
+
+    #[test]
+    fn groups_missed_reminders() {
+        let now = OffsetDateTime::now_utc();
+        let timed = |id: &str, channel: &str, hours: i64| {
+            Message::new(
+                id.to_string(),
+                Activation::Fixed(now - Duration::hours(hours)),
+                "alice".to_string(),
+                channel.to_string(),
+                "bob".to_string(),
+                "text".to_string(),
+            )
+        };
+        let first = timed("a", "origin", 3);
+        let other_channel = timed("b", "other", 2);
+        let second = timed("c", "origin", 1);
+        let upcoming = timed("d", "origin", -1);
+        let untimed = message(false);
+
+        let groups = missed(&[&first, &other_channel, &second, &upcoming, &untimed], now)
+            .into_iter()
+            .map(|group| group.iter().map(|message| message.id()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![vec!["a", "c"], vec!["b"]], groups);
+    }
+}
@@ -0,0 +1,493 @@
+//! How reminders delivered in one chat message are laid out, whether there are several or a single
+//! timed one. Channels pick one of the layouts in the config or with `~set format`.
+//!
+//! Long texts are cut short when several reminders are delivered at once, so one of them can't
+//! take up several chat messages. `~full <id>` shows the rest.
+//!
+//! Timed reminders missed while the bot was offline say how late they are, e.g. `(missed by 3h)`.
+//!
+//! Besides how long ago a reminder was written, the time it was written and, if it is timed, the
+//! time it was set for are shown in the time zone of the recipient, e.g. `(2h ago, set 14:05 for
+//! 16:00)`. Where they were written follows how long ago, e.g. `(2h ago in #forsen during
+//! Tuesday's stream, set 14:05)`. Reminders written during a stream that is saved link to the
+//! moment in the video after their text.
+
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use time::{format_description, OffsetDateTime, UtcOffset};
+
+use crate::{
+    chunker,
+    config::DeliveryStyle,
+    humanize,
+    message::{Activation, Message, Origin, Priority},
+    PREFIX,
+};
+
+#[derive(Debug, Copy, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryFormat {
+    /// `from alice (2): hi (5m ago) - bye (1m ago); from bob: hey (3m ago)`
+    Grouped,
+    /// `alice: hi | alice: bye | bob: hey`
+    Compact,
+    /// `alice (5m ago): hi - alice (1m ago): bye - bob (3m ago): hey`
+    Verbose,
+}
+
+impl Default for DeliveryFormat {
+    fn default() -> Self {
+        DeliveryFormat::Grouped
+    }
+}
+
+impl DeliveryFormat {
+    pub const ALL: &'static [DeliveryFormat] = &[
+        DeliveryFormat::Grouped,
+        DeliveryFormat::Compact,
+        DeliveryFormat::Verbose,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DeliveryFormat::Grouped => "grouped",
+            DeliveryFormat::Compact => "compact",
+            DeliveryFormat::Verbose => "verbose",
+        }
+    }
+
+    /// Lay out `messages`, in the order given. Long texts are cut short if `truncate` is set, the
+    /// link to the stream counts towards their length and is left to `~full` if they are.
+    fn layout(
+        self,
+        messages: &[&Message],
+        style: DeliveryStyle,
+        utc_offset_minutes: Option<i64>,
+        truncate: bool,
+    ) -> String {
+        let now = OffsetDateTime::now_utc();
+        let text = |message: &Message| {
+            let text = message.expanded_text(now, style.precision, utc_offset_minutes);
+            let linked = with_vod(text.clone(), message);
+            let text = if !truncate || linked.chars().count() <= style.preview_chars {
+                linked
+            } else {
+                format!(
+                    "{} ({}full {})",
+                    chunker::preview(&text, style.preview_chars),
+                    PREFIX,
+                    message.id()
+                )
+            };
+
+            match message.activation() {
+                Activation::Fixed(deadline) if style.missed && *deadline < now => format!(
+                    "{} (missed by {})",
+                    text,
+                    humanize::truncated(now - *deadline, style.precision)
+                ),
+                _ => text,
+            }
+        };
+        let ago = |message: &Message| {
+            let set = format!(
+                "{}{}, set {}",
+                humanize::ago(now - message.created(), style.precision),
+                message
+                    .origin()
+                    .map(|origin| format!(" {}", origin_context(origin, now, utc_offset_minutes)))
+                    .unwrap_or_default(),
+                short_timestamp(message.created(), now, utc_offset_minutes)
+            );
+            match message.activation() {
+                Activation::Fixed(at) => format!(
+                    "{} for {}",
+                    set,
+                    short_timestamp(*at, now, utc_offset_minutes)
+                ),
+                _ => set,
+            }
+        };
+
+        match self {
+            DeliveryFormat::Grouped => group_by_author(messages)
+                .into_iter()
+                .map(|(author, group)| {
+                    let author = style.author(author);
+                    let header = match group.len() {
+                        1 => format!("from {}", author),
+                        n => format!("from {} ({})", author, n),
+                    };
+                    let texts = group
+                        .iter()
+                        .map(|message| format!("{} ({})", text(message), ago(message)))
+                        .intersperse(" - ".to_string())
+                        .collect::<String>();
+
+                    format!("{}: {}", header, texts)
+                })
+                .intersperse("; ".to_string())
+                .collect(),
+            DeliveryFormat::Compact => messages
+                .iter()
+                .map(|message| format!("{}: {}", style.author(message.author()), text(message)))
+                .intersperse(" | ".to_string())
+                .collect(),
+            DeliveryFormat::Verbose => messages
+                .iter()
+                .map(|message| {
+                    format!(
+                        "{} ({}): {}",
+                        style.author(message.author()),
+                        ago(message),
+                        text(message)
+                    )
+                })
+                .intersperse(" - ".to_string())
+                .collect(),
+        }
+    }
+}
+
+impl FromStr for DeliveryFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DeliveryFormat::ALL
+            .iter()
+            .copied()
+            .find(|format| format.name().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+/// Format delivered messages in the format of `style`, most urgent and then oldest first. If
+/// there are several, texts longer than [`DeliveryStyle::preview_chars`] are cut short.
+///
+/// Low priority messages are collected into a trailing digest so they never push more urgent
+/// ones towards the end of a long reply.
+///
+/// Placeholders in the texts are expanded with times in the time zone `utc_offset_minutes` away
+/// from UTC.
+pub fn format_deliveries(
+    messages: &[&Message],
+    style: DeliveryStyle,
+    utc_offset_minutes: Option<i64>,
+) -> String {
+    let (mut messages, mut low): (Vec<&Message>, Vec<&Message>) = messages
+        .iter()
+        .copied()
+        .partition(|message| message.priority() != Priority::Low);
+    messages.sort_by_key(|message| (message.priority(), message.created()));
+    low.sort_by_key(|message| message.created());
+
+    let truncate = messages.len() + low.len() > 1;
+    let text = style
+        .format
+        .layout(&messages, style, utc_offset_minutes, truncate);
+    if low.is_empty() {
+        return text;
+    }
+
+    let low = style
+        .format
+        .layout(&low, style, utc_offset_minutes, truncate);
+    if text.is_empty() {
+        format!("low priority {}", low)
+    } else {
+        format!("{} | low priority {}", text, low)
+    }
+}
+
+/// Format `at` concisely in the time zone `utc_offset_minutes` away from UTC, e.g. `18:32` if it's
+/// on the same day as `now` and `Nov 27 18:32` otherwise. Times in UTC say so, since the recipient
+/// didn't set a time zone.
+pub fn short_timestamp(
+    at: OffsetDateTime,
+    now: OffsetDateTime,
+    utc_offset_minutes: Option<i64>,
+) -> String {
+    let offset = utc_offset_minutes
+        .and_then(|minutes| UtcOffset::from_whole_seconds((minutes * 60) as i32).ok())
+        .unwrap_or(UtcOffset::UTC);
+    let (at, now) = (at.to_offset(offset), now.to_offset(offset));
+
+    let format = if at.date() == now.date() {
+        "[hour]:[minute]"
+    } else {
+        "[month repr:short] [day padding:none] [hour]:[minute]"
+    };
+    let text = format_description::parse(format)
+        .ok()
+        .and_then(|format| at.format(&format).ok())
+        .unwrap_or_default();
+
+    if utc_offset_minutes.is_some() {
+        text
+    } else {
+        format!("{} UTC", text)
+    }
+}
+
+/// Append the link to the moment in the stream `message` was written at to `text`, if there is one.
+pub fn with_vod(text: String, message: &Message) -> String {
+    match message.origin().and_then(|origin| origin.vod.as_ref()) {
+        Some(vod) => format!("{} {}", text, vod),
+        None => text,
+    }
+}
+
+/// Where and when a reminder was written, e.g. `in #forsen during Tuesday's stream`, with days in
+/// the time zone `utc_offset_minutes` away from UTC.
+pub fn origin_context(
+    origin: &Origin,
+    now: OffsetDateTime,
+    utc_offset_minutes: Option<i64>,
+) -> String {
+    let channel = format!("in #{}", origin.channel);
+    if !origin.live {
+        return channel;
+    }
+
+    let offset = utc_offset_minutes
+        .and_then(|minutes| UtcOffset::from_whole_seconds((minutes * 60) as i32).ok())
+        .unwrap_or(UtcOffset::UTC);
+    let (at, now) = (origin.at.to_offset(offset), now.to_offset(offset));
+    let format = |description| {
+        format_description::parse(description)
+            .ok()
+            .and_then(|format| at.format(&format).ok())
+            .unwrap_or_default()
+    };
+    let stream = match (now.date() - at.date()).whole_days() {
+        0 => "today's stream".to_string(),
+        1 => "yesterday's stream".to_string(),
+        2..=6 => format!("{}'s stream", format("[weekday]")),
+        _ => format!(
+            "the stream on {}",
+            format("[month repr:short] [day padding:none]")
+        ),
+    };
+
+    format!("{} during {}", channel, stream)
+}
+
+/// Group `messages` by author, in the order the authors first appear.
+fn group_by_author<'a>(messages: &[&'a Message]) -> Vec<(&'a str, Vec<&'a Message>)> {
+    let mut groups: Vec<(&str, Vec<&Message>)> = Vec::new();
+    for message in messages {
+        match groups
+            .iter_mut()
+            .find(|(author, _)| *author == message.author())
+        {
+            Some((_, group)) => group.push(message),
+            None => groups.push((message.author(), vec![message])),
+        }
+    }
+
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+
+    fn message(id: &str, author: &str, text: &str) -> Message {
+        Message::new(
+            id.to_string(),
+            Activation::OnNextMessage,
+            author.to_string(),
+            "channel".to_string(),
+            "bob".to_string(),
+            text.to_string(),
+        )
+    }
+
+    #[test]
+    fn lays_out_each_format() {
+        let hi = message("a", "alice", "hi");
+        let hey = message("b", "carol", "hey");
+        let bye = message("c", "alice", "bye");
+        let messages = [&hi, &hey, &bye];
+        let style = |format| DeliveryStyle {
+            max_message_bytes: 500,
+            precision: 2,
+            preview_chars: 100,
+            anti_ping: false,
+            batch_window: Duration::seconds(5),
+            format,
+            missed: false,
+        };
+
+        assert_eq!(
+            "alice: hi | carol: hey | alice: bye",
+            format_deliveries(&messages, style(DeliveryFormat::Compact), None)
+        );
+
+        let grouped = format_deliveries(&messages, style(DeliveryFormat::Grouped), None);
+        assert!(grouped.starts_with("from alice (2): hi ("));
+        assert!(grouped.contains(") - bye ("));
+        assert!(grouped.contains("; from carol: hey ("));
+
+        let verbose = format_deliveries(&messages, style(DeliveryFormat::Verbose), None);
+        assert!(verbose.starts_with("alice ("));
+        assert!(verbose.contains("): hi - carol ("));
+        assert!(verbose.ends_with("): bye"));
+    }
+
+    #[test]
+    fn links_to_the_stream_after_the_text() {
+        let origin = Origin {
+            channel: "channel".to_string(),
+            message_id: "message".to_string(),
+            at: OffsetDateTime::now_utc(),
+            live: true,
+            vod: Some("https://www.twitch.tv/videos/1?t=0h5m0s".to_string()),
+        };
+        let long = message("a", "alice", &"a".repeat(20)).with_origin(Some(origin));
+        let short = message("b", "carol", "hey");
+        let style = DeliveryStyle {
+            max_message_bytes: 500,
+            precision: 2,
+            preview_chars: 10,
+            anti_ping: false,
+            batch_window: Duration::seconds(5),
+            format: DeliveryFormat::Compact,
+            missed: false,
+        };
+
+        assert_eq!(
+            "alice: aaaaaaaaa… (~full a) | carol: hey",
+            format_deliveries(&[&long, &short], style, None)
+        );
+        assert_eq!(
+            format!(
+                "alice: {} https://www.twitch.tv/videos/1?t=0h5m0s",
+                "a".repeat(20)
+            ),
+            format_deliveries(&[&long], style, None)
+        );
+    }
+
+    #[test]
+    fn truncates_long_texts_of_several() {
+        let long = message("a", "alice", &"a".repeat(20));
+        let short = message("b", "carol", "hey");
+        let style = DeliveryStyle {
+            max_message_bytes: 500,
+            precision: 2,
+            preview_chars: 10,
+            anti_ping: false,
+            batch_window: Duration::seconds(5),
+            format: DeliveryFormat::Compact,
+            missed: false,
+        };
+
+        assert_eq!(
+            "alice: aaaaaaaaa… (~full a) | carol: hey",
+            format_deliveries(&[&long, &short], style, None)
+        );
+        assert_eq!(
+            format!("alice: {}", "a".repeat(20)),
+            format_deliveries(&[&long], style, None)
+        );
+    }
+
+    #[test]
+    fn marks_missed_reminders() {
+        let now = OffsetDateTime::now_utc();
+        let late = message("a", "alice", "hi")
+            .with_activation(Activation::Fixed(now - Duration::hours(3)));
+        let untimed = message("b", "carol", "hey");
+        let style = DeliveryStyle {
+            max_message_bytes: 500,
+            precision: 2,
+            preview_chars: 100,
+            anti_ping: false,
+            batch_window: Duration::seconds(5),
+            format: DeliveryFormat::Compact,
+            missed: true,
+        };
+
+        assert_eq!(
+            "alice: hi (missed by 3h) | carol: hey",
+            format_deliveries(&[&late, &untimed], style, None)
+        );
+        assert_eq!(
+            "alice: hi",
+            format_deliveries(
+                &[&late],
+                DeliveryStyle {
+                    missed: false,
+                    ..style
+                },
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn short_timestamps_in_the_recipients_time_zone() {
+        let now = OffsetDateTime::UNIX_EPOCH + Duration::days(10) + Duration::hours(12);
+
+        assert_eq!(
+            "10:30 UTC",
+            short_timestamp(now - Duration::minutes(90), now, None)
+        );
+        assert_eq!(
+            "12:30",
+            short_timestamp(now - Duration::minutes(90), now, Some(120))
+        );
+        assert_eq!(
+            "Jan 10 23:00 UTC",
+            short_timestamp(now - Duration::hours(13), now, None)
+        );
+        assert_eq!(
+            "Jan 12 00:00",
+            short_timestamp(now + Duration::hours(10), now, Some(120))
+        );
+    }
+
+    #[test]
+    fn origin_says_which_stream() {
+        // a Sunday
+        let now = OffsetDateTime::UNIX_EPOCH + Duration::days(10) + Duration::hours(12);
+        let origin = |ago, live| Origin {
+            channel: "forsen".to_string(),
+            message_id: "message".to_string(),
+            at: now - ago,
+            live,
+            vod: None,
+        };
+
+        assert_eq!(
+            "in #forsen",
+            origin_context(&origin(Duration::hours(1), false), now, None)
+        );
+        assert_eq!(
+            "in #forsen during yesterday's stream",
+            origin_context(&origin(Duration::minutes(750), true), now, None)
+        );
+        assert_eq!(
+            "in #forsen during today's stream",
+            origin_context(&origin(Duration::minutes(750), true), now, Some(120))
+        );
+        assert_eq!(
+            "in #forsen during Thursday's stream",
+            origin_context(&origin(Duration::days(3), true), now, None)
+        );
+        assert_eq!(
+            "in #forsen during the stream on Jan 1",
+            origin_context(&origin(Duration::days(10), true), now, None)
+        );
+    }
+
+    #[test]
+    fn parse_formats() {
+        assert_eq!(Ok(DeliveryFormat::Compact), "Compact".parse());
+        assert_eq!(Err(()), "fancy".parse::<DeliveryFormat>());
+    }
+}
@@ -0,0 +1,87 @@
+use eyre::{Context, Result};
+use time::{Duration, OffsetDateTime};
+
+use crate::audit_log::{AuditEvent, AuditKind, AuditLog};
+
+/// Distribution of how long reminders waited before they were delivered.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub median: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+impl LatencySummary {
+    /// Summarize `latencies`. Returns `None` if there are none.
+    pub fn new(mut latencies: Vec<Duration>) -> Option<Self> {
+        let max = *latencies.iter().max()?;
+        latencies.sort_unstable();
+
+        Some(Self {
+            count: latencies.len(),
+            median: percentile(&latencies, 50),
+            p95: percentile(&latencies, 95),
+            max,
+        })
+    }
+}
+
+/// How long the reminders delivered since `since` waited, timed ones first and the ones delivered
+/// on the next message of their recipient second.
+pub fn delivered_since(
+    audit: &AuditLog,
+    since: OffsetDateTime,
+) -> Result<(Option<LatencySummary>, Option<LatencySummary>)> {
+    let (timed, untimed): (Vec<_>, Vec<_>) = audit
+        .events_since(since)
+        .wrap_err("Failed to read audit log")?
+        .into_iter()
+        .filter(|event| event.kind == AuditKind::Delivered)
+        .partition(|event| event.due.is_some());
+
+    Ok((
+        LatencySummary::new(timed.iter().map(AuditEvent::latency).collect()),
+        LatencySummary::new(untimed.iter().map(AuditEvent::latency).collect()),
+    ))
+}
+
+/// Nearest-rank percentile of the sorted, non-empty `latencies`.
+fn percentile(latencies: &[Duration], percent: usize) -> Duration {
+    let rank = (latencies.len() * percent + 99) / 100;
+
+    latencies[rank.max(1) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_has_no_summary() {
+        assert_eq!(None, LatencySummary::new(Vec::new()));
+    }
+
+    #[test]
+    fn nearest_rank() {
+        let latencies = (1..=20).rev().map(Duration::seconds).collect();
+
+        assert_eq!(
+            Some(LatencySummary {
+                count: 20,
+                median: Duration::seconds(10),
+                p95: Duration::seconds(19),
+                max: Duration::seconds(20),
+            }),
+            LatencySummary::new(latencies)
+        );
+    }
+
+    #[test]
+    fn single_latency() {
+        let summary = LatencySummary::new(vec![Duration::seconds(3)]).unwrap();
+
+        assert_eq!(Duration::seconds(3), summary.median);
+        assert_eq!(Duration::seconds(3), summary.p95);
+    }
+}
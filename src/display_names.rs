@@ -0,0 +1,144 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use eyre::Result;
+use tracing::error;
+
+use crate::ron_store::RonStore;
+
+/// How often names seen since the last save are written.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The display names of users, keyed by login, so deliveries can mention recipients the way
+/// they write their name and `~tell` finds recipients by their localized name.
+///
+/// Only names that differ from the login in case are used in mentions, others like localized
+/// names don't highlight the user.
+///
+/// New names are saved every [`SAVE_INTERVAL`] by [`run`] rather than on every new chatter.
+#[derive(Debug, Clone)]
+pub struct DisplayNames {
+    data: RonStore<HashMap<String, String>>,
+    /// Whether anything changed since the last save.
+    changed: Arc<AtomicBool>,
+}
+
+impl DisplayNames {
+    pub fn from_path(path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            data: RonStore::from_path(path, "display name store")?,
+            changed: Arc::default(),
+        })
+    }
+
+    /// The name to mention `login` by, the login itself if the display name is unknown or
+    /// localized.
+    pub fn name(&self, login: &str) -> String {
+        self.data
+            .read()
+            .get(login)
+            .filter(|name| name.eq_ignore_ascii_case(login))
+            .cloned()
+            .unwrap_or_else(|| login.to_string())
+    }
+
+    /// The login of the user with the localized display name `name`.
+    pub fn login_of(&self, name: &str) -> Option<String> {
+        let name = name.to_lowercase();
+        self.data
+            .read()
+            .iter()
+            .find(|(login, known)| {
+                !known.eq_ignore_ascii_case(login) && known.to_lowercase() == name
+            })
+            .map(|(login, _)| login.clone())
+    }
+
+    /// Whether the display name of `login` is known.
+    pub fn knows(&self, login: &str) -> bool {
+        self.data.read().contains_key(login)
+    }
+
+    /// Remember that `name` is the display name of `login`. Returns whether anything changed.
+    pub fn see(&self, login: &str, name: &str) -> bool {
+        let mut data = self.data.write();
+        if data.get(login).map(String::as_str) == Some(name) {
+            return false;
+        }
+        data.insert(login.to_string(), name.to_string());
+        self.changed.store(true, Ordering::SeqCst);
+
+        true
+    }
+
+    pub fn forget(&self, login: &str) {
+        if self.data.write().remove(login).is_some() {
+            self.changed.store(true, Ordering::SeqCst);
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.changed.store(false, Ordering::SeqCst);
+        self.data.save()
+    }
+}
+
+/// Save `names` every [`SAVE_INTERVAL`] if they changed, until the process exits.
+pub async fn run(names: DisplayNames) {
+    let mut interval = tokio::time::interval(SAVE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if names.changed.load(Ordering::SeqCst) {
+            if let Err(err) = names.save() {
+                names.changed.store(true, Ordering::SeqCst);
+                error!("{:?}", err.wrap_err("Failed to save display name store"));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ron_store::test_path;
+
+    #[test]
+    fn keeps_names_that_mention_the_user() {
+        let names = DisplayNames::from_path(test_path("display-names")).unwrap();
+        assert_eq!("forsen", names.name("forsen"));
+        assert!(!names.knows("forsen"));
+
+        assert!(names.see("forsen", "Forsen"));
+        assert!(!names.see("forsen", "Forsen"));
+        assert_eq!("Forsen", names.name("forsen"));
+
+        assert!(names.see("yamada", "山田"));
+        assert!(names.knows("yamada"));
+        assert_eq!("yamada", names.name("yamada"));
+    }
+
+    #[test]
+    fn localized_names_map_to_logins() {
+        let names = DisplayNames::from_path(test_path("localized-names")).unwrap();
+
+        names.see("alice", "Alice");
+        names.see("yamada", "山田");
+        names.see("bob", "Ölbob");
+
+        assert_eq!(None, names.login_of("alice"));
+        assert_eq!(Some("yamada".to_string()), names.login_of("山田"));
+        assert_eq!(Some("bob".to_string()), names.login_of("ölbob"));
+
+        names.forget("yamada");
+        assert_eq!(None, names.login_of("山田"));
+    }
+}
@@ -2,6 +2,7 @@ use std::str::FromStr;
 
 use pest::Parser;
 use pest_derive::Parser;
+use serde::{Deserialize, Serialize};
 use time::Duration;
 
 macro_rules! handle_rule {
@@ -40,6 +41,121 @@ pub enum Error {
 
     #[error("Failed to parse integer: {0}")]
     ParseInt(#[from] std::num::ParseIntError),
+
+    #[error("Unknown language: {0:?}")]
+    UnknownLanguage(String),
+}
+
+/// The language durations are typed in. Units of other languages are translated to English
+/// before parsing, so English units are always understood.
+#[derive(Debug, Copy, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub enum Language {
+    English,
+    German,
+    French,
+}
+
+impl Default for Language {
+    fn default() -> Self {
+        Language::English
+    }
+}
+
+impl Language {
+    pub const ALL: &'static [Language] = &[Language::English, Language::German, Language::French];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::German => "de",
+            Language::French => "fr",
+        }
+    }
+
+    /// Unit names of the language and the English unit they mean. Connecting words map to
+    /// nothing.
+    fn units(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Language::English => &[("and", "")],
+            Language::German => &[
+                ("jahr", "years"),
+                ("jahre", "years"),
+                ("jahren", "years"),
+                ("monat", "months"),
+                ("monate", "months"),
+                ("monaten", "months"),
+                ("woche", "weeks"),
+                ("wochen", "weeks"),
+                ("tag", "days"),
+                ("tage", "days"),
+                ("tagen", "days"),
+                ("stunde", "hours"),
+                ("stunden", "hours"),
+                ("std", "hours"),
+                ("minuten", "minutes"),
+                ("sekunde", "seconds"),
+                ("sekunden", "seconds"),
+                ("sek", "seconds"),
+                ("und", ""),
+            ],
+            Language::French => &[
+                ("an", "years"),
+                ("ans", "years"),
+                ("année", "years"),
+                ("années", "years"),
+                ("mois", "months"),
+                ("semaine", "weeks"),
+                ("semaines", "weeks"),
+                ("jour", "days"),
+                ("jours", "days"),
+                ("j", "days"),
+                ("heure", "hours"),
+                ("heures", "hours"),
+                ("seconde", "seconds"),
+                ("secondes", "seconds"),
+                ("et", ""),
+            ],
+        }
+    }
+
+    fn translate_word<'a>(self, word: &'a str) -> &'a str {
+        self.units()
+            .iter()
+            .find(|(unit, _)| *unit == word)
+            .map_or(word, |(_, english)| *english)
+    }
+
+    /// Replace the unit names of the language in the lowercase duration `s` with English ones.
+    pub fn translate(self, s: &str) -> String {
+        let mut translated = String::with_capacity(s.len());
+        let mut word = String::new();
+
+        for c in s.chars() {
+            if c.is_alphabetic() {
+                word.push(c);
+                continue;
+            }
+
+            translated.push_str(self.translate_word(&word));
+            word.clear();
+            translated.push(c);
+        }
+        translated.push_str(self.translate_word(&word));
+
+        translated
+    }
+}
+
+impl FromStr for Language {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Language::ALL
+            .iter()
+            .copied()
+            .find(|language| language.code().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::UnknownLanguage(s.to_string()))
+    }
 }
 
 #[derive(Debug, Default)]
@@ -75,16 +191,31 @@ impl FromStr for IntermediateDuration {
     }
 }
 
+impl IntermediateDuration {
+    /// Parse the lowercase duration `s` with the units of `language`.
+    pub fn parse_localized(s: &str, language: Language) -> Result<Self, Error> {
+        language.translate(s).parse()
+    }
+}
+
+/// Parse a duration like `2h 30m` or `1w` with English units. Doesn't need anything of the bot, so
+/// it can be used and fuzzed on its own.
+#[cfg(feature = "duration-parser")]
+pub fn parse_duration(s: &str) -> Result<Duration, Error> {
+    s.parse::<IntermediateDuration>().map(Duration::from)
+}
+
 impl From<IntermediateDuration> for Duration {
     fn from(d: IntermediateDuration) -> Self {
+        // can't overflow, even with every count at u32::MAX
         Duration::seconds(
-            (d.years * 30_779_352
-                + d.months * 2_564_946
-                + d.weeks * 604_800
-                + d.days * 86_400
-                + d.hours * 3_600
-                + d.minutes * 60
-                + d.seconds) as i64,
+            i64::from(d.years) * 30_779_352
+                + i64::from(d.months) * 2_564_946
+                + i64::from(d.weeks) * 604_800
+                + i64::from(d.days) * 86_400
+                + i64::from(d.hours) * 3_600
+                + i64::from(d.minutes) * 60
+                + i64::from(d.seconds),
         )
     }
 }
@@ -111,4 +242,79 @@ mod tests {
 
         assert_eq!(1231234, duration.whole_seconds());
     }
+
+    #[cfg(feature = "duration-parser")]
+    #[test]
+    fn parse_duration_api() {
+        assert_eq!(Duration::minutes(150), parse_duration("2h 30m").unwrap());
+        assert_eq!(Duration::seconds(2_564_946), parse_duration("1M").unwrap());
+        assert!(parse_duration("2h soon").is_err());
+    }
+
+    #[test]
+    fn concatenated_components() {
+        let parse = |s: &str| -> Duration { s.parse::<IntermediateDuration>().unwrap().into() };
+
+        assert_eq!(Duration::minutes(90), parse("1h30m"));
+        assert_eq!(Duration::hours(60), parse("2d12h"));
+        assert_eq!(Duration::minutes(90), parse("1h, 30m"));
+        assert_eq!(Duration::seconds(3_690), parse("1h,1m,30s"));
+    }
+
+    #[test]
+    fn abbreviated_units() {
+        let parse = |s: &str| -> Duration { s.parse::<IntermediateDuration>().unwrap().into() };
+
+        assert_eq!(Duration::minutes(5), parse("5mins"));
+        assert_eq!(Duration::minutes(5), parse("5 min"));
+        assert_eq!(Duration::hours(3), parse("3hrs"));
+        assert_eq!(Duration::hours(1), parse("1hr"));
+        assert_eq!(Duration::seconds(30), parse("30secs"));
+        assert_eq!(Duration::seconds(1), parse("1sec"));
+        assert_eq!(Duration::weeks(2), parse("2wks"));
+        assert_eq!(Duration::weeks(1), parse("1wk"));
+        assert_eq!(Duration::seconds(2_564_946), parse("1mo"));
+        assert_eq!(Duration::seconds(2_564_946 + 60), parse("1mo1m"));
+    }
+
+    #[test]
+    fn huge_counts_do_not_overflow() {
+        let duration: Duration = "999y".parse::<IntermediateDuration>().unwrap().into();
+
+        assert_eq!(999 * 30_779_352, duration.whole_seconds());
+    }
+
+    #[test]
+    fn localized_units() {
+        let parse = |s: &str, language| -> Duration {
+            IntermediateDuration::parse_localized(s, language)
+                .unwrap()
+                .into()
+        };
+
+        assert_eq!(Duration::hours(2), parse("2 stunden", Language::German));
+        assert_eq!(
+            Duration::minutes(90),
+            parse("1 stunde und 30 minuten", Language::German)
+        );
+        assert_eq!(Duration::days(3), parse("3tage", Language::German));
+        assert_eq!(Duration::minutes(5), parse("5min", Language::German));
+        assert_eq!(Duration::weeks(2), parse("2 semaines", Language::French));
+        assert_eq!(Duration::days(1), parse("1j", Language::French));
+        assert_eq!(Duration::hours(1), parse("1h", Language::French));
+
+        assert!(IntermediateDuration::parse_localized("2 stunden", Language::English).is_err());
+    }
+
+    #[test]
+    fn parse_language_codes() {
+        assert_eq!(Language::German, "DE".parse::<Language>().unwrap());
+        assert!("xx".parse::<Language>().is_err());
+    }
+
+    #[test]
+    fn separators_do_not_split_counts() {
+        assert!("1,2h".parse::<IntermediateDuration>().is_err());
+        assert!("1 2h".parse::<IntermediateDuration>().is_err());
+    }
 }
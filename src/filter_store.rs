@@ -0,0 +1,92 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+};
+
+use eyre::Result;
+
+use crate::ron_store::RonStore;
+
+/// Phrases the moderators of a channel don't want to see in reminders, keyed by channel.
+#[derive(Debug, Clone)]
+pub struct FilterStore {
+    data: RonStore<HashMap<String, BTreeSet<String>>>,
+}
+
+impl FilterStore {
+    pub fn from_path(path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            data: RonStore::from_path(path, "filter store")?,
+        })
+    }
+
+    /// Add `phrase` to the filter of `channel`. Returns `false` if it was already there.
+    pub fn add(&self, channel: &str, phrase: &str) -> bool {
+        self.data
+            .write()
+            .entry(channel.to_string())
+            .or_default()
+            .insert(phrase.to_lowercase())
+    }
+
+    /// Remove `phrase` from the filter of `channel`. Returns `false` if it wasn't there.
+    pub fn remove(&self, channel: &str, phrase: &str) -> bool {
+        let mut data = self.data.write();
+
+        let removed = data
+            .get_mut(channel)
+            .map_or(false, |phrases| phrases.remove(&phrase.to_lowercase()));
+        if data.get(channel).map_or(false, BTreeSet::is_empty) {
+            data.remove(channel);
+        }
+
+        removed
+    }
+
+    pub fn phrases(&self, channel: &str) -> Vec<String> {
+        self.data
+            .read()
+            .get(channel)
+            .map(|phrases| phrases.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the first phrase of the filter of `channel` that `text` contains, ignoring case.
+    pub fn find_match(&self, channel: &str, text: &str) -> Option<String> {
+        let text = text.to_lowercase();
+
+        self.data
+            .read()
+            .get(channel)?
+            .iter()
+            .find(|phrase| text.contains(phrase.as_str()))
+            .cloned()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.data.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ron_store::test_path;
+
+    #[test]
+    fn match_ignores_case() {
+        let filters = FilterStore::from_path(test_path("filter-store")).unwrap();
+
+        assert!(filters.add("channel", "Spoiler"));
+        assert!(!filters.add("channel", "spoiler"));
+
+        assert_eq!(
+            Some("spoiler".to_string()),
+            filters.find_match("channel", "big SPOILERS ahead")
+        );
+        assert_eq!(None, filters.find_match("other", "big spoilers ahead"));
+
+        assert!(filters.remove("channel", "SPOILER"));
+        assert_eq!(None, filters.find_match("channel", "big spoilers ahead"));
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::PathBuf,
+};
+
+use eyre::Result;
+
+use crate::ron_store::RonStore;
+
+/// Named lists of recipients, so `~tell @modteam` reaches every member.
+///
+/// Groups of a user are keyed by their login, groups shared by a channel by `#channel`.
+#[derive(Debug, Clone)]
+pub struct GroupStore {
+    data: RonStore<HashMap<String, BTreeMap<String, BTreeSet<String>>>>,
+}
+
+/// The key of the groups shared by `channel`. Logins can't start with `#`.
+pub fn channel_owner(channel: &str) -> String {
+    format!("#{}", channel)
+}
+
+impl GroupStore {
+    pub fn from_path(path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            data: RonStore::from_path(path, "group store")?,
+        })
+    }
+
+    /// Create the group `name` of `owner`, replacing its members if it exists. Returns `false` if
+    /// it was created.
+    pub fn set(&mut self, owner: &str, name: &str, members: BTreeSet<String>) -> bool {
+        self.data
+            .write()
+            .entry(owner.to_string())
+            .or_default()
+            .insert(name.to_lowercase(), members)
+            .is_some()
+    }
+
+    /// Delete the group `name` of `owner`. Returns `false` if there is no such group.
+    pub fn remove(&mut self, owner: &str, name: &str) -> bool {
+        let mut data = self.data.write();
+        let removed = data.get_mut(owner).map_or(false, |groups| {
+            groups.remove(&name.to_lowercase()).is_some()
+        });
+        if data.get(owner).map_or(false, BTreeMap::is_empty) {
+            data.remove(owner);
+        }
+
+        removed
+    }
+
+    pub fn get(&self, owner: &str, name: &str) -> Option<BTreeSet<String>> {
+        self.data
+            .read()
+            .get(owner)?
+            .get(&name.to_lowercase())
+            .cloned()
+    }
+
+    /// The names of the groups of `owner`, sorted.
+    pub fn names(&self, owner: &str) -> Vec<String> {
+        self.data
+            .read()
+            .get(owner)
+            .map(|groups| groups.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// The members of the group `name` as `login` sees it in `channel`: their own group if they
+    /// have one, otherwise the one of the channel.
+    pub fn resolve(&self, login: &str, channel: &str, name: &str) -> Option<BTreeSet<String>> {
+        self.get(login, name)
+            .or_else(|| self.get(&channel_owner(channel), name))
+    }
+
+    /// Forget the groups of `login`.
+    pub fn forget(&mut self, login: &str) {
+        self.data.write().remove(login);
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.data.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ron_store::test_path;
+
+    fn members(logins: &[&str]) -> BTreeSet<String> {
+        logins.iter().map(|login| login.to_string()).collect()
+    }
+
+    #[test]
+    fn own_groups_take_precedence() {
+        let mut groups = GroupStore::from_path(test_path("groups")).unwrap();
+        groups.set(
+            &channel_owner("channel"),
+            "modteam",
+            members(&["alice", "bob"]),
+        );
+        groups.set("carol", "ModTeam", members(&["dave"]));
+
+        assert_eq!(
+            Some(members(&["dave"])),
+            groups.resolve("carol", "channel", "modteam")
+        );
+        assert_eq!(
+            Some(members(&["alice", "bob"])),
+            groups.resolve("erin", "channel", "MODTEAM")
+        );
+        assert_eq!(None, groups.resolve("erin", "other", "modteam"));
+
+        assert!(groups.remove("carol", "modteam"));
+        assert!(!groups.remove("carol", "modteam"));
+        assert!(groups.names("carol").is_empty());
+    }
+}
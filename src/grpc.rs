@@ -0,0 +1,187 @@
+//! The admin API over gRPC, for operators running the bot in a service mesh. Needs the `grpc`
+//! feature, the definitions are in `proto/admin.proto`. Requests are handed to the message loop
+//! like the ones of the [HTTP admin API](crate::admin_api).
+
+use std::sync::Arc;
+
+use eyre::{Context, Result};
+use tokio::sync::{mpsc, oneshot};
+use tonic::{Code, Status};
+use tracing::info;
+
+use crate::admin_api::{self, AdminApiConfig, ApiError, Draft, Operation, Request, Response};
+
+mod proto {
+    tonic::include_proto!("remindme.admin");
+}
+
+use proto::admin_server::{Admin, AdminServer};
+
+struct AdminService {
+    requests: mpsc::Sender<Request>,
+}
+
+impl AdminService {
+    async fn run(&self, operation: Operation) -> Result<Response, Status> {
+        let (respond, response) = oneshot::channel();
+        self.requests
+            .send(Request { operation, respond })
+            .await
+            .map_err(|_| status(ApiError::Unavailable))?;
+
+        response
+            .await
+            .map_err(|_| status(ApiError::Unavailable))?
+            .map_err(status)
+    }
+}
+
+fn status(err: ApiError) -> Status {
+    let code = match err {
+        ApiError::Unauthorized => Code::Unauthenticated,
+        ApiError::NotFound => Code::NotFound,
+        ApiError::MethodNotAllowed => Code::Unimplemented,
+        ApiError::BadRequest(_) => Code::InvalidArgument,
+        ApiError::Unavailable => Code::Unavailable,
+        ApiError::Internal => Code::Internal,
+    };
+
+    Status::new(code, err.to_string())
+}
+
+/// The operation answered with something else than expected.
+fn unexpected(response: Response) -> Status {
+    Status::internal(format!("unexpected response {:?}", response))
+}
+
+fn non_empty(s: String) -> Option<String> {
+    Some(s).filter(|s| !s.is_empty())
+}
+
+impl From<admin_api::Reminder> for proto::Reminder {
+    fn from(reminder: admin_api::Reminder) -> Self {
+        Self {
+            id: reminder.id,
+            author: reminder.author,
+            recipient: reminder.recipient,
+            channel: reminder.channel,
+            text: reminder.text,
+            created: reminder.created,
+            at: reminder.at.unwrap_or_default(),
+        }
+    }
+}
+
+fn channels(response: Response) -> Result<tonic::Response<proto::Channels>, Status> {
+    match response {
+        Response::Channels { channels } => Ok(tonic::Response::new(proto::Channels { channels })),
+        response => Err(unexpected(response)),
+    }
+}
+
+#[tonic::async_trait]
+impl Admin for AdminService {
+    async fn list_reminders(
+        &self,
+        request: tonic::Request<proto::ListRemindersRequest>,
+    ) -> Result<tonic::Response<proto::Reminders>, Status> {
+        let recipient = non_empty(request.into_inner().recipient).map(|r| r.to_lowercase());
+        match self.run(Operation::List { recipient }).await? {
+            Response::Reminders(reminders) => Ok(tonic::Response::new(proto::Reminders {
+                reminders: reminders.into_iter().map(proto::Reminder::from).collect(),
+            })),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    async fn create_reminder(
+        &self,
+        request: tonic::Request<proto::CreateReminderRequest>,
+    ) -> Result<tonic::Response<proto::Reminder>, Status> {
+        let request = request.into_inner();
+        let draft = Draft {
+            author: request.author,
+            channel: request.channel,
+            recipient: request.recipient,
+            text: request.text,
+            at: non_empty(request.at),
+        };
+        match self.run(Operation::Create(draft)).await? {
+            Response::Reminder(reminder) => Ok(tonic::Response::new(reminder.into())),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    async fn cancel_reminder(
+        &self,
+        request: tonic::Request<proto::CancelReminderRequest>,
+    ) -> Result<tonic::Response<proto::CancelReminderResponse>, Status> {
+        let id = request.into_inner().id;
+        match self.run(Operation::Delete { id }).await? {
+            Response::Deleted { deleted } => {
+                Ok(tonic::Response::new(proto::CancelReminderResponse {
+                    id: deleted,
+                }))
+            }
+            response => Err(unexpected(response)),
+        }
+    }
+
+    async fn join_channel(
+        &self,
+        request: tonic::Request<proto::ChannelRequest>,
+    ) -> Result<tonic::Response<proto::Channels>, Status> {
+        let channel = request.into_inner().channel;
+        channels(self.run(Operation::Join { channel }).await?)
+    }
+
+    async fn part_channel(
+        &self,
+        request: tonic::Request<proto::ChannelRequest>,
+    ) -> Result<tonic::Response<proto::Channels>, Status> {
+        let channel = request.into_inner().channel;
+        channels(self.run(Operation::Part { channel }).await?)
+    }
+
+    async fn stats(
+        &self,
+        _request: tonic::Request<proto::StatsRequest>,
+    ) -> Result<tonic::Response<proto::StatsResponse>, Status> {
+        match self.run(Operation::Stats).await? {
+            Response::Stats(stats) => Ok(tonic::Response::new(proto::StatsResponse {
+                pending: stats.pending as u64,
+                timed: stats.timed as u64,
+                active_timers: stats.active_timers as u64,
+                channels: stats.channels,
+                paused: stats.paused,
+            })),
+            response => Err(unexpected(response)),
+        }
+    }
+}
+
+/// Serve the gRPC API until it fails, handing requests to `requests`.
+pub async fn serve(config: AdminApiConfig, requests: mpsc::Sender<Request>) -> Result<()> {
+    let token = Arc::new(config.token);
+    let service = AdminServer::with_interceptor(
+        AdminService { requests },
+        move |request: tonic::Request<()>| {
+            let header = request
+                .metadata()
+                .get("authorization")
+                .and_then(|value| value.to_str().ok());
+            if admin_api::authorized(header, &token) {
+                Ok(request)
+            } else {
+                Err(status(ApiError::Unauthorized))
+            }
+        },
+    );
+
+    info!("Serving the gRPC admin API on {}", config.listen);
+    tonic::transport::Server::builder()
+        .add_service(service)
+        .serve(config.listen)
+        .await
+        .wrap_err("Failed to serve gRPC admin API")
+}
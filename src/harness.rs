@@ -0,0 +1,907 @@
+//! End-to-end tests: chat messages are fed through [`handle_server_message`] like they came from
+//! Twitch, while the bot talks to a [`MockClient`] that records what it sends.
+
+use std::{
+    env, fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex as StdMutex,
+    },
+    time::Duration as StdDuration,
+};
+
+use tokio::time::Instant;
+use twitch_irc::message::{IRCMessage, ServerMessage};
+
+use super::*;
+use crate::storage::StorageConfig;
+
+/// The channel the tests chat in.
+const CHANNEL: &str = "channel";
+
+/// The login of the bot.
+const LOGIN: &str = "remindme";
+
+/// How long to wait for spawned deliveries.
+const TIMEOUT: StdDuration = StdDuration::from_secs(2);
+
+/// A message the bot sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sent {
+    pub channel: String,
+    pub text: String,
+}
+
+/// Stands in for the chat client. Clones share what was sent.
+#[derive(Debug, Clone, Default)]
+pub struct MockClient {
+    sent: Arc<StdMutex<Vec<Sent>>>,
+    /// Set to make every send fail.
+    failing: Arc<AtomicBool>,
+}
+
+/// What a [`MockClient`] fails with.
+#[derive(Debug, thiserror::Error)]
+#[error("Sending is failing")]
+pub struct SendFailed;
+
+impl MockClient {
+    pub async fn connect(&self) {}
+
+    pub fn join(&self, _channel_login: String) {}
+
+    pub fn part(&self, _channel_login: String) {}
+
+    pub async fn say_in_response(
+        &self,
+        channel_login: String,
+        message: String,
+        _reply_to: Option<String>,
+    ) -> Result<(), SendFailed> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(SendFailed);
+        }
+
+        self.sent.lock().unwrap().push(Sent {
+            channel: channel_login,
+            text: message,
+        });
+        Ok(())
+    }
+
+    pub async fn privmsg(&self, channel_login: String, message: String) -> Result<(), SendFailed> {
+        self.say_in_response(channel_login, message, None).await
+    }
+
+    fn fail_sends(&self, failing: bool) {
+        self.failing.store(failing, Ordering::SeqCst);
+    }
+
+    /// Everything sent since the last call.
+    fn take(&self) -> Vec<Sent> {
+        self.sent.lock().unwrap().drain(..).collect()
+    }
+}
+
+/// The bot with its stores in a temporary directory, removed when the harness is dropped.
+struct Harness {
+    state: State,
+    client: Client,
+    mock: MockClient,
+    directory: PathBuf,
+    message_count: usize,
+    /// Stored follow-ups of delivered reminders, which the main loop would queue.
+    follow_ups: mpsc::Receiver<Message>,
+}
+
+impl Harness {
+    /// Set up a bot that has joined [`CHANNEL`]. `name` has to be unique among the tests.
+    fn new(name: &str) -> Self {
+        let directory =
+            env::temp_dir().join(format!("remindme-harness-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let path = |name: &str| directory.join(name);
+
+        let config = Config {
+            storage: StorageConfig::Ron {
+                path: path("messages.ron"),
+                compress: false,
+                backups: 0,
+            },
+            audit_log: path("audit.log"),
+            ..Config::default()
+        };
+        let storage = config.storage.open().unwrap();
+        let store = MessageStore::from_storage(storage, config.instance_id.clone()).unwrap();
+
+        let mock = MockClient::default();
+        let (follow_up_sender, follow_ups) = mpsc::channel(16);
+        let state = State {
+            recent: RecentMessages::new(config.recent_messages),
+            login: LOGIN.to_string(),
+            read_login: LOGIN.to_string(),
+            config_path: path("config.ron"),
+            channels: [CHANNEL.to_string()].into_iter().collect(),
+            store: Arc::new(Mutex::new(store)),
+            seen: SeenStore::from_path(path("seen.ron")).unwrap(),
+            display_names: DisplayNames::from_path(path("display_names.ron")).unwrap(),
+            afk: AfkStore::from_path(path("afk.ron")).unwrap(),
+            filters: FilterStore::from_path(path("filters.ron")).unwrap(),
+            groups: GroupStore::from_path(path("groups.ron")).unwrap(),
+            presets: PresetStore::from_path(path("presets.ron")).unwrap(),
+            watches: WatchStore::from_path(path("watches.ron")).unwrap(),
+            audit: AuditLog::new(
+                config.audit_log.clone(),
+                Duration::days(config.audit_retention_days),
+            ),
+            undo: UndoBuffer::new(UNDO_WINDOW),
+            history: HistoryStore::from_path(path("history.ron"), config.history_size).unwrap(),
+            archive: None,
+            delivered: UndoBuffer::new(SNOOZE_WINDOW),
+            confirmations: Confirmations::new(CONFIRM_WINDOW),
+            timers: Timers::default(),
+            joins: Joins::default(),
+            repeats: RepeatStore::from_path(path("repeats.ron")).unwrap(),
+            schedules: ScheduleStore::from_path(path("schedules.ron")).unwrap(),
+            settings: SettingsStore::from_path(path("settings.ron")).unwrap(),
+            channel_settings: ChannelSettingsStore::from_path(path("channel_settings.ron"))
+                .unwrap(),
+            helix: None,
+            helix_chat: None,
+            live: LiveChannels::default(),
+            commands: command_registry(),
+            cooldowns: Cooldowns::default(),
+            last_server_message: None,
+            pause: Pause::default(),
+            parse_failures: ParseFailures::default(),
+            usage: CommandUsage::default(),
+            follow_ups: follow_up_sender,
+            config,
+        };
+
+        Self {
+            state,
+            client: Client::mock(mock.clone()),
+            mock,
+            directory,
+            message_count: 0,
+            follow_ups,
+        }
+    }
+
+    /// Let `sender` say `text` in [`CHANNEL`].
+    async fn chat(&mut self, sender: &str, text: &str) {
+        self.chat_as(sender, sender, text).await;
+    }
+
+    /// Let `sender`, who goes by the display name `name`, say `text` in [`CHANNEL`].
+    async fn chat_as(&mut self, sender: &str, name: &str, text: &str) {
+        let id = self.message_count;
+        self.message_count += 1;
+        let raw = format!(
+            "@badge-info=;badges=;color=;display-name={name};emotes=;flags=;id=message-{id};mod=0;room-id=1;subscriber=0;tmi-sent-ts=1600000000000;turbo=0;user-id={sender}-id;user-type= :{sender}!{sender}@{sender}.tmi.twitch.tv PRIVMSG #{channel} :{text}",
+            sender = sender,
+            name = name,
+            id = id,
+            channel = CHANNEL,
+            text = text,
+        );
+        let message = ServerMessage::try_from(IRCMessage::parse(&raw).unwrap()).unwrap();
+
+        handle_server_message(&mut self.state, &self.client, LOGIN, message)
+            .await
+            .unwrap();
+        self.client.replies_sent().await;
+    }
+
+    /// Let `login` join [`CHANNEL`].
+    async fn join(&mut self, login: &str) {
+        let raw = format!(
+            ":{login}!{login}@{login}.tmi.twitch.tv JOIN #{channel}",
+            login = login,
+            channel = CHANNEL,
+        );
+        let message = ServerMessage::try_from(IRCMessage::parse(&raw).unwrap()).unwrap();
+
+        handle_server_message(&mut self.state, &self.client, LOGIN, message)
+            .await
+            .unwrap();
+    }
+
+    /// The texts sent since the last call, all of them to [`CHANNEL`].
+    fn sent(&self) -> Vec<String> {
+        self.mock
+            .take()
+            .into_iter()
+            .map(|sent| {
+                assert_eq!(CHANNEL, sent.channel);
+                sent.text
+            })
+            .collect()
+    }
+
+    async fn stored(&self) -> Vec<Message> {
+        let store = self.state.store.lock().await;
+        store.get_all().into_iter().cloned().collect()
+    }
+
+    /// Wait for the spawned deliveries to empty the store, then return what was sent.
+    async fn delivered(&self) -> Vec<String> {
+        self.delivered_until(0).await
+    }
+
+    /// Wait for the spawned deliveries to leave `pending` messages in the store, then return what
+    /// was sent.
+    async fn delivered_until(&self, pending: usize) -> Vec<String> {
+        let deadline = Instant::now() + TIMEOUT;
+        while self.state.store.lock().await.len() > pending {
+            assert!(Instant::now() < deadline, "Messages were not delivered");
+            tokio::time::sleep(StdDuration::from_millis(10)).await;
+        }
+
+        self.sent()
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.directory);
+    }
+}
+
+#[tokio::test]
+async fn tell_is_delivered_when_the_recipient_chats() {
+    let mut harness = Harness::new("tell");
+
+    harness.chat("alice", "~tell bob buy milk").await;
+    let stored = harness.stored().await;
+    assert_eq!(1, stored.len());
+    assert_eq!("bob", stored[0].recipient());
+    let origin = stored[0].origin().unwrap();
+    assert_eq!(
+        (CHANNEL, "message-0"),
+        (origin.channel.as_str(), origin.message_id.as_str())
+    );
+    assert_eq!(
+        vec![format!(
+            "I'll remind bob when they next type in chat [{}]",
+            stored[0].id()
+        )],
+        harness.sent()
+    );
+
+    harness.chat("carol", "hello").await;
+    assert!(harness.sent().is_empty());
+    assert_eq!(1, harness.stored().await.len());
+
+    harness.chat("bob", "hello").await;
+    let delivered = harness.delivered().await;
+    assert_eq!(1, delivered.len());
+    assert!(delivered[0].starts_with("@bob 1 reminder: "));
+    assert!(delivered[0].contains("buy milk"));
+    assert!(delivered[0].contains(" ago in #channel, set "));
+}
+
+#[tokio::test]
+async fn tell_with_several_schedules() {
+    let mut harness = Harness::new("several-schedules");
+
+    harness
+        .chat("alice", "~tell in:10m in:1h bob stretch")
+        .await;
+    let mut stored = harness.stored().await;
+    stored.sort_by_key(Message::due);
+    assert_eq!(2, stored.len());
+    assert!(stored.iter().all(|message| message.text() == "stretch"));
+
+    let sent = harness.sent();
+    assert_eq!(1, sent.len());
+    assert!(sent[0].starts_with(&format!("I'll remind bob [{}] in ", stored[0].id())));
+    assert!(sent[0].contains(&format!("; bob [{}] in ", stored[1].id())));
+}
+
+#[tokio::test]
+async fn follow_up_is_created_after_delivery() {
+    let mut harness = Harness::new("follow-up");
+
+    harness
+        .chat("alice", "~tell bob check oven then in:10m turn it off")
+        .await;
+    let id = harness.stored().await[0].id().to_string();
+    assert_eq!(
+        vec![format!(
+            "I'll remind bob when they next type in chat [{}], then again 10m after that",
+            id
+        )],
+        harness.sent()
+    );
+
+    harness.chat("bob", "hello").await;
+    // the follow-up replaces its parent in the store right away
+    assert!(harness.delivered_until(1).await[0].contains("check oven"));
+    let stored = harness.stored().await;
+    assert_eq!(1, stored.len());
+    assert_eq!("turn it off", stored[0].text());
+    assert_eq!(Some(id.as_str()), stored[0].parent());
+    assert!(stored[0].follow_up().is_none());
+
+    let queued = tokio::time::timeout(TIMEOUT, harness.follow_ups.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored[0].id(), queued.id());
+}
+
+#[tokio::test]
+async fn preset_is_sent_like_a_tell() {
+    let mut harness = Harness::new("preset");
+
+    harness
+        .chat(
+            "alice",
+            "~preset add vodreview \"in:1d cc:editor bob check VOD timestamps\"",
+        )
+        .await;
+    assert_eq!(
+        vec!["Saved preset vodreview, send it with ~preset use vodreview".to_string()],
+        harness.sent()
+    );
+    assert!(harness.stored().await.is_empty());
+
+    harness
+        .chat("alice", "~preset use VodReview from today")
+        .await;
+    let stored = harness.stored().await;
+    assert_eq!(2, stored.len());
+    assert!(stored
+        .iter()
+        .all(|message| message.text() == "check VOD timestamps from today"));
+
+    harness.sent();
+
+    harness.chat("carol", "~preset use vodreview").await;
+    assert_eq!(
+        vec!["Error: There is no preset vodreview".to_string()],
+        harness.sent()
+    );
+}
+
+#[tokio::test]
+async fn broken_presets_count_as_parse_failures() {
+    let mut harness = Harness::new("preset-parse-failure");
+
+    harness
+        .chat("alice", "~preset add broken \"in:2x bob stretch\"")
+        .await;
+    harness.sent();
+
+    assert_eq!(
+        vec![(parse_failures::Category::BadDuration, 1)],
+        harness.state.parse_failures.counts()
+    );
+}
+
+#[tokio::test]
+async fn preview_saves_nothing() {
+    let mut harness = Harness::new("preview");
+
+    harness
+        .chat(
+            "alice",
+            "~preview tell when:offline bob upload the highlights",
+        )
+        .await;
+    assert_eq!(
+        vec![format!(
+            "I'd remind bob when #{} goes offline: \"upload the highlights\". Nothing was saved",
+            CHANNEL
+        )],
+        harness.sent()
+    );
+    assert!(harness.stored().await.is_empty());
+
+    // previews are checked like the reminders themselves
+    harness.chat("alice", "~preview tell in:1s bob hello").await;
+    let sent = harness.sent();
+    assert_eq!(1, sent.len());
+    assert!(sent[0].starts_with("Error: Timed reminders have to be at least"));
+
+    harness.chat("alice", "~preview cancel 1").await;
+    assert_eq!(
+        vec!["Error: Only tell can be previewed, e.g. ~preview tell in:2h alice hello".to_string()],
+        harness.sent()
+    );
+}
+
+#[tokio::test]
+async fn notify_tells_the_author() {
+    let mut harness = Harness::new("notify");
+
+    harness
+        .chat("alice", "~notify @Bob ask about the raid")
+        .await;
+    let id = harness.stored().await[0].id().to_string();
+    assert_eq!(
+        vec![format!("I'll tell you when bob next chats [{}]", id)],
+        harness.sent()
+    );
+
+    harness.chat("carol", "hello").await;
+    assert!(harness.sent().is_empty());
+
+    harness.chat_as("bob", "Bob", "hello").await;
+    assert_eq!(
+        vec!["@alice Bob just chatted in #channel: ask about the raid".to_string()],
+        harness.delivered().await
+    );
+}
+
+#[tokio::test]
+async fn watchword_pings_once() {
+    let mut harness = Harness::new("watchword");
+
+    harness
+        .chat("alice", "~watchword \"drops\" in this channel")
+        .await;
+    assert_eq!(
+        vec!["I'll ping you the next time someone says \"drops\" here".to_string()],
+        harness.sent()
+    );
+
+    harness.chat("alice", "are drops on yet?").await;
+    assert!(harness.sent().is_empty());
+
+    harness.chat("bob", "Drops are live").await;
+    tokio::time::sleep(StdDuration::from_millis(50)).await;
+    assert_eq!(
+        vec!["@alice bob said \"drops\": Drops are live".to_string()],
+        harness.sent()
+    );
+
+    harness.chat("carol", "more drops").await;
+    tokio::time::sleep(StdDuration::from_millis(50)).await;
+    assert!(harness.sent().is_empty());
+}
+
+#[tokio::test]
+async fn joins_are_confirmed_for_the_reading_login() {
+    let mut harness = Harness::new("read-login");
+    harness.state.read_login = "justinfan12345".to_string();
+
+    harness.join(LOGIN).await;
+    assert!(harness.state.joins.joined().is_empty());
+
+    harness.join("justinfan12345").await;
+    assert_eq!(vec![CHANNEL.to_string()], harness.state.joins.joined());
+
+    harness.chat("justinfan12345", "~tell bob hi").await;
+    assert!(harness.sent().is_empty());
+}
+
+#[tokio::test]
+async fn failed_watchword_pings_are_kept() {
+    let mut harness = Harness::new("watchword-failed");
+
+    harness.chat("alice", "~watchword drops").await;
+    harness.sent();
+
+    harness.mock.fail_sends(true);
+    harness.chat("bob", "Drops are live").await;
+    let deadline = Instant::now() + TIMEOUT;
+    while harness.state.watches.of("alice").is_empty() {
+        assert!(Instant::now() < deadline, "Watch wasn't put back");
+        tokio::time::sleep(StdDuration::from_millis(10)).await;
+    }
+
+    harness.mock.fail_sends(false);
+    harness.chat("carol", "drops again").await;
+    tokio::time::sleep(StdDuration::from_millis(50)).await;
+    assert_eq!(
+        vec!["@alice carol said \"drops\": drops again".to_string()],
+        harness.sent()
+    );
+}
+
+#[tokio::test]
+async fn failed_delivery_stays_pending() {
+    let mut harness = Harness::new("failed-delivery");
+
+    harness.chat("alice", "~tell bob buy milk").await;
+    harness.sent();
+
+    harness.mock.fail_sends(true);
+    harness.chat("bob", "hello").await;
+    // every attempt with its backoff
+    let deadline = Instant::now() + StdDuration::from_secs(10);
+    while !harness.state.timers.is_empty() {
+        assert!(Instant::now() < deadline, "Delivery didn't give up");
+        tokio::time::sleep(StdDuration::from_millis(50)).await;
+    }
+    assert_eq!(1, harness.stored().await.len());
+
+    harness.mock.fail_sends(false);
+    harness.chat("bob", "hello again").await;
+    let delivered = harness.delivered().await;
+    assert_eq!(1, delivered.len());
+    assert!(delivered[0].contains("buy milk"));
+}
+
+#[tokio::test]
+async fn cancelled_tell_is_not_delivered() {
+    let mut harness = Harness::new("cancel");
+
+    harness.chat("alice", "~tell bob buy milk").await;
+    let id = harness.stored().await[0].id().to_string();
+    harness.sent();
+
+    harness.chat("dave", &format!("~cancel {}", id)).await;
+    assert_eq!(
+        vec!["You can only cancel reminders you wrote or received".to_string()],
+        harness.sent()
+    );
+    assert_eq!(1, harness.stored().await.len());
+
+    harness.chat("alice", &format!("~cancel {}", id)).await;
+    assert_eq!(1, harness.sent().len());
+    assert!(harness.stored().await.is_empty());
+
+    harness.chat("bob", "hello").await;
+    tokio::time::sleep(StdDuration::from_millis(50)).await;
+    assert!(harness.sent().is_empty());
+}
+
+#[tokio::test]
+async fn transferred_tell_is_delivered_to_the_new_recipient() {
+    let mut harness = Harness::new("transfer");
+
+    harness.chat("alice", "~tell bob buy milk").await;
+    let id = harness.stored().await[0].id().to_string();
+    harness.sent();
+
+    harness
+        .chat("dave", &format!("~transfer {} dave", id))
+        .await;
+    assert_eq!(
+        vec!["You can only transfer reminders you wrote".to_string()],
+        harness.sent()
+    );
+
+    harness
+        .chat("alice", &format!("~transfer {} @Carol", id))
+        .await;
+    assert_eq!(
+        vec![format!("I'll remind carol instead of bob [{}]", id)],
+        harness.sent()
+    );
+    let stored = harness.stored().await;
+    assert_eq!(1, stored.len());
+    assert_eq!(id, stored[0].id());
+    assert_eq!("carol", stored[0].recipient());
+
+    harness.chat("bob", "hello").await;
+    tokio::time::sleep(StdDuration::from_millis(50)).await;
+    assert!(harness.sent().is_empty());
+
+    harness.chat("carol", "hello").await;
+    assert!(harness.delivered().await[0].contains("buy milk"));
+}
+
+#[tokio::test]
+async fn cancel_several_ids() {
+    let mut harness = Harness::new("cancel-several");
+
+    harness.chat("alice", "~tell bob buy milk").await;
+    harness.chat("alice", "~tell bob buy eggs").await;
+    harness.chat("carol", "~tell bob buy bread").await;
+    let mut stored = harness.stored().await;
+    stored.sort_by_key(|message| message.text().to_string());
+    let ids = stored
+        .iter()
+        .map(|message| message.id().to_string())
+        .collect::<Vec<_>>();
+    harness.sent();
+
+    harness
+        .chat(
+            "alice",
+            &format!("~cancel {} {} {} nope", ids[1], ids[2], ids[0]),
+        )
+        .await;
+    assert_eq!(
+        vec![format!(
+            "Removed 2 reminders, use ~undo to restore them. Not found: nope. Not yours to cancel: {}",
+            ids[0]
+        )],
+        harness.sent()
+    );
+    assert_eq!(
+        vec![ids[0].clone()],
+        harness
+            .stored()
+            .await
+            .iter()
+            .map(|message| message.id().to_string())
+            .collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn own_messages_are_ignored() {
+    let mut harness = Harness::new("own");
+
+    harness.chat(LOGIN, "~tell bob buy milk").await;
+    assert!(harness.sent().is_empty());
+    assert!(harness.stored().await.is_empty());
+}
+
+#[tokio::test]
+async fn reminders_per_recipient_are_limited() {
+    let mut harness = Harness::new("per-recipient");
+    let max = harness.state.config.max_pending_per_recipient;
+
+    for _ in 0..max {
+        harness.chat("alice", "~tell bob buy milk").await;
+    }
+    harness.sent();
+
+    harness.chat("alice", "~tell bob buy milk").await;
+    let sent = harness.sent();
+    assert_eq!(1, sent.len());
+    assert!(sent[0].contains("You already have 3 reminders waiting for bob"));
+    assert_eq!(max, harness.stored().await.len());
+
+    harness.chat("carol", "~tell bob buy milk").await;
+    harness.chat("alice", "~tell me buy milk").await;
+    assert_eq!(max + 2, harness.stored().await.len());
+}
+
+#[tokio::test]
+async fn timed_reminders_per_user_are_limited() {
+    let mut harness = Harness::new("timed-per-user");
+    harness.state.config.max_timed_per_user = 2;
+
+    harness.chat("alice", "~tell in:1h bob stretch").await;
+    harness
+        .chat("alice", "~tell in:1d in:2d carol drink water")
+        .await;
+    let sent = harness.sent();
+    assert!(sent[1].contains("You can only have 2 timed reminders waiting"));
+    assert_eq!(1, harness.stored().await.len());
+
+    harness.chat("alice", "~tell carol drink water").await;
+    harness.chat("alice", "~tell in:1d carol drink water").await;
+    assert_eq!(3, harness.stored().await.len());
+}
+
+#[tokio::test]
+async fn defer_holds_reminders() {
+    let mut harness = Harness::new("defer");
+
+    harness.chat("alice", "~tell in:1h bob stretch").await;
+    harness.chat("alice", "~tell bob buy milk").await;
+    let due = harness
+        .stored()
+        .await
+        .iter()
+        .find_map(|message| match message.activation() {
+            Activation::Fixed(at) => Some(*at),
+            _ => None,
+        })
+        .unwrap();
+    harness.sent();
+
+    harness.chat("bob", "~defer 3d").await;
+    assert_eq!(
+        vec!["I'll hold your reminders for the next 3d, 1 timed reminder moved back by as much"],
+        harness.sent()
+    );
+    let stored = harness.stored().await;
+    assert_eq!(2, stored.len());
+    assert!(stored
+        .iter()
+        .any(|message| message.activation() == &Activation::Fixed(due + Duration::days(3))));
+
+    harness.chat("bob", "hello").await;
+    assert!(harness.sent().is_empty());
+
+    harness.chat("alice", "~tell bob call mom").await;
+    harness.sent();
+    harness.chat("bob", "hello").await;
+    let delivered = harness.delivered_until(2).await;
+    assert_eq!(1, delivered.len());
+    assert!(delivered[0].contains("call mom"));
+
+    harness.chat("bob", "~defer off").await;
+    harness.chat("bob", "hello").await;
+    let delivered = harness.delivered_until(1).await;
+    assert_eq!("I'll deliver your reminders as usual again", delivered[0]);
+    assert!(delivered[1].contains("buy milk"));
+}
+
+#[tokio::test]
+async fn reminders_for_the_bot_are_refused() {
+    let mut harness = Harness::new("bot");
+
+    harness.chat("alice", &format!("~tell @{} hi", LOGIN)).await;
+    assert_eq!(
+        vec!["Error: Nice try, but I never forget anything".to_string()],
+        harness.sent()
+    );
+    assert!(harness.stored().await.is_empty());
+
+    harness
+        .chat("alice", &format!("~tell cc:{} bob hi", LOGIN))
+        .await;
+    let stored = harness.stored().await;
+    assert_eq!(1, stored.len());
+    assert_eq!("bob", stored[0].recipient());
+}
+
+#[tokio::test]
+async fn localized_display_names_are_resolved() {
+    let mut harness = Harness::new("display-name");
+
+    harness.chat("alice", "~tell @山田 hi").await;
+    assert_eq!(
+        vec!["Error: I don't know who @山田 is, try their login instead".to_string()],
+        harness.sent()
+    );
+
+    harness.chat_as("yamada", "山田", "hello").await;
+    harness.chat("alice", "~tell @山田 hi").await;
+    let stored = harness.stored().await;
+    assert_eq!(1, stored.len());
+    assert_eq!("yamada", stored[0].recipient());
+}
+
+#[tokio::test]
+async fn admin_api_edits_reminders() {
+    use crate::admin_api::{self, Operation, Request, Response};
+
+    let mut harness = Harness::new("admin-api");
+    harness.chat("alice", "~tell bob buy milk").await;
+    harness.sent();
+    let id = harness.stored().await[0].id().to_string();
+
+    let request = |operation| {
+        let (respond, response) = tokio::sync::oneshot::channel();
+        let request = Request { operation, respond };
+        (request, response)
+    };
+
+    let changes = serde_json::from_str(r#"{"text": "buy oat milk"}"#).unwrap();
+    let (update, response) = request(Operation::Update { id, changes });
+    admin_api::handle_request(&mut harness.state, &harness.client, update).await;
+    let id = match response.await.unwrap().unwrap() {
+        Response::Reminder(reminder) => serde_json::to_value(reminder).unwrap()["id"]
+            .as_str()
+            .unwrap()
+            .to_string(),
+        response => panic!("unexpected response {:?}", response),
+    };
+    let stored = harness.stored().await;
+    assert_eq!(1, stored.len());
+    assert_eq!(id, stored[0].id());
+    assert_eq!("buy oat milk", stored[0].text());
+
+    let (delete, response) = request(Operation::Delete { id: id.clone() });
+    admin_api::handle_request(&mut harness.state, &harness.client, delete).await;
+    assert!(matches!(
+        response.await.unwrap(),
+        Ok(Response::Deleted { deleted }) if deleted == id
+    ));
+    assert!(harness.stored().await.is_empty());
+}
+
+#[tokio::test]
+async fn group_names_cant_hide_logins() {
+    let mut harness = Harness::new("group-login");
+
+    harness.chat("bob", "hello").await;
+    harness.chat("alice", "~group create bob carol,dave").await;
+    assert_eq!(
+        vec!["Error: bob is someone's login, pick another name for the group".to_string()],
+        harness.sent()
+    );
+
+    harness
+        .chat("alice", "~group create friends carol,dave")
+        .await;
+    assert_eq!(
+        vec!["Created @friends with 2 members".to_string()],
+        harness.sent()
+    );
+}
+
+#[tokio::test]
+async fn here_reminders_ignore_the_channel_setting() {
+    let mut harness = Harness::new("here-channel");
+
+    harness.chat("bob", "~settings channel other").await;
+    harness.chat("alice", "~tell here:on bob buy milk").await;
+    harness.chat("alice", "~tell bob buy eggs").await;
+    harness.sent();
+
+    harness.chat("bob", "hello").await;
+    let delivered = harness.delivered_until(1).await;
+    assert_eq!(1, delivered.len());
+    assert!(delivered[0].contains("buy milk"));
+    assert!(!delivered[0].contains("buy eggs"));
+}
+
+#[tokio::test]
+async fn timezone_offsets_are_saved() {
+    let mut harness = Harness::new("timezone-offset");
+
+    harness.chat("alice", "~timezone +02:00").await;
+    assert_eq!(
+        vec!["Your time zone is now UTC+02:00".to_string()],
+        harness.sent()
+    );
+    assert_eq!(
+        Some(120),
+        harness.state.settings.get("alice").utc_offset_minutes
+    );
+
+    harness.chat("alice", "~timezone").await;
+    assert_eq!(
+        vec!["Your time zone is UTC+02:00".to_string()],
+        harness.sent()
+    );
+}
+
+#[tokio::test]
+async fn cancelall_without_reminders_keeps_the_undo() {
+    let mut harness = Harness::new("cancelall-empty");
+
+    harness.chat("alice", "~tell bob buy milk").await;
+    let id = harness.stored().await[0].id().to_string();
+    harness.chat("alice", &format!("~cancel {}", id)).await;
+    harness.sent();
+
+    harness.chat("alice", "~cancelall").await;
+    harness.chat("alice", "~cancelall confirm").await;
+    assert_eq!(
+        vec![
+            "You have no reminders to cancel".to_string(),
+            "You have no reminders to cancel".to_string()
+        ],
+        harness.sent()
+    );
+
+    harness.chat("alice", "~undo").await;
+    assert_eq!(vec!["Restored 1 reminder".to_string()], harness.sent());
+    assert_eq!(1, harness.stored().await.len());
+}
+
+#[tokio::test]
+async fn cancel_filters_matching_nothing_keep_the_undo() {
+    let mut harness = Harness::new("cancel-filter-empty");
+
+    harness.chat("alice", "~tell bob buy milk").await;
+    let id = harness.stored().await[0].id().to_string();
+    harness.chat("alice", &format!("~cancel {}", id)).await;
+    harness.sent();
+
+    harness.chat("alice", "~cancel to:carol").await;
+    assert_eq!(
+        vec!["None of your reminders match that filter".to_string()],
+        harness.sent()
+    );
+
+    harness.chat("alice", "~undo").await;
+    assert_eq!(vec!["Restored 1 reminder".to_string()], harness.sent());
+}
+
+#[tokio::test]
+async fn snoozeall_needs_a_duration_ahead() {
+    let mut harness = Harness::new("snoozeall-zero");
+
+    harness.chat("alice", "~snoozeall 0s").await;
+    assert_eq!(
+        vec!["Error: I can't schedule anything sooner than 30s ahead".to_string()],
+        harness.sent()
+    );
+}
@@ -0,0 +1,505 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+};
+
+use eyre::{eyre, Context, Result};
+use serde::{
+    de::{DeserializeOwned, IgnoredAny},
+    Deserialize, Serialize,
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::proxy::Proxy;
+
+const API_URL: &str = "https://api.twitch.tv/helix";
+
+/// Helix accepts at most this many ids or logins per request.
+const MAX_PER_REQUEST: usize = 100;
+
+/// Helix returns at most this many chatters per page.
+const CHATTERS_PER_PAGE: &str = "1000";
+
+/// Channels whose stream is live, as of the last poll.
+pub type LiveChannels = Arc<RwLock<HashSet<String>>>;
+
+/// A minimal client for the parts of the Twitch Helix API the bot needs.
+#[derive(Debug, Clone)]
+pub struct Helix {
+    http: reqwest::Client,
+    client_id: String,
+    token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Page<T> {
+    data: T,
+    #[serde(default)]
+    pagination: Pagination,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Pagination {
+    cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Stream {
+    id: String,
+    user_login: String,
+    started_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Video {
+    id: String,
+    /// Only set for past broadcasts.
+    #[serde(default)]
+    stream_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct User {
+    id: String,
+    login: String,
+    display_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Channel {
+    broadcaster_login: String,
+    display_name: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Whisper<'a> {
+    message: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct Announcement<'a> {
+    message: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct Chatter {
+    user_login: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Schedule {
+    #[serde(default)]
+    segments: Option<Vec<RawSegment>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawSegment {
+    id: String,
+    start_time: String,
+    #[serde(default)]
+    title: String,
+    /// Set if the segment was canceled.
+    #[serde(default)]
+    canceled_until: Option<String>,
+}
+
+/// The video a stream is saved as while it is live.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vod {
+    pub id: String,
+    /// When the stream started, the start of the video.
+    pub started: OffsetDateTime,
+}
+
+impl Vod {
+    /// A link to the moment `at` of the stream, e.g. `https://www.twitch.tv/videos/1?t=1h23m45s`.
+    pub fn link_at(&self, at: OffsetDateTime) -> String {
+        let seconds = (at - self.started).whole_seconds().max(0);
+        format!(
+            "https://www.twitch.tv/videos/{}?t={}h{}m{}s",
+            self.id,
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    }
+}
+
+/// A planned stream from the schedule of a broadcaster.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub id: String,
+    pub start: OffsetDateTime,
+    pub title: String,
+}
+
+impl Helix {
+    /// `token` may be the chat token, with or without its `oauth:` prefix.
+    pub fn new(client_id: String, token: &str, proxy: Option<&Proxy>) -> Result<Self> {
+        let mut http = reqwest::Client::builder();
+        if let Some(proxy) = proxy {
+            http = http.proxy(proxy.http_proxy().wrap_err("Failed to set up proxy")?);
+        }
+
+        Ok(Self {
+            http: http.build().wrap_err("Failed to build HTTP client")?,
+            client_id,
+            token: token.trim_start_matches("oauth:").to_string(),
+        })
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str, query: &[(&str, &str)]) -> Result<T> {
+        Ok(self.get_page(path, query).await?.data)
+    }
+
+    async fn get_page<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<Page<T>> {
+        self.http
+            .get(format!("{}/{}", API_URL, path))
+            .header("Client-Id", &self.client_id)
+            .bearer_auth(&self.token)
+            .query(query)
+            .send()
+            .await
+            .wrap_err("Failed to send request")?
+            .error_for_status()
+            .wrap_err("Request failed")?
+            .json::<Page<T>>()
+            .await
+            .wrap_err("Failed to deserialize response")
+    }
+
+    async fn post<T: Serialize>(&self, path: &str, query: &[(&str, &str)], body: &T) -> Result<()> {
+        self.http
+            .post(format!("{}/{}", API_URL, path))
+            .header("Client-Id", &self.client_id)
+            .bearer_auth(&self.token)
+            .query(query)
+            .json(body)
+            .send()
+            .await
+            .wrap_err("Failed to send request")?
+            .error_for_status()
+            .wrap_err("Request failed")?;
+
+        Ok(())
+    }
+
+    /// Get the logins among `channels` that are live right now.
+    pub async fn live_channels(&self, channels: &[String]) -> Result<HashSet<String>> {
+        let mut live = HashSet::new();
+
+        for chunk in channels.chunks(MAX_PER_REQUEST) {
+            let query = chunk
+                .iter()
+                .map(|channel| ("user_login", channel.as_str()))
+                .collect::<Vec<_>>();
+
+            live.extend(
+                self.get::<Vec<Stream>>("streams", &query)
+                    .await
+                    .wrap_err("Failed to get streams")?
+                    .into_iter()
+                    .map(|stream| stream.user_login),
+            );
+        }
+
+        Ok(live)
+    }
+
+    /// Get the video the current stream of `broadcaster_id` is saved as. `None` if they aren't
+    /// live or don't save past broadcasts.
+    pub async fn current_vod(&self, broadcaster_id: &str) -> Result<Option<Vod>> {
+        let stream = match self
+            .get::<Vec<Stream>>("streams", &[("user_id", broadcaster_id)])
+            .await
+            .wrap_err("Failed to get stream")?
+            .into_iter()
+            .next()
+        {
+            Some(stream) => stream,
+            None => return Ok(None),
+        };
+
+        let videos = self
+            .get::<Vec<Video>>(
+                "videos",
+                &[
+                    ("user_id", broadcaster_id),
+                    ("type", "archive"),
+                    ("first", "1"),
+                ],
+            )
+            .await
+            .wrap_err("Failed to get videos")?;
+        let video = match videos
+            .into_iter()
+            .find(|video| video.stream_id.as_deref() == Some(stream.id.as_str()))
+        {
+            Some(video) => video,
+            None => return Ok(None),
+        };
+
+        Ok(Some(Vod {
+            id: video.id,
+            started: OffsetDateTime::parse(&stream.started_at, &Rfc3339)
+                .wrap_err("Failed to parse stream start")?,
+        }))
+    }
+
+    /// Get the user id of `login`, if there is such a user.
+    pub async fn user_id(&self, login: &str) -> Result<Option<String>> {
+        let users = self
+            .get::<Vec<User>>("users", &[("login", login)])
+            .await
+            .wrap_err("Failed to get user")?;
+
+        Ok(users.into_iter().next().map(|user| user.id))
+    }
+
+    /// Get the login of the user with the display name `name`. Helix can't look users up by
+    /// display name, so this searches channels for it and only accepts an exact match.
+    pub async fn login_of(&self, name: &str) -> Result<Option<String>> {
+        let channels = self
+            .get::<Vec<Channel>>("search/channels", &[("query", name)])
+            .await
+            .wrap_err("Failed to search channels")?;
+
+        Ok(channels
+            .into_iter()
+            .find(|channel| channel.display_name.to_lowercase() == name.to_lowercase())
+            .map(|channel| channel.broadcaster_login))
+    }
+
+    /// Get the logins among `logins` that belong to a user, leaving out deleted and banned ones.
+    pub async fn existing_users(&self, logins: &[String]) -> Result<HashSet<String>> {
+        let mut existing = HashSet::new();
+
+        for chunk in logins.chunks(MAX_PER_REQUEST) {
+            let query = chunk
+                .iter()
+                .map(|login| ("login", login.as_str()))
+                .collect::<Vec<_>>();
+
+            existing.extend(
+                self.get::<Vec<User>>("users", &query)
+                    .await
+                    .wrap_err("Failed to get users")?
+                    .into_iter()
+                    .map(|user| user.login),
+            );
+        }
+
+        Ok(existing)
+    }
+
+    /// Get the display names of the users among `logins`, keyed by login.
+    pub async fn display_names(&self, logins: &[String]) -> Result<HashMap<String, String>> {
+        let mut names = HashMap::new();
+
+        for chunk in logins.chunks(MAX_PER_REQUEST) {
+            let query = chunk
+                .iter()
+                .map(|login| ("login", login.as_str()))
+                .collect::<Vec<_>>();
+
+            names.extend(
+                self.get::<Vec<User>>("users", &query)
+                    .await
+                    .wrap_err("Failed to get users")?
+                    .into_iter()
+                    .map(|user| (user.login, user.display_name)),
+            );
+        }
+
+        Ok(names)
+    }
+
+    /// Whether the user `from_id` follows `to_id`.
+    pub async fn follows(&self, from_id: &str, to_id: &str) -> Result<bool> {
+        let follows = self
+            .get::<Vec<IgnoredAny>>("users/follows", &[("from_id", from_id), ("to_id", to_id)])
+            .await
+            .wrap_err("Failed to get follows")?;
+
+        Ok(!follows.is_empty())
+    }
+
+    /// Get the logins of everyone in the chat of `broadcaster_id`. `moderator_id` has to be a
+    /// moderator there and the token needs the `moderator:read:chatters` scope.
+    pub async fn chatters(
+        &self,
+        broadcaster_id: &str,
+        moderator_id: &str,
+    ) -> Result<HashSet<String>> {
+        let mut chatters = HashSet::new();
+        let mut cursor = None;
+
+        loop {
+            let mut query = vec![
+                ("broadcaster_id", broadcaster_id),
+                ("moderator_id", moderator_id),
+                ("first", CHATTERS_PER_PAGE),
+            ];
+            if let Some(cursor) = &cursor {
+                query.push(("after", cursor.as_str()));
+            }
+
+            let page = self
+                .get_page::<Vec<Chatter>>("chat/chatters", &query)
+                .await
+                .wrap_err("Failed to get chatters")?;
+            chatters.extend(page.data.into_iter().map(|chatter| chatter.user_login));
+
+            cursor = match page.pagination.cursor {
+                Some(next) if !next.is_empty() => Some(next),
+                _ => return Ok(chatters),
+            };
+        }
+    }
+
+    /// Whisper `message` from `from_id` to `to_id`. The token has to belong to `from_id` and needs
+    /// the `user:manage:whispers` scope.
+    pub async fn whisper(&self, from_id: &str, to_id: &str, message: &str) -> Result<()> {
+        self.post(
+            "whispers",
+            &[("from_user_id", from_id), ("to_user_id", to_id)],
+            &Whisper { message },
+        )
+        .await
+        .wrap_err("Failed to send whisper")
+    }
+
+    /// Post `message` as an announcement of `moderator_id` in the chat of `broadcaster_id`. The
+    /// token has to belong to `moderator_id` and needs the `moderator:manage:announcements` scope.
+    pub async fn announce(
+        &self,
+        broadcaster_id: &str,
+        moderator_id: &str,
+        message: &str,
+    ) -> Result<()> {
+        self.post(
+            "chat/announcements",
+            &[
+                ("broadcaster_id", broadcaster_id),
+                ("moderator_id", moderator_id),
+            ],
+            &Announcement { message },
+        )
+        .await
+        .wrap_err("Failed to send announcement")
+    }
+
+    /// Get the upcoming segments of the stream schedule of `broadcaster_id`, skipping canceled
+    /// ones.
+    pub async fn schedule(&self, broadcaster_id: &str) -> Result<Vec<Segment>> {
+        let schedule = self
+            .get::<Schedule>("schedule", &[("broadcaster_id", broadcaster_id)])
+            .await
+            .wrap_err("Failed to get schedule")?;
+
+        schedule
+            .segments
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|segment| segment.canceled_until.is_none())
+            .map(|segment| {
+                Ok(Segment {
+                    start: OffsetDateTime::parse(&segment.start_time, &Rfc3339)
+                        .wrap_err("Failed to parse segment start")?,
+                    id: segment.id,
+                    title: segment.title,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Sends what Twitch ignores as chat commands, whispers and announcements, through Helix as the
+/// bot. Clones share the user ids looked up so far.
+#[derive(Debug, Clone)]
+pub struct HelixChat {
+    helix: Helix,
+    bot_id: String,
+    /// The user ids of recipients and channels so far by login.
+    ids: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl HelixChat {
+    /// Look up the user id of the bot, `login`, to send as it.
+    pub async fn new(helix: Helix, login: &str) -> Result<Self> {
+        let bot_id = helix
+            .user_id(login)
+            .await?
+            .ok_or_else(|| eyre!("There is no user {}", login))?;
+
+        Ok(Self {
+            helix,
+            bot_id,
+            ids: Arc::default(),
+        })
+    }
+
+    /// Whisper `message` to `login`.
+    pub async fn whisper(&self, login: &str, message: &str) -> Result<()> {
+        let to_id = self.user_id(login).await?;
+
+        self.helix.whisper(&self.bot_id, &to_id, message).await
+    }
+
+    /// Post `message` as an announcement in `channel`, where the bot has to be a moderator.
+    pub async fn announce(&self, channel: &str, message: &str) -> Result<()> {
+        let broadcaster_id = self.user_id(channel).await?;
+
+        self.helix
+            .announce(&broadcaster_id, &self.bot_id, message)
+            .await
+    }
+
+    async fn user_id(&self, login: &str) -> Result<String> {
+        let known = self.ids.read().unwrap().get(login).cloned();
+        if let Some(id) = known {
+            return Ok(id);
+        }
+
+        let id = self
+            .helix
+            .user_id(login)
+            .await?
+            .ok_or_else(|| eyre!("There is no user {}", login))?;
+        self.ids
+            .write()
+            .unwrap()
+            .insert(login.to_string(), id.clone());
+
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn links_to_the_moment_in_the_vod() {
+        let vod = Vod {
+            id: "123".to_string(),
+            started: OffsetDateTime::UNIX_EPOCH,
+        };
+        let at = vod.started + Duration::hours(1) + Duration::minutes(23) + Duration::seconds(4);
+
+        assert_eq!(
+            "https://www.twitch.tv/videos/123?t=1h23m4s",
+            vod.link_at(at)
+        );
+        assert_eq!(
+            "https://www.twitch.tv/videos/123?t=0h0m0s",
+            vod.link_at(vod.started - Duration::minutes(1))
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+};
+
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+    message::{stored::StoredMessage, Message},
+    ron_store::RonStore,
+};
+
+/// A reminder as it was delivered.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct Delivery {
+    at: OffsetDateTime,
+    message: StoredMessage,
+}
+
+/// The reminders delivered to each user most recently, keyed by recipient, so they can be shown
+/// again after chat scrolled past them.
+#[derive(Debug, Clone)]
+pub struct HistoryStore {
+    /// How many deliveries are kept per user.
+    limit: usize,
+    data: RonStore<HashMap<String, VecDeque<Delivery>>>,
+}
+
+impl HistoryStore {
+    pub fn from_path(path: PathBuf, limit: usize) -> Result<Self> {
+        Ok(Self {
+            limit,
+            data: RonStore::from_path(path, "history store")?,
+        })
+    }
+
+    /// Remember that `message` was delivered at `at`, forgetting the oldest delivery to its
+    /// recipient if there are too many.
+    pub fn record(&self, message: &Message, at: OffsetDateTime) {
+        if self.limit == 0 {
+            return;
+        }
+
+        let mut data = self.data.write();
+        let deliveries = data.entry(message.recipient().to_string()).or_default();
+        deliveries.push_back(Delivery {
+            at,
+            message: message.into(),
+        });
+        while deliveries.len() > self.limit {
+            deliveries.pop_front();
+        }
+    }
+
+    /// The reminders delivered to `login` with when they were delivered, newest first.
+    pub fn recent(&self, login: &str) -> Vec<(OffsetDateTime, Message)> {
+        self.data
+            .read()
+            .get(login)
+            .map(|deliveries| {
+                deliveries
+                    .iter()
+                    .rev()
+                    .map(|delivery| (delivery.at, delivery.message.clone().into()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The reminder `id` if it was delivered to `login` recently.
+    pub fn get(&self, login: &str, id: &str) -> Option<Message> {
+        self.data
+            .read()
+            .get(login)?
+            .iter()
+            .map(|delivery| Message::from(delivery.message.clone()))
+            .find(|message| message.id() == id)
+    }
+
+    /// Forget everything delivered to `login`.
+    pub fn forget(&self, login: &str) {
+        self.data.write().remove(login);
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.data.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+    use crate::{message::Activation, ron_store::test_path};
+
+    fn message(id: &str) -> Message {
+        Message::new(
+            id.to_string(),
+            Activation::OnNextMessage,
+            "alice".to_string(),
+            "channel".to_string(),
+            "bob".to_string(),
+            "text".to_string(),
+        )
+    }
+
+    #[test]
+    fn keeps_the_latest_deliveries() {
+        let history = HistoryStore::from_path(test_path("history"), 2).unwrap();
+        let now = OffsetDateTime::UNIX_EPOCH;
+
+        history.record(&message("a"), now);
+        history.record(&message("b"), now + Duration::minutes(1));
+        history.record(&message("c"), now + Duration::minutes(2));
+
+        let ids = history
+            .recent("bob")
+            .into_iter()
+            .map(|(_, message)| message.id().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["c", "b"], ids);
+        assert!(history.recent("alice").is_empty());
+
+        assert!(history.get("bob", "c").is_some());
+        assert!(history.get("bob", "a").is_none());
+        assert!(history.get("alice", "c").is_none());
+    }
+}
@@ -0,0 +1,101 @@
+//! Render durations the way they are shown in chat, e.g. `2h 5m ago` or `in 3d 4h`.
+
+use time::Duration;
+
+/// How many units durations are shown with unless a channel configures otherwise.
+pub const DEFAULT_PRECISION: usize = 2;
+
+/// Units a span is broken into, largest first, with their length in seconds.
+const UNITS: [(i64, &str); 5] = [
+    (365 * 24 * 60 * 60, "y"),
+    (24 * 60 * 60, "d"),
+    (60 * 60, "h"),
+    (60, "m"),
+    (1, "s"),
+];
+
+/// Format the length of `duration` as e.g. `1d 2h 3s`, ignoring its sign.
+pub fn span(duration: Duration) -> String {
+    truncated(duration, UNITS.len())
+}
+
+/// Format the length of `duration` with at most `precision` units, starting at the most
+/// significant one, e.g. `1y 12d` instead of `1y 12d 3h 42m 17s`. Smaller units are cut off.
+pub fn truncated(duration: Duration, precision: usize) -> String {
+    let mut remaining = duration.whole_seconds().abs();
+
+    let counts = UNITS
+        .iter()
+        .map(|(length, unit)| {
+            let count = remaining / length;
+            remaining %= length;
+
+            (count, unit)
+        })
+        .collect::<Vec<_>>();
+    let parts = counts
+        .iter()
+        .skip_while(|(count, _)| *count == 0)
+        .take(precision.max(1))
+        .filter(|(count, _)| *count != 0)
+        .map(|(count, unit)| format!("{}{}", count, unit))
+        .collect::<Vec<_>>();
+
+    if parts.is_empty() {
+        "0s".to_string()
+    } else {
+        parts.join(" ")
+    }
+}
+
+/// Format how long ago something happened with `precision` units, e.g. `2h 5m ago`.
+pub fn ago(elapsed: Duration, precision: usize) -> String {
+    format!("{} ago", truncated(elapsed, precision))
+}
+
+/// Format how long until something happens, e.g. `in 2h 5m`.
+pub fn until(remaining: Duration) -> String {
+    format!("in {}", span(remaining))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn span_breaks_into_units() {
+        let duration = Duration::days(367)
+            + Duration::hours(3)
+            + Duration::minutes(42)
+            + Duration::seconds(17);
+
+        assert_eq!("1y 2d 3h 42m 17s", span(duration));
+        assert_eq!("1h 1s", span(Duration::seconds(3601)));
+        assert_eq!("0s", span(Duration::milliseconds(999)));
+    }
+
+    #[test]
+    fn span_ignores_sign() {
+        assert_eq!("2h 5m", span(-Duration::minutes(125)));
+    }
+
+    #[test]
+    fn truncate_to_most_significant_units() {
+        let duration = Duration::days(377)
+            + Duration::hours(3)
+            + Duration::minutes(42)
+            + Duration::seconds(17);
+
+        assert_eq!("1y 12d", truncated(duration, 2));
+        assert_eq!("1y", truncated(duration, 1));
+        // zero units still count towards the precision
+        assert_eq!("1h", truncated(Duration::seconds(3605), 2));
+        assert_eq!("5s", truncated(Duration::seconds(5), 2));
+    }
+
+    #[test]
+    fn past_and_future() {
+        assert_eq!("2h 5m ago", ago(Duration::minutes(125), DEFAULT_PRECISION));
+        assert_eq!("in 2h 5m", until(Duration::minutes(125)));
+    }
+}
@@ -0,0 +1,61 @@
+use serde::Deserialize;
+use ulid::Ulid;
+
+const BASE36: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to generate cuid")]
+    Cuid(#[from] cuid::CuidError),
+}
+
+/// How message ids are generated.
+#[derive(Debug, Copy, Clone, Deserialize, PartialEq, Eq)]
+pub enum IdGenerator {
+    /// cuid slugs like `ckz4l1r`.
+    Cuid,
+    /// Lexicographically sortable ULIDs, handy for database backends.
+    Ulid,
+    /// Eight random base36 characters, easy to type on mobile.
+    Short,
+}
+
+impl Default for IdGenerator {
+    fn default() -> Self {
+        IdGenerator::Cuid
+    }
+}
+
+impl IdGenerator {
+    pub fn generate(&self) -> Result<String, Error> {
+        match self {
+            IdGenerator::Cuid => Ok(cuid::slug()?),
+            IdGenerator::Ulid => Ok(Ulid::new().to_string().to_lowercase()),
+            IdGenerator::Short => {
+                // the low bits of a ulid are random
+                let mut random = u128::from(Ulid::new());
+
+                Ok((0..8)
+                    .map(|_| {
+                        let c = BASE36[(random % 36) as usize] as char;
+                        random /= 36;
+                        c
+                    })
+                    .collect())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_ids() {
+        let id = IdGenerator::Short.generate().unwrap();
+
+        assert_eq!(8, id.len());
+        assert!(id.chars().all(|c| c.is_ascii_alphanumeric()));
+    }
+}
@@ -0,0 +1,56 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+/// Channels Twitch confirmed the bot joined. Clones share their state.
+#[derive(Debug, Clone, Default)]
+pub struct Joins {
+    joined: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Joins {
+    pub fn confirm(&self, channel: &str) {
+        self.joined.lock().unwrap().insert(channel.to_string());
+    }
+
+    pub fn forget(&self, channel: &str) {
+        self.joined.lock().unwrap().remove(channel);
+    }
+
+    /// The channels the bot is in right now.
+    pub fn joined(&self) -> Vec<String> {
+        self.joined.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Get the channels among `channels` that weren't confirmed yet.
+    pub fn missing<'a>(&self, channels: &'a [String]) -> Vec<&'a String> {
+        let joined = self.joined.lock().unwrap();
+
+        channels
+            .iter()
+            .filter(|channel| !joined.contains(*channel))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_until_confirmed() {
+        let joins = Joins::default();
+        let channels = vec!["first".to_string(), "second".to_string()];
+
+        joins.clone().confirm("first");
+        assert_eq!(vec!["second"], joins.missing(&channels));
+
+        joins.confirm("second");
+        assert!(joins.missing(&channels).is_empty());
+
+        joins.forget("first");
+        assert_eq!(vec!["first"], joins.missing(&channels));
+        assert_eq!(vec!["second".to_string()], joins.joined());
+    }
+}
@@ -0,0 +1,26 @@
+//! The grammar the bot reads reminders in, for tools that want to validate commands like chat
+//! does. Chat reads durations in the channel's language and times of day in the user's zone, so
+//! pass those to [`MessageDefinition::parse_localized`] to match it exactly.
+//!
+//! ```
+//! use twitch_remindme::{parse_tell, Schedule};
+//!
+//! let definition = parse_tell("in:2h alice stretch").unwrap();
+//! assert!(definition.recipients.contains("alice"));
+//! assert_eq!("stretch", definition.text);
+//! assert_eq!(Schedule::Relative(time::Duration::hours(2)), definition.schedule);
+//!
+//! let err = parse_tell("in:2h").unwrap_err();
+//! println!("{}", err.hint("in:2h"));
+//! ```
+
+#![feature(iter_intersperse)]
+
+pub mod date_parser;
+pub mod duration_parser;
+pub mod message_parser;
+pub mod time_zone;
+
+#[cfg(feature = "duration-parser")]
+pub use duration_parser::parse_duration;
+pub use message_parser::{parse_tell, Error, MessageDefinition, Priority, Quote, Schedule};
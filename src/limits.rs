@@ -0,0 +1,154 @@
+//! How far the caps and cooldowns apply to a chatter. Moderators and the broadcaster run their
+//! channel, so they get higher limits or none at all there. The broadcaster changes how far with
+//! `~set modlimits` and `~set broadcasterlimits`. Caps count what a chatter has across all
+//! channels, so no channel can lift them entirely.
+
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use time::Duration;
+
+use crate::{channel_settings::ChannelSettings, permissions::Role};
+
+/// How much higher caps are and how much shorter cooldowns are for [`Tier::Raised`].
+const RAISED_FACTOR: usize = 5;
+
+#[derive(Debug, Copy, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Tier {
+    /// The limits from the config.
+    Normal,
+    /// Caps multiplied and cooldowns divided by [`RAISED_FACTOR`].
+    Raised,
+    /// No caps and no cooldowns.
+    Unlimited,
+}
+
+impl Tier {
+    pub const ALL: &'static [Tier] = &[Tier::Normal, Tier::Raised, Tier::Unlimited];
+
+    /// The tier of a chatter with `role` in a channel with `settings`. Moderators get raised
+    /// limits and the broadcaster none unless the channel says otherwise. The owner of the bot
+    /// has no limits anywhere.
+    pub fn of(role: Role, settings: &ChannelSettings) -> Self {
+        match role {
+            Role::Everyone | Role::Vip => Tier::Normal,
+            Role::Moderator => settings.moderator_limits.unwrap_or(Tier::Raised),
+            Role::Broadcaster => settings.broadcaster_limits.unwrap_or(Tier::Unlimited),
+            Role::Owner => Tier::Unlimited,
+        }
+    }
+
+    /// The tier of a chatter with `role` in a channel with `settings` for caps, which count
+    /// across channels. Only the owner of the bot has no caps, everyone else is raised at most.
+    pub fn across_channels(role: Role, settings: &ChannelSettings) -> Self {
+        match Tier::of(role, settings) {
+            Tier::Unlimited if role != Role::Owner => Tier::Raised,
+            tier => tier,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tier::Normal => "normal",
+            Tier::Raised => "raised",
+            Tier::Unlimited => "unlimited",
+        }
+    }
+
+    /// Whether `count` things are within `cap` for this tier.
+    pub fn allows(self, count: usize, cap: usize) -> bool {
+        self.cap(cap).map_or(true, |cap| count <= cap)
+    }
+
+    /// `cap` for this tier, `None` if there is none.
+    pub fn cap(self, cap: usize) -> Option<usize> {
+        match self {
+            Tier::Normal => Some(cap),
+            Tier::Raised => Some(cap.saturating_mul(RAISED_FACTOR)),
+            Tier::Unlimited => None,
+        }
+    }
+
+    /// `cooldown` for this tier.
+    pub fn cooldown(self, cooldown: Duration) -> Duration {
+        match self {
+            Tier::Normal => cooldown,
+            Tier::Raised => cooldown / RAISED_FACTOR as u32,
+            Tier::Unlimited => Duration::ZERO,
+        }
+    }
+}
+
+impl FromStr for Tier {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Tier::ALL
+            .iter()
+            .copied()
+            .find(|tier| tier.name().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_get_their_tier() {
+        let mut settings = ChannelSettings::default();
+        assert_eq!(Tier::Normal, Tier::of(Role::Vip, &settings));
+        assert_eq!(Tier::Raised, Tier::of(Role::Moderator, &settings));
+        assert_eq!(Tier::Unlimited, Tier::of(Role::Broadcaster, &settings));
+
+        settings.moderator_limits = Some(Tier::Normal);
+        settings.broadcaster_limits = Some(Tier::Raised);
+        assert_eq!(Tier::Normal, Tier::of(Role::Moderator, &settings));
+        assert_eq!(Tier::Raised, Tier::of(Role::Broadcaster, &settings));
+        assert_eq!(Tier::Unlimited, Tier::of(Role::Owner, &settings));
+    }
+
+    #[test]
+    fn channels_cant_lift_caps() {
+        let mut settings = ChannelSettings::default();
+        assert_eq!(
+            Tier::Raised,
+            Tier::across_channels(Role::Broadcaster, &settings)
+        );
+        assert_eq!(
+            Tier::Unlimited,
+            Tier::across_channels(Role::Owner, &settings)
+        );
+
+        settings.moderator_limits = Some(Tier::Unlimited);
+        assert_eq!(
+            Tier::Raised,
+            Tier::across_channels(Role::Moderator, &settings)
+        );
+        settings.broadcaster_limits = Some(Tier::Normal);
+        assert_eq!(
+            Tier::Normal,
+            Tier::across_channels(Role::Broadcaster, &settings)
+        );
+    }
+
+    #[test]
+    fn tiers_scale_limits() {
+        assert!(!Tier::Normal.allows(4, 3));
+        assert!(Tier::Raised.allows(15, 3));
+        assert!(!Tier::Raised.allows(16, 3));
+        assert!(Tier::Unlimited.allows(usize::MAX, 0));
+        assert_eq!(Some(15), Tier::Raised.cap(3));
+
+        assert_eq!(
+            Duration::seconds(2),
+            Tier::Raised.cooldown(Duration::seconds(10))
+        );
+        assert_eq!(
+            Duration::ZERO,
+            Tier::Unlimited.cooldown(Duration::seconds(10))
+        );
+    }
+}
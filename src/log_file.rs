@@ -0,0 +1,178 @@
+//! Logging to a file next to stdout, rotated daily or once it grows too large.
+//!
+//! The current log is `<directory>/<name>`, rotated logs are kept as `<name>.1` being the newest
+//! up to `<name>.<keep>`.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use serde::Deserialize;
+use time::{Date, OffsetDateTime};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogFileConfig {
+    pub directory: PathBuf,
+    #[serde(default = "default_name")]
+    pub name: String,
+    #[serde(default)]
+    pub rotation: Rotation,
+    /// How many rotated logs are kept.
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+}
+
+fn default_name() -> String {
+    "remindme.log".to_string()
+}
+
+fn default_keep() -> usize {
+    7
+}
+
+/// When the log file is rotated.
+#[derive(Debug, Copy, Clone, Deserialize, PartialEq, Eq)]
+pub enum Rotation {
+    /// On the first line logged on a new day (UTC).
+    Daily,
+    /// Before the file would grow beyond this many bytes.
+    Size(u64),
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Rotation::Daily
+    }
+}
+
+/// The log file, rotated as it is written to.
+#[derive(Debug)]
+pub struct RollingFile {
+    config: LogFileConfig,
+    file: File,
+    /// Bytes in the current file.
+    size: u64,
+    /// Day the current file was started on.
+    started: Date,
+}
+
+impl RollingFile {
+    pub fn open(config: LogFileConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.directory)?;
+
+        let path = config.directory.join(&config.name);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let metadata = file.metadata()?;
+        // a file left over from yesterday is rotated on the first write
+        let started = metadata
+            .modified()
+            .map(OffsetDateTime::from)
+            .unwrap_or_else(|_| OffsetDateTime::now_utc())
+            .date();
+
+        Ok(Self {
+            config,
+            file,
+            size: metadata.len(),
+            started,
+        })
+    }
+
+    fn path(&self, number: usize) -> PathBuf {
+        let name = match number {
+            0 => self.config.name.clone(),
+            number => format!("{}.{}", self.config.name, number),
+        };
+
+        self.config.directory.join(name)
+    }
+
+    /// Whether writing `len` more bytes on `today` starts a new file.
+    fn due(&self, len: usize, today: Date) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+
+        match self.config.rotation {
+            Rotation::Daily => today != self.started,
+            Rotation::Size(max) => self.size + len as u64 > max,
+        }
+    }
+
+    fn rotate(&mut self, today: Date) -> io::Result<()> {
+        self.file.flush()?;
+
+        if self.config.keep == 0 {
+            fs::remove_file(self.path(0))?;
+        } else {
+            for number in (0..self.config.keep).rev() {
+                let path = self.path(number);
+                if path.exists() {
+                    fs::rename(&path, self.path(number + 1))?;
+                }
+            }
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path(0))?;
+        self.size = 0;
+        self.started = today;
+
+        Ok(())
+    }
+}
+
+impl Write for RollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let today = OffsetDateTime::now_utc().date();
+        if self.due(buf.len(), today) {
+            self.rotate(today)?;
+        }
+
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn rotates_by_size_and_keeps_the_newest() {
+        let directory = env::temp_dir().join(format!("remindme-logs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        let mut file = RollingFile::open(LogFileConfig {
+            directory: directory.clone(),
+            name: default_name(),
+            rotation: Rotation::Size(10),
+            keep: 2,
+        })
+        .unwrap();
+
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        file.flush().unwrap();
+
+        let read = |name: &str| fs::read_to_string(directory.join(name)).unwrap();
+        assert_eq!("fourth\n", read("remindme.log"));
+        assert_eq!("third\n", read("remindme.log.1"));
+        assert_eq!("second\n", read("remindme.log.2"));
+        assert!(!directory.join("remindme.log.3").exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
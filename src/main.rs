@@ -1,332 +1,5678 @@
 #![feature(hash_drain_filter, iter_intersperse)]
+#![cfg_attr(test, feature(test))]
 #![warn(clippy::dbg_macro)]
 
-mod duration_parser;
+mod admin;
+mod admin_api;
+mod afk_store;
+mod analytics;
+mod archive;
+mod audit_log;
+mod backup;
+mod channel_settings;
+mod chat_route;
+mod chunker;
+mod client;
+mod clock;
+mod command_usage;
+mod commands;
+mod config;
+mod confirmation;
+mod connections;
+mod delivery;
+mod delivery_format;
+mod delivery_stats;
+mod display_names;
+mod filter_store;
+mod group_store;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(test)]
+mod harness;
+mod helix;
+mod history_store;
+mod humanize;
+mod id;
+mod joins;
+mod limits;
+mod log_file;
 mod message;
-mod message_parser;
+mod message_filter;
 mod message_store;
+mod metrics;
+mod parse_failures;
+mod pause;
+mod permissions;
+mod preset_store;
+mod proxy;
+mod quiet_hours;
+mod rate_limits;
+mod recent_messages;
+mod repeat_store;
+mod ron_store;
+mod sanitize;
+mod schedule_store;
+mod seen_store;
+mod send_queue;
+mod settings_store;
+mod snapshots;
+mod storage;
+mod store_cli;
+mod systemd;
+mod telemetry;
+mod template;
+mod timers;
+mod token;
+mod undo_buffer;
+mod watch_store;
 
-use std::{env, path::PathBuf, str::SplitWhitespace};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    env,
+    path::PathBuf,
+    str::SplitWhitespace,
+    sync::Arc,
+};
 
 use eyre::{ensure, eyre, Context, Result};
 use time::{Duration, OffsetDateTime};
-use tokio::time::sleep;
-use tracing::{debug, error, info, instrument, trace, trace_span, Instrument};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::{mpsc, Mutex},
+    time::sleep,
+};
+use tracing::{debug, error, info, instrument, trace, trace_span, warn, Instrument};
 use twitch_irc::{
     login::StaticLoginCredentials,
-    message::{PrivmsgMessage, ServerMessage},
-    ClientConfig, SecureTCPTransport, TwitchIRCClient,
+    message::{PrivmsgMessage, ServerMessage, UserNoticeEvent, UserNoticeMessage, WhisperMessage},
 };
+use twitch_remindme::{date_parser, duration_parser, message_parser, time_zone};
 
 use crate::{
-    message::{Activation, Message},
-    message_parser::MessageDefinition,
-    message_store::MessageStore,
+    admin::handle_admin_command,
+    afk_store::{AfkStatus, AfkStore},
+    analytics::ChannelUsage,
+    archive::Archive,
+    audit_log::{AuditKind, AuditLog},
+    channel_settings::{self, ChannelSettingsStore},
+    chat_route::Route,
+    chunker::preview,
+    client::Client,
+    command_usage::{CommandUsage, UNKNOWN},
+    commands::{Command, Cooldowns, Registry},
+    config::{Config, DeliveryStyle},
+    confirmation::{Action, Confirmations},
+    delivery_format::{format_deliveries, with_vod},
+    delivery_stats::{self, LatencySummary},
+    display_names::DisplayNames,
+    duration_parser::IntermediateDuration,
+    filter_store::FilterStore,
+    group_store::{self, GroupStore},
+    helix::{Helix, HelixChat, LiveChannels, Segment},
+    history_store::HistoryStore,
+    id::IdGenerator,
+    joins::Joins,
+    limits::Tier,
+    message::{Activation, Kind, Message, Origin},
+    message_filter::MessageFilter,
+    message_parser::{MessageDefinition, Quote, Schedule},
+    message_store::{self, MessageStore, SharedStore},
+    parse_failures::ParseFailures,
+    pause::Pause,
+    permissions::Role,
+    preset_store::PresetStore,
+    proxy::Proxy,
+    quiet_hours::QuietHours,
+    recent_messages::RecentMessages,
+    repeat_store::{RepeatStore, RepeatingTimer},
+    schedule_store::{ScheduleStore, ScheduleWatch},
+    seen_store::SeenStore,
+    send_queue::Priority as SendPriority,
+    settings_store::{self, AcceptFrom, Deferral, SettingsStore},
+    timers::Timers,
+    undo_buffer::UndoBuffer,
+    watch_store::{self, WatchStore},
 };
 
-type Client = TwitchIRCClient<SecureTCPTransport, StaticLoginCredentials>;
-
 const PREFIX: char = '~';
 
-async fn handle_cancel_command(
-    store: &mut MessageStore,
-    client: &Client,
-    privmsg: &PrivmsgMessage,
-    parts: &mut SplitWhitespace<'_>,
-) -> Result<()> {
-    if let Some(id) = parts.next() {
-        info!("Removing message with id {}", id);
+/// How long `~undo` can restore cancelled reminders.
+const UNDO_WINDOW: Duration = Duration::minutes(10);
 
-        if store.remove(&Message::from_id(id.to_string())) {
-            store.save().wrap_err("Error saving store")?;
-            client
-                .say_in_response(
-                    privmsg.channel_login.clone(),
-                    "Removed messsage".to_string(),
-                    Some(privmsg.channel_id.clone()),
+/// How long `~snoozeall` can defer reminders after they were delivered.
+const SNOOZE_WINDOW: Duration = Duration::minutes(10);
+
+/// How long destructive commands wait for a confirmation.
+const CONFIRM_WINDOW: Duration = Duration::minutes(1);
+
+/// How often sending a delivery is attempted before giving up.
+const SEND_ATTEMPTS: u32 = 4;
+
+/// Delay before the first retry of a failed send. Doubles with each attempt.
+const SEND_BACKOFF: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// How many edits away a seen login may be to be suggested for a recipient never seen in chat.
+const MAX_TYPO_DISTANCE: usize = 2;
+
+/// How long to wait for Twitch to confirm joins before retrying the missing channels.
+const JOIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How often joining a channel is attempted before giving up.
+const JOIN_ATTEMPTS: u32 = 3;
+
+/// How often Helix is asked which channels are live, to notice streams ending.
+const STREAM_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// How often the chatter lists of `presence_channels` are read.
+const CHATTERS_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// How many notes `~notes` shows at once.
+const NOTES_PER_PAGE: usize = 5;
+
+/// Bytes kept free on every page of `~list` for the page marker.
+const PAGE_MARKER_BYTES: usize = 20;
+
+/// How often the stream schedules of broadcasters are read.
+const SCHEDULE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15 * 60);
+
+/// How often repeating timers are checked.
+const REPEAT_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// How far back `~stats` looks for deliveries.
+const STATS_WINDOW: Duration = Duration::days(1);
+
+/// The windows `~analytics` summarizes, in days.
+const ANALYTICS_WINDOWS: [i64; 2] = [7, 30];
+
+/// How often expired reminders and audit log entries are removed.
+const MAINTENANCE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// How many maintenance runs in a row Helix has to miss the account of a recipient before their
+/// reminders are removed, so a single bad answer doesn't delete anything.
+const ORPHANED_AFTER_MISSES: u32 = 3;
+
+/// How often stored timed messages that are due soon are queued.
+const SCHEDULER_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+/// How far ahead stored timed messages are queued. Longer than [`SCHEDULER_INTERVAL`] so none is
+/// queued late.
+const SCHEDULER_HORIZON: Duration = Duration::minutes(30);
+
+/// How many timed messages the scheduler takes from the store while holding its lock.
+const SCHEDULER_PAGE_SIZE: usize = 500;
+
+/// How long before the bot started a timed reminder must have been due to count as missed while
+/// it was offline.
+const MISSED_AFTER: Duration = Duration::minutes(1);
+
+/// How often reminders are checked for a `deadline:` that passed.
+const DEADLINE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// How many times one `~tell` may schedule a reminder for.
+const MAX_SCHEDULES: usize = 5;
+
+/// How many members a recipient group may have.
+const MAX_GROUP_MEMBERS: usize = 50;
+
+/// How many presets a user may save.
+const MAX_PRESETS: usize = 25;
+
+/// How many words a duration in front of command arguments may span, e.g. `1 stunde 30 minuten`.
+const MAX_DURATION_WORDS: usize = 4;
+
+/// An error whose message is safe to show in chat.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub(crate) struct UserError(String);
+
+/// State owned by the irc message handler.
+pub(crate) struct State {
+    config: Config,
+    /// Login of the bot.
+    login: String,
+    /// Login chat is read as, see [`connections::open`].
+    read_login: String,
+    config_path: PathBuf,
+    channels: BTreeSet<String>,
+    store: SharedStore,
+    recent: RecentMessages,
+    seen: SeenStore,
+    display_names: DisplayNames,
+    afk: AfkStore,
+    filters: FilterStore,
+    groups: GroupStore,
+    presets: PresetStore,
+    watches: WatchStore,
+    audit: AuditLog,
+    undo: UndoBuffer,
+    history: HistoryStore,
+    /// Set if an archive is configured.
+    archive: Option<Archive>,
+    /// The reminders delivered to each user recently, for `~snoozeall`.
+    delivered: UndoBuffer,
+    confirmations: Confirmations,
+    timers: Timers,
+    joins: Joins,
+    repeats: RepeatStore,
+    schedules: ScheduleStore,
+    settings: SettingsStore,
+    channel_settings: ChannelSettingsStore,
+    /// Set if a Helix client id is configured.
+    helix: Option<Helix>,
+    /// Set if the user id of the bot could be looked up through Helix at startup.
+    helix_chat: Option<HelixChat>,
+    /// The channels streaming right now, only known with Helix.
+    live: LiveChannels,
+    commands: Registry,
+    cooldowns: Cooldowns,
+    /// When Twitch last sent anything, for `~status`.
+    last_server_message: Option<OffsetDateTime>,
+    pause: Pause,
+    parse_failures: ParseFailures,
+    /// Commands used in each channel since the start, for `~admin usage`.
+    usage: CommandUsage,
+    /// Follow-ups of delivered reminders, already stored, for the main loop to queue them.
+    follow_ups: mpsc::Sender<Message>,
+}
+
+impl State {
+    /// How deliveries in `channel` are worded, from the config and the settings of the channel.
+    fn delivery_style(&self, channel: &str) -> DeliveryStyle {
+        self.channel_settings
+            .get(channel)
+            .apply(self.config.delivery_style(channel))
+    }
+
+    /// How far the cooldowns apply to the sender of `privmsg` in its channel.
+    fn limit_tier(&self, privmsg: &PrivmsgMessage) -> Tier {
+        Tier::of(
+            Role::of(privmsg, &self.config),
+            &self.channel_settings.get(&privmsg.channel_login),
+        )
+    }
+
+    /// How far the caps apply to the sender of `privmsg`, see [`Tier::across_channels`].
+    fn cap_tier(&self, privmsg: &PrivmsgMessage) -> Tier {
+        Tier::across_channels(
+            Role::of(privmsg, &self.config),
+            &self.channel_settings.get(&privmsg.channel_login),
+        )
+    }
+
+    fn outbox(&self, client: &Client) -> Outbox {
+        Outbox {
+            store: self.store.clone(),
+            filters: self.filters.clone(),
+            audit: self.audit.clone(),
+            settings: self.settings.clone(),
+            display_names: self.display_names.clone(),
+            history: self.history.clone(),
+            archive: self.archive.clone(),
+            pause: self.pause.clone(),
+            client: client.clone(),
+            id_scheme: self.config.id_scheme,
+            follow_ups: self.follow_ups.clone(),
+            helix_chat: self.helix_chat.clone(),
+        }
+    }
+}
+
+/// The handles a scheduled delivery needs. Clones share their stores.
+#[derive(Clone)]
+struct Outbox {
+    store: SharedStore,
+    filters: FilterStore,
+    audit: AuditLog,
+    settings: SettingsStore,
+    display_names: DisplayNames,
+    history: HistoryStore,
+    archive: Option<Archive>,
+    pause: Pause,
+    client: Client,
+    id_scheme: IdGenerator,
+    follow_ups: mpsc::Sender<Message>,
+    helix_chat: Option<HelixChat>,
+}
+
+/// How the recipient of pending reminders showed up in a channel.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Presence {
+    /// They typed in chat.
+    Typed,
+    /// They joined the channel.
+    Joined,
+    /// They showed up in the chatter list.
+    Listed,
+}
+
+/// Users who showed up in the chatter list of a channel since the last poll.
+#[derive(Debug)]
+struct Arrivals {
+    channel: String,
+    logins: Vec<String>,
+}
+
+/// A segment of a watched stream schedule that should be reminded of soon.
+#[derive(Debug)]
+struct UpcomingSegment {
+    channel: String,
+    segment: Segment,
+    /// Ids of every upcoming segment of the schedule.
+    upcoming: Vec<String>,
+}
+
+/// Handle `~cancel <id>...`, removing every listed reminder the sender wrote or received.
+async fn handle_cancel_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let args = ctx.parts.clone().intersperse(" ").collect::<String>();
+    if args.contains(':') {
+        return handle_cancel_filter(ctx, &args).await;
+    }
+
+    let targets = ctx.parts.by_ref().collect::<Vec<_>>();
+    if targets.is_empty() {
+        return ctx.reply("Error: Missing id".to_string()).await;
+    }
+
+    let sender = &ctx.privmsg.sender.login;
+    let mut removed = Vec::new();
+    let mut missing = Vec::new();
+    let mut foreign = Vec::new();
+    let mut store = ctx.state.store.lock().await;
+    for arg in &targets {
+        let found = store
+            .resolve(sender, arg)
+            // the watched user can't see `~notify` watches, so they can't cancel them either
+            .filter(|message| message.kind() != Kind::Notification || message.author() == sender)
+            .map(|message| {
+                (
+                    message.id().to_string(),
+                    message.author() == sender || message.recipient() == sender,
                 )
-                .await
-                .wrap_err("Failed to send reply")?;
+            });
+
+        let id = match found {
+            None => {
+                missing.push(*arg);
+                continue;
+            }
+            Some((_, false)) => {
+                foreign.push(*arg);
+                continue;
+            }
+            Some((id, true)) => id,
+        };
+        info!("Removing message with id {}", id);
+
+        let message = store
+            .take(&id)
+            .ok_or_else(|| eyre!("Message vanished from store"))?;
+
+        // don't let reminders vanish without their author noticing
+        if message.recipient() == sender && message.author() != sender {
+            let notice = Message::new(
+                ctx.state
+                    .config
+                    .id_scheme
+                    .generate()
+                    .wrap_err("Failed to generate id")?,
+                Activation::OnNextMessage,
+                sender.clone(),
+                message.channel().to_string(),
+                message.author().to_string(),
+                format!(
+                    "declined your reminder [{}]: {}",
+                    message.id(),
+                    preview(message.text(), 30)
+                ),
+            );
+            info!("Notifying {} with {}", message.author(), notice.id());
+            store.insert(notice);
+        }
+        removed.push(message);
+    }
+
+    if !removed.is_empty() {
+        store.save().wrap_err("Error saving store")?;
+    }
+    // don't hold the lock while talking to chat
+    drop(store);
+
+    if !removed.is_empty() {
+        for message in &removed {
+            ctx.state
+                .audit
+                .record(AuditKind::Cancelled, message)
+                .wrap_err("Failed to write audit log")?;
+        }
+    }
+
+    let response = if targets.len() == 1 {
+        if !missing.is_empty() {
+            "There is no reminder with that id".to_string()
+        } else if !foreign.is_empty() {
+            "You can only cancel reminders you wrote or received".to_string()
         } else {
-            client
-                .say_in_response(
-                    privmsg.channel_login.clone(),
-                    "You do not have access to a reminder with that id".to_string(),
-                    Some(privmsg.channel_id.clone()),
-                )
-                .await
-                .wrap_err("Failed to send reply")?;
+            format!("Removed messsage, use {}undo to restore it", PREFIX)
         }
     } else {
-        client
-            .say_in_response(
-                privmsg.channel_login.clone(),
-                "Error: Missing id".to_string(),
-                Some(privmsg.channel_id.clone()),
-            )
+        let mut sentences = Vec::new();
+        if !removed.is_empty() {
+            sentences.push(format!(
+                "Removed {}, use {}undo to restore them",
+                format_num(removed.len(), "reminder", "reminders"),
+                PREFIX
+            ));
+        }
+        if !missing.is_empty() {
+            sentences.push(format!("Not found: {}", missing.join(", ")));
+        }
+        if !foreign.is_empty() {
+            sentences.push(format!("Not yours to cancel: {}", foreign.join(", ")));
+        }
+        sentences.join(". ")
+    };
+    if !removed.is_empty() {
+        ctx.state.undo.push(sender, removed);
+    }
+
+    ctx.reply(response).await
+}
+
+/// Handle `~cancel <filter>`, removing every reminder of the sender matching the filter.
+async fn handle_cancel_filter(ctx: &mut commands::Context<'_>, args: &str) -> Result<()> {
+    let filter = args.parse::<MessageFilter>().map_err(|err| {
+        let hint = err.to_string();
+        eyre::Report::new(err).wrap_err(UserError(hint))
+    })?;
+
+    let messages = {
+        let mut store = ctx.state.store.lock().await;
+        let ids = store
+            .query(&ctx.privmsg.sender.login, &filter)
+            .into_iter()
+            .map(|message| message.id().to_string())
+            .collect::<Vec<_>>();
+        let messages = ids
+            .iter()
+            .filter_map(|id| store.take(id))
+            .collect::<Vec<_>>();
+        if !messages.is_empty() {
+            store.save().wrap_err("Error saving store")?;
+        }
+
+        messages
+    };
+
+    if messages.is_empty() {
+        return ctx
+            .reply("None of your reminders match that filter".to_string())
+            .await;
+    }
+
+    info!("Removing {} messages matching {:?}", messages.len(), filter);
+    for message in &messages {
+        ctx.state
+            .audit
+            .record(AuditKind::Cancelled, message)
+            .wrap_err("Failed to write audit log")?;
+    }
+
+    let response = format!(
+        "Removed {}, use {}undo to restore them",
+        format_num(messages.len(), "reminder", "reminders"),
+        PREFIX
+    );
+    ctx.state.undo.push(&ctx.privmsg.sender.login, messages);
+
+    ctx.reply(response).await
+}
+
+/// Handle `~transfer <id> <user>`, giving a pending reminder the sender wrote to someone else. It
+/// keeps its id and schedule.
+async fn handle_transfer_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let usage = || eyre!(UserError(format!("Usage: {}transfer <id> <user>", PREFIX)));
+    let arg = ctx.parts.next().ok_or_else(usage)?;
+    let user = ctx.parts.next().ok_or_else(usage)?;
+    if ctx.parts.next().is_some() {
+        return Err(usage());
+    }
+
+    let author = ctx.privmsg.sender.login.clone();
+    let message = ctx
+        .state
+        .store
+        .lock()
+        .await
+        .resolve(&author, arg)
+        .cloned()
+        .ok_or_else(|| eyre!(UserError("There is no reminder with that id".to_string())))?;
+    if message.author() != author {
+        return Err(eyre!(UserError(
+            "You can only transfer reminders you wrote".to_string()
+        )));
+    }
+    if message.kind() != Kind::Reminder {
+        return Err(eyre!(UserError(
+            "Only reminders can be transferred".to_string()
+        )));
+    }
+
+    let name = user.trim_start_matches('@');
+    let recipient = if name.eq_ignore_ascii_case("me") {
+        author.clone()
+    } else if is_login(name) {
+        name.to_lowercase()
+    } else {
+        resolve_display_name(ctx.state, name)
             .await
-            .wrap_err("Failed to send reply")?;
+            .wrap_err("Failed to resolve display name")?
+            .ok_or_else(|| {
+                eyre!(UserError(format!(
+                    "I don't know who {} is, try their login instead",
+                    name
+                )))
+            })?
+    };
+    if is_bot_mention(&recipient, &ctx.state.login) {
+        return Err(eyre!(UserError(
+            "Nice try, but I never forget anything".to_string()
+        )));
+    }
+    if recipient == message.recipient() {
+        return Err(eyre!(UserError(format!(
+            "That reminder is already for {}",
+            recipient
+        ))));
     }
+    if !accepts_reminder(ctx.state, &recipient, ctx.privmsg).await {
+        return Err(eyre!(UserError(format!(
+            "{} can't receive your reminder",
+            recipient
+        ))));
+    }
+    check_pending_per_recipient(
+        ctx.state,
+        &author,
+        &HashSet::from([recipient.clone()]),
+        1,
+        ctx.cap_tier(),
+    )
+    .await?;
 
-    Ok(())
+    // a queued delivery picks up the new recipient from the store
+    let transferred = message.clone().with_recipient(recipient.clone());
+    {
+        let mut store = ctx.state.store.lock().await;
+        store.insert(transferred.clone());
+        store.save().wrap_err("Failed to save store")?;
+    }
+    info!(
+        "Transferred message {} from {} to {}",
+        message.id(),
+        message.recipient(),
+        recipient
+    );
+    ctx.state
+        .audit
+        .record(AuditKind::Transferred, &transferred)
+        .wrap_err("Failed to write audit log")?;
+
+    ctx.reply(format!(
+        "I'll remind {} instead of {} [{}]",
+        recipient,
+        message.recipient(),
+        message.id()
+    ))
+    .await
 }
-async fn handle_tell_command(
-    store: &mut MessageStore,
-    client: &Client,
-    privmsg: &PrivmsgMessage,
-    parts: &mut SplitWhitespace<'_>,
-) -> Result<()> {
-    let text = parts.intersperse(" ").collect::<String>();
 
-    if text.is_empty() {
-        return client
-            .say_in_response(
-                privmsg.channel_login.clone(),
-                "Error: Message is empty".to_string(),
-                Some(privmsg.channel_id.clone()),
+/// Handle `~history [page]`, showing the reminders delivered to the sender most recently again.
+/// Pages after the first are only kept in the archive.
+async fn handle_history_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let page = parse_page(ctx.parts.next())
+        .ok_or_else(|| eyre!(UserError(format!("Usage: {}history [page]", PREFIX))))?;
+
+    let login = &ctx.privmsg.sender.login;
+    let size = ctx.state.config.history_size;
+    let deliveries = match &ctx.state.archive {
+        Some(archive) => archive
+            .delivered_to(login)
+            .wrap_err("Failed to read archive")?
+            .into_iter()
+            .skip((page - 1) * size)
+            .take(size)
+            .map(|archived| (archived.delivered_at, archived.message))
+            .collect(),
+        None if page == 1 => ctx.state.history.recent(login),
+        None => {
+            return Err(eyre!(UserError(format!(
+                "I only keep your last {} reminders",
+                size
+            ))))
+        }
+    };
+    if deliveries.is_empty() {
+        let reply = if page == 1 {
+            "You got no reminders recently"
+        } else {
+            "There are no older reminders"
+        };
+        return ctx.reply(reply.to_string()).await;
+    }
+
+    let style = ctx.state.delivery_style(&ctx.privmsg.channel_login);
+    let now = OffsetDateTime::now_utc();
+    let text = deliveries
+        .iter()
+        .map(|(at, message)| {
+            format!(
+                "[{}] from {}, delivered {}: {}",
+                message.id(),
+                style.author(message.author()),
+                humanize::ago(now - *at, style.precision),
+                message.text()
             )
-            .await
-            .wrap_err("Failed to send reply");
+        })
+        .intersperse(" | ".to_string())
+        .collect::<String>();
+
+    for chunk in chunker::split(&text, style.max_message_bytes) {
+        ctx.reply(chunk).await?;
     }
 
-    let mut def = text
-        .parse::<MessageDefinition>()
-        .wrap_err("Failed to parse message")?;
+    Ok(())
+}
+
+/// Handle `~full <id>`, showing the complete text of a reminder delivered to the sender, which
+/// deliveries of several reminders cut short.
+async fn handle_full_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let id = ctx
+        .parts
+        .next()
+        .ok_or_else(|| eyre!(UserError(format!("Usage: {}full <id>", PREFIX))))?;
 
-    if def.recipients.remove("me") {
-        def.recipients.insert(privmsg.sender.login.clone());
+    let login = &ctx.privmsg.sender.login;
+    let message = match (ctx.state.history.get(login, id), &ctx.state.archive) {
+        (Some(message), _) => Some(message),
+        (None, Some(archive)) => archive
+            .delivered_to(login)
+            .wrap_err("Failed to read archive")?
+            .into_iter()
+            .find(|archived| archived.message.id() == id)
+            .map(|archived| archived.message),
+        (None, None) => None,
+    }
+    .ok_or_else(|| {
+        eyre!(UserError(format!(
+            "There is no reminder with id {} in your history, see {}history",
+            id, PREFIX
+        )))
+    })?;
+
+    let style = ctx.state.delivery_style(&ctx.privmsg.channel_login);
+    let text = format!(
+        "[{}] from {}: {}",
+        message.id(),
+        style.author(message.author()),
+        with_vod(
+            message.expanded_text(
+                OffsetDateTime::now_utc(),
+                style.precision,
+                ctx.state
+                    .settings
+                    .get(login)
+                    .utc_offset_minutes_at(message.created())
+            ),
+            &message
+        )
+    );
+    for chunk in chunker::split(&text, style.max_message_bytes) {
+        ctx.reply(chunk).await?;
     }
 
-    let messages = def.into_messages(&privmsg.sender.login, &privmsg.channel_login);
+    Ok(())
+}
 
-    let response;
+/// Handle `~redeliver <id> [whisper]`, sending a reminder from the history of the sender again,
+/// in chat or as a whisper.
+async fn handle_redeliver_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let usage = || {
+        eyre!(UserError(format!(
+            "Usage: {}redeliver <id> [whisper]",
+            PREFIX
+        )))
+    };
+    let id = ctx.parts.next().ok_or_else(usage)?;
+    let whisper = match ctx.parts.next() {
+        None => false,
+        Some("whisper") => true,
+        Some(_) => return Err(usage()),
+    };
 
-    // TODO: adapt for scheduled messages
-    if messages.len() == 1 {
-        let message = messages.first().unwrap();
+    if whisper && ctx.state.helix_chat.is_none() {
+        return Err(eyre!(UserError(
+            "I can't whisper here, leave out whisper to get it in chat".to_string()
+        )));
+    }
 
-        if message.recipient() == privmsg.sender.login {
-            response = format!(
-                "I'll remind you the next time you type in chat [{}]",
-                message.id()
-            )
+    let login = &ctx.privmsg.sender.login;
+    let message = ctx.state.history.get(login, id).ok_or_else(|| {
+        eyre!(UserError(format!(
+            "There is no reminder with id {} in your history, see {}history",
+            id, PREFIX
+        )))
+    })?;
+    info!("Redelivering message {}", id);
+
+    let mut style = ctx.state.delivery_style(&ctx.privmsg.channel_login);
+    let heading = if whisper {
+        // nobody else sees whispers, so there is nobody to keep from being pinged
+        style.anti_ping = false;
+        "Your reminder".to_string()
+    } else {
+        format!("@{} your reminder", ctx.privmsg.sender.name)
+    };
+    let text = format_deliveries(
+        &[&message],
+        style,
+        ctx.state
+            .settings
+            .get(login)
+            .utc_offset_minutes_at(message.created()),
+    );
+
+    let prefix = format!("{} ", heading);
+    let budget = style.max_message_bytes.saturating_sub(prefix.len());
+    for chunk in chunker::split(&text, budget) {
+        let text = format!("{}{}", prefix, chunk);
+        if whisper {
+            self::whisper(ctx.state.helix_chat.as_ref(), login, &text)
+                .await
+                .wrap_err("Failed to send whisper")?;
         } else {
-            response = format!(
-                "I'll remind {} when they next type in chat [{}]",
-                message.recipient(),
-                message.id()
-            )
+            ctx.reply(text).await?;
         }
-    } else {
-        response = format!(
-            "I'll remind {} next time they type in chat",
-            messages
-                .iter()
-                .map(|message| format!("{} [{}]", message.recipient(), message.id()))
-                .intersperse(", ".to_string())
-                .collect::<String>()
-        )
     }
 
-    let ids = messages
-        .iter()
-        .map(|message| message.id())
-        .intersperse(", ")
-        .collect::<String>();
-    info!("Inserting messages with ids: {}", ids);
+    Ok(())
+}
+
+/// Handle `~snoozeall <duration>`, delivering the reminders the sender got in the last minutes,
+/// or is about to get, again after `duration`.
+async fn handle_snoozeall_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let usage = || eyre!(UserError(format!("Usage: {}snoozeall <duration>", PREFIX)));
+    let duration = ctx.parts.by_ref().intersperse(" ").collect::<String>();
+    if duration.is_empty() {
+        return Err(usage());
+    }
+    let language = ctx
+        .state
+        .channel_settings
+        .get(&ctx.privmsg.channel_login)
+        .language
+        .unwrap_or_default();
+    let duration: Duration =
+        IntermediateDuration::parse_localized(&duration.to_lowercase(), language)
+            .map_err(|_| usage())?
+            .into();
+    check_schedule_soon(&ctx.state.config, duration)?;
+    check_schedule_ahead(&ctx.state.config, duration)?;
+
+    let login = &ctx.privmsg.sender.login;
+    let at = OffsetDateTime::now_utc() + duration;
+    let messages = ctx
+        .state
+        .delivered
+        .pop(login)
+        .ok_or_else(|| eyre!(UserError("You got no reminders recently".to_string())))?
+        .into_iter()
+        .map(|message| message.with_activation(Activation::Fixed(at)))
+        .collect::<Vec<_>>();
+    info!(
+        "Snoozing messages: {}",
+        messages
+            .iter()
+            .map(|message| message.id())
+            .intersperse(", ")
+            .collect::<String>()
+    );
+
+    {
+        let mut store = ctx.state.store.lock().await;
+        for message in &messages {
+            // the ones about to be delivered are still in the store
+            store.remove(message);
+            store.insert(message.clone());
+        }
+        store.save().wrap_err("Failed to save store")?;
+    }
+
+    for message in &messages {
+        // a delivery that is already sending it leaves the snoozed one in the store and queues
+        // it when done, the others would be released by the delivery that won't happen now
+        if !ctx.state.timers.is_sending(message.id()) {
+            ctx.state.timers.finish(message.id());
+        }
+        ctx.state
+            .audit
+            .record(AuditKind::Snoozed, message)
+            .wrap_err("Failed to write audit log")?;
+    }
+    queue_messages(ctx.state, ctx.client, &messages).await;
+
+    ctx.reply(format!(
+        "I'll remind you of {} again {}",
+        format_num(messages.len(), "reminder", "reminders"),
+        humanize::until(duration)
+    ))
+    .await
+}
+
+/// Handle `~defer <duration> [new]`, moving every timed reminder waiting for the sender back by
+/// `duration` and holding the ones waiting for them to type until then. With `new` reminders
+/// written in the meantime wait too. `~defer off` delivers them as usual again.
+async fn handle_defer_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let usage = || {
+        eyre!(UserError(format!(
+            "Usage: {}defer <duration> [new] or {}defer off",
+            PREFIX, PREFIX
+        )))
+    };
+    let login = ctx.privmsg.sender.login.clone();
+    let mut args = ctx.parts.by_ref().collect::<Vec<_>>();
+
+    if let [arg] = args.as_slice() {
+        if arg.eq_ignore_ascii_case("off") {
+            let deferred = ctx.state.settings.get(&login).deferral.is_some();
+            ctx.state
+                .settings
+                .update(&login, |settings| settings.deferral = None);
+            ctx.state
+                .settings
+                .save()
+                .wrap_err("Failed to save settings store")?;
+
+            return ctx
+                .reply(
+                    if deferred {
+                        "I'll deliver your reminders as usual again"
+                    } else {
+                        "You didn't defer your reminders"
+                    }
+                    .to_string(),
+                )
+                .await;
+        }
+    }
+
+    let new = args
+        .last()
+        .map_or(false, |arg| arg.eq_ignore_ascii_case("new"));
+    if new {
+        args.pop();
+    }
+    if args.is_empty() {
+        return Err(usage());
+    }
+    let language = ctx
+        .state
+        .channel_settings
+        .get(&ctx.privmsg.channel_login)
+        .language
+        .unwrap_or_default();
+    let duration: Duration =
+        IntermediateDuration::parse_localized(&args.join(" ").to_lowercase(), language)
+            .map_err(|_| usage())?
+            .into();
+    if duration <= Duration::ZERO {
+        return Err(usage());
+    }
+    check_schedule_ahead(&ctx.state.config, duration)?;
+
+    let now = OffsetDateTime::now_utc();
+    let moved = {
+        let mut store = ctx.state.store.lock().await;
+
+        // the ones this very message was about to deliver wait as well
+        let (claimed, delivered): (Vec<Message>, Vec<Message>) = ctx
+            .state
+            .delivered
+            .pop(&login)
+            .unwrap_or_default()
+            .into_iter()
+            .partition(|message| store.get_by_id(message.id()).is_some());
+        ctx.state.delivered.push(&login, delivered);
+        for message in &claimed {
+            store.remove(message);
+            store.insert(message.clone());
+            ctx.state.timers.finish(message.id());
+        }
+
+        let moved = store
+            .get_by_recipient(&login)
+            .into_iter()
+            .filter(|message| message.kind() == Kind::Reminder)
+            .filter_map(|message| match message.activation() {
+                Activation::Fixed(at) => Some(
+                    message
+                        .clone()
+                        .with_activation(Activation::Fixed(*at + duration)),
+                ),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // queued deliveries notice the new time and leave them to the scheduler
+        for message in &moved {
+            store.remove(message);
+            store.insert(message.clone());
+        }
+        store.save().wrap_err("Failed to save store")?;
+
+        moved.len()
+    };
+    info!("Deferred {} timed messages of {}", moved, login);
+
+    ctx.state.settings.update(&login, |settings| {
+        settings.deferral = Some(Deferral {
+            since: now,
+            until: now + duration,
+            new,
+        })
+    });
+    ctx.state
+        .settings
+        .save()
+        .wrap_err("Failed to save settings store")?;
+
+    let mut response = format!(
+        "I'll hold your reminders for the next {}",
+        humanize::span(duration)
+    );
+    if new {
+        response.push_str(", including the ones you get in the meantime");
+    }
+    if moved > 0 {
+        response.push_str(&format!(
+            ", {} moved back by as much",
+            format_num(moved, "timed reminder", "timed reminders")
+        ));
+    }
+
+    ctx.reply(response).await
+}
 
+/// Queue the delivery of the scheduled ones among `messages`.
+async fn queue_messages(state: &State, client: &Client, messages: &[Message]) {
     for message in messages {
         if message.activation() != &Activation::OnNextMessage {
-            // queue scheduled messages
-            spawn_queue_message_task(store.clone(), client.clone(), message.clone()).await;
+            spawn_queue_message_task(
+                state.outbox(client),
+                state.timers.clone(),
+                state.config.quiet_hours.get(message.channel()).copied(),
+                state.delivery_style(message.channel()),
+                message.clone(),
+            )
+            .await;
+        }
+    }
+}
+
+/// Restore the reminders the sender removed most recently.
+async fn handle_undo_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let messages = match ctx.state.undo.pop(&ctx.privmsg.sender.login) {
+        Some(messages) => messages,
+        None => return Err(eyre!(UserError("There is nothing to undo".to_string()))),
+    };
+
+    info!(
+        "Restoring messages: {}",
+        messages
+            .iter()
+            .map(|message| message.id())
+            .intersperse(", ")
+            .collect::<String>()
+    );
+
+    {
+        let mut store = ctx.state.store.lock().await;
+        for message in &messages {
+            store.insert(message.clone());
+        }
+        store.save().wrap_err("Failed to save store")?;
+    }
+
+    for message in &messages {
+        ctx.state
+            .audit
+            .record(AuditKind::Restored, message)
+            .wrap_err("Failed to write audit log")?;
+    }
+    queue_messages(ctx.state, ctx.client, &messages).await;
+
+    let count = messages.len();
+
+    ctx.reply(format!(
+        "Restored {}",
+        format_num(count, "reminder", "reminders")
+    ))
+    .await
+}
+
+/// Handle `~cancelall` and `~forgetme`. They only run when invoked with `confirm` or confirmed
+/// with `~yes` afterwards.
+async fn handle_destructive_command(ctx: &mut commands::Context<'_>, action: Action) -> Result<()> {
+    let login = &ctx.privmsg.sender.login;
+
+    let response = if ctx.parts.next() == Some("confirm") {
+        run_action(ctx.state, login, action).await?
+    } else {
+        let what = match action {
+            Action::CancelAll => {
+                let count = ctx
+                    .state
+                    .store
+                    .lock()
+                    .await
+                    .get_by_author(login)
+                    .into_iter()
+                    .filter(|message| message.kind() != Kind::Note)
+                    .count();
+                if count == 0 {
+                    return ctx
+                        .reply("You have no reminders to cancel".to_string())
+                        .await;
+                }
+
+                format!(
+                    "This removes {} you wrote",
+                    format_num(count, "reminder", "reminders")
+                )
+            }
+            Action::ForgetMe => {
+                "This removes all your reminders and everything else I know about you".to_string()
+            }
+        };
+        ctx.state.confirmations.request(login, action);
+
+        format!(
+            "{}. Type {}yes within a minute to confirm or {}no to abort",
+            what, PREFIX, PREFIX
+        )
+    };
+
+    ctx.reply(response).await
+}
+
+/// Handle `~yes` and `~no` answering a pending confirmation.
+async fn handle_confirmation_command(
+    ctx: &mut commands::Context<'_>,
+    confirmed: bool,
+) -> Result<()> {
+    let login = &ctx.privmsg.sender.login;
+
+    let response = match (confirmed, ctx.state.confirmations.confirm(login)) {
+        (_, None) => return Err(eyre!(UserError("There is nothing to confirm".to_string()))),
+        (true, Some(action)) => run_action(ctx.state, login, action).await?,
+        (false, Some(_)) => "Okay, nothing was changed".to_string(),
+    };
+
+    ctx.reply(response).await
+}
+
+/// Execute a confirmed destructive `action` for `login` and describe the outcome.
+async fn run_action(state: &mut State, login: &str, action: Action) -> Result<String> {
+    let mut store = state.store.lock().await;
+
+    match action {
+        Action::CancelAll => {
+            // notes aren't reminders, `~notes delete` removes them
+            let ids = store
+                .get_by_author(login)
+                .into_iter()
+                .filter(|message| message.kind() != Kind::Note)
+                .map(|message| message.id().to_string())
+                .collect::<Vec<_>>();
+            let messages = ids
+                .iter()
+                .filter_map(|id| store.take(id))
+                .collect::<Vec<_>>();
+            // an empty undo entry would replace the one of an earlier `~cancel`
+            if messages.is_empty() {
+                return Ok("You have no reminders to cancel".to_string());
+            }
+            store.save().wrap_err("Failed to save store")?;
+            for message in &messages {
+                state
+                    .audit
+                    .record(AuditKind::Cancelled, message)
+                    .wrap_err("Failed to write audit log")?;
+            }
+            info!("Removed {} messages of {}", messages.len(), login);
+
+            let response = format!(
+                "Removed {}, use {}undo to restore them",
+                format_num(messages.len(), "reminder", "reminders"),
+                PREFIX
+            );
+            state.undo.push(login, messages);
+
+            Ok(response)
+        }
+        Action::ForgetMe => {
+            let messages = store.remove_user(login);
+            store.save().wrap_err("Failed to save store")?;
+            for message in &messages {
+                state
+                    .audit
+                    .record(AuditKind::Cancelled, message)
+                    .wrap_err("Failed to write audit log")?;
+            }
+
+            state.seen.forget(login);
+            state.seen.save().wrap_err("Failed to save seen store")?;
+            state.display_names.forget(login);
+            state
+                .display_names
+                .save()
+                .wrap_err("Failed to save display name store")?;
+            if state.afk.pop(login).is_some() {
+                state.afk.save().wrap_err("Failed to save afk store")?;
+            }
+            state.watches.forget(login);
+            state
+                .watches
+                .save()
+                .wrap_err("Failed to save watch store")?;
+            state.settings.forget(login);
+            state
+                .settings
+                .save()
+                .wrap_err("Failed to save settings store")?;
+            state.recent.forget(login);
+            state.groups.forget(login);
+            state.groups.save().wrap_err("Failed to save group store")?;
+            state.presets.forget(login);
+            state
+                .presets
+                .save()
+                .wrap_err("Failed to save preset store")?;
+            state.history.forget(login);
+            state
+                .history
+                .save()
+                .wrap_err("Failed to save history store")?;
+            if let Some(archive) = &state.archive {
+                archive.forget(login).wrap_err("Failed to clean archive")?;
+            }
+            state.undo.push(login, Vec::new());
+            info!("Forgot {}", login);
+
+            Ok(match messages.len() {
+                0 => "I forgot everything about you".to_string(),
+                removed => format!(
+                    "I forgot everything about you, including {}",
+                    format_num(removed, "reminder", "reminders")
+                ),
+            })
+        }
+    }
+}
+
+/// Refuse to schedule sooner than `min_schedule_seconds` ahead, anything sooner races the
+/// confirmation.
+fn check_schedule_soon(config: &Config, ahead: Duration) -> Result<()> {
+    let min = Duration::seconds(config.min_schedule_seconds);
+    if ahead <= Duration::ZERO || ahead < min {
+        return Err(eyre!(UserError(format!(
+            "I can't schedule anything sooner than {} ahead",
+            humanize::span(min)
+        ))));
+    }
+
+    Ok(())
+}
+
+/// Refuse to schedule further than `max_schedule_days` ahead.
+fn check_schedule_ahead(config: &Config, ahead: Duration) -> Result<()> {
+    let max = Duration::days(config.max_schedule_days);
+    if ahead > max {
+        return Err(eyre!(UserError(format!(
+            "I can only schedule up to {} ahead",
+            humanize::span(max)
+        ))));
+    }
+
+    Ok(())
+}
+
+/// Take the duration `parts` start with, spelled in the language of `channel` like in `~tell`.
+/// It may span several words, like `5 minuten`.
+fn next_duration(
+    state: &State,
+    channel: &str,
+    parts: &mut SplitWhitespace<'_>,
+) -> Option<Duration> {
+    let language = state
+        .channel_settings
+        .get(channel)
+        .language
+        .unwrap_or_default();
+    let words = parts.clone().take(MAX_DURATION_WORDS).collect::<Vec<_>>();
+
+    // the longest match, so `5 minuten` isn't read as `5` followed by text
+    (1..=words.len()).rev().find_map(|count| {
+        let duration = IntermediateDuration::parse_localized(
+            &words[..count].join(" ").to_lowercase(),
+            language,
+        )
+        .ok()?;
+        parts.nth(count - 1);
+
+        Some(duration.into())
+    })
+}
+
+/// Find the chat line a reminder should quote, formatted for embedding into its text.
+fn resolve_quote(
+    def: &MessageDefinition,
+    recent: &RecentMessages,
+    privmsg: &PrivmsgMessage,
+) -> Result<Option<String>> {
+    let channel = &privmsg.channel_login;
+
+    let (user, line) = match &def.quote {
+        Some(Quote::Last) => match recent.last_except(channel, &privmsg.sender.login) {
+            Some((user, line)) => (user.to_string(), line),
+            None => {
+                return Err(eyre!(UserError(
+                    "I haven't seen anything to quote here yet".to_string()
+                )))
+            }
+        },
+        Some(Quote::User(user)) => (
+            user.clone(),
+            recent.last_from(channel, user).unwrap_or_default(),
+        ),
+        None if def.text.split_whitespace().any(|word| word == "^") => {
+            if def.recipients.len() != 1 {
+                return Err(eyre!(UserError(
+                    "Use quote:<user> to choose whose message to quote".to_string()
+                )));
+            }
+
+            let user = def.recipients.iter().next().unwrap();
+            (
+                user.clone(),
+                recent.last_from(channel, user).unwrap_or_default(),
+            )
+        }
+        None => return Ok(None),
+    };
+
+    if line.is_empty() {
+        return Err(eyre!(UserError(format!(
+            "I haven't seen {} say anything here recently",
+            user
+        ))));
+    }
+
+    Ok(Some(format!("{}: \"{}\"", user, line)))
+}
+
+async fn handle_tell_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let response =
+        create_reminders(ctx.state, ctx.client, ctx.privmsg, &mut ctx.parts, false).await?;
+
+    ctx.reply(response).await
+}
+
+/// Create the reminders `parts` of a `~tell` describe and return the confirmation for the author.
+/// Reminders `whispered` to the bot don't say where they were written.
+async fn create_reminders(
+    state: &mut State,
+    client: &Client,
+    privmsg: &PrivmsgMessage,
+    parts: &mut SplitWhitespace<'_>,
+    whispered: bool,
+) -> Result<String> {
+    let Reminders {
+        messages,
+        rejected,
+        schedules,
+        chat,
+        deadline,
+        channel,
+        live,
+        at,
+        ..
+    } = prepare_reminders(state, privmsg, parts, whispered, false).await?;
+
+    let mut response;
+
+    let now = OffsetDateTime::now_utc();
+    let due = |message: &Message| match message.activation() {
+        Activation::Fixed(at) => {
+            // show the time in the zone it was given in, so changes to daylight saving time
+            // are visible
+            let at_text = match message.time_zone().and_then(time_zone::Zone::named) {
+                Some(zone) => format_local_timestamp(*at, Some(zone.offset_minutes_at(*at))),
+                None => format_timestamp(*at, now),
+            };
+            Some(format!("{}, at {}", humanize::until(*at - now), at_text))
+        }
+        _ => None,
+    };
+    let recipient = |message: &Message| {
+        if message.recipient() == privmsg.sender.login {
+            "you".to_string()
+        } else {
+            message.recipient().to_string()
+        }
+    };
+
+    let trigger = match messages.first().map(Message::activation) {
+        Some(Activation::OnRaid) => Some(format!("when {} gets raided", channel)),
+        Some(Activation::OnOffline) => Some(format!("when {} goes offline", channel)),
+        Some(Activation::Fixed(_)) => messages.first().and_then(due),
+        _ => None,
+    };
+    if schedules > 1 {
+        let mut sorted = messages.iter().collect::<Vec<_>>();
+        sorted.sort_by_key(|message| (message.recipient(), message.due()));
+        response = format!(
+            "I'll remind {}",
+            sorted
+                .into_iter()
+                .map(|message| format!(
+                    "{} [{}] {}",
+                    recipient(message),
+                    message.id(),
+                    due(message).unwrap_or_default()
+                ))
+                .intersperse("; ".to_string())
+                .collect::<String>()
+        )
+    } else if let Some(trigger) = trigger {
+        response = format!(
+            "I'll remind {} {}",
+            messages
+                .iter()
+                .map(|message| format!("{} [{}]", recipient(message), message.id()))
+                .intersperse(", ".to_string())
+                .collect::<String>(),
+            trigger
+        )
+    } else if chat {
+        // listing every id would flood the channel
+        response = format!(
+            "I'll remind {} chatters next time they type in chat",
+            messages.len()
+        )
+    } else if messages.len() == 1 {
+        let message = messages.first().unwrap();
+
+        if message.recipient() == privmsg.sender.login {
+            response = format!(
+                "I'll remind you the next time you type in chat [{}]",
+                message.id()
+            )
+        } else {
+            response = format!(
+                "I'll remind {} when they next type in chat [{}]",
+                message.recipient(),
+                message.id()
+            )
+        }
+    } else {
+        response = format!(
+            "I'll remind {} next time they type in chat",
+            messages
+                .iter()
+                .map(|message| format!("{} [{}]", message.recipient(), message.id()))
+                .intersperse(", ".to_string())
+                .collect::<String>()
+        )
+    }
+
+    if !rejected.is_empty() {
+        response = format!("{}, but {} can't receive it", response, rejected.join(", "));
+    }
+
+    if let Some(follow_up) = messages.first().and_then(Message::follow_up) {
+        response = format!(
+            "{}, then again {} after that",
+            response,
+            humanize::span(follow_up.after)
+        );
+    }
+
+    if let Some(deadline) = deadline {
+        response = format!(
+            "{}. I'll tell you if it wasn't delivered {}",
+            response,
+            humanize::until(deadline)
+        );
+    }
+
+    // most likely a typo, the reminder would never be delivered
+    let mut unseen = messages
+        .iter()
+        .map(Message::recipient)
+        .filter(|recipient| state.seen.last_seen(recipient).is_none())
+        .collect::<Vec<_>>();
+    unseen.sort_unstable();
+    for recipient in unseen {
+        let hint = match state.seen.closest(recipient, MAX_TYPO_DISTANCE) {
+            Some(suggestion) => format!("did you mean {}?", suggestion),
+            None => "typo?".to_string(),
+        };
+        response = format!(
+            "{} (I've never seen {} in chat, {})",
+            response, recipient, hint
+        );
+    }
+
+    spawn_display_name_lookup(state, &messages);
+
+    let ids = messages
+        .iter()
+        .map(|message| message.id())
+        .intersperse(", ")
+        .collect::<String>();
+    info!("Inserting messages with ids: {}", ids);
+
+    {
+        let mut store = state.store.lock().await;
+        for message in &messages {
+            store.insert(message.clone());
+        }
+        store.save().wrap_err("Failed to save store")?;
+    }
+
+    // insert before queuing so the timers find the messages in the store
+    queue_messages(state, client, &messages).await;
+    if live {
+        spawn_vod_lookup(state, &messages, &privmsg.channel_id, at);
+    }
+
+    Ok(response)
+}
+
+/// The reminders a `~tell` describes, checked but not stored yet.
+struct Reminders {
+    /// One per recipient and schedule, recipient by recipient.
+    messages: Vec<Message>,
+    /// Recipients who don't accept reminders from the author, sorted.
+    rejected: Vec<String>,
+    schedules: usize,
+    /// Whether everyone who chatted recently is reminded.
+    chat: bool,
+    deadline: Option<Duration>,
+    /// Where the reminders are delivered.
+    channel: String,
+    /// Whether the channel was given with `channel:`.
+    explicit_channel: bool,
+    /// Whether the reminders were written during a stream.
+    live: bool,
+    /// When the reminders were written.
+    at: OffsetDateTime,
+}
+
+/// Parse and check the reminders `parts` of a `~tell` describe, like [`create_reminders`] does
+/// before storing them. Failures of a `dry_run` aren't recorded as parse failures.
+async fn prepare_reminders(
+    state: &mut State,
+    privmsg: &PrivmsgMessage,
+    parts: &mut SplitWhitespace<'_>,
+    whispered: bool,
+    dry_run: bool,
+) -> Result<Reminders> {
+    let text = sanitize::sanitize(&parts.intersperse(" ").collect::<String>());
+
+    if text.is_empty() {
+        return Err(eyre!(UserError("Message is empty".to_string())));
+    }
+
+    let zone = state.settings.get(&privmsg.sender.login).zone();
+    let language = state
+        .channel_settings
+        .get(&privmsg.channel_login)
+        .language
+        .unwrap_or_default();
+    let mut def = MessageDefinition::parse_localized(&text, &zone, language).map_err(|err| {
+        // mistakes in previews aren't counted, trying things out is the point
+        if !dry_run {
+            state
+                .parse_failures
+                .record(&err, &text, state.config.log_parse_failures);
+        }
+        let hint = err.hint(&text);
+        eyre::Report::new(err).wrap_err(UserError(hint))
+    })?;
+
+    let schedules = def.schedules().count();
+    if schedules > MAX_SCHEDULES {
+        return Err(eyre!(UserError(format!(
+            "A reminder can be scheduled for at most {} times",
+            MAX_SCHEDULES
+        ))));
+    }
+
+    for schedule in def.schedules() {
+        let ahead = match schedule {
+            Schedule::Relative(duration) => *duration,
+            Schedule::Fixed(at) => *at - OffsetDateTime::now_utc(),
+            _ => continue,
+        };
+        // anything sooner races the confirmation and is better served by the next chat line
+        let min = Duration::seconds(state.config.min_schedule_seconds);
+        if ahead < min {
+            return Err(eyre!(UserError(format!(
+                "Timed reminders have to be at least {} ahead, leave out the time to remind \
+                 them when they next type in chat instead",
+                humanize::span(min)
+            ))));
+        }
+        check_schedule_ahead(&state.config, ahead)?;
+    }
+
+    if def.recipients.remove("me") {
+        def.recipients.insert(privmsg.sender.login.clone());
+    }
+
+    // expanded before the checks below so every member can opt out on their own
+    for recipient in def.recipients.clone() {
+        if let Some(members) = state.groups.resolve(
+            &privmsg.sender.login,
+            &privmsg.channel_login,
+            recipient.trim_start_matches('@'),
+        ) {
+            def.recipients.remove(&recipient);
+            def.recipients.extend(members.iter().cloned());
+        }
+    }
+
+    for recipient in def.recipients.clone() {
+        if is_login(recipient.trim_start_matches('@')) {
+            continue;
+        }
+
+        let login = resolve_display_name(state, recipient.trim_start_matches('@'))
+            .await
+            .wrap_err("Failed to resolve display name")?
+            .ok_or_else(|| {
+                eyre!(UserError(format!(
+                    "I don't know who {} is, try their login instead",
+                    recipient
+                )))
+            })?;
+        def.recipients.remove(&recipient);
+        def.recipients.insert(login);
+    }
+
+    let bot = state.login.as_str();
+    if def
+        .recipients
+        .iter()
+        .any(|recipient| is_bot_mention(recipient, bot))
+    {
+        def.recipients
+            .retain(|recipient| !is_bot_mention(recipient, bot));
+        if def.recipients.is_empty() {
+            return Err(eyre!(UserError(
+                "Nice try, but I never forget anything".to_string()
+            )));
+        }
+    }
+
+    if def.chat {
+        if Role::of(privmsg, &state.config) < Role::Moderator {
+            return Err(eyre!(UserError(
+                "Only moderators can remind everyone in chat".to_string()
+            )));
+        }
+        if def.schedule != Schedule::None {
+            return Err(eyre!(UserError(
+                "cc:chat can only remind everyone when they next type in chat".to_string()
+            )));
+        }
+
+        let since =
+            OffsetDateTime::now_utc() - Duration::minutes(state.config.chat_broadcast_minutes);
+        let chatters = state
+            .seen
+            .seen_since(&privmsg.channel_login, since)
+            .into_iter()
+            .filter(|login| *login != privmsg.sender.login && !state.config.is_ignored(login))
+            .collect::<Vec<_>>();
+        def.recipients.extend(chatters);
+        // an announcement for this stream is of no use elsewhere
+        def.here.get_or_insert(true);
+    }
+
+    if def.deadline.is_some() && matches!(def.schedule, Schedule::Relative(_) | Schedule::Fixed(_))
+    {
+        return Err(eyre!(UserError(
+            "deadline: only works for reminders delivered when the recipient types in chat"
+                .to_string()
+        )));
+    }
+    let deadline = def.deadline;
+
+    // the author is only told the reminder can't be delivered, not why
+    let mut rejected = Vec::new();
+    for recipient in &def.recipients {
+        if !accepts_reminder(state, recipient, privmsg).await {
+            rejected.push(recipient.clone());
+        }
+    }
+    rejected.sort();
+    def.recipients
+        .retain(|recipient| !rejected.contains(recipient));
+    if def.recipients.is_empty() {
+        return Err(eyre!(UserError(format!(
+            "{} can't receive your reminder",
+            rejected.join(", ")
+        ))));
+    }
+    let tier = state.cap_tier(privmsg);
+    check_pending_per_recipient(
+        state,
+        &privmsg.sender.login,
+        &def.recipients,
+        schedules,
+        tier,
+    )
+    .await?;
+    check_timed_per_user(
+        state,
+        &privmsg.sender.login,
+        def.schedules()
+            .filter(|schedule| schedule.is_timed())
+            .count()
+            * def.recipients.len(),
+        tier,
+    )
+    .await?;
+
+    if let Some(quote) = resolve_quote(&def, &state.recent, privmsg)? {
+        def.text = if def.text.split_whitespace().any(|word| word == "^") {
+            def.text
+                .split(' ')
+                .map(|word| if word == "^" { quote.as_str() } else { word })
+                .intersperse(" ")
+                .collect()
+        } else {
+            format!("{} {}", def.text, quote)
+        };
+    }
+
+    // reminders for another channel are only delivered there unless `here:` says otherwise
+    let explicit_channel = def.channel.is_some();
+    let channel = match def.channel.take() {
+        Some(channel) if !state.channels.contains(&channel) => {
+            return Err(eyre!(UserError(format!(
+                "I can't deliver in #{} because I'm not in that channel",
+                channel
+            ))))
+        }
+        Some(channel) => channel,
+        None => privmsg.channel_login.clone(),
+    };
+
+    if state.filters.find_match(&channel, &def.text).is_some() {
+        return Err(eyre!(UserError(format!(
+            "Your reminder contains a phrase that is filtered in #{}",
+            channel
+        ))));
+    }
+
+    let scoped = explicit_channel
+        || delivery::scoped_by_default(
+            &state.config,
+            &state.channel_settings.get(&channel),
+            &channel,
+        );
+    def.here.get_or_insert(scoped);
+
+    let chat = def.chat;
+    let at = OffsetDateTime::from_unix_timestamp(privmsg.server_timestamp.timestamp())
+        .unwrap_or_else(|_| OffsetDateTime::now_utc());
+    let live = !whispered && state.live.read().unwrap().contains(&privmsg.channel_login);
+    // the link to the stream is added once it's known, see `spawn_vod_lookup`
+    let origin = (!whispered).then(|| Origin {
+        channel: privmsg.channel_login.clone(),
+        message_id: privmsg.message_id.clone(),
+        at,
+        live,
+        vod: None,
+    });
+    let messages = Message::from_definition(
+        def,
+        &state.config.id_scheme,
+        &privmsg.sender.login,
+        &channel,
+    )
+    .wrap_err("Failed to create messages")?
+    .into_iter()
+    .map(|message| message.with_origin(origin.clone()))
+    .collect::<Vec<_>>();
+
+    Ok(Reminders {
+        messages,
+        rejected,
+        schedules,
+        chat,
+        deadline,
+        channel,
+        explicit_channel,
+        live,
+        at,
+    })
+}
+
+/// Look up the display names of recipients of `messages` that weren't seen in chat yet, so their
+/// deliveries mention them the way they write their name. Runs in the background, so the
+/// confirmation doesn't wait for Helix.
+fn spawn_display_name_lookup(state: &State, messages: &[Message]) {
+    let helix = match &state.helix {
+        Some(helix) => helix.clone(),
+        None => return,
+    };
+
+    let mut unknown = messages
+        .iter()
+        .map(Message::recipient)
+        .filter(|recipient| !state.display_names.knows(recipient))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    unknown.sort_unstable();
+    unknown.dedup();
+    if unknown.is_empty() {
+        return;
+    }
+
+    let display_names = state.display_names.clone();
+    tokio::spawn(async move {
+        match helix.display_names(&unknown).await {
+            Ok(names) => {
+                for (login, name) in names {
+                    display_names.see(&login, &name);
+                }
+            }
+            Err(err) => warn!("{:?}", err.wrap_err("Failed to look up display names")),
+        }
+    });
+}
+
+/// Look up the video of the current stream of `channel_id` and link the moment `at` in the stored
+/// `messages`, which were written during it. Runs in the background, so the confirmation doesn't
+/// wait for Helix. The reminders are still worth having without the link.
+fn spawn_vod_lookup(state: &State, messages: &[Message], channel_id: &str, at: OffsetDateTime) {
+    let helix = match &state.helix {
+        Some(helix) => helix.clone(),
+        None => return,
+    };
+
+    let store = state.store.clone();
+    let ids = messages
+        .iter()
+        .map(|message| message.id().to_string())
+        .collect::<Vec<_>>();
+    let channel_id = channel_id.to_string();
+    tokio::spawn(
+        async move {
+            let vod = match helix.current_vod(&channel_id).await {
+                Ok(Some(vod)) => vod.link_at(at),
+                Ok(None) => return,
+                Err(err) => {
+                    warn!(
+                        "{:?}",
+                        err.wrap_err("Failed to get the video of the stream")
+                    );
+                    return;
+                }
+            };
+
+            let mut store = store.lock().await;
+            for id in &ids {
+                // unless it was delivered or cancelled meanwhile
+                let message = match store.get_by_id(id) {
+                    Some(message) => message.clone(),
+                    None => continue,
+                };
+                let origin = message.origin().cloned().map(|origin| Origin {
+                    vod: Some(vod.clone()),
+                    ..origin
+                });
+                store.insert(message.with_origin(origin));
+            }
+            if let Err(err) = store.save() {
+                error!("{:?}", err.wrap_err("Failed to save store"));
+            }
+        }
+        .in_current_span(),
+    );
+}
+
+/// Parse the page number argument of a listing, pages start at 1.
+fn parse_page(arg: Option<&str>) -> Option<usize> {
+    match arg {
+        Some(page) => page.parse::<usize>().ok().filter(|page| *page > 0),
+        None => Some(1),
+    }
+}
+
+/// Handle `~list [page] [tag:<tag>]`, listing the pending reminders of the sender one chat
+/// message per page.
+async fn handle_list_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let usage = || eyre!(UserError("Usage: list [page] [tag:<tag>]".to_string()));
+    let sender = &ctx.privmsg.sender.login;
+    let mut tag = None;
+    let mut page = None;
+    for part in &mut ctx.parts {
+        match part.strip_prefix("tag:") {
+            Some(filter) if !filter.is_empty() && tag.is_none() => {
+                tag = Some(filter.to_lowercase())
+            }
+            None if page.is_none() => page = Some(parse_page(Some(part)).ok_or_else(usage)?),
+            _ => return Err(usage()),
+        }
+    }
+    let page = page.unwrap_or(1);
+    let max_message_bytes = ctx
+        .state
+        .delivery_style(&ctx.privmsg.channel_login)
+        .max_message_bytes;
+
+    let entries = {
+        let store = ctx.state.store.lock().await;
+        let mut messages = match &tag {
+            Some(tag) => store
+                .get_by_tag(tag)
+                .into_iter()
+                .filter(|message| message.author() == sender)
+                .collect(),
+            None => store.get_by_author(sender),
+        };
+        messages.retain(|message| message.kind() != Kind::Note);
+        messages.sort_by_key(|message| message.number());
+
+        messages
+            .iter()
+            .map(|message| {
+                format!(
+                    "#{} {}: {}",
+                    message.number(),
+                    message.recipient(),
+                    preview(message.text(), 30)
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let pages = chunker::paginate(
+        &entries,
+        " | ",
+        max_message_bytes.saturating_sub(PAGE_MARKER_BYTES),
+    );
+
+    let response = match (pages.get(page - 1), &tag) {
+        (_, None) if pages.is_empty() => "You have no pending reminders".to_string(),
+        (_, Some(tag)) if pages.is_empty() => {
+            format!("You have no pending reminders tagged {}", tag)
+        }
+        (Some(text), _) if pages.len() == 1 => text.clone(),
+        (Some(text), _) => format!("{} | page {}/{}", text, page, pages.len()),
+        (None, _) => format!("You only have {} pages of reminders", pages.len()),
+    };
+
+    ctx.reply(response).await
+}
+
+async fn handle_find_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let needle = ctx.parts.by_ref().intersperse(" ").collect::<String>();
+    if needle.is_empty() {
+        return Err(eyre!(UserError("Usage: find <text>".to_string())));
+    }
+
+    let store = ctx.state.store.lock().await;
+    let mut messages = store.search(&ctx.privmsg.sender.login, &needle);
+    messages.sort_by_key(|message| message.created());
+
+    let response = if messages.is_empty() {
+        format!("None of your pending reminders mention \"{}\"", needle)
+    } else {
+        messages
+            .iter()
+            .map(|message| {
+                let direction = if message.author() == ctx.privmsg.sender.login {
+                    format!("to {}", message.recipient())
+                } else {
+                    format!("from {}", message.author())
+                };
+
+                format!(
+                    "{} [{}]: {}",
+                    direction,
+                    message.id(),
+                    preview(message.text(), 30)
+                )
+            })
+            .intersperse(" | ".to_string())
+            .collect()
+    };
+    // don't hold the lock while talking to chat
+    drop(store);
+
+    ctx.reply(response).await
+}
+
+async fn handle_bot_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    ctx.reply(format!(
+        "I let you leave messages for others. Written by @Chronophylos in Rust. Version {}",
+        env!("CARGO_PKG_VERSION")
+    ))
+    .await
+}
+
+async fn handle_lastseen_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let user = match ctx.parts.next() {
+        Some(user) => user.trim_start_matches('@').to_lowercase(),
+        None => return Err(eyre!(UserError("Missing user".to_string()))),
+    };
+
+    let response = match ctx.state.seen.last_seen(&user) {
+        Some((channel, time)) => format!(
+            "{} was last seen in #{} {}",
+            user,
+            channel,
+            humanize::ago(
+                OffsetDateTime::now_utc() - time,
+                humanize::DEFAULT_PRECISION
+            )
+        ),
+        None => format!("I have never seen {} type in chat", user),
+    };
+
+    ctx.reply(response).await
+}
+
+async fn handle_afk_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let reason = ctx.parts.by_ref().intersperse(" ").collect::<String>();
+
+    ctx.state.afk.set(
+        &ctx.privmsg.sender.login,
+        AfkStatus {
+            since: OffsetDateTime::now_utc(),
+            channel: ctx.privmsg.channel_login.clone(),
+            reason: reason.clone(),
+        },
+    );
+    ctx.state.afk.save().wrap_err("Failed to save afk store")?;
+
+    let response = if reason.is_empty() {
+        format!("{} is now afk", ctx.privmsg.sender.name)
+    } else {
+        format!("{} is now afk: {}", ctx.privmsg.sender.name, reason)
+    };
+
+    ctx.reply(response).await
+}
+
+/// Show or change whether reminders are delivered to the sender without mentioning them.
+async fn handle_silent_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let login = &ctx.privmsg.sender.login;
+
+    let silent = match ctx.parts.next().map(str::to_lowercase).as_deref() {
+        None => ctx.state.settings.get(login).silent,
+        Some("on") => true,
+        Some("off") => false,
+        Some(_) => {
+            return Err(eyre!(UserError(format!(
+                "Usage: {}silent [on|off]",
+                PREFIX
+            ))))
+        }
+    };
+
+    if silent != ctx.state.settings.get(login).silent {
+        ctx.state
+            .settings
+            .update(login, |settings| settings.silent = silent);
+        ctx.state
+            .settings
+            .save()
+            .wrap_err("Failed to save settings store")?;
+    }
+
+    let response = if silent {
+        "Your reminders are delivered without mentioning you"
+    } else {
+        "Your reminders are delivered with a mention"
+    };
+
+    ctx.reply(response.to_string()).await
+}
+
+/// Show or change the time zone timed reminders for the sender are rendered in.
+async fn handle_timezone_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let login = &ctx.privmsg.sender.login;
+
+    let response = match ctx.parts.next() {
+        None => {
+            let settings = ctx.state.settings.get(login);
+            match (settings.time_zone, settings.utc_offset_minutes) {
+                (Some(name), _) => format!("Your time zone is {}", name),
+                (None, Some(minutes)) => {
+                    format!("Your time zone is {}", format_utc_offset(minutes))
+                }
+                (None, None) => format!(
+                    "You have no time zone set, use {}timezone <+hh:mm|Region/City> to set one",
+                    PREFIX
+                ),
+            }
+        }
+        Some("off") => {
+            ctx.state.settings.update(login, |settings| {
+                settings.utc_offset_minutes = None;
+                settings.time_zone = None;
+            });
+            ctx.state
+                .settings
+                .save()
+                .wrap_err("Failed to save settings store")?;
+
+            "Your time zone was reset, times are shown in UTC".to_string()
+        }
+        Some(zone) if time_zone::Zone::named(zone).is_some() => {
+            // the canonical spelling, e.g. `Europe/Berlin` for `europe/berlin`
+            let name = time_zone::Zone::named(zone)
+                .and_then(|zone| zone.name())
+                .unwrap_or(zone)
+                .to_string();
+
+            ctx.state.settings.update(login, |settings| {
+                settings.time_zone = Some(name.clone());
+                settings.utc_offset_minutes = None;
+            });
+            ctx.state
+                .settings
+                .save()
+                .wrap_err("Failed to save settings store")?;
+
+            format!("Your time zone is now {}", name)
+        }
+        Some(offset) => {
+            let minutes = match date_parser::parse_utc_offset(offset) {
+                Some(minutes) => minutes,
+                None => {
+                    return Err(eyre!(UserError(format!(
+                        "Could not parse time zone {}, use an offset like +02:00 or UTC-5 or a \
+                         zone like Europe/Berlin",
+                        offset
+                    ))))
+                }
+            };
+
+            ctx.state.settings.update(login, |settings| {
+                settings.utc_offset_minutes = Some(minutes);
+                settings.time_zone = None;
+            });
+            ctx.state
+                .settings
+                .save()
+                .wrap_err("Failed to save settings store")?;
+
+            format!("Your time zone is now {}", format_utc_offset(minutes))
+        }
+    };
+
+    ctx.reply(response).await
+}
+
+/// Show how long reminders delivered recently waited, so it's visible when the bot falls
+/// behind.
+async fn handle_stats_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let (timed, untimed) = delivery_stats::delivered_since(
+        &ctx.state.audit,
+        OffsetDateTime::now_utc() - STATS_WINDOW,
+    )?;
+    let (pending, due) = {
+        let store = ctx.state.store.lock().await;
+
+        (
+            store.len(),
+            store
+                .due_before(OffsetDateTime::now_utc() + STATS_WINDOW)
+                .len(),
+        )
+    };
+
+    let response = format!(
+        "Pending: {} ({} timed due within a day). Delivered in the last day: {}, {}",
+        pending,
+        due,
+        format_latency("timed", timed),
+        format_latency("on next message", untimed),
+    );
+
+    ctx.reply(response).await
+}
+
+/// Handle `~analytics`, summarizing how the bot was used in the channel recently.
+async fn handle_analytics_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let channel = &ctx.privmsg.channel_login;
+    let now = OffsetDateTime::now_utc();
+    let longest = ANALYTICS_WINDOWS.iter().max().copied().unwrap_or_default();
+    let events = ctx
+        .state
+        .audit
+        .events_since(now - Duration::days(longest))
+        .wrap_err("Failed to read audit log")?;
+    let pending = ctx
+        .state
+        .store
+        .lock()
+        .await
+        .get_all()
+        .into_iter()
+        .filter(|message| message.channel() == channel)
+        .cloned()
+        .collect::<Vec<_>>();
+    let pending = pending.iter().collect::<Vec<_>>();
+
+    let usages = ANALYTICS_WINDOWS
+        .iter()
+        .map(|days| {
+            let usage = ChannelUsage::new(channel, now - Duration::days(*days), &events, &pending);
+            (days, usage)
+        })
+        .collect::<Vec<_>>();
+    let windows = usages
+        .iter()
+        .map(|(days, usage)| {
+            format!(
+                "Last {} days: {} created, {} delivered, average delay {}",
+                days,
+                usage.created,
+                usage.delivered,
+                usage
+                    .average_delay
+                    .map_or_else(|| "-".to_string(), humanize::span)
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // authors of the longest window
+    let style = ctx.state.delivery_style(channel);
+    let top_authors = match usages.last() {
+        Some((_, usage)) if !usage.top_authors.is_empty() => usage
+            .top_authors
+            .iter()
+            .map(|(author, count)| format!("{} ({})", style.author(author), count))
+            .intersperse(", ".to_string())
+            .collect(),
+        _ => "none".to_string(),
+    };
+
+    ctx.reply(format!(
+        "{}. Top authors: {}",
+        windows.join(". "),
+        top_authors
+    ))
+    .await
+}
+
+/// Handle `~status`, a health check of the connection, the joined channels and the store.
+async fn handle_status_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let now = OffsetDateTime::now_utc();
+    let ago = |at: Option<OffsetDateTime>| {
+        at.map_or_else(
+            || "never".to_string(),
+            |at| humanize::ago(now - at, humanize::DEFAULT_PRECISION),
+        )
+    };
+
+    let channels = ctx.state.channels.iter().cloned().collect::<Vec<_>>();
+    let missing = ctx.state.joins.missing(&channels);
+    let joined = if missing.is_empty() {
+        format!("joined all {} channels", channels.len())
+    } else {
+        format!(
+            "joined {}/{} channels, waiting for {}",
+            channels.len() - missing.len(),
+            channels.len(),
+            missing
+                .iter()
+                .map(|channel| channel.as_str())
+                .intersperse(", ")
+                .collect::<String>()
+        )
+    };
+
+    let (pending, timed, last_saved, size) = {
+        let store = ctx.state.store.lock().await;
+        let timed = store
+            .get_all()
+            .into_iter()
+            .filter(|message| matches!(message.activation(), Activation::Fixed(_)))
+            .count();
+
+        (
+            store.len(),
+            timed,
+            store.last_saved(),
+            store.approximate_size(),
+        )
+    };
+
+    let response = format!(
+        "{}IRC: last message from Twitch {}, {}. Reminders: {} pending, {} timed, {} timers \
+         active. Store: saved {}, about {} in memory",
+        if ctx.state.pause.is_paused() {
+            "Paused. "
+        } else {
+            ""
+        },
+        ago(ctx.state.last_server_message),
+        joined,
+        pending,
+        timed,
+        ctx.state.timers.len(),
+        ago(last_saved),
+        format_bytes(size)
+    );
+
+    ctx.reply(response).await
+}
+
+/// Format `bytes` with a binary unit, e.g. `1.5 MiB`.
+fn format_bytes(bytes: usize) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next in &UNITS[1..] {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+
+    format!("{:.1} {}", value, unit)
+}
+
+fn format_latency(label: &str, summary: Option<LatencySummary>) -> String {
+    match summary {
+        Some(summary) => format!(
+            "{} {} (median {}, p95 {}, max {})",
+            summary.count,
+            label,
+            humanize::span(summary.median),
+            humanize::span(summary.p95),
+            humanize::span(summary.max)
+        ),
+        None => format!("0 {}", label),
+    }
+}
+
+/// Whether `recipient` accepts reminders from the sender of `privmsg`. Reminders for followers
+/// only are rejected if Helix isn't configured, but accepted if it fails to answer, so an outage
+/// doesn't stop everyone from writing reminders.
+async fn accepts_reminder(state: &State, recipient: &str, privmsg: &PrivmsgMessage) -> bool {
+    let author = &privmsg.sender.login;
+    if recipient == author {
+        return true;
+    }
+
+    let settings = state.settings.get(recipient);
+    if settings.ignored.contains(author) {
+        return false;
+    }
+
+    match settings.accept_from {
+        AcceptFrom::Anyone => true,
+        AcceptFrom::Nobody => false,
+        AcceptFrom::Followers => {
+            let helix = match &state.helix {
+                Some(helix) => helix,
+                None => return false,
+            };
+            let follows = async {
+                match helix.user_id(recipient).await? {
+                    Some(recipient_id) => helix.follows(&privmsg.sender.id, &recipient_id).await,
+                    None => Ok(false),
+                }
+            };
+
+            match follows.await {
+                Ok(follows) => follows,
+                Err(err) => {
+                    warn!(
+                        "Failed to check whether {} follows {}, accepting the reminder: {:?}",
+                        author, recipient, err
+                    );
+                    true
+                }
+            }
+        }
+    }
+}
+
+/// Whether `name` is the login of someone the bot saw in chat or Helix knows.
+async fn is_known_login(state: &State, name: &str) -> Result<bool> {
+    if state.seen.last_seen(name).is_some() {
+        return Ok(true);
+    }
+
+    match &state.helix {
+        Some(helix) => Ok(helix
+            .user_id(name)
+            .await
+            .wrap_err("Failed to look up user")?
+            .is_some()),
+        None => Ok(false),
+    }
+}
+
+/// Whether `name` can be a Twitch login rather than a localized display name.
+fn is_login(name: &str) -> bool {
+    name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Get the login of the user with the localized display name `name`, from the display names seen
+/// in chat or else from Helix.
+async fn resolve_display_name(state: &State, name: &str) -> Result<Option<String>> {
+    if let Some(login) = state.display_names.login_of(name) {
+        return Ok(Some(login));
+    }
+
+    match &state.helix {
+        Some(helix) => helix.login_of(name).await,
+        None => Ok(None),
+    }
+}
+
+/// Reject `adding` reminders of `author` for each of `recipients` if any of them would have more
+/// than [`Config::max_pending_per_recipient`] undelivered reminders from them, as far as `tier`
+/// applies it. Reminders to oneself aren't limited.
+async fn check_pending_per_recipient(
+    state: &State,
+    author: &str,
+    recipients: &HashSet<String>,
+    adding: usize,
+    tier: Tier,
+) -> Result<()> {
+    let max = match tier.cap(state.config.max_pending_per_recipient) {
+        Some(max) => max,
+        None => return Ok(()),
+    };
+    let store = state.store.lock().await;
+    let pending = store.get_by_author(author);
+
+    let mut full = recipients
+        .iter()
+        .filter(|recipient| *recipient != author)
+        .filter(|recipient| {
+            pending
+                .iter()
+                .filter(|message| message.recipient() == recipient.as_str())
+                .count()
+                + adding
+                > max
+        })
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    if full.is_empty() {
+        return Ok(());
+    }
+
+    full.sort_unstable();
+    if adding > 1 {
+        return Err(eyre!(UserError(format!(
+            "You can only have {} reminders waiting for {}, cancel some or schedule fewer",
+            max,
+            full.join(", ")
+        ))));
+    }
+    Err(eyre!(UserError(format!(
+        "You already have {} reminders waiting for {}, cancel one or wait until they are delivered",
+        max,
+        full.join(", ")
+    ))))
+}
+
+/// Reject `adding` timed reminders of `author` if they would have more than
+/// [`Config::max_timed_per_user`] waiting, as far as `tier` applies it.
+async fn check_timed_per_user(
+    state: &State,
+    author: &str,
+    adding: usize,
+    tier: Tier,
+) -> Result<()> {
+    let max = match tier.cap(state.config.max_timed_per_user) {
+        Some(max) if adding > 0 => max,
+        _ => return Ok(()),
+    };
+    let timed = state
+        .store
+        .lock()
+        .await
+        .get_by_author(author)
+        .into_iter()
+        .filter(|message| matches!(message.activation(), Activation::Fixed(_)))
+        .count();
+    if timed + adding <= max {
+        return Ok(());
+    }
+
+    Err(eyre!(UserError(format!(
+        "You can only have {} timed reminders waiting, cancel some or leave out the time to \
+         remind them when they next type in chat",
+        max
+    ))))
+}
+
+/// Handle `~pending`, telling the sender how many reminders are waiting for them without showing
+/// any of them.
+async fn handle_pending_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let (pending, timed) = {
+        let store = ctx.state.store.lock().await;
+        let messages = store
+            .get_by_recipient(&ctx.privmsg.sender.login)
+            .into_iter()
+            // `~notify` watches are for their author, the watched user isn't told about them
+            .filter(|message| !matches!(message.kind(), Kind::Note | Kind::Notification))
+            .collect::<Vec<_>>();
+        let timed = messages
+            .iter()
+            .filter(|message| message.activation() != &Activation::OnNextMessage)
+            .count();
+
+        (messages.len(), timed)
+    };
+
+    let response = match (pending, timed) {
+        (0, _) => "No reminders are waiting for you".to_string(),
+        (_, 0) => format!(
+            "{} waiting for you",
+            format_num(pending, "reminder is", "reminders are")
+        ),
+        _ => format!(
+            "{} waiting for you, {} of them timed",
+            format_num(pending, "reminder is", "reminders are"),
+            timed
+        ),
+    };
+
+    ctx.reply(response).await
+}
+
+/// Handle `~acceptfrom [anyone|followers|nobody]`, showing or changing who may leave reminders
+/// for the sender.
+async fn handle_accept_from_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let login = ctx.privmsg.sender.login.clone();
+
+    let accept_from = match ctx.parts.next().map(str::to_lowercase).as_deref() {
+        None => ctx.state.settings.get(&login).accept_from,
+        Some("anyone") => AcceptFrom::Anyone,
+        Some("followers") if ctx.state.helix.is_none() => {
+            return Err(eyre!(UserError(
+                "I can't check follows since I have no Helix client id".to_string()
+            )))
+        }
+        Some("followers") => AcceptFrom::Followers,
+        Some("nobody") => AcceptFrom::Nobody,
+        Some(_) => {
+            return Err(eyre!(UserError(format!(
+                "Usage: {}acceptfrom anyone|followers|nobody",
+                PREFIX
+            ))))
+        }
+    };
+
+    if accept_from != ctx.state.settings.get(&login).accept_from {
+        ctx.state
+            .settings
+            .update(&login, |settings| settings.accept_from = accept_from);
+        ctx.state
+            .settings
+            .save()
+            .wrap_err("Failed to save settings store")?;
+    }
+
+    let response = match accept_from {
+        AcceptFrom::Anyone => "You accept reminders from anyone",
+        AcceptFrom::Followers => "You accept reminders from your followers",
+        AcceptFrom::Nobody => "You only accept reminders from yourself",
+    };
+
+    ctx.reply(response.to_string()).await
+}
+
+/// Handle `~ignore [user]` and `~unignore <user>`, rejecting or accepting reminders from `user`
+/// for the sender. Lists the ignored users without one.
+async fn handle_ignore_command(ctx: &mut commands::Context<'_>, ignore: bool) -> Result<()> {
+    let login = ctx.privmsg.sender.login.clone();
+
+    let user = match ctx.parts.next() {
+        Some(user) => user.trim_start_matches('@').to_lowercase(),
+        None if ignore => {
+            let ignored = ctx.state.settings.get(&login).ignored;
+            let response = if ignored.is_empty() {
+                "You don't ignore anyone".to_string()
+            } else {
+                format!(
+                    "You ignore reminders from {}",
+                    ignored
+                        .iter()
+                        .map(String::as_str)
+                        .intersperse(", ")
+                        .collect::<String>()
+                )
+            };
+
+            return ctx.reply(response).await;
+        }
+        None => {
+            return Err(eyre!(UserError(format!(
+                "Usage: {}unignore <user>",
+                PREFIX
+            ))))
+        }
+    };
+
+    let mut changed = false;
+    ctx.state.settings.update(&login, |settings| {
+        changed = if ignore {
+            settings.ignored.insert(user.clone())
+        } else {
+            settings.ignored.remove(&user)
+        };
+    });
+    if changed {
+        ctx.state
+            .settings
+            .save()
+            .wrap_err("Failed to save settings store")?;
+    }
+
+    let response = match (ignore, changed) {
+        (true, true) => format!("I won't accept reminders from {} for you anymore", user),
+        (true, false) => format!("You already ignore {}", user),
+        (false, true) => format!("I'll accept reminders from {} for you again", user),
+        (false, false) => format!("You don't ignore {}", user),
+    };
+
+    ctx.reply(response).await
+}
+
+/// Handle `~settings [<setting> [<value>]]`, showing or changing how reminders are delivered to
+/// the sender.
+async fn handle_settings_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let login = ctx.privmsg.sender.login.clone();
+    let mut settings = ctx.state.settings.get(&login);
+    let key = ctx
+        .parts
+        .next()
+        .map(|key| {
+            key.parse::<settings_store::Key>()
+                .map_err(|err| eyre!(UserError(err.to_string())))
+        })
+        .transpose()?;
+
+    let response = match (key, ctx.parts.next()) {
+        (Some(key), Some(value)) => {
+            settings
+                .set(key, value)
+                .map_err(|err| eyre!(UserError(err.to_string())))?;
+            if let Some(channel) = settings.channel.as_ref().filter(|channel| {
+                key == settings_store::Key::Channel && !ctx.state.channels.contains(*channel)
+            }) {
+                return Err(eyre!(UserError(format!(
+                    "I can't deliver in #{} because I'm not in that channel",
+                    channel
+                ))));
+            }
+
+            let value = settings.get(key);
+            ctx.state
+                .settings
+                .update(&login, |current| *current = settings);
+            ctx.state
+                .settings
+                .save()
+                .wrap_err("Failed to save settings store")?;
+            info!("Set {} of {} to {}", key.name(), login, value);
+
+            format!("Set {} to {}", key.name(), value)
+        }
+        (Some(key), None) => format!("{}: {}", key.name(), settings.get(key)),
+        (None, _) => settings_store::Key::ALL
+            .iter()
+            .map(|key| format!("{}: {}", key.name(), settings.get(*key)))
+            .intersperse(", ".to_string())
+            .collect(),
+    };
+
+    ctx.reply(response).await
+}
+
+/// Handle `~set <setting> <value>`, changing a setting of the current channel.
+async fn handle_set_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let usage = || {
+        eyre!(UserError(format!(
+            "Usage: {}set <setting> <value>, use default as the value to reset it",
+            PREFIX
+        )))
+    };
+    let key = ctx.parts.next().ok_or_else(usage)?;
+    let value = ctx.parts.next().ok_or_else(usage)?;
+
+    let channel = &ctx.privmsg.channel_login;
+    let key = key
+        .parse::<channel_settings::Key>()
+        .map_err(|err| eyre!(UserError(err.to_string())))?;
+    if key.broadcaster_only() && ctx.role() < Role::Broadcaster {
+        return Err(eyre!(UserError(format!(
+            "Only the broadcaster can change {}",
+            key.name()
+        ))));
+    }
+    ctx.state
+        .channel_settings
+        .set(channel, key, value)
+        .map_err(|err| eyre!(UserError(err.to_string())))?;
+    ctx.state
+        .channel_settings
+        .save()
+        .wrap_err("Failed to save channel settings store")?;
+    info!("Set {} of {} to {}", key.name(), channel, value);
+
+    ctx.reply(format!("Set {} to {}", key.name(), value)).await
+}
+
+/// Handle `~get [setting]`, showing the settings changed in the current channel.
+async fn handle_get_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let settings = ctx.state.channel_settings.get(&ctx.privmsg.channel_login);
+    let keys = match ctx.parts.next() {
+        Some(key) => vec![key
+            .parse::<channel_settings::Key>()
+            .map_err(|err| eyre!(UserError(err.to_string())))?],
+        None => channel_settings::Key::ALL.to_vec(),
+    };
+
+    let response = keys
+        .into_iter()
+        .map(|key| {
+            format!(
+                "{}: {}",
+                key.name(),
+                settings.get(key).unwrap_or_else(|| "default".to_string())
+            )
+        })
+        .intersperse(", ".to_string())
+        .collect::<String>();
+
+    ctx.reply(response).await
+}
+
+/// Handle `~help [command]`, listing the commands the sender may run or showing how to use one.
+async fn handle_help_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let response = match ctx.parts.next() {
+        Some(name) => {
+            let name = ctx
+                .state
+                .config
+                .resolve_command(&ctx.privmsg.channel_login, name.trim_start_matches(PREFIX));
+
+            match ctx.state.commands.find(&name) {
+                Some(command) => format!("Usage: {}", command.help()),
+                None => return Err(eyre!(UserError(format!("Unknown command {}", name)))),
+            }
+        }
+        None => format!(
+            "Commands: {}. Use {}help <command> to see how to use one",
+            ctx.state
+                .commands
+                .available(ctx)
+                .iter()
+                .map(|command| format!("{}{}", PREFIX, command.name))
+                .intersperse(", ".to_string())
+                .collect::<String>(),
+            PREFIX
+        ),
+    };
+
+    ctx.reply(response).await
+}
+
+/// Make sure `text` can't be read as a chat command, neither by Twitch nor by bots like us.
+///
+/// Delivered reminders are split into chunks, so user controlled text can end up at the start
+/// of a message.
+fn defuse(text: &str) -> String {
+    match text.chars().next() {
+        Some('/' | '.' | '!' | PREFIX) => format!("\u{200b}{}", text),
+        _ => text.to_string(),
+    }
+}
+
+/// Handle `~countdown <duration> <text>`, posting `text` to the channel once `duration` passed.
+async fn handle_countdown_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let usage = || eyre!(UserError("Usage: countdown <duration> <text>".to_string()));
+    let duration =
+        next_duration(ctx.state, &ctx.privmsg.channel_login, &mut ctx.parts).ok_or_else(usage)?;
+    check_schedule_ahead(&ctx.state.config, duration)?;
+    let text = ctx.parts.by_ref().intersperse(" ").collect::<String>();
+    if text.is_empty() {
+        return Err(usage());
+    }
+
+    let message = Message::channel_wide(
+        Kind::Countdown,
+        ctx.state
+            .config
+            .id_scheme
+            .generate()
+            .wrap_err("Failed to generate id")?,
+        OffsetDateTime::now_utc() + duration,
+        ctx.privmsg.sender.login.clone(),
+        ctx.privmsg.channel_login.clone(),
+        text,
+    );
+    let response = format!(
+        "Countdown set, I'll post it {} [{}]",
+        humanize::until(duration),
+        message.id()
+    );
+
+    {
+        let mut store = ctx.state.store.lock().await;
+        store.insert(message.clone());
+        store.save().wrap_err("Failed to save store")?;
+    }
+    queue_messages(ctx.state, ctx.client, &[message]).await;
+
+    ctx.reply(response).await
+}
+
+/// Handle `~schedule at:<hh:mm>|in:<duration> <text>`, `~schedule list` and
+/// `~schedule cancel <id>`, managing one-off announcements in the current channel.
+async fn handle_schedule_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let usage = || {
+        eyre!(UserError(
+            "Usage: schedule at:<hh:mm>|in:<duration> <text>, schedule list, schedule cancel <id>"
+                .to_string()
+        ))
+    };
+    let channel = &ctx.privmsg.channel_login;
+    let recipient = format!("#{}", channel);
+
+    let response = match ctx.parts.next().ok_or_else(usage)? {
+        "list" => {
+            let store = ctx.state.store.lock().await;
+            let mut announcements = store
+                .get_by_recipient(&recipient)
+                .into_iter()
+                .filter(|message| message.kind() == Kind::Announcement)
+                .collect::<Vec<_>>();
+            announcements.sort_by_key(|message| message.due());
+
+            if announcements.is_empty() {
+                "No announcements are scheduled in this channel".to_string()
+            } else {
+                announcements
+                    .iter()
+                    .map(|message| {
+                        format!(
+                            "[{}] {}: {}",
+                            message.id(),
+                            humanize::until(message.due() - OffsetDateTime::now_utc()),
+                            preview(message.text(), 30)
+                        )
+                    })
+                    .intersperse(" | ".to_string())
+                    .collect()
+            }
+        }
+        "cancel" => {
+            let id = ctx.parts.next().ok_or_else(usage)?;
+            let mut store = ctx.state.store.lock().await;
+
+            let scheduled = store.get_by_id(id).map_or(false, |message| {
+                message.kind() == Kind::Announcement && message.recipient() == recipient
+            });
+            if !scheduled {
+                return Err(eyre!(UserError(
+                    "There is no announcement with that id in this channel".to_string()
+                )));
+            }
+
+            let message = store
+                .take(id)
+                .ok_or_else(|| eyre!("Message vanished from store"))?;
+            store.save().wrap_err("Failed to save store")?;
+            ctx.state
+                .audit
+                .record(AuditKind::Cancelled, &message)
+                .wrap_err("Failed to write audit log")?;
+
+            "Cancelled the announcement".to_string()
+        }
+        time => {
+            let now = OffsetDateTime::now_utc();
+            let at = if let Some(time) = time.strip_prefix("at:") {
+                date_parser::next_occurrence(
+                    now,
+                    date_parser::parse_clock_time(time).ok_or_else(usage)?,
+                )
+            } else if let Some(duration) = time.strip_prefix("in:") {
+                let language = ctx
+                    .state
+                    .channel_settings
+                    .get(&ctx.privmsg.channel_login)
+                    .language
+                    .unwrap_or_default();
+                let duration: Duration =
+                    IntermediateDuration::parse_localized(&duration.to_lowercase(), language)
+                        .map_err(|_| usage())?
+                        .into();
+                check_schedule_ahead(&ctx.state.config, duration)?;
+                now + duration
+            } else {
+                return Err(usage());
+            };
+
+            let text = ctx.parts.by_ref().intersperse(" ").collect::<String>();
+            if text.is_empty() {
+                return Err(usage());
+            }
+
+            let message = Message::channel_wide(
+                Kind::Announcement,
+                ctx.state
+                    .config
+                    .id_scheme
+                    .generate()
+                    .wrap_err("Failed to generate id")?,
+                at,
+                ctx.privmsg.sender.login.clone(),
+                channel.clone(),
+                text,
+            );
+            let response = format!(
+                "Announcement scheduled {} [{}]",
+                humanize::until(at - now),
+                message.id()
+            );
+
+            {
+                let mut store = ctx.state.store.lock().await;
+                store.insert(message.clone());
+                store.save().wrap_err("Failed to save store")?;
+            }
+            queue_messages(ctx.state, ctx.client, &[message]).await;
+
+            response
+        }
+    };
+
+    ctx.reply(response).await
+}
+
+/// Handle `~beforestream <duration>|off`, reminding the broadcaster `duration` ahead of every
+/// segment of their stream schedule.
+async fn handle_before_stream_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let usage = || eyre!(UserError("Usage: beforestream <duration>|off".to_string()));
+    let channel = &ctx.privmsg.channel_login;
+
+    let response = match ctx.parts.clone().next().ok_or_else(usage)? {
+        "off" => {
+            if ctx.state.schedules.remove(channel) {
+                ctx.state
+                    .schedules
+                    .save()
+                    .wrap_err("Failed to save schedule store")?;
+                "I'll no longer remind you of your schedule".to_string()
+            } else {
+                "I wasn't reminding you of your schedule".to_string()
+            }
+        }
+        _ => {
+            let lead = next_duration(ctx.state, channel, &mut ctx.parts).ok_or_else(usage)?;
+            // checked as stored, in whole minutes
+            let lead = Duration::minutes(lead.whole_minutes());
+            check_schedule_soon(&ctx.state.config, lead)?;
+            check_schedule_ahead(&ctx.state.config, lead)?;
+
+            ctx.state.schedules.set(
+                channel,
+                ScheduleWatch {
+                    broadcaster_id: ctx.privmsg.channel_id.clone(),
+                    broadcaster_login: ctx.privmsg.channel_login.clone(),
+                    lead_minutes: lead.whole_minutes(),
+                    reminded: Default::default(),
+                },
+            );
+            ctx.state
+                .schedules
+                .save()
+                .wrap_err("Failed to save schedule store")?;
+
+            format!(
+                "I'll remind you {} before every stream on your schedule",
+                humanize::span(lead)
+            )
+        }
+    };
+
+    ctx.reply(response).await
+}
+
+/// Handle `~notify <user> [text]`, telling the sender the next time `user` types in a joined
+/// channel.
+async fn handle_notify_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let user = ctx
+        .parts
+        .next()
+        .map(|user| user.trim_start_matches('@').to_lowercase())
+        .filter(|user| is_login(user) && !user.is_empty())
+        .ok_or_else(|| eyre!(UserError("Usage: notify <user> [text]".to_string())))?;
+    if user == ctx.privmsg.sender.login || user == ctx.state.login {
+        return Err(eyre!(UserError(format!(
+            "I can't notify you about {}",
+            user
+        ))));
+    }
+    let text = ctx.parts.by_ref().intersperse(" ").collect::<String>();
+
+    let message = Message::notification(
+        ctx.state
+            .config
+            .id_scheme
+            .generate()
+            .wrap_err("Failed to generate id")?,
+        ctx.privmsg.sender.login.clone(),
+        ctx.privmsg.channel_login.clone(),
+        user.clone(),
+        text,
+    );
+    let response = format!("I'll tell you when {} next chats [{}]", user, message.id());
+
+    {
+        let mut store = ctx.state.store.lock().await;
+        store.insert(message);
+        store.save().wrap_err("Failed to save store")?;
+    }
+
+    ctx.reply(response).await
+}
+
+/// Handle `~watchword <keyword>|list|remove <keyword>`, pinging the sender the next time someone
+/// says the keyword in the current channel.
+async fn handle_watchword_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let usage = || {
+        eyre!(UserError(format!(
+            "Usage: {}watchword <keyword>|list|remove <keyword>",
+            PREFIX
+        )))
+    };
+    let args = ctx.parts.by_ref().intersperse(" ").collect::<String>();
+    let login = ctx.privmsg.sender.login.clone();
+    let channel = ctx.privmsg.channel_login.clone();
+
+    let response = if args == "list" {
+        let watched = ctx.state.watches.of(&login);
+        if watched.is_empty() {
+            "You don't watch for any keywords".to_string()
+        } else {
+            format!(
+                "You watch for {}",
+                watched
+                    .iter()
+                    .map(|(channel, keyword)| format!("\"{}\" in #{}", keyword, channel))
+                    .intersperse(", ".to_string())
+                    .collect::<String>()
+            )
+        }
+    } else if let Some(keyword) = args.strip_prefix("remove ") {
+        let keyword = watch_store::parse_keyword(keyword).ok_or_else(usage)?;
+        let watches = &ctx.state.watches;
+        if !watches.remove(&channel, &login, &keyword) {
+            return Err(eyre!(UserError(format!(
+                "You don't watch for \"{}\" here",
+                keyword
+            ))));
+        }
+        watches.save().wrap_err("Failed to save watch store")?;
+
+        format!("Stopped watching for \"{}\" here", keyword)
+    } else {
+        let keyword = watch_store::parse_keyword(&args).ok_or_else(usage)?;
+        let tier = ctx.cap_tier();
+        let max = ctx.state.config.max_watches_per_user;
+        let watches = &ctx.state.watches;
+        if !tier.allows(watches.of(&login).len() + 1, max) {
+            return Err(eyre!(UserError(format!(
+                "You can watch for at most {} keywords, remove one with {}watchword remove <keyword>",
+                tier.cap(max).unwrap_or(max),
+                PREFIX
+            ))));
+        }
+        if !watches.add(&channel, &login, &keyword) {
+            return Err(eyre!(UserError(format!(
+                "You already watch for \"{}\" here",
+                keyword
+            ))));
+        }
+        watches.save().wrap_err("Failed to save watch store")?;
+
+        format!(
+            "I'll ping you the next time someone says \"{}\" here",
+            keyword
+        )
+    };
+
+    ctx.reply(response).await
+}
+
+/// Handle `~note <text>`, keeping `text` for the sender.
+async fn handle_note_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let text = ctx.parts.by_ref().intersperse(" ").collect::<String>();
+    if text.is_empty() {
+        return Err(eyre!(UserError("Usage: note <text>".to_string())));
+    }
+
+    let message = Message::note(
+        ctx.state
+            .config
+            .id_scheme
+            .generate()
+            .wrap_err("Failed to generate id")?,
+        ctx.privmsg.sender.login.clone(),
+        ctx.privmsg.channel_login.clone(),
+        text,
+    );
+    let response = format!(
+        "Noted, see {}notes for all your notes [{}]",
+        PREFIX,
+        message.id()
+    );
+
+    {
+        let mut store = ctx.state.store.lock().await;
+        store.insert(message);
+        store.save().wrap_err("Failed to save store")?;
+    }
+
+    ctx.reply(response).await
+}
+
+/// Handle `~notes [page]` and `~notes delete <id|#>`, listing or removing the notes of the
+/// sender.
+async fn handle_notes_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let usage = || {
+        eyre!(UserError(
+            "Usage: notes [page], notes delete <id|#>".to_string()
+        ))
+    };
+    let sender = &ctx.privmsg.sender.login;
+    let mut store = ctx.state.store.lock().await;
+
+    let response = match ctx.parts.next() {
+        Some("delete") => {
+            let token = ctx.parts.next().ok_or_else(usage)?;
+            let id = store
+                .resolve(sender, token)
+                .filter(|message| message.kind() == Kind::Note)
+                .map(|message| message.id().to_string())
+                .ok_or_else(|| eyre!(UserError("You have no note with that id".to_string())))?;
+
+            let message = store
+                .take(&id)
+                .ok_or_else(|| eyre!("Message vanished from store"))?;
+            store.save().wrap_err("Failed to save store")?;
+            ctx.state.undo.push(sender, vec![message]);
+
+            format!("Deleted the note, use {}undo to restore it", PREFIX)
+        }
+        page => {
+            let page = parse_page(page).ok_or_else(usage)?;
+
+            let mut notes = store
+                .get_by_author(sender)
+                .into_iter()
+                .filter(|message| message.kind() == Kind::Note)
+                .collect::<Vec<_>>();
+            notes.sort_by_key(|message| message.created());
+            let pages = (notes.len() + NOTES_PER_PAGE - 1) / NOTES_PER_PAGE;
+
+            if notes.is_empty() {
+                format!("You have no notes, add one with {}note <text>", PREFIX)
+            } else if page > pages {
+                format!("You only have {} pages of notes", pages)
+            } else {
+                format!(
+                    "Notes {}/{}: {}",
+                    page,
+                    pages,
+                    notes
+                        .iter()
+                        .skip((page - 1) * NOTES_PER_PAGE)
+                        .take(NOTES_PER_PAGE)
+                        .map(|message| format!(
+                            "#{} [{}]: {}",
+                            message.number(),
+                            message.id(),
+                            preview(message.text(), 50)
+                        ))
+                        .intersperse(" | ".to_string())
+                        .collect::<String>()
+                )
+            }
+        }
+    };
+    drop(store);
+
+    ctx.reply(response).await
+}
+
+/// Handle `~timer add <interval> <text>`, `~timer remove <id>` and `~timer list`, managing the
+/// repeating timers of the current channel.
+async fn handle_timer_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let usage = || {
+        eyre!(UserError(
+            "Usage: timer add <interval> <text>, timer remove <id>, timer list".to_string()
+        ))
+    };
+    let channel = &ctx.privmsg.channel_login;
+
+    let response = match ctx.parts.next().ok_or_else(usage)? {
+        "add" => {
+            let interval = next_duration(ctx.state, channel, &mut ctx.parts).ok_or_else(usage)?;
+            check_schedule_ahead(&ctx.state.config, interval)?;
+            if interval < Duration::minutes(1) {
+                return Err(eyre!(UserError(
+                    "Timers can repeat at most every minute".to_string()
+                )));
+            }
+
+            let text = ctx.parts.by_ref().intersperse(" ").collect::<String>();
+            if text.is_empty() {
+                return Err(usage());
+            }
+
+            let id = ctx
+                .state
+                .config
+                .id_scheme
+                .generate()
+                .wrap_err("Failed to generate id")?;
+            ctx.state.repeats.add(
+                channel,
+                RepeatingTimer {
+                    id: id.clone(),
+                    interval_minutes: interval.whole_minutes(),
+                    text,
+                },
+            );
+            ctx.state
+                .repeats
+                .save()
+                .wrap_err("Failed to save repeat store")?;
+
+            format!(
+                "I'll post it every {} while the stream is live [{}]",
+                humanize::span(Duration::minutes(interval.whole_minutes())),
+                id
+            )
+        }
+        "remove" => {
+            let id = ctx.parts.next().ok_or_else(usage)?;
+
+            if ctx.state.repeats.remove(channel, id) {
+                ctx.state
+                    .repeats
+                    .save()
+                    .wrap_err("Failed to save repeat store")?;
+                "Removed the timer".to_string()
+            } else {
+                "There is no timer with that id in this channel".to_string()
+            }
+        }
+        "list" => {
+            let timers = ctx.state.repeats.timers(channel);
+
+            if timers.is_empty() {
+                "There are no timers in this channel".to_string()
+            } else {
+                timers
+                    .iter()
+                    .map(|timer| {
+                        format!(
+                            "[{}] every {}: {}",
+                            timer.id,
+                            humanize::span(timer.interval()),
+                            preview(&timer.text, 30)
+                        )
+                    })
+                    .intersperse(" | ".to_string())
+                    .collect()
+            }
+        }
+        _ => return Err(usage()),
+    };
+
+    ctx.reply(response).await
+}
+
+/// Handle `~filter add|remove|list [phrase]`, managing the banned phrases of the current channel.
+async fn handle_filter_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let channel = &ctx.privmsg.channel_login;
+    let subcommand = ctx.parts.next().map(|s| s.to_lowercase());
+    let phrase = ctx.parts.by_ref().intersperse(" ").collect::<String>();
+
+    let response = match (subcommand.as_deref(), phrase.is_empty()) {
+        (Some("add"), false) => {
+            let added = ctx.state.filters.add(channel, &phrase);
+            ctx.state
+                .filters
+                .save()
+                .wrap_err("Failed to save filter store")?;
+
+            if added {
+                format!("Reminders containing \"{}\" are now rejected", phrase)
+            } else {
+                format!("\"{}\" is already filtered", phrase)
+            }
+        }
+        (Some("remove"), false) => {
+            let removed = ctx.state.filters.remove(channel, &phrase);
+            ctx.state
+                .filters
+                .save()
+                .wrap_err("Failed to save filter store")?;
+
+            if removed {
+                format!("\"{}\" is no longer filtered", phrase)
+            } else {
+                format!("\"{}\" is not filtered", phrase)
+            }
+        }
+        (Some("list"), _) => {
+            let phrases = ctx.state.filters.phrases(channel);
+
+            if phrases.is_empty() {
+                "No phrases are filtered in this channel".to_string()
+            } else {
+                format!(
+                    "Filtered phrases: {}",
+                    phrases
+                        .iter()
+                        .map(|phrase| format!("\"{}\"", phrase))
+                        .intersperse(", ".to_string())
+                        .collect::<String>()
+                )
+            }
+        }
+        _ => {
+            return Err(eyre!(UserError(
+                "Usage: filter add <phrase> | remove <phrase> | list".to_string()
+            )))
+        }
+    };
+
+    ctx.reply(response).await
+}
+
+/// Handle `~group create <name> <members>|delete <name>|list`. Groups of moderators are shared by
+/// the channel, everyone else creates groups for themselves.
+async fn handle_group_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let login = ctx.privmsg.sender.login.clone();
+    let channel = group_store::channel_owner(&ctx.privmsg.channel_login);
+    let owner = if ctx.role() >= Role::Moderator {
+        channel.clone()
+    } else {
+        login.clone()
+    };
+
+    let subcommand = ctx.parts.next().map(str::to_lowercase);
+    let name = ctx
+        .parts
+        .next()
+        .map(|name| name.trim_start_matches('@').to_lowercase());
+    let members = ctx
+        .parts
+        .by_ref()
+        .flat_map(|part| part.split(','))
+        .map(|member| member.trim_start_matches('@').to_lowercase())
+        .filter(|member| !member.is_empty())
+        .collect::<BTreeSet<_>>();
+
+    let response = match (subcommand.as_deref(), name) {
+        (Some("create"), Some(name)) if !members.is_empty() => {
+            if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(eyre!(UserError(
+                    "Group names may only contain letters, digits and underscores".to_string()
+                )));
+            }
+            if members.len() > MAX_GROUP_MEMBERS {
+                return Err(eyre!(UserError(format!(
+                    "Groups can have at most {} members",
+                    MAX_GROUP_MEMBERS
+                ))));
+            }
+            // the group would take the reminders meant for them
+            if is_known_login(ctx.state, &name).await? {
+                return Err(eyre!(UserError(format!(
+                    "{} is someone's login, pick another name for the group",
+                    name
+                ))));
+            }
+
+            let count = members.len();
+            let replaced = ctx.state.groups.set(&owner, &name, members);
+            ctx.state
+                .groups
+                .save()
+                .wrap_err("Failed to save group store")?;
+
+            format!(
+                "{} @{} with {} members",
+                if replaced { "Updated" } else { "Created" },
+                name,
+                count
+            )
+        }
+        (Some("delete"), Some(name)) => {
+            // moderators may still have groups of their own from before
+            let removed =
+                ctx.state.groups.remove(&owner, &name) || ctx.state.groups.remove(&login, &name);
+            ctx.state
+                .groups
+                .save()
+                .wrap_err("Failed to save group store")?;
+
+            if removed {
+                format!("Deleted @{}", name)
+            } else {
+                format!("There is no group @{}", name)
+            }
+        }
+        (Some("list"), None) => {
+            let own = ctx.state.groups.names(&login);
+            let shared = ctx.state.groups.names(&channel);
+
+            match (own.is_empty(), shared.is_empty()) {
+                (true, true) => "There are no groups yet".to_string(),
+                _ => [("Your groups", own), ("Groups of this channel", shared)]
+                    .into_iter()
+                    .filter(|(_, names)| !names.is_empty())
+                    .map(|(label, names)| format!("{}: {}", label, names.join(", ")))
+                    .intersperse("; ".to_string())
+                    .collect(),
+            }
+        }
+        _ => {
+            return Err(eyre!(UserError(format!(
+                "Usage: {}group create <name> <user,user,...> | delete <name> | list",
+                PREFIX
+            ))))
+        }
+    };
+
+    ctx.reply(response).await
+}
+
+/// Handle `~preview tell <reminder>`, telling the sender how `~tell` reads the reminder without
+/// creating it, to try out the attributes.
+async fn handle_preview_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let channel = ctx.privmsg.channel_login.clone();
+    let author = ctx.privmsg.sender.login.clone();
+
+    let command = ctx
+        .parts
+        .next()
+        .map(|word| ctx.state.config.resolve_command(&channel, word));
+    if command.as_deref() != Some("tell") {
+        return Err(eyre!(UserError(format!(
+            "Only tell can be previewed, e.g. {}preview tell in:2h alice hello",
+            PREFIX
+        ))));
+    }
+
+    let reminders = prepare_reminders(ctx.state, ctx.privmsg, &mut ctx.parts, false, true).await?;
+    let messages = &reminders.messages;
+    let first = messages
+        .first()
+        .ok_or_else(|| eyre!("A checked reminder has no messages"))?;
+
+    let recipients = if reminders.chat {
+        format!("{} chatters", messages.len())
+    } else {
+        let mut recipients = messages
+            .iter()
+            .map(Message::recipient)
+            .map(|recipient| {
+                if recipient == author {
+                    "you"
+                } else {
+                    recipient
+                }
+            })
+            .collect::<Vec<_>>();
+        recipients.sort_unstable();
+        recipients.dedup();
+        recipients.into_iter().intersperse(", ").collect::<String>()
+    };
+
+    // every recipient gets the same schedules
+    let now = OffsetDateTime::now_utc();
+    let schedules = messages
+        .iter()
+        .take_while(|message| message.recipient() == first.recipient())
+        .map(|message| match message.activation() {
+            Activation::Fixed(at) => {
+                let at_text = match message.time_zone().and_then(time_zone::Zone::named) {
+                    Some(zone) => format_local_timestamp(*at, Some(zone.offset_minutes_at(*at))),
+                    None => format_timestamp(*at, now),
+                };
+                format!("{}, at {}", humanize::until(*at - now), at_text)
+            }
+            Activation::OnRaid => format!("when #{} gets raided", reminders.channel),
+            Activation::OnOffline => format!("when #{} goes offline", reminders.channel),
+            _ => "when they next type in chat".to_string(),
+        })
+        .intersperse("; ".to_string())
+        .collect::<String>();
+
+    let mut response = format!("I'd remind {} {}", recipients, schedules);
+    if reminders.explicit_channel {
+        response = format!("{} in #{}", response, reminders.channel);
+    }
+    response = format!("{}: \"{}\"", response, first.text());
+    if !reminders.rejected.is_empty() {
+        response = format!(
+            "{}, but {} can't receive it",
+            response,
+            reminders.rejected.join(", ")
+        );
+    }
+    if let Some(follow_up) = first.follow_up() {
+        response = format!(
+            "{}, then again {} after that",
+            response,
+            humanize::span(follow_up.after)
+        );
+    }
+    if let Some(deadline) = reminders.deadline {
+        response = format!(
+            "{}. I'd tell you if it wasn't delivered {}",
+            response,
+            humanize::until(deadline)
+        );
+    }
+
+    ctx.reply(format!("{}. Nothing was saved", response)).await
+}
+
+/// Handle `~preset`, saving what would follow `~tell` under a name to send it again with
+/// `~preset use <name>`, optionally with more text.
+async fn handle_preset_command(ctx: &mut commands::Context<'_>) -> Result<()> {
+    let login = ctx.privmsg.sender.login.clone();
+    let subcommand = ctx.parts.next().map(str::to_lowercase);
+    let name = ctx.parts.next().map(str::to_lowercase);
+    let rest = ctx.parts.by_ref().intersperse(" ").collect::<String>();
+
+    let response = match (subcommand.as_deref(), name) {
+        (Some("add"), Some(name)) if !rest.is_empty() => {
+            if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(eyre!(UserError(
+                    "Preset names may only contain letters, digits and underscores".to_string()
+                )));
+            }
+            if ctx.state.presets.get(&login, &name).is_none()
+                && ctx.state.presets.names(&login).len() >= MAX_PRESETS
+            {
+                return Err(eyre!(UserError(format!(
+                    "You can have at most {} presets, remove one first",
+                    MAX_PRESETS
+                ))));
+            }
+
+            // quoting the whole reminder keeps it apart from the name
+            let definition = rest
+                .strip_prefix('"')
+                .and_then(|rest| rest.strip_suffix('"'))
+                .unwrap_or(&rest)
+                .trim()
+                .to_string();
+            let zone = ctx.state.settings.get(&login).zone();
+            let language = ctx
+                .state
+                .channel_settings
+                .get(&ctx.privmsg.channel_login)
+                .language
+                .unwrap_or_default();
+            MessageDefinition::parse_localized(&definition, &zone, language).map_err(|err| {
+                ctx.state.parse_failures.record(
+                    &err,
+                    &definition,
+                    ctx.state.config.log_parse_failures,
+                );
+                let hint = err.hint(&definition);
+                eyre::Report::new(err).wrap_err(UserError(hint))
+            })?;
+
+            let replaced = ctx.state.presets.set(&login, &name, definition);
+            ctx.state
+                .presets
+                .save()
+                .wrap_err("Failed to save preset store")?;
+
+            format!(
+                "{} preset {}, send it with {}preset use {}",
+                if replaced { "Updated" } else { "Saved" },
+                name,
+                PREFIX,
+                name
+            )
+        }
+        (Some("use"), Some(name)) => {
+            let definition = ctx
+                .state
+                .presets
+                .get(&login, &name)
+                .ok_or_else(|| eyre!(UserError(format!("There is no preset {}", name))))?;
+            let expanded = if rest.is_empty() {
+                definition
+            } else {
+                format!("{} {}", definition, rest)
+            };
+
+            let response = create_reminders(
+                ctx.state,
+                ctx.client,
+                ctx.privmsg,
+                &mut expanded.split_whitespace(),
+                false,
+            )
+            .await?;
+
+            return ctx.reply(response).await;
+        }
+        (Some("remove"), Some(name)) if rest.is_empty() => {
+            let removed = ctx.state.presets.remove(&login, &name);
+            ctx.state
+                .presets
+                .save()
+                .wrap_err("Failed to save preset store")?;
+
+            if removed {
+                format!("Removed preset {}", name)
+            } else {
+                format!("There is no preset {}", name)
+            }
+        }
+        (Some("list"), None) => {
+            let names = ctx.state.presets.names(&login);
+            if names.is_empty() {
+                "You have no presets yet".to_string()
+            } else {
+                format!("Your presets: {}", names.join(", "))
+            }
+        }
+        _ => {
+            return Err(eyre!(UserError(format!(
+                "Usage: {}preset add <name> <reminder> | use <name> [text] | remove <name> | list",
+                PREFIX
+            ))))
+        }
+    };
+
+    ctx.reply(response).await
+}
+
+/// Check whether `word` addresses the bot, e.g. `@bot`, `bot` or `@bot,`.
+fn is_bot_mention(word: &str, login: &str) -> bool {
+    word.strip_prefix('@')
+        .unwrap_or(word)
+        .trim_end_matches(&[',', ':'][..])
+        .eq_ignore_ascii_case(login)
+}
+
+/// Every chat command with its handler.
+fn command_registry() -> Registry {
+    Registry::new(vec![
+        Command::new("tell", "<user> <message>", |ctx| {
+            Box::pin(handle_tell_command(ctx))
+        }),
+        Command::new("cancel", "<id|#...|filter>", |ctx| {
+            Box::pin(handle_cancel_command(ctx))
+        }),
+        Command::new("undo", "", |ctx| Box::pin(handle_undo_command(ctx))),
+        Command::new("transfer", "<id> <user>", |ctx| {
+            Box::pin(handle_transfer_command(ctx))
+        }),
+        Command::new("snoozeall", "<duration>", |ctx| {
+            Box::pin(handle_snoozeall_command(ctx))
+        }),
+        Command::new("defer", "<duration> [new]|off", |ctx| {
+            Box::pin(handle_defer_command(ctx))
+        }),
+        Command::new("history", "[page]", |ctx| {
+            Box::pin(handle_history_command(ctx))
+        })
+        .with_cooldown(Duration::seconds(10)),
+        Command::new("full", "<id>", |ctx| Box::pin(handle_full_command(ctx)))
+            .with_cooldown(Duration::seconds(5)),
+        Command::new("redeliver", "<id> [whisper]", |ctx| {
+            Box::pin(handle_redeliver_command(ctx))
+        })
+        .with_cooldown(Duration::seconds(5)),
+        Command::new("cancelall", "", |ctx| {
+            Box::pin(handle_destructive_command(ctx, Action::CancelAll))
+        }),
+        Command::new("forgetme", "", |ctx| {
+            Box::pin(handle_destructive_command(ctx, Action::ForgetMe))
+        }),
+        Command::new("yes", "", |ctx| {
+            Box::pin(handle_confirmation_command(ctx, true))
+        }),
+        Command::new("no", "", |ctx| {
+            Box::pin(handle_confirmation_command(ctx, false))
+        }),
+        Command::new("pending", "", |ctx| Box::pin(handle_pending_command(ctx))),
+        Command::new("list", "[page] [tag:<tag>]", |ctx| {
+            Box::pin(handle_list_command(ctx))
+        }),
+        Command::new("find", "<text>", |ctx| Box::pin(handle_find_command(ctx))),
+        Command::new("lastseen", "<user>", |ctx| {
+            Box::pin(handle_lastseen_command(ctx))
+        })
+        .with_aliases(&["seen"]),
+        Command::new("afk", "[reason]", |ctx| Box::pin(handle_afk_command(ctx))),
+        Command::new("watchword", "<keyword>|list|remove <keyword>", |ctx| {
+            Box::pin(handle_watchword_command(ctx))
+        }),
+        Command::new("notify", "<user> [text]", |ctx| {
+            Box::pin(handle_notify_command(ctx))
+        }),
+        Command::new("note", "<text>", |ctx| Box::pin(handle_note_command(ctx))),
+        Command::new("notes", "[page|delete <id>]", |ctx| {
+            Box::pin(handle_notes_command(ctx))
+        }),
+        Command::new("silent", "[on|off]", |ctx| {
+            Box::pin(handle_silent_command(ctx))
+        }),
+        Command::new("settings", "[<setting> [<value>|default]]", |ctx| {
+            Box::pin(handle_settings_command(ctx))
+        }),
+        Command::new("acceptfrom", "[anyone|followers|nobody]", |ctx| {
+            Box::pin(handle_accept_from_command(ctx))
+        }),
+        Command::new("ignore", "[user]", |ctx| {
+            Box::pin(handle_ignore_command(ctx, true))
+        }),
+        Command::new("unignore", "<user>", |ctx| {
+            Box::pin(handle_ignore_command(ctx, false))
+        }),
+        Command::new("timezone", "[<+hh:mm>|<Region/City>|off]", |ctx| {
+            Box::pin(handle_timezone_command(ctx))
+        })
+        .with_aliases(&["tz"]),
+        Command::new("countdown", "<duration> <text>", |ctx| {
+            Box::pin(handle_countdown_command(ctx))
+        })
+        .with_role(Role::Moderator),
+        Command::new(
+            "schedule",
+            "at:<hh:mm>|in:<duration> <text>|list|cancel <id>",
+            |ctx| Box::pin(handle_schedule_command(ctx)),
+        )
+        .with_role(Role::Moderator),
+        Command::new("timer", "add <interval> <text>|remove <id>|list", |ctx| {
+            Box::pin(handle_timer_command(ctx))
+        })
+        .with_role(Role::Moderator),
+        Command::new(
+            "group",
+            "create <name> <user,user,...>|delete <name>|list",
+            |ctx| Box::pin(handle_group_command(ctx)),
+        ),
+        Command::new(
+            "preset",
+            "add <name> <reminder>|use <name> [text]|remove <name>|list",
+            |ctx| Box::pin(handle_preset_command(ctx)),
+        ),
+        Command::new("preview", "tell <user> <message>", |ctx| {
+            Box::pin(handle_preview_command(ctx))
+        }),
+        Command::new("filter", "add|remove <phrase>|list", |ctx| {
+            Box::pin(handle_filter_command(ctx))
+        })
+        .with_role(Role::Moderator),
+        Command::new("beforestream", "<duration>|off", |ctx| {
+            Box::pin(handle_before_stream_command(ctx))
+        })
+        .with_role(Role::Broadcaster),
+        Command::new(
+            "admin",
+            "reload|channels|usage [channel]|purge <user>|say <channel> <text>|pause|resume",
+            |ctx| Box::pin(handle_admin_command(ctx)),
+        )
+        .with_role(Role::Owner)
+        .available_while_paused(),
+        Command::new("set", "<setting> <value>|default", |ctx| {
+            Box::pin(handle_set_command(ctx))
+        })
+        .with_role(Role::Moderator),
+        Command::new("get", "[setting]", |ctx| Box::pin(handle_get_command(ctx)))
+            .with_role(Role::Moderator),
+        Command::new("stats", "", |ctx| Box::pin(handle_stats_command(ctx)))
+            .with_cooldown(Duration::seconds(10)),
+        Command::new("analytics", "", |ctx| {
+            Box::pin(handle_analytics_command(ctx))
+        })
+        .with_role(Role::Moderator)
+        .with_cooldown(Duration::seconds(30)),
+        Command::new("status", "", |ctx| Box::pin(handle_status_command(ctx)))
+            .with_role(Role::Moderator)
+            .with_cooldown(Duration::seconds(10))
+            .available_while_paused(),
+        Command::new("bot", "", |ctx| Box::pin(handle_bot_command(ctx)))
+            .with_cooldown(Duration::seconds(5)),
+        Command::new("help", "[command]", |ctx| {
+            Box::pin(handle_help_command(ctx))
+        })
+        .with_aliases(&["commands"])
+        .with_cooldown(Duration::seconds(5)),
+    ])
+}
+
+async fn handle_commands(
+    state: &mut State,
+    client: &Client,
+    login: &str,
+    privmsg: &PrivmsgMessage,
+) -> Result<()> {
+    let mut parts = privmsg.message_text.split_whitespace();
+
+    // `explicit` is false for mentions, where unknown commands are most likely just chat
+    let (command, explicit) = match parts.next() {
+        Some(word) if word.eq_ignore_ascii_case("!bot") => ("bot", true),
+        Some(word) if word.starts_with(PREFIX) => (
+            word.strip_prefix(PREFIX)
+                .ok_or_else(|| eyre!("Failed to remove prefix"))?,
+            true,
+        ),
+        Some(word) if is_bot_mention(word, login) => match parts.next() {
+            Some(command) => (command, false),
+            None => return Ok(()),
+        },
+        _ => {
+            // message does not start with the prefix
+            return Ok(());
+        }
+    };
+    let name = state
+        .config
+        .resolve_command(&privmsg.channel_login, command);
+
+    let command = match state.commands.find(&name) {
+        Some(command) => *command,
+        None if !explicit => return Ok(()),
+        None => {
+            state.usage.record(&privmsg.channel_login, UNKNOWN);
+            return client
+                .say_in_response(
+                    privmsg.channel_login.clone(),
+                    format!("Unknown command, try {}help", PREFIX),
+                    Some(privmsg.channel_id.clone()),
+                )
+                .await
+                .wrap_err("Failed to send reply");
+        }
+    };
+    state.usage.record(&privmsg.channel_login, command.name);
+    let ctx = commands::Context {
+        state,
+        client,
+        privmsg,
+        parts,
+    };
+
+    let result = commands::dispatch(command, ctx).await;
+
+    if let Err(err) = result {
+        error!("{:?}", err);
+
+        let text = match err.downcast_ref::<UserError>() {
+            Some(user_error) => format!("Error: {}", user_error),
+            None => "Error: Something went wrong, please try again later".to_string(),
+        };
+
+        client
+            .say_in_response(
+                privmsg.channel_login.clone(),
+                text,
+                Some(privmsg.channel_id.clone()),
+            )
+            .await
+            .wrap_err("Failed to send error reply")?;
+    }
+
+    Ok(())
+}
+
+/// Handle a whisper to the bot. With `whisper_commands`, `tell` works there without the prefix
+/// and the confirmation is whispered back, so nobody in chat sees the reminder being written.
+async fn handle_whisper(
+    state: &mut State,
+    client: &Client,
+    whisper: &WhisperMessage,
+) -> Result<()> {
+    // whispers to an account only reading chat aren't meant for the bot
+    if !state.config.whisper_commands
+        || !whisper.recipient_login.eq_ignore_ascii_case(&state.login)
+        || state.config.is_ignored(&whisper.sender.login)
+    {
+        return Ok(());
+    }
+    // replies can only be whispered through Helix
+    let (helix, helix_chat) = match (&state.helix, &state.helix_chat) {
+        (Some(helix), Some(helix_chat)) => (helix.clone(), helix_chat.clone()),
+        _ => {
+            warn!(
+                "Ignoring whispered command, whisper_commands needs helix_client_id and the user id \
+                 of the bot"
+            );
+            return Ok(());
+        }
+    };
+
+    let reply = match run_whispered_command(state, client, &helix, whisper).await {
+        Ok(response) => response,
+        Err(err) => {
+            error!("{:?}", err);
+
+            match err.downcast_ref::<UserError>() {
+                Some(user_error) => format!("Error: {}", user_error),
+                None => "Error: Something went wrong, please try again later".to_string(),
+            }
+        }
+    };
+
+    helix_chat
+        .whisper(&whisper.sender.login, &reply)
+        .await
+        .wrap_err("Failed to reply to whisper")
+}
+
+/// Run the command in `whisper` as if its sender wrote it in the channel the reminder is for,
+/// either the one in `channel:` or the one they receive reminders in.
+async fn run_whispered_command(
+    state: &mut State,
+    client: &Client,
+    helix: &Helix,
+    whisper: &WhisperMessage,
+) -> Result<String> {
+    let mut parts = whisper.message_text.split_whitespace();
+    let name = match parts.next() {
+        Some(word) => state
+            .config
+            .resolve_command("", word.strip_prefix(PREFIX).unwrap_or(word)),
+        None => return Ok("Whisper me tell <user> <message>".to_string()),
+    };
+    let command = match state.commands.find(&name) {
+        Some(command) if command.name == "tell" => *command,
+        _ => {
+            return Err(eyre!(UserError(format!(
+                "Only tell works in whispers, try {}help in chat for the rest",
+                PREFIX
+            ))))
+        }
+    };
+
+    // errors in the definition are reported when the reminder is created
+    let settings = state.settings.get(&whisper.sender.login);
+    let text = sanitize::sanitize(&parts.clone().intersperse(" ").collect::<String>());
+    let channel = MessageDefinition::parse_localized(&text, &settings.zone(), Default::default())
+        .ok()
+        .and_then(|def| def.channel)
+        .or(settings.channel)
+        .ok_or_else(|| {
+            eyre!(UserError(
+                "Tell me where to deliver it with channel:#name before the recipient".to_string()
+            ))
+        })?;
+    if !state.channels.contains(&channel) {
+        return Err(eyre!(UserError(format!(
+            "I can't deliver in #{} because I'm not in that channel",
+            channel
+        ))));
+    }
+
+    let channel_id = helix
+        .user_id(&channel)
+        .await
+        .wrap_err("Failed to get the user id of the channel")?
+        .ok_or_else(|| eyre!("There is no user {}", channel))?;
+    let privmsg = whispered_privmsg(whisper, &channel, &channel_id);
+
+    state.usage.record(&channel, command.name);
+    let mut ctx = commands::Context {
+        state,
+        client,
+        privmsg: &privmsg,
+        parts,
+    };
+    for middleware in commands::MIDDLEWARE {
+        middleware(&command, &mut ctx)?;
+    }
+
+    create_reminders(ctx.state, ctx.client, ctx.privmsg, &mut ctx.parts, true).await
+}
+
+/// A chat message by the sender of `whisper` in `channel`, so whispered commands go through the
+/// same checks as the ones in chat. The sender has no badges there, moderators in the channel
+/// get no more than everyone else.
+fn whispered_privmsg(whisper: &WhisperMessage, channel: &str, channel_id: &str) -> PrivmsgMessage {
+    let sent = chrono::Utc::now();
+
+    PrivmsgMessage {
+        channel_login: channel.to_string(),
+        channel_id: channel_id.to_string(),
+        message_text: whisper.message_text.clone(),
+        is_action: false,
+        sender: whisper.sender.clone(),
+        badge_info: Vec::new(),
+        badges: Vec::new(),
+        bits: None,
+        name_color: whisper.name_color.clone(),
+        emotes: Vec::new(),
+        message_id: format!("whisper-{}", sent.timestamp_millis()),
+        server_timestamp: sent,
+        source: whisper.source.clone(),
+    }
+}
+
+#[instrument(
+    skip(outbox, quiet_hours, style, message),
+    fields(id = message.id(), channel = message.channel(), user = message.recipient())
+)]
+async fn queue_message(
+    outbox: Outbox,
+    quiet_hours: Option<QuietHours>,
+    style: DeliveryStyle,
+    message: Message,
+) -> Result<()> {
+    let Outbox {
+        store,
+        filters,
+        audit,
+        settings,
+        display_names,
+        history,
+        archive,
+        pause,
+        client,
+        ..
+    } = &outbox;
+
+    if let Activation::Fixed(deadline) = message.activation() {
+        if *deadline > OffsetDateTime::now_utc() {
+            debug!("Queuing message");
+
+            clock::sleep_until(*deadline).await;
+        }
+
+        if message.kind() == Kind::Reminder {
+            if let Some(until) = settings
+                .get(message.recipient())
+                .deferred_until(message.created(), OffsetDateTime::now_utc())
+            {
+                debug!("Holding message until the recipient wants it at {}", until);
+
+                clock::sleep_until(until).await;
+            }
+        }
+
+        if let Some(end) =
+            quiet_hours.and_then(|quiet| quiet.end_of_window(OffsetDateTime::now_utc()))
+        {
+            debug!("Holding message until the quiet hours end at {}", end);
+
+            clock::sleep_until(end).await;
+        }
+
+        if pause.is_paused() {
+            debug!("Holding message until the bot is resumed");
+            pause.resumed().await;
+        }
+
+        // don't hold the lock while talking to chat
+        let message = {
+            let mut store = store.lock().await;
+
+            let message = match store.get_by_id(message.id()) {
+                None => {
+                    debug!("Message was removed while queued");
+                    return Ok(());
+                }
+                // the scheduler queues it again for the new time
+                Some(stored) if stored.activation() != message.activation() => {
+                    debug!("Message was rescheduled while queued");
+                    return Ok(());
+                }
+                // it might have been transferred to someone else meanwhile
+                Some(stored) => stored.clone(),
+            };
+
+            let pending = store.get_by_recipient(message.recipient());
+            if delivery::batch(&message, &pending, style.batch_window).is_none() {
+                debug!("Message is delivered together with an earlier one");
+                return Ok(());
+            }
+
+            if !store.claim(&message).wrap_err("Failed to claim message")? {
+                debug!("Message was claimed by another instance");
+                return Ok(());
+            }
+
+            message
+        };
+
+        // the filter might have changed since the message was created
+        if filters
+            .find_match(message.channel(), message.text())
+            .is_some()
+        {
+            info!("Dropping timed message blocked by the channel filter");
+
+            {
+                let mut store = store.lock().await;
+                ensure!(store.remove(&message), "Failed to remove message");
+                store.save().wrap_err("Failed to save store")?;
+            }
+            audit
+                .record(AuditKind::Cancelled, &message)
+                .wrap_err("Failed to write audit log")?;
+
+            return client
+                .say(message.channel().to_string(), blocked_notice(&message))
+                .await
+                .wrap_err("Failed to notify author");
+        }
+
+        if deliver_batch(&outbox, style, &message)
+            .await
+            .wrap_err("Failed to deliver batch")?
+        {
+            return Ok(());
+        }
+
+        info!("Replaying timed message");
+
+        let text = match message.kind() {
+            // notes and notifications are never timed
+            Kind::Reminder | Kind::Note | Kind::Notification => format!(
+                "{} one timed message for you: {}",
+                mention(
+                    &display_names.name(message.recipient()),
+                    message.silent() || settings.get(message.recipient()).silent
+                ),
+                format_deliveries(
+                    &[&message],
+                    style,
+                    settings
+                        .get(message.recipient())
+                        .utc_offset_minutes_at(message.created())
+                )
+            ),
+            Kind::Countdown => format!(
+                "Countdown over: {}",
+                message.expanded_text(OffsetDateTime::now_utc(), style.precision, None)
+            ),
+            Kind::Announcement => {
+                message.expanded_text(OffsetDateTime::now_utc(), style.precision, None)
+            }
+        };
+        let whisper_to = (message.kind() == Kind::Reminder
+            && (message.whisper() || settings.get(message.recipient()).whisper))
+            .then(|| message.recipient());
+        let priority = match message.kind() {
+            Kind::Reminder | Kind::Note | Kind::Notification => SendPriority::Delivery,
+            Kind::Countdown | Kind::Announcement => SendPriority::Announcement,
+        };
+        let sent = match message.kind() {
+            Kind::Announcement => announce(&outbox, message.channel(), text).await,
+            _ => say_or_whisper(&outbox, priority, message.channel(), text, whisper_to).await,
+        };
+        if let Err(err) = sent {
+            error!("{:?}", err.wrap_err("Failed to replay message in chat"));
+
+            {
+                let mut store = store.lock().await;
+                if message.kind() != Kind::Reminder {
+                    info!("Dropping message that can't be delivered later");
+                    store.remove(&message);
+                    return store.save().wrap_err("Failed to save store");
+                }
+
+                info!("Delivering message on the next chat message of the recipient instead");
+                if store.remove(&message) {
+                    store.insert(message.clone().with_activation(Activation::OnNextMessage));
+                }
+                store.save().wrap_err("Failed to save store")?;
+            }
+
+            release_claims(store, &[message]).await;
+            return Ok(());
+        }
+
+        let follow_up = {
+            let mut store = store.lock().await;
+            let follow_up = if store.remove(&message) {
+                insert_follow_up(&mut store, outbox.id_scheme, &message)?
+            } else {
+                debug!("Message was removed while it was delivered");
+                None
+            };
+            store.save().wrap_err("Failed to save store")?;
+            follow_up
+        };
+        audit
+            .record(AuditKind::Delivered, &message)
+            .wrap_err("Failed to write audit log")?;
+        if message.kind() == Kind::Reminder {
+            let now = OffsetDateTime::now_utc();
+            history.record(&message, now);
+            history.save().wrap_err("Failed to save history store")?;
+            if let Some(archive) = archive {
+                if let Err(err) = archive.record(&message, message.channel(), now) {
+                    error!("{:?}", err.wrap_err("Failed to archive message"));
+                }
+            }
+        }
+        queue_follow_ups(&outbox, follow_up).await;
+    }
+
+    Ok(())
+}
+
+/// Deliver `message` together with the timed reminders for its recipient that are due within
+/// [`DeliveryStyle::batch_window`], waiting for the last of them. Returns `false` if there are
+/// none, leaving `message` to the caller. If `message` was cancelled or changed while waiting,
+/// only the others are delivered, since they skipped their own tasks.
+async fn deliver_batch(outbox: &Outbox, style: DeliveryStyle, message: &Message) -> Result<bool> {
+    let latest = {
+        let store = outbox.store.lock().await;
+        let pending = store.get_by_recipient(message.recipient());
+        delivery::batch(message, &pending, style.batch_window)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|other| match other.activation() {
+                Activation::Fixed(at) => Some(*at),
+                _ => None,
+            })
+            .max()
+    };
+    let latest = match latest {
+        Some(latest) => latest,
+        None => return Ok(false),
+    };
+
+    if latest > OffsetDateTime::now_utc() {
+        debug!(
+            "Waiting for reminders due until {} to deliver them together",
+            latest
+        );
+        clock::sleep_until(latest).await;
+    }
+
+    // reminders cancelled or blocked in the meantime are left to their own tasks
+    let mut messages = Vec::new();
+    let current = {
+        let mut store = outbox.store.lock().await;
+        let current =
+            store.get_by_id(message.id()).map(Message::activation) == Some(message.activation());
+        if current {
+            messages.push(message.clone());
+        } else {
+            debug!("Message was cancelled or changed while waiting for its batch");
+        }
+
+        let pending = store.get_by_recipient(message.recipient());
+        let batch = delivery::batch(message, &pending, style.batch_window)
+            .unwrap_or_default()
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        for other in batch {
+            if other.id() != message.id()
+                && outbox
+                    .filters
+                    .find_match(other.channel(), other.text())
+                    .is_none()
+                && store.claim(&other).wrap_err("Failed to claim message")?
+            {
+                messages.push(other);
+            }
+        }
+
+        current
+    };
+    if current && messages.len() == 1 {
+        return Ok(false);
+    }
+    if messages.is_empty() {
+        return Ok(true);
+    }
+
+    info!("Replaying {} timed messages together", messages.len());
+    let recipient = message.recipient();
+    let silent = outbox.settings.get(recipient).silent || messages.iter().all(Message::silent);
+    let heading = format!(
+        "{} {}",
+        mention(&outbox.display_names.name(recipient), silent),
+        format_num(messages.len(), "timed reminder", "timed reminders")
+    );
+    deliver(outbox, message.channel(), None, &heading, style, messages).await?;
+
+    Ok(true)
+}
+
+/// Deliver the timed reminders that became due while the bot was offline, one digest per
+/// recipient and channel marked with how late they are, instead of one chat message each.
+async fn deliver_missed(
+    outbox: &Outbox,
+    timers: &Timers,
+    config: &Config,
+    channel_settings: &ChannelSettingsStore,
+    channels: &BTreeSet<String>,
+) {
+    let groups = {
+        let store = outbox.store.lock().await;
+        let now = OffsetDateTime::now_utc();
+        let pending = store
+            .due_before(now - MISSED_AFTER)
+            .into_iter()
+            .filter(|message| channels.contains(message.channel()))
+            // left to the scheduler, which holds them until the deferral ends
+            .filter(|message| {
+                message.kind() != Kind::Reminder
+                    || outbox
+                        .settings
+                        .get(message.recipient())
+                        .deferred_until(message.created(), now)
+                        .is_none()
+            })
+            .collect::<Vec<_>>();
+
+        // the timers keep the scheduler from queuing them on its own
+        delivery::missed(&pending, now)
+            .into_iter()
+            .map(|group| {
+                group
+                    .into_iter()
+                    .filter(|message| timers.start(message.id()))
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .filter(|group| !group.is_empty())
+            .collect::<Vec<_>>()
+    };
+
+    for messages in groups {
+        let outbox = outbox.clone();
+        let timers = timers.clone();
+        let channel = messages[0].channel().to_string();
+        let quiet_hours = config.quiet_hours.get(&channel).copied();
+        let style = DeliveryStyle {
+            missed: true,
+            ..channel_settings
+                .get(&channel)
+                .apply(config.delivery_style(&channel))
+        };
+
+        tokio::spawn(async move {
+            let ids = messages
+                .iter()
+                .map(|message| message.id().to_string())
+                .collect::<Vec<_>>();
+            if let Err(err) = deliver_missed_digest(&outbox, quiet_hours, style, messages)
+                .await
+                .wrap_err_with(|| format!("Failed to deliver missed reminders in {}", channel))
+            {
+                error!("{:?}", err);
+            }
+
+            for id in &ids {
+                timers.finish(id);
+            }
+        });
+    }
+}
+
+/// Deliver `messages`, missed reminders for the same recipient in the same channel, as one digest
+/// once the quiet hours are over and the bot isn't paused.
+async fn deliver_missed_digest(
+    outbox: &Outbox,
+    quiet_hours: Option<QuietHours>,
+    style: DeliveryStyle,
+    messages: Vec<Message>,
+) -> Result<()> {
+    if let Some(end) = quiet_hours.and_then(|quiet| quiet.end_of_window(OffsetDateTime::now_utc()))
+    {
+        debug!(
+            "Holding missed reminders until the quiet hours end at {}",
+            end
+        );
+        clock::sleep_until(end).await;
+    }
+
+    if outbox.pause.is_paused() {
+        debug!("Holding missed reminders until the bot is resumed");
+        outbox.pause.resumed().await;
+    }
+
+    // reminders cancelled or blocked in the meantime are left to the scheduler
+    let mut claimed = Vec::new();
+    {
+        let mut store = outbox.store.lock().await;
+        for message in messages {
+            if store.get_by_id(message.id()).is_some()
+                && outbox
+                    .filters
+                    .find_match(message.channel(), message.text())
+                    .is_none()
+                && store.claim(&message).wrap_err("Failed to claim message")?
+            {
+                claimed.push(message);
+            }
+        }
+    }
+    let (recipient, channel) = match claimed.first() {
+        Some(message) => (
+            message.recipient().to_string(),
+            message.channel().to_string(),
+        ),
+        None => return Ok(()),
+    };
+
+    info!(
+        "Delivering {} reminders missed while offline",
+        claimed.len()
+    );
+    let silent = outbox.settings.get(&recipient).silent || claimed.iter().all(Message::silent);
+    let heading = format!(
+        "{} {} missed while I was offline",
+        mention(&outbox.display_names.name(&recipient), silent),
+        format_num(claimed.len(), "reminder", "reminders")
+    );
+    deliver(outbox, &channel, None, &heading, style, claimed).await
+}
+
+/// Queue the delivery of `message` unless it already has an active timer.
+async fn spawn_queue_message_task(
+    outbox: Outbox,
+    timers: Timers,
+    quiet_hours: Option<QuietHours>,
+    style: DeliveryStyle,
+    message: Message,
+) {
+    let id = message.id().to_string();
+
+    if !timers.start(&id) {
+        debug!("Message {} is already queued", id);
+        return;
+    }
+
+    tokio::spawn(async move {
+        if let Err(err) = queue_message(outbox, quiet_hours, style, message)
+            .await
+            .wrap_err_with(|| format!("Failed to handle scheduled message {}", id))
+        {
+            error!("{:?}", err);
+        }
+
+        timers.finish(&id);
+    });
+}
+
+#[instrument(
+    skip(state, client, login, privmsg),
+    fields(channel = privmsg.channel_login.as_str(), user = privmsg.sender.login.as_str())
+)]
+async fn handle_privmsg(
+    state: &mut State,
+    client: &Client,
+    login: &str,
+    privmsg: &PrivmsgMessage,
+) -> Result<()> {
+    let (notifications, mut messages): (Vec<Message>, Vec<Message>) = claim_pending(
+        state,
+        client,
+        &privmsg.channel_login,
+        &privmsg.sender.login,
+        Some(privmsg.channel_id.clone()),
+        Presence::Typed,
+    )
+    .await?
+    .into_iter()
+    .partition(|message| message.kind() == Kind::Notification);
+    if !notifications.is_empty() {
+        spawn_notifications(
+            state,
+            client,
+            &privmsg.channel_login,
+            &privmsg.sender.name,
+            notifications,
+        );
+    }
+    state
+        .delivered
+        .append(&privmsg.sender.login, messages.iter().cloned().collect());
+
+    if let Some(status) = state.afk.pop(&privmsg.sender.login) {
+        state.afk.save().wrap_err("Failed to save afk store")?;
+
+        let elapsed = humanize::ago(
+            OffsetDateTime::now_utc() - status.since,
+            state.config.duration_precision(&privmsg.channel_login),
+        );
+        let response = if status.reason.is_empty() {
+            format!(
+                "{} is no longer afk (went afk {})",
+                privmsg.sender.name, elapsed
+            )
+        } else {
+            format!(
+                "{} is no longer afk (went afk {}): {}",
+                privmsg.sender.name, elapsed, status.reason
+            )
+        };
+
+        client
+            .say_in_response(
+                privmsg.channel_login.clone(),
+                response,
+                Some(privmsg.channel_id.clone()),
+            )
+            .await
+            .wrap_err("Failed to send reply")?;
+    }
+
+    // before commands so `~forgetme` also forgets this message
+    state.seen.see(
+        &privmsg.sender.login,
+        &privmsg.channel_login,
+        OffsetDateTime::now_utc(),
+    );
+    state
+        .display_names
+        .see(&privmsg.sender.login, &privmsg.sender.name);
+
+    handle_commands(state, client, login, privmsg)
+        .await
+        .wrap_err("Failed to handle commands")?;
+
+    state.recent.push(
+        &privmsg.channel_login,
+        &privmsg.sender.login,
+        &privmsg.message_text,
+    );
+
+    // commands aren't part of the conversation
+    if !privmsg.message_text.starts_with(PREFIX) {
+        spawn_watch_pings(state, client, privmsg);
+    }
+
+    // `~snoozeall` takes the reminders it defers out of the buffer
+    messages.retain(|message| {
+        state
+            .delivered
+            .contains(&privmsg.sender.login, message.id())
+    });
+
+    // don't hold up the chat of every channel while sending
+    if !messages.is_empty() {
+        let heading = delivery_heading(
+            state,
+            &privmsg.sender.login,
+            &privmsg.sender.name,
+            &messages,
+        );
+
+        spawn_delivery(
+            state,
+            client,
+            privmsg.channel_login.clone(),
+            Some(privmsg.channel_id.clone()),
+            heading,
+            messages,
+        );
+    }
+
+    Ok(())
+}
+
+/// Claim the reminders of `recipient` that can be delivered in `channel`, registering them with
+/// [`State::timers`]. If the recipient only joined the channel, just the reminders that asked for
+/// it are claimed, notifications only once they typed. Reminders blocked by the filter of the
+/// channel are dropped instead and their authors told so.
+async fn claim_pending(
+    state: &State,
+    client: &Client,
+    channel: &str,
+    recipient: &str,
+    reply_to: Option<String>,
+    presence: Presence,
+) -> Result<Vec<Message>> {
+    // they stay pending until the recipient shows up after the bot is resumed
+    if state.pause.is_paused() {
+        return Ok(Vec::new());
+    }
+    let settings = state.settings.get(recipient);
+
+    let messages = {
+        let mut store = state.store.lock().await;
+        let mut messages = store.get_pending(recipient, channel);
+        // `here:` reminders can only be delivered where they were written
+        messages.retain(|message| message.here() || settings.delivers_in(channel));
+        match presence {
+            Presence::Typed => {}
+            Presence::Joined => {
+                messages.retain(|message| delivery::deliverable_on_join(message, channel))
+            }
+            Presence::Listed => messages.retain(|message| message.kind() != Kind::Notification),
+        }
+
+        // the recipient asked for them later with `~defer`
+        let now = OffsetDateTime::now_utc();
+        messages.retain(|message| settings.deferred_until(message.created(), now).is_none());
+
+        // another instance might have seen the recipient first
+        messages.retain(|message| match store.claim(message) {
+            Ok(claimed) => claimed,
+            Err(err) => {
+                error!("{:?}", err.wrap_err("Failed to claim message"));
+                true
+            }
+        });
+
+        // an earlier message of the recipient might still be delivering them
+        messages.retain(|message| state.timers.start(message.id()));
+
+        messages
+    };
+
+    drop_blocked(state, client, channel, reply_to, messages).await
+}
+
+/// Remove the claimed `messages` the filter of `channel` blocks, telling their authors, and
+/// return the others. The filter might have changed since the messages were created.
+async fn drop_blocked(
+    state: &State,
+    client: &Client,
+    channel: &str,
+    reply_to: Option<String>,
+    messages: Vec<Message>,
+) -> Result<Vec<Message>> {
+    let filters = &state.filters;
+    let (blocked, messages): (Vec<Message>, Vec<Message>) = messages
+        .into_iter()
+        .partition(|message| filters.find_match(channel, message.text()).is_some());
+    for message in &blocked {
+        info!(
+            "Dropping message {} blocked by the channel filter",
+            message.id()
+        );
+
+        {
+            let mut store = state.store.lock().await;
+            store.remove(message);
+            store.save().wrap_err("Failed to save store")?;
+        }
+        state.timers.finish(message.id());
+        state
+            .audit
+            .record(AuditKind::Cancelled, message)
+            .wrap_err("Failed to write audit log")?;
+        client
+            .say_in_response(
+                channel.to_string(),
+                blocked_notice(message),
+                reply_to.clone(),
+            )
+            .await
+            .wrap_err("Failed to notify author")?;
+    }
+
+    Ok(messages)
+}
+
+/// Introduce the delivery of `messages` to `recipient`, who goes by `name`.
+fn delivery_heading(state: &State, recipient: &str, name: &str, messages: &[Message]) -> String {
+    format!(
+        "{} {}",
+        mention(name, is_silent(state, recipient, messages)),
+        format_num(messages.len(), "reminder", "reminders")
+    )
+}
+
+/// Deliver `messages` in a separate task, once the bot isn't paused. Their ids have to be
+/// registered with [`State::timers`] already and are released once the delivery is done.
+fn spawn_delivery(
+    state: &State,
+    client: &Client,
+    channel: String,
+    reply_to: Option<String>,
+    heading: String,
+    messages: Vec<Message>,
+) {
+    let outbox = state.outbox(client);
+    let timers = state.timers.clone();
+    let style = state.delivery_style(&channel);
+    let quiet_hours = state.config.quiet_hours.get(&channel).copied();
+    for message in &messages {
+        timers.sending(message.id());
+    }
+
+    tokio::spawn(
+        async move {
+            let sent = messages
+                .iter()
+                .map(|message| (message.id().to_string(), *message.activation()))
+                .collect::<Vec<_>>();
+
+            if outbox.pause.is_paused() {
+                debug!("Holding delivery until the bot is resumed");
+                outbox.pause.resumed().await;
+            }
+
+            if let Err(err) = deliver(
+                &outbox,
+                &channel,
+                reply_to.as_deref(),
+                &heading,
+                style,
+                messages,
+            )
+            .await
+            {
+                error!("{:?}", err);
+            }
+
+            for (id, _) in &sent {
+                timers.finish(id);
+            }
+
+            // `~snoozeall` moved these while they were being sent
+            let snoozed = {
+                let store = outbox.store.lock().await;
+                sent.iter()
+                    .filter_map(|(id, activation)| {
+                        store
+                            .get_by_id(id)
+                            .filter(|stored| stored.activation() != activation)
+                            .cloned()
+                    })
+                    .collect::<Vec<_>>()
+            };
+            for message in snoozed {
+                spawn_queue_message_task(
+                    outbox.clone(),
+                    timers.clone(),
+                    quiet_hours,
+                    style,
+                    message,
+                )
+                .await;
+            }
+        }
+        .in_current_span(),
+    );
+}
+
+/// Ping everyone watching for a keyword `privmsg` contains, in a separate task. Every watch fires
+/// once, watches whose ping couldn't be sent are put back.
+fn spawn_watch_pings(state: &State, client: &Client, privmsg: &PrivmsgMessage) {
+    let watches = state.watches.take_matches(
+        &privmsg.channel_login,
+        &privmsg.sender.login,
+        &privmsg.message_text,
+    );
+    if watches.is_empty() {
+        return;
+    }
+    if let Err(err) = state.watches.save() {
+        error!("{:?}", err.wrap_err("Failed to save watch store"));
+    }
+
+    let store = state.watches.clone();
+    let display_names = state.display_names.clone();
+    let settings = state.settings.clone();
+    let client = client.clone();
+    let channel = privmsg.channel_login.clone();
+    let sender = privmsg.sender.name.clone();
+    let text = preview(&privmsg.message_text, 100);
+
+    tokio::spawn(
+        async move {
+            let mut failed = false;
+            for watch in watches {
+                info!("Pinging {} about {:?}", watch.login, watch.keyword);
+
+                let ping = format!(
+                    "{} {} said \"{}\": {}",
+                    mention(
+                        &display_names.name(&watch.login),
+                        settings.get(&watch.login).silent
+                    ),
+                    sender,
+                    watch.keyword,
+                    text
+                );
+                if let Err(err) = client
+                    .say_with_priority(SendPriority::Delivery, channel.clone(), ping, None)
+                    .await
+                {
+                    error!("{:?}", err.wrap_err("Failed to send ping"));
+                    failed |= store.put(&channel, watch);
+                }
+            }
+
+            if failed {
+                if let Err(err) = store.save() {
+                    error!("{:?}", err.wrap_err("Failed to save watch store"));
+                }
+            }
+        }
+        .in_current_span(),
+    );
+}
+
+/// Tell the authors of `notifications` that their recipient, who goes by `name`, just typed in
+/// `channel`, in a separate task. Their ids have to be registered with [`State::timers`] already
+/// and are released once they were sent.
+fn spawn_notifications(
+    state: &State,
+    client: &Client,
+    channel: &str,
+    name: &str,
+    notifications: Vec<Message>,
+) {
+    let outbox = state.outbox(client);
+    let timers = state.timers.clone();
+    let channel = channel.to_string();
+    let name = name.to_string();
+
+    tokio::spawn(
+        async move {
+            for message in notifications {
+                if let Err(err) = notify(&outbox, &channel, &name, &message).await {
+                    error!("{:?}", err);
+                }
+                timers.finish(message.id());
+            }
+        }
+        .in_current_span(),
+    );
+}
+
+/// Tell the author of the notification `message` that its recipient, who goes by `name`, typed
+/// in `channel`. The notification is sent where it was set up and removed once that worked.
+async fn notify(outbox: &Outbox, channel: &str, name: &str, message: &Message) -> Result<()> {
+    let author = message.author();
+    let settings = outbox.settings.get(author);
+
+    let mut text = format!(
+        "{} {} just chatted in #{}",
+        mention(&outbox.display_names.name(author), settings.silent),
+        name,
+        channel
+    );
+    if !message.text().is_empty() {
+        text = format!("{}: {}", text, message.text());
+    }
+
+    let sent = say_or_whisper(
+        outbox,
+        SendPriority::Delivery,
+        message.channel(),
+        text,
+        settings.whisper.then(|| author),
+    )
+    .await;
+    if let Err(err) = sent {
+        release_claims(&outbox.store, std::slice::from_ref(message)).await;
+        return Err(err).wrap_err("Failed to send notification");
+    }
+
+    {
+        let mut store = outbox.store.lock().await;
+        if !store.remove(message) {
+            debug!("Notification was removed while it was sent");
+        }
+        store.save().wrap_err("Failed to save store")?;
+    }
+    outbox
+        .audit
+        .record(AuditKind::Delivered, message)
+        .wrap_err("Failed to write audit log")
+}
+
+/// Deliver the reminders waiting for a raid on the channel of `notice`.
+async fn handle_raid(
+    state: &State,
+    client: &Client,
+    notice: &UserNoticeMessage,
+    viewer_count: u64,
+) -> Result<()> {
+    info!(
+        "{} raided {} with {} viewers",
+        notice.sender.login, notice.channel_login, viewer_count
+    );
+
+    let event = format!(
+        "{} is raiding with {} viewers",
+        notice.sender.name, viewer_count
+    );
+    deliver_triggered(
+        state,
+        client,
+        &notice.channel_login,
+        Activation::OnRaid,
+        &event,
+    )
+    .await
+}
+
+/// Deliver the reminders waiting for the stream of `channel` to end.
+async fn handle_stream_offline(state: &State, client: &Client, channel: &str) -> Result<()> {
+    info!("{} went offline", channel);
+
+    let event = format!("{} went offline", channel);
+    deliver_triggered(state, client, channel, Activation::OnOffline, &event).await
+}
+
+/// Deliver the reminders in `channel` waiting for `activation`, one chat message per recipient
+/// mentioning `event`. The event won't come again, so while paused they are claimed right away
+/// and delivered once the bot is resumed.
+async fn deliver_triggered(
+    state: &State,
+    client: &Client,
+    channel: &str,
+    activation: Activation,
+    event: &str,
+) -> Result<()> {
+    if state.pause.is_paused() {
+        info!("Holding reminders for {} until resumed", event);
+    }
+
+    let messages = {
+        let mut store = state.store.lock().await;
+        let mut messages = store
+            .get_triggered(channel, &activation)
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        messages.retain(|message| match store.claim(message) {
+            Ok(claimed) => claimed,
+            Err(err) => {
+                error!("{:?}", err.wrap_err("Failed to claim message"));
+                true
+            }
+        });
+        messages.retain(|message| state.timers.start(message.id()));
+
+        messages
+    };
+    let messages = drop_blocked(state, client, channel, None, messages).await?;
+
+    let mut by_recipient: HashMap<String, Vec<Message>> = HashMap::new();
+    for message in messages {
+        by_recipient
+            .entry(message.recipient().to_string())
+            .or_default()
+            .push(message);
+    }
+
+    for (recipient, messages) in by_recipient {
+        let silent = is_silent(state, &recipient, &messages);
+        spawn_delivery(
+            state,
+            client,
+            channel.to_string(),
+            None,
+            format!(
+                "{} {}",
+                mention(&state.display_names.name(&recipient), silent),
+                event
+            ),
+            messages,
+        );
+    }
+
+    Ok(())
+}
+
+/// Whether `messages` should be delivered to `recipient` without mentioning them, either
+/// because they asked for it or because every message was sent with `silent:true`.
+fn is_silent(state: &State, recipient: &str, messages: &[Message]) -> bool {
+    state.settings.get(recipient).silent || messages.iter().all(Message::silent)
+}
+
+/// Address `name` in a delivery, leaving out the `@` if they shouldn't be notified.
+fn mention(name: &str, silent: bool) -> String {
+    if silent {
+        name.to_string()
+    } else {
+        format!("@{}", name)
+    }
+}
+
+/// Send `messages` to `channel` after `heading` and remove them once every chunk was sent. If
+/// sending fails they stay pending and their claims are released.
+#[instrument(
+    skip(outbox, channel, reply_to, heading, style, messages),
+    fields(channel = channel, user = messages.iter().next().map_or("", Message::recipient))
+)]
+async fn deliver(
+    outbox: &Outbox,
+    channel: &str,
+    reply_to: Option<&str>,
+    heading: &str,
+    style: DeliveryStyle,
+    mut messages: Vec<Message>,
+) -> Result<()> {
+    // the heading and the time zone are taken from the oldest message
+    message_store::sort_oldest_first(&mut messages);
+    info!(
+        "Replaying messages: {}",
+        messages
+            .iter()
+            .map(|m| m.id())
+            .intersperse(",")
+            .collect::<String>()
+    );
+
+    let utc_offset_minutes = messages.iter().next().and_then(|message| {
+        outbox
+            .settings
+            .get(message.recipient())
+            .utc_offset_minutes_at(message.created())
+    });
+    let text = format_deliveries(
+        &messages.iter().collect::<Vec<_>>(),
+        style,
+        utc_offset_minutes,
+    );
+
+    // every chunk starts with the heading, so it's clear who continuations are for and the
+    // text can't start with a chat command
+    let whisper_to = messages
+        .iter()
+        .next()
+        .map(Message::recipient)
+        .filter(|recipient| {
+            outbox.settings.get(recipient).whisper || messages.iter().all(Message::whisper)
+        });
+    let prefix = format!("{}: ", heading);
+    let budget = style.max_message_bytes.saturating_sub(prefix.len());
+
+    for chunk in chunker::split(&text, budget) {
+        let text = format!("{}{}", prefix, chunk);
+        let sent = match whisper_to {
+            Some(recipient) => whisper(outbox.helix_chat.as_ref(), recipient, &text).await,
+            None => {
+                say_with_retry(
+                    &outbox.client,
+                    SendPriority::Delivery,
+                    channel,
+                    text,
+                    reply_to,
+                )
+                .await
+            }
+        };
+
+        // the messages stay pending, so they are retried next time
+        if let Err(err) = sent {
+            release_claims(&outbox.store, &messages).await;
+            return Err(err).wrap_err("Failed to deliver messages");
+        }
+    }
+
+    let mut follow_ups = Vec::new();
+    {
+        let mut store = outbox.store.lock().await;
+        for message in &messages {
+            // unless `~snoozeall` moved it while it was being sent
+            let unchanged = store
+                .get_by_id(message.id())
+                .map_or(false, |stored| stored.activation() == message.activation());
+            if unchanged {
+                store.remove(message);
+                follow_ups.extend(insert_follow_up(&mut store, outbox.id_scheme, message)?);
+            }
+        }
+        store.save().wrap_err("Failed to save store")?;
+    }
+
+    let now = OffsetDateTime::now_utc();
+    for message in &messages {
+        outbox
+            .audit
+            .record(AuditKind::Delivered, message)
+            .wrap_err("Failed to write audit log")?;
+        outbox.history.record(message, now);
+        if let Some(archive) = &outbox.archive {
+            if let Err(err) = archive.record(message, channel, now) {
+                error!("{:?}", err.wrap_err("Failed to archive message"));
+            }
+        }
+    }
+    outbox
+        .history
+        .save()
+        .wrap_err("Failed to save history store")?;
+    queue_follow_ups(outbox, follow_ups).await;
+
+    Ok(())
+}
+
+/// Put the follow-up of the delivered reminder `parent` into `store`, in the same change that
+/// removes `parent`, so a restart in between doesn't lose the rest of the chain. Returns the
+/// follow-up, if `parent` has one.
+fn insert_follow_up(
+    store: &mut MessageStore,
+    id_scheme: IdGenerator,
+    parent: &Message,
+) -> Result<Option<Message>> {
+    if parent.kind() != Kind::Reminder || parent.follow_up().is_none() {
+        return Ok(None);
+    }
+
+    let id = id_scheme.generate().wrap_err("Failed to generate id")?;
+    let message = match parent.next_in_chain(id, OffsetDateTime::now_utc()) {
+        Some(message) => message,
+        None => return Ok(None),
+    };
+    info!("Creating follow-up {} of {}", message.id(), parent.id());
+    store.insert(message.clone());
+
+    Ok(Some(message))
+}
+
+/// Hand the stored `follow_ups` to the main loop, which queues them. Whatever doesn't get there
+/// is picked up by the scheduler after the next start.
+async fn queue_follow_ups(outbox: &Outbox, follow_ups: impl IntoIterator<Item = Message>) {
+    for message in follow_ups {
+        if let Err(err) = outbox.follow_ups.send(message).await {
+            warn!(
+                "Not queueing follow-up {}, the bot is shutting down",
+                err.0.id()
+            );
+        }
+    }
+}
+
+/// Give up the claims on `messages` after failing to deliver them, so whichever instance sees
+/// their recipient next delivers them.
+async fn release_claims(store: &SharedStore, messages: &[Message]) {
+    let store = store.lock().await;
+    for message in messages {
+        if let Err(err) = store.release(message) {
+            error!("{:?}", err.wrap_err("Failed to release claim"));
+        }
+    }
+}
+
+/// Send `text` to `channel`, optionally as a reply, retrying with exponential backoff if
+/// sending fails.
+async fn say_with_retry(
+    client: &Client,
+    priority: SendPriority,
+    channel: &str,
+    text: String,
+    reply_to: Option<&str>,
+) -> Result<()> {
+    let mut backoff = SEND_BACKOFF;
+
+    for attempt in 1.. {
+        match client
+            .say_with_priority(
+                priority,
+                channel.to_string(),
+                text.clone(),
+                reply_to.map(str::to_string),
+            )
+            .await
+        {
+            Ok(()) => return Ok(()),
+            Err(err) if attempt < SEND_ATTEMPTS => {
+                warn!(
+                    "Failed to send message (attempt {}/{}): {}",
+                    attempt, SEND_ATTEMPTS, err
+                );
+                sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(err) => return Err(err).wrap_err("Failed to send message"),
+        }
+    }
+
+    unreachable!()
+}
+
+/// Whisper `text` to `login` through Helix, since Twitch ignores `/w` in chat.
+async fn whisper(helix_chat: Option<&HelixChat>, login: &str, text: &str) -> Result<()> {
+    helix_chat
+        .ok_or_else(|| eyre!("Whispers need helix_client_id and the user id of the bot"))?
+        .whisper(login, text)
+        .await
+}
+
+/// Whisper `text` to `whisper_to` if set, otherwise send it to `channel` like [`say_with_retry`].
+async fn say_or_whisper(
+    outbox: &Outbox,
+    priority: SendPriority,
+    channel: &str,
+    text: String,
+    whisper_to: Option<&str>,
+) -> Result<()> {
+    match whisper_to {
+        Some(login) => whisper(outbox.helix_chat.as_ref(), login, &text).await,
+        None => say_with_retry(&outbox.client, priority, channel, text, None).await,
+    }
+}
+
+/// Post `text` as an announcement in `channel` through Helix. Without Helix, or if Twitch refuses
+/// because the bot isn't a moderator there, it is sent as a plain chat message instead.
+async fn announce(outbox: &Outbox, channel: &str, text: String) -> Result<()> {
+    if let Some(helix_chat) = &outbox.helix_chat {
+        match helix_chat.announce(channel, &text).await {
+            Ok(()) => return Ok(()),
+            Err(err) => warn!(
+                "{:?}",
+                err.wrap_err("Failed to announce, sending a chat message instead")
+            ),
+        }
+    }
+
+    say_with_retry(
+        &outbox.client,
+        SendPriority::Announcement,
+        channel,
+        defuse(&text),
+        None,
+    )
+    .await
+}
+
+/// Check whether `privmsg` was sent by another bot, either one we know of or one that wears a bot
+/// badge.
+fn is_from_bot(state: &State, privmsg: &PrivmsgMessage) -> bool {
+    state.config.is_ignored(&privmsg.sender.login)
+        || privmsg.badges.iter().any(|badge| badge.name == "bot-badge")
+}
+
+/// Tell the author of `message` that it won't be delivered because of the channel filter.
+fn blocked_notice(message: &Message) -> String {
+    format!(
+        "@{} your reminder for {} [{}] was not delivered because it contains a phrase filtered in this channel",
+        message.author(),
+        message.recipient(),
+        message.id()
+    )
+}
+
+/// Poll which of the channels in `joins` are live, keeping `live` up to date and sending the ones
+/// whose stream ended to `offline`. Channels joined or parted later are picked up on the next
+/// poll.
+async fn watch_streams(
+    helix: Helix,
+    joins: Joins,
+    live: LiveChannels,
+    offline: mpsc::Sender<String>,
+) {
+    let mut interval = tokio::time::interval(STREAM_POLL_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let now_live = match helix.live_channels(&joins.joined()).await {
+            Ok(now_live) => now_live,
+            Err(err) => {
+                error!("{:?}", err.wrap_err("Failed to poll streams"));
+                continue;
+            }
+        };
+
+        let ended = {
+            let mut live = live.write().unwrap();
+            let ended = live.difference(&now_live).cloned().collect::<Vec<_>>();
+            *live = now_live;
+
+            ended
+        };
+
+        for channel in ended {
+            if offline.send(channel).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Read the stream schedules of every watched channel and send the segments whose reminder is
+/// due before the next poll to `upcoming`.
+async fn watch_schedules(
+    helix: Helix,
+    schedules: ScheduleStore,
+    upcoming: mpsc::Sender<UpcomingSegment>,
+) {
+    let mut interval = tokio::time::interval(SCHEDULE_POLL_INTERVAL);
+    // twice the interval so a slow poll can't skip a segment
+    let horizon = Duration::try_from(SCHEDULE_POLL_INTERVAL * 2).unwrap();
+
+    loop {
+        interval.tick().await;
+
+        for (channel, watch) in schedules.all() {
+            let segments = match helix.schedule(&watch.broadcaster_id).await {
+                Ok(segments) => segments,
+                Err(err) => {
+                    error!(
+                        "{:?}",
+                        err.wrap_err(format!("Failed to read schedule of {}", channel))
+                    );
+                    continue;
+                }
+            };
+
+            let now = OffsetDateTime::now_utc();
+            let ids = segments
+                .iter()
+                .map(|segment| segment.id.clone())
+                .collect::<Vec<_>>();
+
+            for segment in segments {
+                let at = segment.start - watch.lead();
+                if at <= now || at > now + horizon || watch.reminded.contains(&segment.id) {
+                    continue;
+                }
+
+                let segment = UpcomingSegment {
+                    channel: channel.clone(),
+                    segment,
+                    upcoming: ids.clone(),
+                };
+                if upcoming.send(segment).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Read the chatter lists of `channels` and send everyone who wasn't there on the previous poll
+/// to `arrivals`. The first poll of a channel only records who is there, otherwise everyone
+/// already watching when the bot starts would get their reminders at once. `login` has to be a
+/// moderator in every channel.
+async fn watch_chatters(
+    helix: Helix,
+    login: String,
+    channels: Vec<String>,
+    arrivals: mpsc::Sender<Arrivals>,
+) {
+    let mut interval = tokio::time::interval(CHATTERS_POLL_INTERVAL);
+    let mut ids: HashMap<String, String> = HashMap::new();
+    let mut present: HashMap<String, HashSet<String>> = HashMap::new();
+
+    loop {
+        interval.tick().await;
+
+        let moderator_id = match user_id_cached(&helix, &mut ids, &login).await {
+            Some(id) => id,
+            None => continue,
+        };
+
+        for channel in &channels {
+            let broadcaster_id = match user_id_cached(&helix, &mut ids, channel).await {
+                Some(id) => id,
+                None => continue,
+            };
+
+            let chatters = match helix.chatters(&broadcaster_id, &moderator_id).await {
+                Ok(chatters) => chatters,
+                Err(err) => {
+                    error!(
+                        "{:?}",
+                        err.wrap_err(format!("Failed to read chatters of {}", channel))
+                    );
+                    continue;
+                }
+            };
+
+            let previous = match present.insert(channel.clone(), chatters.clone()) {
+                Some(previous) => previous,
+                None => continue,
+            };
+            let logins = chatters
+                .into_iter()
+                .filter(|chatter| {
+                    !chatter.eq_ignore_ascii_case(&login) && !previous.contains(chatter)
+                })
+                .collect::<Vec<_>>();
+            if logins.is_empty() {
+                continue;
+            }
+
+            let arrived = Arrivals {
+                channel: channel.clone(),
+                logins,
+            };
+            if arrivals.send(arrived).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Look up the user id of `login`, remembering it in `ids`.
+async fn user_id_cached(
+    helix: &Helix,
+    ids: &mut HashMap<String, String>,
+    login: &str,
+) -> Option<String> {
+    if let Some(id) = ids.get(login) {
+        return Some(id.clone());
+    }
+
+    match helix.user_id(login).await {
+        Ok(Some(id)) => {
+            ids.insert(login.to_string(), id.clone());
+            Some(id)
+        }
+        Ok(None) => {
+            warn!("There is no user {}", login);
+            None
+        }
+        Err(err) => {
+            error!(
+                "{:?}",
+                err.wrap_err(format!("Failed to get user id of {}", login))
+            );
+            None
         }
-        store.insert(message);
     }
+}
 
-    store.save().wrap_err("Failed to save store")?;
+/// Deliver the reminders waiting for users who just showed up in a chatter list.
+async fn handle_arrivals(state: &State, client: &Client, arrivals: Arrivals) -> Result<()> {
+    let Arrivals { channel, logins } = arrivals;
 
-    client
-        .say_in_response(
-            privmsg.channel_login.clone(),
-            response,
-            Some(privmsg.channel_id.clone()),
-        )
-        .await
-        .wrap_err("Failed to send reply")
+    for login in logins {
+        if !state.config.is_ignored(&login) {
+            deliver_on_arrival(state, client, &channel, &login, false).await?;
+        }
+    }
+
+    Ok(())
 }
 
-async fn handle_bot_command(client: &Client, privmsg: &PrivmsgMessage) -> Result<()> {
-    client
-        .say_in_response(
-            privmsg.channel_login.clone(),
-            format!(
-                "I let you leave messages for others. Written by @Chronophylos in Rust. Version {}",
-                env!("CARGO_PKG_VERSION")
-            ),
-            Some(privmsg.channel_id.clone()),
-        )
-        .await
-        .wrap_err("Failed to send reply")
+/// Deliver the reminders waiting for `login`, who just showed up in `channel` without typing.
+/// If they `joined` the channel, only the reminders that asked for it are delivered.
+async fn deliver_on_arrival(
+    state: &State,
+    client: &Client,
+    channel: &str,
+    login: &str,
+    joined: bool,
+) -> Result<()> {
+    let presence = if joined {
+        Presence::Joined
+    } else {
+        Presence::Listed
+    };
+    let messages = claim_pending(state, client, channel, login, None, presence).await?;
+    if messages.is_empty() {
+        return Ok(());
+    }
+
+    info!("Delivering to {} who showed up in {}", login, channel);
+    let heading = delivery_heading(state, login, &state.display_names.name(login), &messages);
+    spawn_delivery(state, client, channel.to_string(), None, heading, messages);
+
+    Ok(())
 }
 
-async fn handle_commands(
-    store: &mut MessageStore,
+/// Create the reminder for an upcoming segment of a watched schedule.
+async fn handle_upcoming_segment(
+    state: &mut State,
     client: &Client,
-    privmsg: &PrivmsgMessage,
+    upcoming: UpcomingSegment,
 ) -> Result<()> {
-    let mut parts = privmsg.message_text.split_whitespace();
+    let UpcomingSegment {
+        channel,
+        segment,
+        upcoming,
+    } = upcoming;
 
-    match parts.next() {
-        Some("!bot") => handle_bot_command(client, privmsg)
-            .await
-            .wrap_err("Failed to handle bot command")?,
-        Some(word) if word.starts_with(PREFIX) => {
-            let command = word
-                .strip_prefix(PREFIX)
-                .ok_or_else(|| eyre!("Failed to remove prefix"))?;
-
-            match command {
-                "tell" => handle_tell_command(store, client, privmsg, &mut parts)
-                    .await
-                    .wrap_err("Failed to handle tell command"),
-                "cancel" => handle_cancel_command(store, client, privmsg, &mut parts)
-                    .await
-                    .wrap_err("Failed to handle cancel command"),
-                "bot" => handle_bot_command(client, privmsg)
-                    .await
-                    .wrap_err("Failed to handle bot command"),
-                _ => {
-                    Err(eyre!("Unknown command"))
-                    // error unknown command
-                }
-            }?
-        }
-        _ => {
-            // message does not start with the prefix
-        }
+    let watch = match state.schedules.get(&channel) {
+        Some(watch) => watch,
+        None => return Ok(()),
+    };
+    if !state
+        .schedules
+        .mark_reminded(&channel, &segment.id, &upcoming)
+    {
+        return Ok(());
+    }
+    state
+        .schedules
+        .save()
+        .wrap_err("Failed to save schedule store")?;
+
+    let title = if segment.title.is_empty() {
+        String::new()
+    } else {
+        format!(" \"{}\"", segment.title)
+    };
+    let message = Message::new(
+        state
+            .config
+            .id_scheme
+            .generate()
+            .wrap_err("Failed to generate id")?,
+        Activation::Fixed(segment.start - watch.lead()),
+        watch.broadcaster_login.clone(),
+        channel,
+        watch.broadcaster_login.clone(),
+        format!(
+            "your scheduled stream{} starts {}",
+            title,
+            humanize::until(watch.lead())
+        ),
+    );
+    info!("Reminding of segment {} with {}", segment.id, message.id());
+
+    {
+        let mut store = state.store.lock().await;
+        store.insert(message.clone());
+        store.save().wrap_err("Failed to save store")?;
     }
+    queue_messages(state, client, &[message]).await;
 
     Ok(())
 }
 
-#[instrument(skip(store, client, message), fields(id = message.id()))]
-async fn queue_message(mut store: MessageStore, client: Client, message: Message) -> Result<()> {
-    if let Activation::Fixed(deadline) = message.activation() {
-        let now = OffsetDateTime::now_utc();
-        let duration = *deadline - now;
+/// Post the repeating timers of every live channel whenever their interval passed. Intervals
+/// start counting when the stream is first seen live.
+async fn run_repeating_timers(repeats: RepeatStore, live: LiveChannels, client: Client) {
+    let mut interval = tokio::time::interval(REPEAT_CHECK_INTERVAL);
+    let mut last_posted: HashMap<String, OffsetDateTime> = HashMap::new();
 
-        if duration.is_positive() {
-            debug!("Queuing message");
+    loop {
+        interval.tick().await;
 
-            sleep(duration.try_into().wrap_err("Failed to convert duration")?).await;
+        let now = OffsetDateTime::now_utc();
+        let channels = live.read().unwrap().clone();
+        let mut due = Vec::new();
+        let mut active = HashSet::new();
+
+        for channel in &channels {
+            for timer in repeats.timers(channel) {
+                let last = *last_posted.entry(timer.id.clone()).or_insert(now);
+                if now - last >= timer.interval() {
+                    last_posted.insert(timer.id.clone(), now);
+                    due.push((channel.clone(), timer.text.clone()));
+                }
+                active.insert(timer.id);
+            }
         }
 
-        info!("Replaying timed message");
+        // offline channels and removed timers start over
+        last_posted.retain(|id, _| active.contains(id));
 
-        client
-            .say(
-                message.channel().to_string(),
-                format!(
-                    "@{} one timed message for you {}",
-                    message.recipient(),
-                    message
-                ),
+        for (channel, text) in due {
+            if let Err(err) = say_with_retry(
+                &client,
+                SendPriority::Announcement,
+                &channel,
+                defuse(&text),
+                None,
             )
             .await
-            .wrap_err("Failed to replay message in chat")?;
-
-        ensure!(store.remove(&message), "Failed to remove message");
-
-        store.save().wrap_err("Failed to save store")?;
+            {
+                error!("{:?}", err.wrap_err("Failed to post repeating timer"));
+            }
+        }
     }
-
-    Ok(())
 }
 
-async fn spawn_queue_message_task(store: MessageStore, client: Client, message: Message) {
-    let id = message.id().to_string();
+/// Join `channels` at a pace Twitch accepts, retrying the ones that weren't confirmed in time.
+async fn join_channels(client: Client, joins: Joins, channels: Vec<String>) {
+    let mut missing = channels.iter().collect::<Vec<_>>();
 
-    tokio::spawn(async move {
-        if let Err(err) = queue_message(store, client, message)
-            .await
-            .wrap_err_with(|| format!("Failed to handle scheduled message {}", id))
-        {
-            error!("{:?}", err);
+    for attempt in 1..=JOIN_ATTEMPTS {
+        for channel in missing {
+            info!("Joining {}", channel);
+            client.join(channel.clone());
+            sleep(client.join_interval()).await;
         }
-    });
-}
 
-async fn handle_privmsg(
-    store: &mut MessageStore,
-    client: &Client,
-    privmsg: &PrivmsgMessage,
-) -> Result<()> {
-    let messages = store.pop_pending(&privmsg.sender.login);
-    store.save().wrap_err("Error saving store")?;
+        sleep(JOIN_TIMEOUT).await;
 
-    handle_commands(store, client, privmsg)
-        .await
-        .wrap_err("Failed to handle commands")?;
+        missing = joins.missing(&channels);
+        if missing.is_empty() {
+            return;
+        }
 
-    // process pending messages
-    if !messages.is_empty() {
-        info!(
-            "Replaying messages: {}",
-            messages
-                .iter()
-                .map(|m| m.id())
-                .intersperse(",")
-                .collect::<String>()
+        warn!(
+            "Failed to join {} channels on attempt {}",
+            missing.len(),
+            attempt
         );
+    }
 
-        let text = messages
+    error!(
+        "Giving up joining {}",
+        missing
             .iter()
-            .map(|message| message.to_string())
-            .intersperse(" - ".to_string())
-            .collect::<String>();
-
-        let reply = format!(
-            "@{} {}: {}",
-            privmsg.sender.name,
-            format_num(messages.len(), "reminder", "reminders"),
-            text
-        );
+            .map(|channel| channel.as_str())
+            .intersperse(", ")
+            .collect::<String>()
+    );
+}
 
-        for chunk in reply
-            .chars()
-            .collect::<Vec<char>>()
-            .chunks(450)
-            .map(|c| c.iter().collect::<String>())
-        {
-            client
-                .say_in_response(
-                    privmsg.channel_login.clone(),
-                    chunk,
-                    Some(privmsg.channel_id.clone()),
-                )
-                .await
-                .wrap_err("Failed to send reply")?;
-        }
+/// Swap in a freshly loaded config and join or part the channels that changed.
+pub(crate) fn reload_config(state: &mut State, client: &Client) -> Result<()> {
+    let config = Config::from_path(state.config_path.clone()).wrap_err("Failed to load config")?;
+    let channels = config.channels();
+
+    tokio::spawn(join_channels(
+        client.clone(),
+        state.joins.clone(),
+        channels.difference(&state.channels).cloned().collect(),
+    ));
+
+    for channel in state.channels.difference(&channels) {
+        info!("Parting {}", channel);
+        client.part(channel.clone());
     }
 
+    state.channels = channels;
+    state.config = config;
+    info!("Reloaded config");
+
     Ok(())
 }
 
 async fn handle_server_message(
-    store: &mut MessageStore,
+    state: &mut State,
     client: &Client,
     login: &str,
     message: ServerMessage,
 ) -> Result<()> {
     trace!("Received message: {:?}", message);
+    state.last_server_message = Some(OffsetDateTime::now_utc());
 
     match message {
-        ServerMessage::Privmsg(privmsg) => handle_privmsg(store, client, &privmsg)
+        ServerMessage::Privmsg(privmsg)
+            if privmsg.sender.login.eq_ignore_ascii_case(login)
+                || privmsg.sender.login == state.read_login =>
+        {
+            trace!("Ignoring own message");
+        }
+        ServerMessage::Privmsg(privmsg) if is_from_bot(state, &privmsg) => {
+            trace!("Ignoring message of bot {}", privmsg.sender.login);
+        }
+        ServerMessage::Privmsg(privmsg) => handle_privmsg(state, client, login, &privmsg)
             .await
             .wrap_err("Failed to handle privmsg")?,
         ServerMessage::Join(join) => {
-            if join.user_login == login {
+            if join.user_login == state.read_login {
                 info!("Joined channel {}", join.channel_login);
+                state.joins.confirm(&join.channel_login);
+            } else if !state.config.is_ignored(&join.user_login) {
+                deliver_on_arrival(state, client, &join.channel_login, &join.user_login, true)
+                    .await
+                    .wrap_err("Failed to handle join")?;
+            }
+        }
+        ServerMessage::Part(part) => {
+            if part.user_login == state.read_login {
+                info!("Parted channel {}", part.channel_login);
+                state.joins.forget(&part.channel_login);
+
+                // parted by Twitch rather than by a reload or the admin API
+                if state.channels.contains(&part.channel_login) {
+                    warn!("Rejoining {}", part.channel_login);
+                    tokio::spawn(join_channels(
+                        client.clone(),
+                        state.joins.clone(),
+                        vec![part.channel_login],
+                    ));
+                }
+            }
+        }
+        ServerMessage::UserNotice(notice) => {
+            if let UserNoticeEvent::Raid { viewer_count, .. } = notice.event {
+                handle_raid(state, client, &notice, viewer_count)
+                    .await
+                    .wrap_err("Failed to handle raid")?;
             }
         }
         ServerMessage::Notice(notice) => {
@@ -335,6 +5681,9 @@ async fn handle_server_message(
                 return Err(eyre!("Failed to authenticate"));
             }
         }
+        ServerMessage::Whisper(whisper) => handle_whisper(state, client, &whisper)
+            .await
+            .wrap_err("Failed to handle whisper")?,
         ServerMessage::Reconnect(_) => client.connect().await,
         _ => {}
     }
@@ -342,33 +5691,601 @@ async fn handle_server_message(
     Ok(())
 }
 
+/// Queue the stored timed messages of `channels` that are due within [`SCHEDULER_HORIZON`],
+/// starting right away. Wakes up again when the next message comes within the horizon, or after
+/// [`SCHEDULER_INTERVAL`] at the latest. Messages due later don't hold a task until then.
+async fn run_scheduler(
+    outbox: Outbox,
+    timers: Timers,
+    config: Config,
+    channel_settings: ChannelSettingsStore,
+    channels: BTreeSet<String>,
+) {
+    deliver_missed(&outbox, &timers, &config, &channel_settings, &channels).await;
+
+    loop {
+        let before = OffsetDateTime::now_utc() + SCHEDULER_HORIZON;
+        let mut cursor = None;
+        loop {
+            let (messages, next) = {
+                let store = outbox.store.lock().await;
+                let (page, next) =
+                    store.get_scheduled_before(before, cursor.as_ref(), SCHEDULER_PAGE_SIZE);
+
+                let messages = page
+                    .into_iter()
+                    .filter(|message| channels.contains(message.channel()))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                (messages, next)
+            };
+
+            for message in messages {
+                spawn_queue_message_task(
+                    outbox.clone(),
+                    timers.clone(),
+                    config.quiet_hours.get(message.channel()).copied(),
+                    channel_settings
+                        .get(message.channel())
+                        .apply(config.delivery_style(message.channel())),
+                    message,
+                )
+                .await;
+            }
+
+            if next.is_none() {
+                break;
+            }
+            cursor = next;
+        }
+
+        let wait = match outbox.store.lock().await.next_deadline(before) {
+            Some(deadline) => (deadline - SCHEDULER_HORIZON - OffsetDateTime::now_utc())
+                .try_into()
+                .unwrap_or_default(),
+            None => SCHEDULER_INTERVAL,
+        };
+        sleep(wait.min(SCHEDULER_INTERVAL)).await;
+    }
+}
+
+/// Tell authors about their reminders that still weren't delivered by their `deadline:`, every
+/// [`DEADLINE_INTERVAL`].
+async fn run_deadlines(outbox: Outbox, channels: BTreeSet<String>) {
+    let mut interval = tokio::time::interval(DEADLINE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = notify_missed_deadlines(&outbox, &channels).await {
+            error!("{:?}", err);
+        }
+    }
+}
+
+/// Tell the authors of the reminders in `channels` whose deadline passed that the recipient still
+/// didn't get them, so they can reach them another way. A deadline is only cleared once its
+/// author was told, so failed notices are tried again the next time.
+async fn notify_missed_deadlines(outbox: &Outbox, channels: &BTreeSet<String>) -> Result<()> {
+    if outbox.pause.is_paused() {
+        return Ok(());
+    }
+
+    let now = OffsetDateTime::now_utc();
+    let mut cursor = None;
+    loop {
+        let (missed, next) = {
+            let store = outbox.store.lock().await;
+            let (page, next) = store.get_missed_before(now, cursor.as_ref(), SCHEDULER_PAGE_SIZE);
+
+            let missed = page
+                .into_iter()
+                .filter(|message| channels.contains(message.channel()))
+                .cloned()
+                .collect::<Vec<_>>();
+            (missed, next)
+        };
+
+        for message in missed {
+            if let Err(err) = notify_missed_deadline(outbox, &message, now).await {
+                error!("{:?}", err);
+            }
+        }
+
+        if next.is_none() {
+            return Ok(());
+        }
+        cursor = next;
+    }
+}
+
+/// Tell the author of `message` that its deadline passed and clear it, unless the message changed
+/// in the meantime.
+async fn notify_missed_deadline(
+    outbox: &Outbox,
+    message: &Message,
+    now: OffsetDateTime,
+) -> Result<()> {
+    info!("Reminder {} missed its deadline", message.id());
+
+    let author = message.author();
+    let settings = outbox.settings.get(author);
+    let text = format!(
+        "{} {} still hasn't gotten your reminder [{}] from {}, you might want to reach them \
+         another way",
+        mention(&outbox.display_names.name(author), settings.silent),
+        outbox.display_names.name(message.recipient()),
+        message.id(),
+        humanize::ago(now - message.created(), humanize::DEFAULT_PRECISION)
+    );
+
+    say_or_whisper(
+        outbox,
+        SendPriority::Delivery,
+        message.channel(),
+        text,
+        settings.whisper.then(|| author),
+    )
+    .await
+    .wrap_err("Failed to tell author about a missed deadline")?;
+
+    let mut store = outbox.store.lock().await;
+    let current = match store.get_by_id(message.id()) {
+        Some(current) if current.deadline() == message.deadline() => current.clone(),
+        _ => return Ok(()),
+    };
+    store.insert(current.with_deadline(None));
+    store.save().wrap_err("Failed to save store")
+}
+
+/// Pick up external changes to the store and prune it every [`MAINTENANCE_INTERVAL`], starting
+/// right away.
+async fn run_maintenance(
+    store: SharedStore,
+    audit: AuditLog,
+    config: Config,
+    helix: Option<Helix>,
+) {
+    let mut interval = tokio::time::interval(MAINTENANCE_INTERVAL);
+    let started = OffsetDateTime::now_utc();
+    let mut misses = HashMap::new();
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = maintain(
+            &store,
+            &audit,
+            &config,
+            helix.as_ref(),
+            started,
+            &mut misses,
+        )
+        .await
+        {
+            error!("{:?}", err);
+        }
+    }
+}
+
+/// Prune the store. Overdue reminders are only removed if their deadline passed after the bot
+/// `started`, the others are still delivered late. `misses` counts how often in a row Helix
+/// didn't know each recipient.
+async fn maintain(
+    store: &SharedStore,
+    audit: &AuditLog,
+    config: &Config,
+    helix: Option<&Helix>,
+    started: OffsetDateTime,
+    misses: &mut HashMap<String, u32>,
+) -> Result<()> {
+    if store
+        .lock()
+        .await
+        .reload_if_changed()
+        .wrap_err("Failed to reload store")?
+    {
+        warn!("The storage was changed externally, reloaded it");
+    }
+
+    let orphaned = orphaned_recipients(store, config, helix, misses).await;
+
+    let now = OffsetDateTime::now_utc();
+    let (expired, overdue, orphaned) = {
+        let mut store = store.lock().await;
+
+        let expired = match config.reminder_retention_days {
+            Some(days) => store.remove_older_than(now - Duration::days(days)),
+            None => Vec::new(),
+        };
+        let overdue = match config.overdue_retention_days {
+            Some(days) => store.remove_overdue(started, now - Duration::days(days)),
+            None => Vec::new(),
+        };
+        let orphaned = orphaned
+            .iter()
+            .flat_map(|recipient| store.remove_addressed_to(recipient))
+            .collect::<Vec<_>>();
+        store.save().wrap_err("Failed to save store")?;
+
+        (expired, overdue, orphaned)
+    };
+
+    for message in expired.iter().chain(&overdue).chain(&orphaned) {
+        audit
+            .record(AuditKind::Expired, message)
+            .wrap_err("Failed to write audit log")?;
+    }
+    if !expired.is_empty() || !overdue.is_empty() || !orphaned.is_empty() {
+        info!(
+            "Pruned {} expired, {} overdue and {} orphaned reminders",
+            expired.len(),
+            overdue.len(),
+            orphaned.len()
+        );
+    }
+
+    let pruned = audit.prune().wrap_err("Failed to prune audit log")?;
+    if pruned > 0 {
+        info!("Pruned {} audit log entries", pruned);
+    }
+
+    Ok(())
+}
+
+/// Get the recipients of stored messages that will never receive them, because the bot ignores
+/// them or their account is gone. Accounts are only checked if there is a Helix client, and only
+/// count as gone once Helix missed them [`ORPHANED_AFTER_MISSES`] times in a row.
+async fn orphaned_recipients(
+    store: &SharedStore,
+    config: &Config,
+    helix: Option<&Helix>,
+    misses: &mut HashMap<String, u32>,
+) -> Vec<String> {
+    // countdowns and announcements are addressed to channels
+    let recipients = store
+        .lock()
+        .await
+        .recipients()
+        .into_iter()
+        .filter(|recipient| !recipient.is_empty() && !recipient.starts_with('#'))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    let (mut orphaned, recipients): (Vec<_>, Vec<_>) = recipients
+        .into_iter()
+        .partition(|recipient| config.is_ignored(recipient));
+
+    if let Some(helix) = helix {
+        match helix.existing_users(&recipients).await {
+            Ok(existing) => {
+                misses.retain(|recipient, _| recipients.contains(recipient));
+                for recipient in recipients {
+                    if existing.contains(&recipient) {
+                        misses.remove(&recipient);
+                        continue;
+                    }
+
+                    let count = misses.entry(recipient.clone()).or_default();
+                    *count += 1;
+                    if *count >= ORPHANED_AFTER_MISSES {
+                        misses.remove(&recipient);
+                        orphaned.push(recipient);
+                    }
+                }
+            }
+            Err(err) => error!("{:?}", err.wrap_err("Failed to check recipients")),
+        }
+    }
+
+    orphaned
+}
+
 #[tokio::main]
 pub async fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
+    let config_path =
+        PathBuf::from(env::var("REMINDME_CONFIG").unwrap_or_else(|_| "config.ron".to_string()));
+    let config = Config::from_path(config_path.clone()).wrap_err("Failed to load config")?;
+
+    telemetry::init(config.log_file.as_ref()).wrap_err("Failed to set up tracing")?;
+
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if args.first().map(String::as_str) == Some("store") {
+        return store_cli::run(&config, &args[1..]);
+    }
+    if args.first().map(String::as_str) == Some("restore") {
+        return backup::restore(&config, &args[1..]).await;
+    }
 
     let login = env::var("TWITCH_LOGIN").wrap_err("Failed to get TWITCH_LOGIN")?;
     let token = env::var("TWITCH_TOKEN").wrap_err("Failed to get TWITCH_TOKEN")?;
+    let _reporting = telemetry::init_error_reporting(config.sentry_dsn.as_deref());
+
+    let proxy = config
+        .proxy
+        .as_deref()
+        .map(str::parse::<Proxy>)
+        .transpose()
+        .wrap_err("Failed to parse proxy")?;
+    let route = config
+        .irc
+        .route(proxy.clone())
+        .wrap_err("Failed to set up chat connections")?;
+    if let Some(route) = &route {
+        Route::use_for_chat(route.clone());
+    }
+    token::verify(
+        &token,
+        &login,
+        &token::required_scopes(&config),
+        proxy.as_ref(),
+    )
+    .await
+    .wrap_err("TWITCH_TOKEN can't be used")?;
+    let read_credentials = connections::read_credentials(config.irc.read_identity, proxy.as_ref())
+        .await
+        .wrap_err("Failed to set up the reading account")?;
+    let (mut incoming_messages, client, read_login) = connections::open(
+        &config.irc,
+        route.is_some(),
+        StaticLoginCredentials::new(login.clone(), Some(token.clone())),
+        read_credentials,
+    );
+    let channels = config.channels();
+
+    let storage = config.storage.open().wrap_err("Failed to open storage")?;
+    let store: SharedStore = Arc::new(Mutex::new(
+        MessageStore::from_storage(storage, config.instance_id.clone())
+            .wrap_err("Failed to open storage")?,
+    ));
+    let seen =
+        SeenStore::from_path(PathBuf::from("seen.ron")).wrap_err("Failed to open seen storage")?;
+    let display_names = DisplayNames::from_path(PathBuf::from("display_names.ron"))
+        .wrap_err("Failed to open display name storage")?;
+    let afk =
+        AfkStore::from_path(PathBuf::from("afk.ron")).wrap_err("Failed to open afk storage")?;
+    let filters = FilterStore::from_path(PathBuf::from("filters.ron"))
+        .wrap_err("Failed to open filter storage")?;
+    let groups = GroupStore::from_path(PathBuf::from("groups.ron"))
+        .wrap_err("Failed to open group storage")?;
+    let presets = PresetStore::from_path(PathBuf::from("presets.ron"))
+        .wrap_err("Failed to open preset storage")?;
+    let watches = WatchStore::from_path(PathBuf::from("watches.ron"))
+        .wrap_err("Failed to open watch storage")?;
+    let repeats = RepeatStore::from_path(PathBuf::from("repeats.ron"))
+        .wrap_err("Failed to open repeat storage")?;
+    let schedules = ScheduleStore::from_path(PathBuf::from("schedules.ron"))
+        .wrap_err("Failed to open schedule storage")?;
+    let settings = SettingsStore::from_path(PathBuf::from("settings.ron"))
+        .wrap_err("Failed to open settings storage")?;
+    let channel_settings = ChannelSettingsStore::from_path(PathBuf::from("channel_settings.ron"))
+        .wrap_err("Failed to open channel settings storage")?;
+    let history = HistoryStore::from_path(PathBuf::from("history.ron"), config.history_size)
+        .wrap_err("Failed to open history storage")?;
+    let archive = config.archive.clone().map(Archive::new);
+
+    let audit = AuditLog::new(
+        config.audit_log.clone(),
+        Duration::days(config.audit_retention_days),
+    );
+
+    let live = LiveChannels::default();
+    let joins = Joins::default();
+    let (offline_sender, mut offline) = mpsc::channel(16);
+    let (segment_sender, mut upcoming_segments) = mpsc::channel(16);
+    let (arrival_sender, mut arrivals) = mpsc::channel(16);
+    let (follow_up_sender, mut follow_ups) = mpsc::channel(16);
+    let helix = config
+        .helix_client_id
+        .as_ref()
+        .map(|client_id| Helix::new(client_id.clone(), &token, proxy.as_ref()))
+        .transpose()
+        .wrap_err("Failed to set up Helix client")?;
+    match &helix {
+        Some(helix) => {
+            tokio::spawn(
+                watch_schedules(helix.clone(), schedules.clone(), segment_sender)
+                    .instrument(trace_span!("schedule_watcher")),
+            );
+            tokio::spawn(
+                watch_streams(helix.clone(), joins.clone(), live.clone(), offline_sender)
+                .instrument(trace_span!("stream_watcher")),
+            );
+            if !config.presence_channels.is_empty() {
+                tokio::spawn(
+                    watch_chatters(
+                        helix.clone(),
+                        login.clone(),
+                        config
+                            .presence_channels
+                            .iter()
+                            .map(|channel| channel.to_lowercase())
+                            .filter(|channel| channels.contains(channel))
+                            .collect(),
+                        arrival_sender,
+                    )
+                    .instrument(trace_span!("chatter_watcher")),
+                );
+            }
+        }
+        None => info!(
+            "No Helix client id configured, when:offline reminders, repeating timers, schedule reminders and presence delivery won't trigger, whispers won't be sent and announcements are plain chat messages"
+        ),
+    }
+    let helix_chat = match &helix {
+        Some(helix) => match HelixChat::new(helix.clone(), &login).await {
+            Ok(helix_chat) => Some(helix_chat),
+            Err(err) => {
+                warn!(
+                    "{:?}",
+                    err.wrap_err("Failed to get the user id of the bot, whispers won't be sent")
+                );
+                None
+            }
+        },
+        None => None,
+    };
+    tokio::spawn(
+        run_repeating_timers(repeats.clone(), live, client.clone())
+            .instrument(trace_span!("repeating_timers")),
+    );
+
+    tokio::spawn(
+        run_maintenance(store.clone(), audit.clone(), config.clone(), helix.clone())
+            .instrument(trace_span!("maintenance")),
+    );
+    tokio::spawn(
+        display_names::run(display_names.clone()).instrument(trace_span!("display_names")),
+    );
+    tokio::spawn(seen_store::run(seen.clone()).instrument(trace_span!("seen")));
+
+    if let Some(snapshots) = config.snapshots.clone() {
+        tokio::spawn(snapshots::run(store.clone(), snapshots).instrument(trace_span!("snapshots")));
+    }
+    if let Some(backup) = config.backup.clone() {
+        tokio::spawn(backup::run(store.clone(), backup).instrument(trace_span!("backup")));
+    }
+
+    let (api_sender, mut api_requests) = mpsc::channel(16);
+    #[cfg(feature = "grpc")]
+    if let Some(grpc) = config.grpc.clone() {
+        let api_sender = api_sender.clone();
+        tokio::spawn(
+            async move {
+                if let Err(err) = grpc::serve(grpc, api_sender).await {
+                    error!("{:?}", err);
+                }
+            }
+            .instrument(trace_span!("grpc")),
+        );
+    }
+    #[cfg(not(feature = "grpc"))]
+    if config.grpc.is_some() {
+        return Err(eyre!("Serving the gRPC admin API needs the grpc feature"));
+    }
+    if let Some(api) = config.admin_api.clone() {
+        tokio::spawn(
+            async move {
+                if let Err(err) = admin_api::serve(api, api_sender).await {
+                    error!("{:?}", err);
+                }
+            }
+            .instrument(trace_span!("admin_api")),
+        );
+    }
 
-    // default configuration is to join chat as anonymous.
-    let config = ClientConfig::new_simple(StaticLoginCredentials::new(login.clone(), Some(token)));
-    let (mut incoming_messages, client) = Client::new(config);
+    let mut hangup = signal(SignalKind::hangup()).wrap_err("Failed to listen for SIGHUP")?;
 
-    let store = MessageStore::from_path(PathBuf::from("messages.ron"))
-        .wrap_err("Failed to open storage")?;
+    let delivery_config = config.clone();
+    let timers = Timers::default();
+    let pause = Pause::default();
 
     // first thing you should do: start consuming incoming messages,
     // otherwise they will back up.
     let handle = tokio::spawn(
         {
             let client = client.clone();
-            let mut store = store.clone();
+            let mut state = State {
+                recent: RecentMessages::new(config.recent_messages),
+                config,
+                login: login.clone(),
+                read_login,
+                config_path,
+                channels: channels.clone(),
+                store: store.clone(),
+                seen: seen.clone(),
+                display_names: display_names.clone(),
+                afk,
+                filters: filters.clone(),
+                groups,
+                presets,
+                watches,
+                audit: audit.clone(),
+                undo: UndoBuffer::new(UNDO_WINDOW),
+                history: history.clone(),
+                archive: archive.clone(),
+                delivered: UndoBuffer::new(SNOOZE_WINDOW),
+                confirmations: Confirmations::new(CONFIRM_WINDOW),
+                timers: timers.clone(),
+                joins: joins.clone(),
+                repeats,
+                schedules: schedules.clone(),
+                settings: settings.clone(),
+                channel_settings: channel_settings.clone(),
+                helix,
+                helix_chat: helix_chat.clone(),
+                live: live.clone(),
+                commands: command_registry(),
+                cooldowns: Cooldowns::default(),
+                last_server_message: None,
+                pause: pause.clone(),
+                parse_failures: ParseFailures::default(),
+                usage: CommandUsage::default(),
+                follow_ups: follow_up_sender.clone(),
+            };
+            // pinged from this loop so a hanging handler gets the bot restarted
+            let watchdog_interval = systemd::watchdog_interval();
+            let mut watchdog = tokio::time::interval(
+                watchdog_interval.unwrap_or(std::time::Duration::from_secs(60)),
+            );
+
             async move {
-                while let Some(message) = incoming_messages.recv().await {
-                    if let Err(err) = handle_server_message(&mut store, &client, &login, message)
-                        .await
-                        .wrap_err("Failed to handle server message")
-                    {
-                        error!("{:?}", err)
+                loop {
+                    tokio::select! {
+                        _ = watchdog.tick(), if watchdog_interval.is_some() => {
+                            systemd::notify_watchdog();
+                        }
+                        message = incoming_messages.recv() => {
+                            let message = match message {
+                                Some(message) => message,
+                                None => break,
+                            };
+
+                            if let Err(err) =
+                                handle_server_message(&mut state, &client, &login, message)
+                                    .await
+                                    .wrap_err("Failed to handle server message")
+                            {
+                                error!("{:?}", err)
+                            }
+                        }
+                        Some(channel) = offline.recv() => {
+                            if let Err(err) = handle_stream_offline(&state, &client, &channel)
+                                .await
+                                .wrap_err("Failed to handle stream going offline")
+                            {
+                                error!("{:?}", err)
+                            }
+                        }
+                        Some(arrived) = arrivals.recv() => {
+                            if let Err(err) = handle_arrivals(&state, &client, arrived)
+                                .await
+                                .wrap_err("Failed to handle arrivals")
+                            {
+                                error!("{:?}", err)
+                            }
+                        }
+                        Some(request) = api_requests.recv() => {
+                            admin_api::handle_request(&mut state, &client, request).await;
+                        }
+                        Some(follow_up) = follow_ups.recv() => {
+                            queue_messages(&state, &client, &[follow_up]).await;
+                        }
+                        Some(upcoming) = upcoming_segments.recv() => {
+                            if let Err(err) = handle_upcoming_segment(&mut state, &client, upcoming)
+                                .await
+                                .wrap_err("Failed to handle upcoming segment")
+                            {
+                                error!("{:?}", err)
+                            }
+                        }
+                        _ = hangup.recv() => {
+                            info!("Received SIGHUP");
+
+                            if let Err(err) = reload_config(&mut state, &client) {
+                                error!("{:?}", err)
+                            }
+                        }
                     }
                 }
 
@@ -378,49 +6295,101 @@ pub async fn main() -> Result<()> {
         .instrument(trace_span!("irc_message_handler")),
     );
 
-    // join channels
-    for channel in env::var("TWITCH_CHANNELS")
-        .unwrap_or_else(|_| "colnahuacatl".to_string())
-        .split(',')
-    {
-        info!("Joining {}", channel);
-        client.join(channel.to_string());
-    }
+    tokio::spawn({
+        let join = join_channels(
+            client.clone(),
+            joins.clone(),
+            channels.iter().cloned().collect(),
+        );
 
-    // queue messages
-    for message in store.get_all() {
-        spawn_queue_message_task(store.clone(), client.clone(), message.to_owned()).await;
+        async move {
+            join.await;
+            systemd::notify_ready();
+        }
+    });
+
+    // queue messages of the channels this instance is responsible for
+    let outbox = Outbox {
+        store,
+        filters,
+        audit,
+        settings,
+        display_names,
+        history,
+        archive,
+        pause,
+        client,
+        id_scheme: delivery_config.id_scheme,
+        follow_ups: follow_up_sender,
+        helix_chat,
+    };
+    tokio::spawn(
+        run_deadlines(outbox.clone(), channels.clone()).instrument(trace_span!("deadlines")),
+    );
+    tokio::spawn(
+        run_scheduler(outbox, timers, delivery_config, channel_settings, channels)
+            .instrument(trace_span!("scheduler")),
+    );
+
+    let result = handle.await.wrap_err("Failed to run bot")?;
+    // what was seen since the last interval would be lost otherwise
+    if let Err(err) = seen.flush() {
+        error!("{:?}", err.wrap_err("Failed to save seen store"));
     }
+    telemetry::shutdown();
 
-    handle.await.wrap_err("Failed to run bot")?
+    result
 }
 
-pub(crate) fn format_duration(duration: Duration) -> String {
-    let days = duration.whole_days();
-    let years = days / 356;
-    let hours = duration.whole_hours() - days * 24;
-    let minutes = duration.whole_minutes() - hours * 60 - days * 24 * 60;
-    let seconds = duration.whole_seconds() - minutes * 60 - hours * 60 * 60 - days * 24 * 60 * 60;
+/// Format `at` as `18:32 UTC`, adding the date unless it is on the same day as `now`.
+fn format_timestamp(at: OffsetDateTime, now: OffsetDateTime) -> String {
+    let at = at.to_offset(time::UtcOffset::UTC);
 
-    vec![
-        format_short_num(years, "y"),
-        format_short_num(days, "d"),
-        format_short_num(hours, "h"),
-        format_short_num(minutes, "m"),
-        format_short_num(seconds, "s"),
-        "ago".to_string(),
-    ]
-    .into_iter()
-    .filter(|s| !s.is_empty())
-    .intersperse(" ".to_string())
-    .collect()
+    if at.date() == now.to_offset(time::UtcOffset::UTC).date() {
+        format!("{:02}:{:02} UTC", at.hour(), at.minute())
+    } else {
+        format!(
+            "{:02}:{:02} UTC on {}-{:02}-{:02}",
+            at.hour(),
+            at.minute(),
+            at.year(),
+            u8::from(at.month()),
+            at.day()
+        )
+    }
 }
 
-fn format_short_num(num: i64, text: &str) -> String {
-    match num {
-        0 => String::new(),
-        x => format!("{}{}", x, text),
+/// Format `at` as `2021-11-27 18:32 UTC+01:00` in the time zone `utc_offset_minutes` away from
+/// UTC, or in UTC if there is none.
+fn format_local_timestamp(at: OffsetDateTime, utc_offset_minutes: Option<i64>) -> String {
+    let offset = utc_offset_minutes
+        .and_then(|minutes| time::UtcOffset::from_whole_seconds((minutes * 60) as i32).ok())
+        .unwrap_or(time::UtcOffset::UTC);
+    let at = at.to_offset(offset);
+
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02} {}",
+        at.year(),
+        u8::from(at.month()),
+        at.day(),
+        at.hour(),
+        at.minute(),
+        format_utc_offset(offset.whole_minutes().into())
+    )
+}
+
+/// Format an offset from UTC as `UTC`, `UTC+02:00` or `UTC-05:30`.
+fn format_utc_offset(minutes: i64) -> String {
+    if minutes == 0 {
+        return "UTC".to_string();
     }
+
+    format!(
+        "UTC{}{:02}:{:02}",
+        if minutes < 0 { '-' } else { '+' },
+        minutes.abs() / 60,
+        minutes.abs() % 60
+    )
 }
 
 fn format_num(num: usize, singular: &str, plural: &str) -> String {
@@ -1,14 +1,27 @@
-use std::{fmt::Display, hash::Hash};
+pub mod stored;
+
+use std::{borrow::Borrow, collections::BTreeSet, hash::Hash};
 
-use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
 
-use crate::{format_duration, message_parser::Schedule};
+pub use crate::message_parser::Priority;
+use crate::{
+    format_local_timestamp, humanize,
+    id::{self, IdGenerator},
+    message_parser::{FollowUp, MessageDefinition, Schedule},
+    template,
+};
 
-#[derive(Debug, Copy, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Activation {
     OnNextMessage,
     Fixed(OffsetDateTime),
+    /// Deliver in the channel of the message when it gets raided.
+    OnRaid,
+    /// Deliver in the channel of the message when its stream ends.
+    OnOffline,
+    /// Never deliver.
+    Never,
 }
 
 impl Default for Activation {
@@ -23,11 +36,51 @@ impl From<Schedule> for Activation {
             Schedule::None => Activation::OnNextMessage,
             Schedule::Relative(duration) => Activation::Fixed(OffsetDateTime::now_utc() + duration),
             Schedule::Fixed(datetime) => Activation::Fixed(datetime),
+            Schedule::Raid => Activation::OnRaid,
+            Schedule::Offline => Activation::OnOffline,
         }
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+/// How a message is delivered.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Kind {
+    /// Delivered to its recipient.
+    Reminder,
+    /// Posted to the whole channel when it is due. Its recipient is the channel, prefixed with
+    /// `#` so it can't collide with a login.
+    Countdown,
+    /// Posted to the whole channel as an announcement when it is due. Has no recipient either.
+    Announcement,
+    /// Kept for its author, who is also its recipient, and never delivered.
+    Note,
+    /// Sent to its author instead of its recipient, once the recipient next types in chat.
+    Notification,
+}
+
+impl Default for Kind {
+    fn default() -> Self {
+        Kind::Reminder
+    }
+}
+
+/// Where and when a reminder was written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    /// The channel the reminder was written in, which is not necessarily where it is delivered.
+    pub channel: String,
+    /// The id of the chat message that created the reminder.
+    pub message_id: String,
+    /// When that chat message was sent.
+    pub at: OffsetDateTime,
+    /// Whether the channel was live at the time.
+    pub live: bool,
+    /// A link to the moment in the video of the stream, if it is saved.
+    pub vod: Option<String>,
+}
+
+/// A reminder. See [`stored`] for how it is persisted.
+#[derive(Debug, Clone)]
 pub struct Message {
     id: String,
     activation: Activation,
@@ -36,20 +89,29 @@ pub struct Message {
     created: OffsetDateTime,
     channel: String,
     text: String,
-}
-
-impl Display for Message {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let now = OffsetDateTime::now_utc();
-
-        write!(
-            f,
-            "{} ({}): {}",
-            self.author,
-            format_duration((now - self.created).abs()),
-            self.text
-        )
-    }
+    /// Short number unique among the pending messages of the author. Assigned by the store.
+    number: u32,
+    /// Only deliver in `channel`.
+    here: bool,
+    priority: Priority,
+    tags: BTreeSet<String>,
+    kind: Kind,
+    /// Deliver without mentioning the recipient.
+    silent: bool,
+    /// Also deliver when the recipient joins a channel, before they type.
+    on_join: bool,
+    /// Whisper the message to the recipient instead of saying it in chat.
+    whisper: bool,
+    /// The named time zone the due time was given in.
+    time_zone: Option<String>,
+    /// When the author is told that the message still wasn't delivered.
+    deadline: Option<OffsetDateTime>,
+    /// Created once this message was delivered.
+    follow_up: Option<FollowUp>,
+    /// The id of the message this one follows up on.
+    parent: Option<String>,
+    /// Where the reminder was written, unknown for reminders from before it was recorded.
+    origin: Option<Origin>,
 }
 
 impl PartialEq for Message {
@@ -66,23 +128,16 @@ impl Hash for Message {
     }
 }
 
-impl Default for Message {
-    fn default() -> Self {
-        let created = OffsetDateTime::now_utc();
-        Self {
-            id: cuid::slug().unwrap_or_else(|_| created.to_string()),
-            activation: Default::default(),
-            author: Default::default(),
-            recipient: Default::default(),
-            created,
-            channel: Default::default(),
-            text: Default::default(),
-        }
+/// Allows looking up messages in sets by their id. Sound because `Hash` and `Eq` only use the id.
+impl Borrow<str> for Message {
+    fn borrow(&self) -> &str {
+        &self.id
     }
 }
 
 impl Message {
     pub fn new(
+        id: String,
         activation: Activation,
         author: String,
         channel: String,
@@ -90,30 +145,298 @@ impl Message {
         text: String,
     ) -> Self {
         Self {
+            id,
             activation,
             author,
-            channel,
             recipient,
+            created: OffsetDateTime::now_utc(),
+            channel,
             text,
-            ..Default::default()
+            number: 0,
+            here: false,
+            priority: Priority::Normal,
+            tags: BTreeSet::new(),
+            kind: Kind::Reminder,
+            silent: false,
+            on_join: false,
+            whisper: false,
+            time_zone: None,
+            deadline: None,
+            follow_up: None,
+            parent: None,
+            origin: None,
         }
     }
 
-    pub fn from_id(id: String) -> Self {
-        Self {
+    /// Create a note of `author`.
+    pub fn note(id: String, author: String, channel: String, text: String) -> Self {
+        let recipient = author.clone();
+        let mut message = Self::new(id, Activation::Never, author, channel, recipient, text);
+        message.kind = Kind::Note;
+
+        message
+    }
+
+    /// Create a notification for `author` about `recipient` typing in chat, sent to `channel`.
+    pub fn notification(
+        id: String,
+        author: String,
+        channel: String,
+        recipient: String,
+        text: String,
+    ) -> Self {
+        let mut message = Self::new(
             id,
-            ..Default::default()
-        }
+            Activation::OnNextMessage,
+            author,
+            channel,
+            recipient,
+            text,
+        );
+        message.kind = Kind::Notification;
+
+        message
+    }
+
+    /// Create a message of `kind` posted to the whole of `channel` at `at`.
+    pub fn channel_wide(
+        kind: Kind,
+        id: String,
+        at: OffsetDateTime,
+        author: String,
+        channel: String,
+        text: String,
+    ) -> Self {
+        let recipient = format!("#{}", channel);
+        let mut message = Self::new(id, Activation::Fixed(at), author, channel, recipient, text);
+        message.kind = kind;
+        message.here = true;
+
+        message
+    }
+
+    /// Copy the message under a new `id`, e.g. to change it while a timer of the old version
+    /// is running.
+    pub fn with_id(mut self, id: String) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn with_recipient(mut self, recipient: String) -> Self {
+        self.recipient = recipient;
+        self
+    }
+
+    pub fn with_text(mut self, text: String) -> Self {
+        self.text = text;
+        self
+    }
+
+    pub fn with_activation(mut self, activation: Activation) -> Self {
+        self.activation = activation;
+        self
+    }
+
+    pub fn with_here(mut self, here: bool) -> Self {
+        self.here = here;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_tags(mut self, tags: BTreeSet<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    pub fn with_silent(mut self, silent: bool) -> Self {
+        self.silent = silent;
+        self
+    }
+
+    pub fn with_on_join(mut self, on_join: bool) -> Self {
+        self.on_join = on_join;
+        self
+    }
+
+    pub fn with_whisper(mut self, whisper: bool) -> Self {
+        self.whisper = whisper;
+        self
+    }
+
+    pub fn with_time_zone(mut self, time_zone: Option<String>) -> Self {
+        self.time_zone = time_zone;
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Option<OffsetDateTime>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    pub fn with_follow_up(mut self, follow_up: Option<FollowUp>) -> Self {
+        self.follow_up = follow_up;
+        self
+    }
+
+    pub fn with_origin(mut self, origin: Option<Origin>) -> Self {
+        self.origin = origin;
+        self
     }
 
     pub fn id(&self) -> &str {
         &self.id
     }
 
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+
+    pub(crate) fn set_number(&mut self, number: u32) {
+        self.number = number;
+    }
+
+    pub fn here(&self) -> bool {
+        self.here
+    }
+
+    pub fn silent(&self) -> bool {
+        self.silent
+    }
+
+    pub fn on_join(&self) -> bool {
+        self.on_join
+    }
+
+    pub fn whisper(&self) -> bool {
+        self.whisper
+    }
+
+    pub fn time_zone(&self) -> Option<&str> {
+        self.time_zone.as_deref()
+    }
+
+    pub fn deadline(&self) -> Option<OffsetDateTime> {
+        self.deadline
+    }
+
+    pub fn follow_up(&self) -> Option<&FollowUp> {
+        self.follow_up.as_ref()
+    }
+
+    pub fn parent(&self) -> Option<&str> {
+        self.parent.as_deref()
+    }
+
+    pub fn origin(&self) -> Option<&Origin> {
+        self.origin.as_ref()
+    }
+
+    /// Create the follow-up of the message under `id`, due its delay after the message was
+    /// `delivered`. `None` if the message has no follow-up.
+    pub fn next_in_chain(&self, id: String, delivered: OffsetDateTime) -> Option<Message> {
+        let follow_up = self.follow_up.as_ref()?;
+        let mut message = Message::new(
+            id,
+            Activation::Fixed(delivered + follow_up.after),
+            self.author.clone(),
+            self.channel.clone(),
+            self.recipient.clone(),
+            follow_up.text.clone(),
+        )
+        .with_here(self.here)
+        .with_priority(self.priority)
+        .with_tags(self.tags.clone())
+        .with_silent(self.silent)
+        .with_whisper(self.whisper)
+        .with_follow_up(follow_up.then.as_deref().cloned())
+        .with_origin(self.origin.clone());
+        message.parent = Some(self.id.clone());
+
+        Some(message)
+    }
+
+    /// Roughly how many bytes the message takes in memory, including its strings.
+    pub fn approximate_size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.id.len()
+            + self.author.len()
+            + self.recipient.len()
+            + self.channel.len()
+            + self.text.len()
+            + self.tags.iter().map(String::len).sum::<usize>()
+            + self.time_zone.as_ref().map_or(0, String::len)
+            + self.parent.as_ref().map_or(0, String::len)
+            + self.origin.as_ref().map_or(0, |origin| {
+                origin.channel.len()
+                    + origin.message_id.len()
+                    + origin.vod.as_ref().map_or(0, String::len)
+            })
+            + chain_size(self.follow_up.as_ref())
+    }
+
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    pub fn tags(&self) -> &BTreeSet<String> {
+        &self.tags
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    pub fn author(&self) -> &str {
+        &self.author
+    }
+
+    pub fn created(&self) -> OffsetDateTime {
+        self.created
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The text with the placeholders `{elapsed}`, `{created}`, `{author}`, `{recipient}` and
+    /// `{channel}` filled in as of `now`. Times are shown with `precision` units and in the time
+    /// zone `utc_offset_minutes` away from UTC.
+    pub fn expanded_text(
+        &self,
+        now: OffsetDateTime,
+        precision: usize,
+        utc_offset_minutes: Option<i64>,
+    ) -> String {
+        template::expand(&self.text, |name| match name {
+            "elapsed" => Some(humanize::truncated(now - self.created, precision)),
+            "created" => Some(format_local_timestamp(self.created, utc_offset_minutes)),
+            "author" => Some(self.author.clone()),
+            "recipient" => Some(self.recipient.clone()),
+            "channel" => Some(self.channel.clone()),
+            _ => None,
+        })
+    }
+
     pub fn activation(&self) -> &Activation {
         &self.activation
     }
 
+    /// When the message became deliverable: its due time if it is timed, otherwise when it was
+    /// created.
+    pub fn due(&self) -> OffsetDateTime {
+        match self.activation {
+            Activation::Fixed(at) => at.max(self.created),
+            Activation::OnNextMessage
+            | Activation::OnRaid
+            | Activation::OnOffline
+            | Activation::Never => self.created,
+        }
+    }
+
     pub fn recipient(&self) -> &str {
         &self.recipient
     }
@@ -122,3 +445,113 @@ impl Message {
         &self.channel
     }
 }
+
+impl Message {
+    /// Create the messages `definition` describes for `author` in `channel`, one per recipient and
+    /// schedule.
+    pub fn from_definition(
+        definition: MessageDefinition,
+        ids: &IdGenerator,
+        author: &str,
+        channel: &str,
+    ) -> Result<Vec<Message>, id::Error> {
+        let activations = definition
+            .schedules()
+            .cloned()
+            .map(Activation::from)
+            .collect::<Vec<_>>();
+        let here = definition.here.unwrap_or_default();
+        let text = definition.text;
+        let tags = definition.tags;
+        let deadline = definition
+            .deadline
+            .map(|window| definition.created + window);
+        definition
+            .recipients
+            .iter()
+            .flat_map(|recipient| {
+                activations
+                    .iter()
+                    .map(move |activation| (recipient, *activation))
+            })
+            .map(|(recipient, activation)| {
+                ids.generate().map(|id| {
+                    Message::new(
+                        id,
+                        activation,
+                        author.to_string(),
+                        channel.to_string(),
+                        recipient.clone(),
+                        text.clone(),
+                    )
+                    .with_here(here)
+                    .with_priority(definition.priority)
+                    .with_tags(tags.clone())
+                    .with_silent(definition.silent)
+                    .with_on_join(definition.on_join)
+                    .with_whisper(definition.whisper)
+                    .with_time_zone(definition.time_zone.clone())
+                    .with_deadline(deadline)
+                    .with_follow_up(definition.follow_up.clone())
+                })
+            })
+            .collect()
+    }
+}
+
+/// Roughly how many bytes `follow_up` and the follow-ups after it take in memory.
+fn chain_size(follow_up: Option<&FollowUp>) -> usize {
+    follow_up.map_or(0, |follow_up| {
+        std::mem::size_of::<FollowUp>()
+            + follow_up.text.len()
+            + chain_size(follow_up.then.as_deref())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_message_per_recipient() {
+        let definition = MessageDefinition {
+            text: "this is text".to_string(),
+            recipients: ["foo".to_string(), "bar".to_string()].into(),
+            ..Default::default()
+        };
+
+        let mut recipients =
+            Message::from_definition(definition, &IdGenerator::default(), "me", "channel")
+                .unwrap()
+                .into_iter()
+                .map(|message| message.recipient().to_string())
+                .collect::<Vec<_>>();
+        recipients.sort();
+
+        assert_eq!(vec!["bar", "foo"], recipients);
+    }
+
+    #[test]
+    fn follow_ups_link_to_their_parent() {
+        let definition = "me check oven then in:10m turn it off then in:1h clean it"
+            .parse::<MessageDefinition>()
+            .unwrap();
+        let message =
+            Message::from_definition(definition, &IdGenerator::default(), "alice", "channel")
+                .unwrap()
+                .remove(0);
+        let delivered = OffsetDateTime::UNIX_EPOCH;
+
+        let next = message.next_in_chain("b".to_string(), delivered).unwrap();
+        assert_eq!("turn it off", next.text());
+        assert_eq!(Some(message.id()), next.parent());
+        assert_eq!(
+            &Activation::Fixed(delivered + time::Duration::minutes(10)),
+            next.activation()
+        );
+
+        let last = next.next_in_chain("c".to_string(), delivered).unwrap();
+        assert_eq!("clean it", last.text());
+        assert_eq!(None, last.next_in_chain("d".to_string(), delivered));
+    }
+}
@@ -0,0 +1,373 @@
+//! The on-disk representation of [`Message`].
+//!
+//! Storages never serialize `Message` directly. They write the latest variant of
+//! [`StoredMessage`] and read any variant, so the runtime types can change without breaking
+//! existing files. When the layout changes, add a new variant and convert the old ones in
+//! `From<StoredMessage> for Message`.
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+use super::{Activation, Kind, Message, Origin, Priority};
+use crate::message_parser::FollowUp;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum StoredMessage {
+    V1(MessageV1),
+}
+
+/// The layout used since the first release. Files written before the format was versioned
+/// contain bare `MessageV1`s.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MessageV1 {
+    id: String,
+    activation: ActivationV1,
+    author: String,
+    recipient: String,
+    created: OffsetDateTime,
+    channel: String,
+    text: String,
+    #[serde(default)]
+    number: u32,
+    #[serde(default)]
+    here: bool,
+    #[serde(default)]
+    priority: PriorityV1,
+    #[serde(default)]
+    tags: BTreeSet<String>,
+    #[serde(default)]
+    kind: KindV1,
+    #[serde(default)]
+    silent: bool,
+    #[serde(default)]
+    on_join: bool,
+    #[serde(default)]
+    whisper: bool,
+    #[serde(default)]
+    time_zone: Option<String>,
+    #[serde(default)]
+    deadline: Option<OffsetDateTime>,
+    #[serde(default)]
+    follow_up: Option<FollowUpV1>,
+    #[serde(default)]
+    parent: Option<String>,
+    #[serde(default)]
+    origin: Option<OriginV1>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OriginV1 {
+    channel: String,
+    message_id: String,
+    at: OffsetDateTime,
+    #[serde(default)]
+    live: bool,
+    #[serde(default)]
+    vod: Option<String>,
+}
+
+impl From<OriginV1> for Origin {
+    fn from(origin: OriginV1) -> Self {
+        Self {
+            channel: origin.channel,
+            message_id: origin.message_id,
+            at: origin.at,
+            live: origin.live,
+            vod: origin.vod,
+        }
+    }
+}
+
+impl From<&Origin> for OriginV1 {
+    fn from(origin: &Origin) -> Self {
+        Self {
+            channel: origin.channel.clone(),
+            message_id: origin.message_id.clone(),
+            at: origin.at,
+            live: origin.live,
+            vod: origin.vod.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FollowUpV1 {
+    after_seconds: i64,
+    text: String,
+    #[serde(default)]
+    then: Option<Box<FollowUpV1>>,
+}
+
+impl From<FollowUpV1> for FollowUp {
+    fn from(follow_up: FollowUpV1) -> Self {
+        Self {
+            after: Duration::seconds(follow_up.after_seconds),
+            text: follow_up.text,
+            then: follow_up.then.map(|then| Box::new((*then).into())),
+        }
+    }
+}
+
+impl From<&FollowUp> for FollowUpV1 {
+    fn from(follow_up: &FollowUp) -> Self {
+        Self {
+            after_seconds: follow_up.after.whole_seconds(),
+            text: follow_up.text.clone(),
+            then: follow_up.then.as_deref().map(|then| Box::new(then.into())),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+pub enum ActivationV1 {
+    OnNextMessage,
+    Fixed(OffsetDateTime),
+    OnRaid,
+    OnOffline,
+    Never,
+}
+
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+pub enum PriorityV1 {
+    High,
+    Normal,
+    Low,
+}
+
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+pub enum KindV1 {
+    Reminder,
+    Countdown,
+    Announcement,
+    Note,
+    Notification,
+}
+
+impl Default for KindV1 {
+    fn default() -> Self {
+        KindV1::Reminder
+    }
+}
+
+impl Default for PriorityV1 {
+    fn default() -> Self {
+        PriorityV1::Normal
+    }
+}
+
+impl From<MessageV1> for Message {
+    fn from(message: MessageV1) -> Self {
+        Self {
+            id: message.id,
+            activation: match message.activation {
+                ActivationV1::OnNextMessage => Activation::OnNextMessage,
+                ActivationV1::Fixed(at) => Activation::Fixed(at),
+                ActivationV1::OnRaid => Activation::OnRaid,
+                ActivationV1::OnOffline => Activation::OnOffline,
+                ActivationV1::Never => Activation::Never,
+            },
+            author: message.author,
+            recipient: message.recipient,
+            created: message.created,
+            channel: message.channel,
+            text: message.text,
+            number: message.number,
+            here: message.here,
+            priority: match message.priority {
+                PriorityV1::High => Priority::High,
+                PriorityV1::Normal => Priority::Normal,
+                PriorityV1::Low => Priority::Low,
+            },
+            tags: message.tags,
+            kind: match message.kind {
+                KindV1::Reminder => Kind::Reminder,
+                KindV1::Countdown => Kind::Countdown,
+                KindV1::Announcement => Kind::Announcement,
+                KindV1::Note => Kind::Note,
+                KindV1::Notification => Kind::Notification,
+            },
+            silent: message.silent,
+            on_join: message.on_join,
+            whisper: message.whisper,
+            time_zone: message.time_zone,
+            deadline: message.deadline,
+            follow_up: message.follow_up.map(FollowUp::from),
+            parent: message.parent,
+            origin: message.origin.map(Origin::from),
+        }
+    }
+}
+
+impl From<StoredMessage> for Message {
+    fn from(message: StoredMessage) -> Self {
+        match message {
+            StoredMessage::V1(message) => message.into(),
+        }
+    }
+}
+
+impl From<&Message> for StoredMessage {
+    fn from(message: &Message) -> Self {
+        StoredMessage::V1(MessageV1 {
+            id: message.id.clone(),
+            activation: match message.activation {
+                Activation::OnNextMessage => ActivationV1::OnNextMessage,
+                Activation::Fixed(at) => ActivationV1::Fixed(at),
+                Activation::OnRaid => ActivationV1::OnRaid,
+                Activation::OnOffline => ActivationV1::OnOffline,
+                Activation::Never => ActivationV1::Never,
+            },
+            author: message.author.clone(),
+            recipient: message.recipient.clone(),
+            created: message.created,
+            channel: message.channel.clone(),
+            text: message.text.clone(),
+            number: message.number,
+            here: message.here,
+            priority: match message.priority {
+                Priority::High => PriorityV1::High,
+                Priority::Normal => PriorityV1::Normal,
+                Priority::Low => PriorityV1::Low,
+            },
+            tags: message.tags.clone(),
+            kind: match message.kind {
+                Kind::Reminder => KindV1::Reminder,
+                Kind::Countdown => KindV1::Countdown,
+                Kind::Announcement => KindV1::Announcement,
+                Kind::Note => KindV1::Note,
+                Kind::Notification => KindV1::Notification,
+            },
+            silent: message.silent,
+            on_join: message.on_join,
+            whisper: message.whisper,
+            time_zone: message.time_zone.clone(),
+            deadline: message.deadline,
+            follow_up: message.follow_up.as_ref().map(FollowUpV1::from),
+            parent: message.parent.clone(),
+            origin: message.origin.as_ref().map(OriginV1::from),
+        })
+    }
+}
+
+/// Parse a single message in any format ever written.
+pub fn from_str(s: &str) -> Result<Message, ron::Error> {
+    ron::de::from_str::<StoredMessage>(s)
+        .map(Message::from)
+        .or_else(|err| {
+            ron::de::from_str::<MessageV1>(s)
+                .map(Message::from)
+                .map_err(|_| err)
+        })
+}
+
+/// Parse a list of messages in any format ever written.
+pub fn list_from_str(s: &str) -> Result<Vec<Message>, ron::Error> {
+    match ron::de::from_str::<Vec<StoredMessage>>(s) {
+        Ok(messages) => Ok(messages.into_iter().map(Message::from).collect()),
+        Err(err) => ron::de::from_str::<Vec<MessageV1>>(s)
+            .map(|messages| messages.into_iter().map(Message::from).collect())
+            .map_err(|_| err),
+    }
+}
+
+/// Split the list in `s` into the source of its entries without parsing them, so they can be
+/// parsed one by one. Returns `None` if `s` isn't a list or its brackets don't match up.
+pub fn split_list(s: &str) -> Option<Vec<&str>> {
+    let inner = s.trim().strip_prefix('[')?.strip_suffix(']')?;
+
+    let mut entries = Vec::new();
+    let mut depth = 0_usize;
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in inner.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.checked_sub(1)?,
+            ',' if depth == 0 => {
+                entries.push(&inner[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if in_string || depth != 0 {
+        return None;
+    }
+    entries.push(&inner[start..]);
+
+    Some(
+        entries
+            .into_iter()
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEGACY: &str = r#"[(id:"ckz4l1r",activation:OnNextMessage,author:"alice",recipient:"bob",created:(2021,330,12,0,0,0,0,0,0),channel:"channel",text:"hi")]"#;
+
+    #[test]
+    fn split_list_entries() {
+        assert_eq!(
+            Some(vec!["V1((a:\"x, (y)\"))", "V1((b:[1,2]))"]),
+            split_list(" [V1((a:\"x, (y)\")), V1((b:[1,2])),]\n")
+        );
+        assert_eq!(Some(vec![]), split_list("[]"));
+        assert_eq!(None, split_list("[V1((a:1)]"));
+        assert_eq!(None, split_list("V1(())"));
+    }
+
+    #[test]
+    fn load_unversioned_messages() {
+        let messages = list_from_str(LEGACY).unwrap();
+
+        assert_eq!(1, messages.len());
+        assert_eq!("ckz4l1r", messages[0].id());
+        assert_eq!(Priority::Normal, messages[0].priority());
+    }
+
+    #[test]
+    fn roundtrip_latest_version() {
+        let message = list_from_str(LEGACY).unwrap().remove(0);
+        let data = ron::ser::to_string(&StoredMessage::from(&message)).unwrap();
+
+        assert!(data.starts_with("V1("));
+        assert_eq!(message, from_str(&data).unwrap());
+    }
+
+    #[test]
+    fn roundtrip_countdown() {
+        let message = Message::channel_wide(
+            Kind::Countdown,
+            "id".to_string(),
+            OffsetDateTime::UNIX_EPOCH,
+            "alice".to_string(),
+            "channel".to_string(),
+            "break over".to_string(),
+        );
+        let data = ron::ser::to_string(&StoredMessage::from(&message)).unwrap();
+        let loaded = from_str(&data).unwrap();
+
+        assert_eq!(Kind::Countdown, loaded.kind());
+        assert_eq!("#channel", loaded.recipient());
+        assert!(loaded.here());
+    }
+}
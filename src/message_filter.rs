@@ -0,0 +1,160 @@
+use std::str::FromStr;
+
+use time::{Date, Month, OffsetDateTime};
+
+use crate::message::Message;
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum Error {
+    #[error("expected at least one filter like to:<user>")]
+    Empty,
+
+    #[error("unknown filter '{0}' — try to:, tag:, before: or after:")]
+    UnknownKey(String),
+
+    #[error("couldn't understand '{0}' — expected a date like 2024-01-31")]
+    InvalidDate(String),
+}
+
+/// Selects messages by their recipient, tag or creation date, e.g.
+/// `to:alice tag:raid before:2024-01-01`. All given conditions must match.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MessageFilter {
+    pub recipient: Option<String>,
+    pub tag: Option<String>,
+    /// Only messages created before the start of this day.
+    pub before: Option<OffsetDateTime>,
+    /// Only messages created on or after the start of this day.
+    pub after: Option<OffsetDateTime>,
+}
+
+impl FromStr for MessageFilter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filter = MessageFilter::default();
+
+        for word in s.split_whitespace() {
+            let (key, value) = match word.split_once(':') {
+                Some((key, value)) if !value.is_empty() => (key.to_lowercase(), value),
+                _ => return Err(Error::UnknownKey(word.to_string())),
+            };
+
+            match key.as_str() {
+                "to" => filter.recipient = Some(value.trim_start_matches('@').to_lowercase()),
+                "tag" => filter.tag = Some(value.to_lowercase()),
+                "before" => filter.before = Some(parse_date(value)?),
+                "after" => filter.after = Some(parse_date(value)?),
+                _ => return Err(Error::UnknownKey(word.to_string())),
+            }
+        }
+
+        if filter == MessageFilter::default() {
+            return Err(Error::Empty);
+        }
+
+        Ok(filter)
+    }
+}
+
+/// Parse `YYYY-MM-DD` as midnight UTC.
+fn parse_date(s: &str) -> Result<OffsetDateTime, Error> {
+    let invalid = || Error::InvalidDate(s.to_string());
+
+    let mut parts = s.splitn(3, '-');
+    let mut next = || -> Result<u16, Error> {
+        parts
+            .next()
+            .and_then(|part| part.parse().ok())
+            .ok_or_else(invalid)
+    };
+    let (year, month, day) = (next()?, next()?, next()?);
+
+    let month = u8::try_from(month)
+        .ok()
+        .and_then(|month| Month::try_from(month).ok())
+        .ok_or_else(invalid)?;
+    let day = u8::try_from(day).map_err(|_| invalid())?;
+    let date = Date::from_calendar_date(year.into(), month, day).map_err(|_| invalid())?;
+
+    Ok(date.midnight().assume_utc())
+}
+
+impl MessageFilter {
+    pub fn matches(&self, message: &Message) -> bool {
+        self.recipient
+            .as_deref()
+            .map_or(true, |recipient| message.recipient() == recipient)
+            && self
+                .tag
+                .as_ref()
+                .map_or(true, |tag| message.tags().contains(tag))
+            && self
+                .before
+                .map_or(true, |before| message.created() < before)
+            && self.after.map_or(true, |after| message.created() >= after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Activation;
+
+    #[test]
+    fn parse_filters() {
+        let filter = "to:@Alice before:2024-01-01"
+            .parse::<MessageFilter>()
+            .unwrap();
+
+        assert_eq!(Some("alice".to_string()), filter.recipient);
+        assert_eq!(
+            Some(
+                Date::from_calendar_date(2024, Month::January, 1)
+                    .unwrap()
+                    .midnight()
+                    .assume_utc()
+            ),
+            filter.before
+        );
+
+        assert_eq!(Err(Error::Empty), "".parse::<MessageFilter>());
+        assert_eq!(
+            Err(Error::InvalidDate("2024-13-01".to_string())),
+            "before:2024-13-01".parse::<MessageFilter>()
+        );
+        assert_eq!(
+            Err(Error::UnknownKey("from:bob".to_string())),
+            "from:bob".parse::<MessageFilter>()
+        );
+    }
+
+    #[test]
+    fn matches_all_conditions() {
+        let message = Message::new(
+            "id".to_string(),
+            Activation::OnNextMessage,
+            "bob".to_string(),
+            "channel".to_string(),
+            "alice".to_string(),
+            "text".to_string(),
+        );
+
+        assert!("to:alice"
+            .parse::<MessageFilter>()
+            .unwrap()
+            .matches(&message));
+        assert!(!"to:carol"
+            .parse::<MessageFilter>()
+            .unwrap()
+            .matches(&message));
+        assert!(!"to:alice before:2000-01-01"
+            .parse::<MessageFilter>()
+            .unwrap()
+            .matches(&message));
+        assert!(!"tag:raid"
+            .parse::<MessageFilter>()
+            .unwrap()
+            .matches(&message));
+    }
+}
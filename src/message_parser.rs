@@ -1,16 +1,86 @@
-use std::{collections::HashSet, str::FromStr};
+//! The grammar of `~tell` and `~remind`: recipients, attributes like `in:2h` or `cc:other` and
+//! the text of the reminder.
+//!
+//! The text may end in follow-ups like `check oven then in:10m turn it off`, reminders that are
+//! only created once the one before them was delivered.
 
-use pest::Parser;
+use std::{
+    collections::{BTreeSet, HashSet},
+    str::FromStr,
+};
+
+use pest::{
+    error::{ErrorVariant, InputLocation},
+    Parser,
+};
 use pest_derive::Parser;
 use time::{Duration, OffsetDateTime};
 
-use crate::{duration_parser::IntermediateDuration, message::Message};
+use crate::{
+    date_parser::{self, WeekdayTime},
+    duration_parser::{IntermediateDuration, Language},
+    time_zone::Zone,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Schedule {
     None,
     Relative(Duration),
     Fixed(OffsetDateTime),
+    /// When the channel gets raided.
+    Raid,
+    /// When the stream of the channel ends.
+    Offline,
+}
+
+impl Schedule {
+    /// Whether the reminder is due at a point in time rather than on an event.
+    pub fn is_timed(&self) -> bool {
+        matches!(self, Schedule::Relative(_) | Schedule::Fixed(_))
+    }
+}
+
+/// Whose chat line should be embedded into the reminder text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Quote {
+    /// The latest line in the channel by anyone but the author.
+    Last,
+    User(String),
+}
+
+/// A reminder created once the one it follows up on was delivered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FollowUp {
+    /// How long after the delivery it is due.
+    pub after: Duration,
+    pub text: String,
+    /// The follow-up of this follow-up.
+    pub then: Option<Box<FollowUp>>,
+}
+
+/// Separates the text of a reminder from its follow-up.
+const FOLLOW_UP_SEPARATOR: &str = " then in:";
+
+/// Attribute keys understood by the parser, listed in error hints.
+const ATTRIBUTE_KEYS: &[&str] = &[
+    "cc", "in", "at", "on", "when", "quote", "here", "anywhere", "channel", "priority", "tag",
+    "silent", "onjoin", "whisper", "deadline",
+];
+
+/// Order in which reminders are delivered. Variants are declared from most to least urgent so
+/// sorting puts urgent reminders first.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    High,
+    Normal,
+    /// Delivered after everything else.
+    Low,
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::Normal
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -18,13 +88,69 @@ pub struct MessageDefinition {
     pub text: String,
     pub created: OffsetDateTime,
     pub schedule: Schedule,
+    /// Further times given after a timed `schedule`, e.g. `in:10m in:1h`. Each creates another
+    /// reminder.
+    pub extra_schedules: Vec<Schedule>,
     pub recipients: HashSet<String>,
+    pub quote: Option<Quote>,
+    /// Only deliver in the channel the reminder was created in. `None` uses the channel's
+    /// default.
+    pub here: Option<bool>,
+    /// Deliver in this channel instead of the one the reminder was created in.
+    pub channel: Option<String>,
+    pub priority: Priority,
+    pub tags: BTreeSet<String>,
+    /// Deliver without mentioning the recipient.
+    pub silent: bool,
+    /// Also deliver when the recipient joins the channel, before they type.
+    pub on_join: bool,
+    /// Whisper the reminder to the recipient instead of saying it in chat.
+    pub whisper: bool,
+    /// The named time zone a time of day in the schedule was read in.
+    pub time_zone: Option<String>,
+    /// Also remind everyone who chatted recently (`cc:chat`).
+    pub chat: bool,
+    /// Tell the author if the reminder still wasn't delivered this long after it was created.
+    pub deadline: Option<Duration>,
+    pub follow_up: Option<FollowUp>,
+}
+
+impl Default for MessageDefinition {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            created: OffsetDateTime::now_utc(),
+            schedule: Schedule::None,
+            extra_schedules: Vec::new(),
+            recipients: HashSet::new(),
+            quote: None,
+            here: None,
+            channel: None,
+            priority: Priority::Normal,
+            tags: BTreeSet::new(),
+            silent: false,
+            on_join: false,
+            whisper: false,
+            time_zone: None,
+            chat: false,
+            deadline: None,
+            follow_up: None,
+        }
+    }
 }
 
 impl FromStr for MessageDefinition {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_localized(s, &Zone::default(), Language::English)
+    }
+}
+
+impl MessageDefinition {
+    /// Parse `s`, reading times of day like `at:18:30` in `zone` and durations with the units of
+    /// `language`.
+    pub fn parse_localized(s: &str, zone: &Zone, language: Language) -> Result<Self, Error> {
         let message_pair = MessageDefinitionParser::parse(Rule::message, s)
             .map_err(|source| Error::ParseRule {
                 rule: Rule::message,
@@ -33,12 +159,9 @@ impl FromStr for MessageDefinition {
             .next()
             .unwrap();
 
-        let mut def = MessageDefinition {
-            text: String::new(),
-            created: OffsetDateTime::now_utc(),
-            schedule: Schedule::None,
-            recipients: HashSet::new(),
-        };
+        let mut def = MessageDefinition::default();
+        // the attribute that set the schedule, to point out conflicting ones
+        let mut scheduled_by = None;
 
         for pair in message_pair.into_inner() {
             match pair.as_rule() {
@@ -60,13 +183,92 @@ impl FromStr for MessageDefinition {
                             }
                         };
 
+                        let attribute = format!("{}:{}", key, value);
                         match key {
+                            "cc" if value.eq_ignore_ascii_case("chat") => def.chat = true,
                             "cc" => {
                                 def.recipients.insert(value.to_lowercase());
                             }
                             "in" => {
-                                def.schedule = Schedule::Relative(
-                                    value.to_lowercase().parse::<IntermediateDuration>()?.into(),
+                                let schedule = match IntermediateDuration::parse_localized(
+                                    &value.to_lowercase(),
+                                    language,
+                                ) {
+                                    Ok(duration) => Schedule::Relative(duration.into()),
+                                    // `in:"next monday 9am"` reads naturally too
+                                    Err(_) if WeekdayTime::parse(value).is_some() => {
+                                        def.time_zone = zone.name().map(str::to_string);
+                                        parse_weekday_time(key, value, def.created, zone)?
+                                    }
+                                    Err(source) => {
+                                        return Err(Error::ParseDuration {
+                                            key: key.to_string(),
+                                            value: value.to_string(),
+                                            source,
+                                        })
+                                    }
+                                };
+                                def.add_schedule(schedule, attribute, &mut scheduled_by)?;
+                            }
+                            "at" => {
+                                let time =
+                                    date_parser::parse_clock_time(value).ok_or_else(|| {
+                                        Error::InvalidValue {
+                                            key: key.to_string(),
+                                            value: value.to_string(),
+                                            expected: "a time like 18:30, noon or midnight",
+                                        }
+                                    })?;
+                                def.add_schedule(
+                                    Schedule::Fixed(date_parser::next_local_occurrence(
+                                        def.created,
+                                        time,
+                                        zone,
+                                    )),
+                                    attribute,
+                                    &mut scheduled_by,
+                                )?;
+                                def.time_zone = zone.name().map(str::to_string);
+                            }
+                            "on" => {
+                                let schedule = parse_weekday_time(key, value, def.created, zone)?;
+                                def.add_schedule(schedule, attribute, &mut scheduled_by)?;
+                                def.time_zone = zone.name().map(str::to_string);
+                            }
+                            "when" => {
+                                let schedule = parse_when(key, value)?;
+                                def.add_schedule(schedule, attribute, &mut scheduled_by)?;
+                            }
+                            "quote" => {
+                                def.quote = Some(match value.to_lowercase().as_str() {
+                                    "last" => Quote::Last,
+                                    user => Quote::User(user.trim_start_matches('@').to_string()),
+                                })
+                            }
+                            "here" => def.here = Some(parse_bool(key, value)?),
+                            "anywhere" => def.here = Some(!parse_bool(key, value)?),
+                            "channel" => {
+                                def.channel = Some(value.trim_start_matches('#').to_lowercase())
+                            }
+                            "priority" => def.priority = parse_priority(key, value)?,
+                            "tag" => {
+                                def.tags.insert(value.to_lowercase());
+                            }
+                            "silent" => def.silent = parse_bool(key, value)?,
+                            "onjoin" => def.on_join = parse_bool(key, value)?,
+                            "whisper" => def.whisper = parse_bool(key, value)?,
+                            "deadline" => {
+                                def.deadline = Some(
+                                    IntermediateDuration::parse_localized(
+                                        &value.to_lowercase(),
+                                        language,
+                                    )
+                                    .map_err(|source| Error::ParseDuration {
+                                        key: key.to_string(),
+                                        value: value.to_string(),
+                                        source,
+                                    })?
+                                    .into(),
                                 )
                             }
                             _ => return Err(Error::UnknownAttributeKey(key.to_string())),
@@ -77,7 +279,11 @@ impl FromStr for MessageDefinition {
                     def.recipients
                         .insert(pair.as_span().as_str().to_lowercase());
                 }
-                Rule::text => def.text = pair.as_span().as_str().to_string(),
+                Rule::text => {
+                    let (text, follow_up) = parse_follow_up(pair.as_span().as_str(), language)?;
+                    def.text = text;
+                    def.follow_up = follow_up;
+                }
                 Rule::EOI => {
                     let s = pair.as_span().as_str();
                     if !s.is_empty() {
@@ -90,24 +296,134 @@ impl FromStr for MessageDefinition {
 
         Ok(def)
     }
+
+    /// Set the schedule given by `attribute`, or add another time if both it and the current one
+    /// are timed. Times can't be mixed with events and there can only be one event.
+    /// `scheduled_by` is the attribute that set the schedule so far.
+    fn add_schedule(
+        &mut self,
+        schedule: Schedule,
+        attribute: String,
+        scheduled_by: &mut Option<String>,
+    ) -> Result<(), Error> {
+        match scheduled_by {
+            None => {
+                self.schedule = schedule;
+                *scheduled_by = Some(attribute);
+            }
+            Some(_) if schedule.is_timed() && self.schedule.is_timed() => {
+                self.extra_schedules.push(schedule)
+            }
+            Some(first) => {
+                return Err(Error::ConflictingSchedules {
+                    first: first.clone(),
+                    second: attribute,
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Every schedule a reminder is created for, the first one given first.
+    pub fn schedules(&self) -> impl Iterator<Item = &Schedule> {
+        std::iter::once(&self.schedule).chain(&self.extra_schedules)
+    }
 }
 
-impl MessageDefinition {
-    pub fn into_messages(self, author: &str, channel: &str) -> Vec<Message> {
-        let activation = self.schedule.into();
-        self.recipients
-            .into_iter()
-            .map(|recipient| {
-                Message::new(
-                    activation,
-                    author.to_string(),
-                    channel.to_string(),
-                    recipient,
-                    self.text.clone(),
-                )
-            })
-            .collect()
+/// Split `text` at the first ` then in:<duration> ` into the text of the reminder and its
+/// follow-ups.
+fn parse_follow_up(text: &str, language: Language) -> Result<(String, Option<FollowUp>), Error> {
+    let at = match text.find(FOLLOW_UP_SEPARATOR) {
+        Some(at) => at,
+        None => return Ok((text.to_string(), None)),
+    };
+
+    let rest = &text[at + FOLLOW_UP_SEPARATOR.len()..];
+    let (value, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+    let after = IntermediateDuration::parse_localized(&value.to_lowercase(), language)
+        .map_err(|source| Error::ParseDuration {
+            key: "in".to_string(),
+            value: value.to_string(),
+            source,
+        })?
+        .into();
+    let (follow_up_text, then) = parse_follow_up(rest.trim(), language)?;
+    if follow_up_text.is_empty() {
+        return Err(Error::EmptyFollowUp(value.to_string()));
     }
+
+    Ok((
+        text[..at].to_string(),
+        Some(FollowUp {
+            after,
+            text: follow_up_text,
+            then: then.map(Box::new),
+        }),
+    ))
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, Error> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" => Ok(false),
+        _ => Err(Error::InvalidValue {
+            key: key.to_string(),
+            value: value.to_string(),
+            expected: "true or false",
+        }),
+    }
+}
+
+fn parse_when(key: &str, value: &str) -> Result<Schedule, Error> {
+    match value.to_lowercase().as_str() {
+        "raid" => Ok(Schedule::Raid),
+        "offline" => Ok(Schedule::Offline),
+        _ => Err(Error::InvalidValue {
+            key: key.to_string(),
+            value: value.to_string(),
+            expected: "raid or offline",
+        }),
+    }
+}
+
+fn parse_weekday_time(
+    key: &str,
+    value: &str,
+    now: OffsetDateTime,
+    zone: &Zone,
+) -> Result<Schedule, Error> {
+    let invalid = |expected| Error::InvalidValue {
+        key: key.to_string(),
+        value: value.to_string(),
+        expected,
+    };
+
+    WeekdayTime::parse(value)
+        .ok_or_else(|| invalid("a weekday and time like \"next monday 9am\""))?
+        .resolve(now, zone)
+        .map(Schedule::Fixed)
+        .ok_or_else(|| invalid("a day that hasn't passed yet, try \"next\" instead of \"this\""))
+}
+
+fn parse_priority(key: &str, value: &str) -> Result<Priority, Error> {
+    match value.to_lowercase().as_str() {
+        "high" => Ok(Priority::High),
+        "normal" => Ok(Priority::Normal),
+        "low" => Ok(Priority::Low),
+        _ => Err(Error::InvalidValue {
+            key: key.to_string(),
+            value: value.to_string(),
+            expected: "high, normal or low",
+        }),
+    }
+}
+
+/// Parse the arguments of `~tell`, e.g. `in:2h alice stretch`, like chat does in a channel without
+/// a language for a user without a time zone: times of day are read in UTC and durations in
+/// English. Use [`MessageDefinition::parse_localized`] for other channels and users.
+pub fn parse_tell(input: &str) -> Result<MessageDefinition, Error> {
+    input.parse()
 }
 
 #[derive(Parser)]
@@ -128,17 +444,105 @@ pub enum Error {
     #[error("Unknown attribute key: {0:?}")]
     UnknownAttributeKey(String),
 
-    #[error("Failed to parse duration")]
-    ParseDuration(#[from] crate::duration_parser::Error),
+    #[error("Invalid value {value:?} for attribute {key:?}, expected {expected}")]
+    InvalidValue {
+        key: String,
+        value: String,
+        expected: &'static str,
+    },
+
+    #[error("Failed to parse duration {value:?} of attribute {key:?}")]
+    ParseDuration {
+        key: String,
+        value: String,
+        source: crate::duration_parser::Error,
+    },
+
+    #[error("Follow-up after {0:?} has no text")]
+    EmptyFollowUp(String),
+
+    #[error("Schedule {second:?} conflicts with {first:?}")]
+    ConflictingSchedules { first: String, second: String },
+}
+
+impl Error {
+    /// Describe the error in a way that can be shown to the user in chat.
+    pub fn hint(&self, input: &str) -> String {
+        match self {
+            Error::ParseRule { source, .. } => {
+                let pos = match source.location {
+                    InputLocation::Pos(pos) => pos,
+                    InputLocation::Span((start, _)) => start,
+                };
+                let word = input
+                    .get(pos..)
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default();
+
+                match &source.variant {
+                    ErrorVariant::ParsingError { positives, .. }
+                        if positives.contains(&Rule::recipient) =>
+                    {
+                        if word.is_empty() {
+                            "expected a recipient followed by a message".to_string()
+                        } else {
+                            format!(
+                                "couldn't understand '{}' — expected a recipient like @user",
+                                word
+                            )
+                        }
+                    }
+                    _ if word.is_empty() => "unexpected end of message".to_string(),
+                    _ => format!("couldn't understand '{}' at position {}", word, pos + 1),
+                }
+            }
+            Error::DanglingChars(s) => format!("couldn't understand '{}'", s),
+            Error::UnknownAttributeKey(key) => format!(
+                "unknown attribute '{}' — try one of {}",
+                key,
+                ATTRIBUTE_KEYS
+                    .iter()
+                    .map(|key| format!("{}:", key))
+                    .intersperse(", ".to_string())
+                    .collect::<String>()
+            ),
+            Error::InvalidValue {
+                key,
+                value,
+                expected,
+            } => format!(
+                "couldn't understand '{}:{}' — expected {}",
+                key, value, expected
+            ),
+            Error::ParseDuration { key, value, .. } => format!(
+                "couldn't understand '{}:{}' — expected a duration like 2h or 30m",
+                key, value
+            ),
+            Error::EmptyFollowUp(value) => format!(
+                "expected a message after 'then in:{}' to remind them of",
+                value
+            ),
+            Error::ConflictingSchedules { first, second } => format!(
+                "'{}' and '{}' can't be combined — give one or more times, or a single when:",
+                first, second
+            ),
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use std::collections::HashSet;
+    use std::collections::{BTreeSet, HashSet};
 
-    use time::OffsetDateTime;
+    use time::Duration;
 
-    use crate::message_parser::{MessageDefinition, Schedule};
+    use crate::{
+        duration_parser::Language,
+        message_parser::{FollowUp, MessageDefinition, Priority, Quote, Schedule},
+        time_zone::Zone,
+    };
 
     #[test]
     fn parse_empty() {
@@ -173,6 +577,21 @@ mod test {
         assert_eq!(Schedule::None, def.schedule);
     }
 
+    #[test]
+    fn parse_cc_chat() {
+        let def = "cc:Chat recipient stream moved"
+            .parse::<MessageDefinition>()
+            .unwrap();
+
+        assert!(def.chat);
+        assert_eq!(
+            ["recipient".to_string()]
+                .into_iter()
+                .collect::<HashSet<_>>(),
+            def.recipients
+        );
+    }
+
     #[test]
     fn test_uppercase() {
         let def = "cc:\"other\" cc:Foo recIpient actual message"
@@ -191,20 +610,291 @@ mod test {
     }
 
     #[test]
-    fn message_definition_into_messages() {
-        let def = MessageDefinition {
-            text: "this is text".to_string(),
-            created: OffsetDateTime::now_utc(),
-            schedule: Schedule::None,
-            recipients: ["foo".to_string(), "bar".to_string()].into(),
+    fn parse_quote_attribute() {
+        let def = "quote:@Bob alice remember this ^"
+            .parse::<MessageDefinition>()
+            .unwrap();
+
+        assert_eq!(Some(Quote::User("bob".to_string())), def.quote);
+        assert_eq!("remember this ^", &def.text);
+
+        let def = "quote:last alice ^".parse::<MessageDefinition>().unwrap();
+
+        assert_eq!(Some(Quote::Last), def.quote);
+    }
+
+    #[test]
+    fn parse_here_attribute() {
+        let def = "here:yes alice text".parse::<MessageDefinition>().unwrap();
+        assert_eq!(Some(true), def.here);
+
+        let input = "here:maybe alice text";
+        let err = input.parse::<MessageDefinition>().unwrap_err();
+        assert_eq!(
+            "couldn't understand 'here:maybe' — expected true or false",
+            err.hint(input)
+        );
+    }
+
+    #[test]
+    fn parse_anywhere_attribute() {
+        let def = "anywhere:true alice text"
+            .parse::<MessageDefinition>()
+            .unwrap();
+        assert_eq!(Some(false), def.here);
+    }
+
+    #[test]
+    fn parse_silent_attribute() {
+        let def = "silent:true alice text"
+            .parse::<MessageDefinition>()
+            .unwrap();
+        assert!(def.silent);
+
+        let def = "alice text".parse::<MessageDefinition>().unwrap();
+        assert!(!def.silent);
+    }
+
+    #[test]
+    fn parse_onjoin_attribute() {
+        let def = "onjoin:true alice text"
+            .parse::<MessageDefinition>()
+            .unwrap();
+        assert!(def.on_join);
+
+        let def = "alice text".parse::<MessageDefinition>().unwrap();
+        assert!(!def.on_join);
+    }
+
+    #[test]
+    fn parse_whisper_attribute() {
+        let def = "when:offline whisper:yes alice upload the highlights"
+            .parse::<MessageDefinition>()
+            .unwrap();
+        assert!(def.whisper);
+
+        let def = "alice text".parse::<MessageDefinition>().unwrap();
+        assert!(!def.whisper);
+    }
+
+    #[test]
+    fn parse_deadline_attribute() {
+        let def = "deadline:2h alice text"
+            .parse::<MessageDefinition>()
+            .unwrap();
+        assert_eq!(Some(Duration::hours(2)), def.deadline);
+        assert_eq!(Schedule::None, def.schedule);
+
+        let input = "deadline:soon alice text";
+        let err = input.parse::<MessageDefinition>().unwrap_err();
+        assert_eq!(
+            "couldn't understand 'deadline:soon' — expected a duration like 2h or 30m",
+            err.hint(input)
+        );
+    }
+
+    #[test]
+    fn parse_several_schedules() {
+        let def = "in:10m in:1h in:1d alice review"
+            .parse::<MessageDefinition>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                &Schedule::Relative(Duration::minutes(10)),
+                &Schedule::Relative(Duration::hours(1)),
+                &Schedule::Relative(Duration::days(1)),
+            ],
+            def.schedules().collect::<Vec<_>>()
+        );
+
+        // events can't be mixed with times or each other
+        let input = "in:10m when:raid alice review";
+        let err = input.parse::<MessageDefinition>().unwrap_err();
+        assert_eq!(
+            "'in:10m' and 'when:raid' can't be combined — give one or more times, or a single when:",
+            err.hint(input)
+        );
+        assert!("when:raid when:offline alice review"
+            .parse::<MessageDefinition>()
+            .is_err());
+    }
+
+    #[test]
+    fn parse_follow_ups() {
+        let def = "in:30m me check oven then in:10m turn it off then in:1h clean it"
+            .parse::<MessageDefinition>()
+            .unwrap();
+
+        assert_eq!("check oven", def.text);
+        assert_eq!(Schedule::Relative(Duration::minutes(30)), def.schedule);
+        assert_eq!(
+            Some(FollowUp {
+                after: Duration::minutes(10),
+                text: "turn it off".to_string(),
+                then: Some(Box::new(FollowUp {
+                    after: Duration::hours(1),
+                    text: "clean it".to_string(),
+                    then: None,
+                })),
+            }),
+            def.follow_up
+        );
+
+        let def = "alice and then some".parse::<MessageDefinition>().unwrap();
+        assert_eq!("and then some", def.text);
+        assert_eq!(None, def.follow_up);
+
+        let input = "alice check oven then in:10m";
+        let err = input.parse::<MessageDefinition>().unwrap_err();
+        assert_eq!(
+            "expected a message after 'then in:10m' to remind them of",
+            err.hint(input)
+        );
+    }
+
+    #[test]
+    fn parse_channel_attribute() {
+        let def = "channel:#OtherChannel alice text"
+            .parse::<MessageDefinition>()
+            .unwrap();
+
+        assert_eq!(Some("otherchannel".to_string()), def.channel);
+        assert_eq!("text", &def.text);
+    }
+
+    #[test]
+    fn parse_priority_attribute() {
+        let def = "priority:HIGH alice text"
+            .parse::<MessageDefinition>()
+            .unwrap();
+        assert_eq!(Priority::High, def.priority);
+
+        let def = "alice text".parse::<MessageDefinition>().unwrap();
+        assert_eq!(Priority::Normal, def.priority);
+    }
+
+    #[test]
+    fn parse_when_attribute() {
+        let def = "when:Raid alice greet the raiders"
+            .parse::<MessageDefinition>()
+            .unwrap();
+        assert_eq!(Schedule::Raid, def.schedule);
+
+        let def = "when:offline alice upload the highlights"
+            .parse::<MessageDefinition>()
+            .unwrap();
+        assert_eq!(Schedule::Offline, def.schedule);
+
+        assert!("when:later alice text"
+            .parse::<MessageDefinition>()
+            .is_err());
+    }
+
+    #[test]
+    fn parse_at_attribute() {
+        let def = "at:noon alice lunch".parse::<MessageDefinition>().unwrap();
+        assert!(matches!(def.schedule, Schedule::Fixed(at) if at > def.created));
+
+        let def = MessageDefinition::parse_localized(
+            "at:18:30 alice dinner",
+            &Zone::Fixed(60),
+            Language::English,
+        )
+        .unwrap();
+        let at = match def.schedule {
+            Schedule::Fixed(at) => at,
+            schedule => panic!("unexpected schedule {:?}", schedule),
+        };
+        assert!(at > def.created && at - def.created <= Duration::days(1));
+        assert_eq!((17, 30), (at.hour(), at.minute()));
+
+        let def = MessageDefinition::parse_localized(
+            "at:9:00 alice standup",
+            &Zone::named("Europe/Berlin").unwrap(),
+            Language::English,
+        )
+        .unwrap();
+        assert_eq!(Some("Europe/Berlin".to_string()), def.time_zone);
+
+        assert!("at:teatime alice text"
+            .parse::<MessageDefinition>()
+            .is_err());
+    }
+
+    #[test]
+    fn parse_localized_durations() {
+        let def = MessageDefinition::parse_localized(
+            "in:\"2 stunden\" alice text",
+            &Zone::default(),
+            Language::German,
+        )
+        .unwrap();
+        assert_eq!(Schedule::Relative(Duration::hours(2)), def.schedule);
+
+        assert!("in:\"2 stunden\" alice text"
+            .parse::<MessageDefinition>()
+            .is_err());
+    }
+
+    #[test]
+    fn parse_weekday_attributes() {
+        let def = "in:\"next monday 9am\" alice standup"
+            .parse::<MessageDefinition>()
+            .unwrap();
+        let at = match def.schedule {
+            Schedule::Fixed(at) => at,
+            schedule => panic!("unexpected schedule {:?}", schedule),
         };
+        assert_eq!(time::Weekday::Monday, at.weekday());
+        assert!(at - def.created > Duration::ZERO && at - def.created <= Duration::weeks(2));
+
+        let def = "on:\"sunday 20:00\" alice watch party"
+            .parse::<MessageDefinition>()
+            .unwrap();
+        assert!(matches!(def.schedule, Schedule::Fixed(at) if at > def.created));
+
+        assert!("on:someday alice text"
+            .parse::<MessageDefinition>()
+            .is_err());
+        assert!("in:\"next someday\" alice text"
+            .parse::<MessageDefinition>()
+            .is_err());
+    }
+
+    #[test]
+    fn parse_tag_attributes() {
+        let def = "tag:Raid tag:ops alice text"
+            .parse::<MessageDefinition>()
+            .unwrap();
 
         assert_eq!(
-            vec!["foo", "bar"],
-            def.into_messages("me", "channel")
+            ["raid", "ops"]
                 .into_iter()
-                .map(|message| message.recipient().to_string())
-                .collect::<Vec<_>>()
+                .map(|s| s.to_string())
+                .collect::<BTreeSet<_>>(),
+            def.tags
+        );
+    }
+
+    #[test]
+    fn hint_for_bad_duration() {
+        let input = "in:2x recipient actual message";
+        let err = input.parse::<MessageDefinition>().unwrap_err();
+
+        assert_eq!(
+            "couldn't understand 'in:2x' — expected a duration like 2h or 30m",
+            err.hint(input)
+        );
+    }
+
+    #[test]
+    fn hint_for_missing_recipient() {
+        let input = "cc:foo";
+        let err = input.parse::<MessageDefinition>().unwrap_err();
+
+        assert_eq!(
+            "expected a recipient followed by a message",
+            err.hint(input)
         );
     }
 }
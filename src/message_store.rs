@@ -1,108 +1,988 @@
 use std::{
-    collections::{HashMap, HashSet},
-    fs::File,
-    path::PathBuf,
+    collections::{BTreeSet, HashMap, HashSet},
+    ops::Bound,
+    sync::Arc,
 };
 
-use eyre::{eyre, Context, Result};
+use eyre::{Context, Result};
+use time::OffsetDateTime;
+use tokio::sync::Mutex;
+use tracing::{instrument, warn};
 
-use crate::message::{Activation, Message};
+use crate::{
+    delivery,
+    message::{Activation, Kind, Message},
+    message_filter::MessageFilter,
+    storage::{Operation, Storage},
+};
+
+/// How many journaled operations are written before the next save writes a full snapshot.
+const COMPACT_AFTER: usize = 100;
+
+/// A [`MessageStore`] shared by the chat handler and the scheduled deliveries, so they all see
+/// and persist the same messages.
+///
+/// The lock can be held across `.await`, but shouldn't be while waiting on the network.
+pub type SharedStore = Arc<Mutex<MessageStore>>;
+
+/// Where [`MessageStore::get_scheduled_before`] or [`MessageStore::get_missed_before`] continue
+/// from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    deadline: OffsetDateTime,
+    id: String,
+}
 
-#[derive(Debug, Clone)]
+/// Every stored message, indexed by recipient, id, author, tag and deadline.
+///
+/// All messages are loaded into memory when the store is opened and stay there. Only reading
+/// them is paged, with [`MessageStore::get_scheduled_before`], and snapshots are written without
+/// copying them first.
+#[derive(Debug)]
 pub struct MessageStore {
-    path: PathBuf,
+    storage: Arc<dyn Storage>,
+    instance_id: String,
     data: HashMap<String, HashSet<Message>>,
+    /// Maps message ids to their recipient, the key into `data`.
+    ids: HashMap<String, String>,
+    /// Maps authors to the ids of their messages.
+    authors: HashMap<String, HashSet<String>>,
+    /// Maps tags to the ids of the messages carrying them.
+    tags: HashMap<String, HashSet<String>>,
+    /// Deadlines and ids of the messages with a fixed deadline, in the order they are due.
+    deadlines: BTreeSet<(OffsetDateTime, String)>,
+    /// `deadline:`s and ids of the messages that have one, in the order they pass.
+    missed: BTreeSet<(OffsetDateTime, String)>,
+    /// Changes since the last save.
+    unsaved: Vec<Operation>,
+    /// Number of operations journaled since the last snapshot.
+    journaled: usize,
+    /// When changes were last written to the storage.
+    last_saved: Option<OffsetDateTime>,
+    /// Number of operations saved since the store was loaded.
+    changes: u64,
 }
 
 impl MessageStore {
-    pub fn from_path(path: PathBuf) -> Result<Self> {
-        let raw_data = if path.exists() {
-            if path.is_dir() {
-                return Err(eyre!("Path points to a directory"));
-            }
+    pub fn from_storage(storage: Arc<dyn Storage>, instance_id: String) -> Result<Self> {
+        let raw_data = storage.load().wrap_err("Failed to load storage")?;
 
-            let file = File::open(&path).wrap_err("Failed to open storage")?;
-            ron::de::from_reader(file).wrap_err("Failed to deserialize storage")?
-        } else {
-            HashSet::<Message>::new()
+        let mut store = Self {
+            storage,
+            instance_id,
+            data: HashMap::new(),
+            ids: HashMap::new(),
+            authors: HashMap::new(),
+            tags: HashMap::new(),
+            deadlines: BTreeSet::new(),
+            missed: BTreeSet::new(),
+            unsaved: Vec::new(),
+            journaled: 0,
+            last_saved: None,
+            changes: 0,
         };
 
-        let data = raw_data.into_iter().fold(
-            HashMap::<String, HashSet<Message>>::new(),
-            |mut acc, message| {
-                acc.entry(message.recipient().to_string())
-                    .and_modify(|messages| {
-                        messages.insert(message.clone());
-                    })
-                    .or_insert_with(|| {
-                        let mut set = HashSet::new();
-                        set.insert(message);
-                        set
-                    });
-                acc
-            },
-        );
+        for message in raw_data {
+            store.insert(message);
+        }
+        store.unsaved.clear();
+
+        Ok(store)
+    }
+
+    /// Load the messages from the storage again if something else changed them, like an operator
+    /// editing the file by hand. Changes that weren't saved yet are applied on top. Returns
+    /// whether the store was reloaded.
+    pub fn reload_if_changed(&mut self) -> Result<bool> {
+        if !self
+            .storage
+            .changed()
+            .wrap_err("Failed to check storage for changes")?
+        {
+            return Ok(false);
+        }
+
+        let raw_data = self.storage.load().wrap_err("Failed to load storage")?;
+        let unsaved = std::mem::take(&mut self.unsaved);
+
+        self.data.clear();
+        self.ids.clear();
+        self.authors.clear();
+        self.tags.clear();
+        self.deadlines.clear();
+        self.missed.clear();
+        for message in raw_data {
+            self.insert(message);
+        }
+        self.unsaved.clear();
+
+        for operation in unsaved {
+            match operation {
+                Operation::Insert(message) => self.insert(message),
+                Operation::Remove(id) => {
+                    if self.take(&id).is_none() {
+                        // keep the removal in case the message is still journaled
+                        self.unsaved.push(Operation::Remove(id));
+                    }
+                }
+            }
+        }
 
-        Ok(Self { path, data })
+        Ok(true)
     }
 
-    pub fn insert(&mut self, message: Message) {
+    pub fn insert(&mut self, mut message: Message) {
+        self.take(message.id());
+
+        let taken = self
+            .get_by_author(message.author())
+            .into_iter()
+            .map(|message| message.number())
+            .collect::<HashSet<_>>();
+        if message.number() == 0 || taken.contains(&message.number()) {
+            message.set_number((1..).find(|number| !taken.contains(number)).unwrap());
+        }
+
+        self.ids
+            .insert(message.id().to_string(), message.recipient().to_string());
+        self.authors
+            .entry(message.author().to_string())
+            .or_default()
+            .insert(message.id().to_string());
+        for tag in message.tags() {
+            self.tags
+                .entry(tag.clone())
+                .or_default()
+                .insert(message.id().to_string());
+        }
+        if let Activation::Fixed(deadline) = message.activation() {
+            self.deadlines.insert((*deadline, message.id().to_string()));
+        }
+        if let Some(deadline) = message.deadline() {
+            self.missed.insert((deadline, message.id().to_string()));
+        }
+        self.unsaved.push(Operation::Insert(message.clone()));
         self.data
             .entry(message.recipient().to_string())
-            .and_modify(|messages| {
-                messages.insert(message.clone());
-            })
-            .or_insert_with(|| {
-                let mut set = HashSet::new();
-                set.insert(message);
-                set
-            });
+            .or_default()
+            .insert(message);
     }
 
-    /// Get all message that have not been sent yet. This does not include timedout scheduled
-    /// messages.
-    pub fn pop_pending(&mut self, username: &str) -> HashSet<Message> {
-        self.data
-            .get_mut(username)
-            .map(|messages| {
-                messages
-                    .drain_filter(|message| {
-                        matches!(message.activation(), Activation::OnNextMessage)
-                    })
-                    .collect::<HashSet<_>>()
-            })
-            .unwrap_or_default()
+    /// Get all message that have not been sent yet and can be delivered in `channel`. This does
+    /// not include timedout scheduled messages.
+    ///
+    /// The messages stay in the store so they can be removed once they were actually delivered.
+    /// They are sorted oldest first, so they are read in the order they were written.
+    pub fn get_pending(&self, username: &str, channel: &str) -> Vec<Message> {
+        let mut pending = self
+            .data
+            .get(username)
+            .into_iter()
+            .flatten()
+            .filter(|message| delivery::deliverable_in(message, channel))
+            .cloned()
+            .collect::<Vec<_>>();
+        sort_oldest_first(&mut pending);
+
+        pending
     }
 
     pub fn get_all(&self) -> HashSet<&Message> {
         self.data.values().flatten().collect()
     }
 
+    /// Number of messages in the store.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// When changes were last written to the storage, `None` if nothing changed since startup.
+    pub fn last_saved(&self) -> Option<OffsetDateTime> {
+        self.last_saved
+    }
+
+    /// Number of changes saved since the store was loaded, to tell whether it changed.
+    pub fn changes(&self) -> u64 {
+        self.changes
+    }
+
+    /// Roughly how many bytes the messages take in memory, leaving out the indexes.
+    pub fn approximate_size(&self) -> usize {
+        self.data
+            .values()
+            .flatten()
+            .map(Message::approximate_size)
+            .sum()
+    }
+
+    /// Get the messages with a fixed deadline before `before`, in the order they are due.
+    pub fn due_before(&self, before: OffsetDateTime) -> Vec<&Message> {
+        self.deadlines
+            .range(..(before, String::new()))
+            .filter_map(|(_, id)| self.get_by_id(id))
+            .collect()
+    }
+
+    /// Get the earliest fixed deadline at or after `from`.
+    pub fn next_deadline(&self, from: OffsetDateTime) -> Option<OffsetDateTime> {
+        self.deadlines
+            .range((from, String::new())..)
+            .next()
+            .map(|(deadline, _)| *deadline)
+    }
+
+    /// Get at most `limit` of the messages with a fixed deadline before `before`, in the order
+    /// they are due, starting after `cursor`. Also returns the cursor to continue from, unless
+    /// this was the last page.
+    pub fn get_scheduled_before(
+        &self,
+        before: OffsetDateTime,
+        cursor: Option<&Cursor>,
+        limit: usize,
+    ) -> (Vec<&Message>, Option<Cursor>) {
+        self.page(&self.deadlines, before, cursor, limit)
+    }
+
+    /// Get at most `limit` of the messages whose `deadline:` passed before `before`, in the order
+    /// they passed, starting after `cursor`. Also returns the cursor to continue from, unless
+    /// this was the last page.
+    pub fn get_missed_before(
+        &self,
+        before: OffsetDateTime,
+        cursor: Option<&Cursor>,
+        limit: usize,
+    ) -> (Vec<&Message>, Option<Cursor>) {
+        self.page(&self.missed, before, cursor, limit)
+    }
+
+    /// Get at most `limit` of the messages in `index` before `before`, starting after `cursor`.
+    fn page(
+        &self,
+        index: &BTreeSet<(OffsetDateTime, String)>,
+        before: OffsetDateTime,
+        cursor: Option<&Cursor>,
+        limit: usize,
+    ) -> (Vec<&Message>, Option<Cursor>) {
+        let start = match cursor {
+            Some(cursor) => Bound::Excluded((cursor.deadline, cursor.id.clone())),
+            None => Bound::Unbounded,
+        };
+
+        let mut entries = index
+            .range((start, Bound::Unbounded))
+            .take_while(|(deadline, _)| *deadline < before);
+        let page = entries.by_ref().take(limit).collect::<Vec<_>>();
+        let next = match (page.last(), entries.next()) {
+            (Some((deadline, id)), Some(_)) => Some(Cursor {
+                deadline: *deadline,
+                id: id.clone(),
+            }),
+            _ => None,
+        };
+
+        let messages = page
+            .into_iter()
+            .filter_map(|(_, id)| self.get_by_id(id))
+            .collect();
+
+        (messages, next)
+    }
+
+    pub fn get_by_id(&self, id: &str) -> Option<&Message> {
+        let recipient = self.ids.get(id)?;
+
+        self.data.get(recipient)?.get(id)
+    }
+
+    /// Get every message written by `author`.
+    pub fn get_by_author(&self, author: &str) -> Vec<&Message> {
+        self.authors
+            .get(author)
+            .map(|ids| ids.iter().filter_map(|id| self.get_by_id(id)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the messages of `author` that match `filter`.
+    pub fn query(&self, author: &str, filter: &MessageFilter) -> Vec<&Message> {
+        self.get_by_author(author)
+            .into_iter()
+            .filter(|message| filter.matches(message))
+            .collect()
+    }
+
+    /// Get the messages waiting for `activation` in `channel`.
+    pub fn get_triggered(&self, channel: &str, activation: &Activation) -> Vec<&Message> {
+        self.data
+            .values()
+            .flatten()
+            .filter(|message| message.channel() == channel && message.activation() == activation)
+            .collect()
+    }
+
+    /// Get every message addressed to `recipient`.
+    pub fn get_by_recipient(&self, recipient: &str) -> Vec<&Message> {
+        self.data.get(recipient).into_iter().flatten().collect()
+    }
+
+    /// Get every message tagged with `tag`.
+    pub fn get_by_tag(&self, tag: &str) -> Vec<&Message> {
+        self.tags
+            .get(tag)
+            .map(|ids| ids.iter().filter_map(|id| self.get_by_id(id)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the messages written by or addressed to `login` whose text contains `needle`,
+    /// ignoring case.
+    pub fn search(&self, login: &str, needle: &str) -> Vec<&Message> {
+        let needle = needle.to_lowercase();
+        let mut messages = self.get_by_author(login);
+        messages.extend(
+            self.data
+                .get(login)
+                .into_iter()
+                .flatten()
+                .filter(|message| message.author() != login),
+        );
+
+        messages
+            .into_iter()
+            .filter(|message| message.text().to_lowercase().contains(&needle))
+            .collect()
+    }
+
+    /// Find a message of `author` by its id or its short number.
+    pub fn resolve(&self, author: &str, token: &str) -> Option<&Message> {
+        self.get_by_id(token).or_else(|| {
+            let number = token.trim_start_matches('#').parse::<u32>().ok()?;
+
+            self.get_by_author(author)
+                .into_iter()
+                .find(|message| message.number() == number)
+        })
+    }
+
     pub fn remove(&mut self, message: &Message) -> bool {
+        self.take(message.id()).is_some()
+    }
+
+    /// Remove the message with `id` from the store and return it.
+    pub fn take(&mut self, id: &str) -> Option<Message> {
+        let recipient = self.ids.get(id)?;
+        let message = self.data.get_mut(recipient)?.take(id)?;
+
+        self.unindex(&message);
+        self.unsaved
+            .push(Operation::Remove(message.id().to_string()));
+
+        Some(message)
+    }
+
+    /// Remove every message authored by or addressed to `login`.
+    pub fn remove_user(&mut self, login: &str) -> Vec<Message> {
+        let mut ids = self
+            .get_by_author(login)
+            .into_iter()
+            .map(|message| message.id().to_string())
+            .collect::<Vec<_>>();
+        ids.extend(
+            self.data
+                .get(login)
+                .into_iter()
+                .flatten()
+                .map(|message| message.id().to_string()),
+        );
+
+        ids.into_iter().filter_map(|id| self.take(&id)).collect()
+    }
+
+    /// Remove every message that has been waiting since before `cutoff`. Timed messages wait
+    /// from the time they are due, not from when they were created. Notes aren't waiting for
+    /// anything and are kept.
+    pub fn remove_older_than(&mut self, cutoff: OffsetDateTime) -> Vec<Message> {
+        let ids = self
+            .get_all()
+            .into_iter()
+            .filter(|message| message.kind() != Kind::Note && message.due() < cutoff)
+            .map(|message| message.id().to_string())
+            .collect::<Vec<_>>();
+
+        ids.into_iter().filter_map(|id| self.take(&id)).collect()
+    }
+
+    /// Remove every message with a fixed deadline from `since` up to `cutoff`.
+    pub fn remove_overdue(
+        &mut self,
+        since: OffsetDateTime,
+        cutoff: OffsetDateTime,
+    ) -> Vec<Message> {
+        if cutoff <= since {
+            return Vec::new();
+        }
+
+        let ids = self
+            .deadlines
+            .range((since, String::new())..(cutoff, String::new()))
+            .map(|(_, id)| id.clone())
+            .collect::<Vec<_>>();
+
+        ids.into_iter().filter_map(|id| self.take(&id)).collect()
+    }
+
+    /// Remove every message addressed to `recipient`.
+    pub fn remove_addressed_to(&mut self, recipient: &str) -> Vec<Message> {
+        let ids = self
+            .get_by_recipient(recipient)
+            .into_iter()
+            .map(|message| message.id().to_string())
+            .collect::<Vec<_>>();
+
+        ids.into_iter().filter_map(|id| self.take(&id)).collect()
+    }
+
+    /// Get everyone with messages addressed to them.
+    pub fn recipients(&self) -> Vec<&str> {
         self.data
-            .values_mut()
-            .map(|messages| messages.remove(message))
-            .any(|x| x)
+            .iter()
+            .filter(|(_, messages)| !messages.is_empty())
+            .map(|(recipient, _)| recipient.as_str())
+            .collect()
+    }
+
+    /// Remove `message` from the id, author, tag and deadline indexes.
+    fn unindex(&mut self, message: &Message) {
+        self.ids.remove(message.id());
+        if let Activation::Fixed(deadline) = message.activation() {
+            self.deadlines
+                .remove(&(*deadline, message.id().to_string()));
+        }
+        if let Some(deadline) = message.deadline() {
+            self.missed.remove(&(deadline, message.id().to_string()));
+        }
+
+        if let Some(ids) = self.authors.get_mut(message.author()) {
+            ids.remove(message.id());
+
+            if ids.is_empty() {
+                self.authors.remove(message.author());
+            }
+        }
+
+        for tag in message.tags() {
+            if let Some(ids) = self.tags.get_mut(tag) {
+                ids.remove(message.id());
+
+                if ids.is_empty() {
+                    self.tags.remove(tag);
+                }
+            }
+        }
+    }
+
+    /// Claim the delivery of `message` for this instance. Returns `false` if another instance
+    /// sharing the storage is responsible for it, the message is then dropped from memory so no
+    /// timer of this instance waits for it anymore. The other instance removes it from the
+    /// storage once it's delivered.
+    pub fn claim(&mut self, message: &Message) -> Result<bool> {
+        let claimed = self.storage.claim(message, &self.instance_id)?;
+        if !claimed {
+            self.evict(message.id());
+        }
+
+        Ok(claimed)
+    }
+
+    /// Drop the message with `id` from memory without recording its removal.
+    fn evict(&mut self, id: &str) {
+        let recipient = match self.ids.get(id) {
+            Some(recipient) => recipient.clone(),
+            None => return,
+        };
+
+        if let Some(message) = self
+            .data
+            .get_mut(&recipient)
+            .and_then(|messages| messages.take(id))
+        {
+            self.unindex(&message);
+        }
     }
 
-    pub fn save(&self) -> Result<()> {
-        let file = File::create(&self.path).wrap_err("Failed to open storage")?;
+    /// Give up the claim on `message` after failing to deliver it, so it's pending for every
+    /// instance again.
+    pub fn release(&self, message: &Message) -> Result<()> {
+        self.storage.release(message, &self.instance_id)
+    }
+
+    /// Persist the changes since the last save. Most saves only append to the journal of the
+    /// storage, every so often a full snapshot is written instead.
+    #[instrument(skip(self), fields(operations = self.unsaved.len()))]
+    pub fn save(&mut self) -> Result<()> {
+        if self.reload_if_changed()? {
+            warn!("The storage was changed externally, reloaded it before saving");
+        }
+
+        let operations = std::mem::take(&mut self.unsaved);
+        if operations.is_empty() {
+            return Ok(());
+        }
+        self.changes += operations.len() as u64;
+
+        if self.journaled + operations.len() < COMPACT_AFTER {
+            match self.storage.append(&operations) {
+                Ok(true) => {
+                    self.journaled += operations.len();
+                    self.last_saved = Some(OffsetDateTime::now_utc());
+                    return Ok(());
+                }
+                Ok(false) => {}
+                Err(err) => warn!("{:?}", err.wrap_err("Failed to append to journal")),
+            }
+        }
+
+        self.journaled = 0;
         let data = self
             .data
             .values()
             .flat_map(|set| set.iter())
             .collect::<Vec<&Message>>();
 
-        write_store(file, &data).wrap_err("Failed to write storeage")
+        self.storage.save(&data)?;
+        self.last_saved = Some(OffsetDateTime::now_utc());
+
+        Ok(())
     }
 }
 
-#[cfg(not(feature = "pretty_store"))]
-fn write_store(file: File, data: &[&Message]) -> Result<(), ron::Error> {
-    ron::ser::to_writer(file, &data)
+/// Sort `messages` by when they were created, messages created at the same time by id.
+pub fn sort_oldest_first(messages: &mut [Message]) {
+    messages.sort_by(|a, b| (a.created(), a.id()).cmp(&(b.created(), b.id())));
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+    use crate::id::IdGenerator;
+
+    #[derive(Debug)]
+    struct NullStorage;
+
+    impl Storage for NullStorage {
+        fn load(&self) -> Result<Vec<Message>> {
+            Ok(Vec::new())
+        }
+
+        fn save(&self, _messages: &[&Message]) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn message(author: &str, recipient: &str) -> Message {
+        Message::new(
+            IdGenerator::Short.generate().unwrap(),
+            Activation::OnNextMessage,
+            author.to_string(),
+            "channel".to_string(),
+            recipient.to_string(),
+            "text".to_string(),
+        )
+    }
+
+    /// Storage of which another instance claimed everything.
+    #[derive(Debug)]
+    struct ClaimedElsewhere;
+
+    impl Storage for ClaimedElsewhere {
+        fn load(&self) -> Result<Vec<Message>> {
+            Ok(Vec::new())
+        }
+
+        fn save(&self, _messages: &[&Message]) -> Result<()> {
+            Ok(())
+        }
+
+        fn claim(&self, _message: &Message, _instance: &str) -> Result<bool> {
+            Ok(false)
+        }
+    }
+
+    #[test]
+    fn lost_claims_are_evicted() {
+        let mut store =
+            MessageStore::from_storage(Arc::new(ClaimedElsewhere), "test".to_string()).unwrap();
+        let message = message("alice", "bob");
+        store.insert(message.clone());
+        store.unsaved.clear();
+
+        assert!(!store.claim(&message).unwrap());
+        assert!(store.get_by_id(message.id()).is_none());
+        assert!(store.get_by_author("alice").is_empty());
+        // the storage still has it until the other instance delivers it
+        assert!(store.unsaved.is_empty());
+    }
+
+    #[test]
+    fn remembers_last_save() {
+        let mut store =
+            MessageStore::from_storage(Arc::new(NullStorage), "test".to_string()).unwrap();
+
+        store.save().unwrap();
+        assert_eq!(None, store.last_saved());
+
+        store.insert(message("alice", "bob"));
+        store.save().unwrap();
+        assert!(store.last_saved().is_some());
+        assert!(store.approximate_size() > std::mem::size_of::<Message>());
+    }
+
+    #[test]
+    fn indexes_follow_removals() {
+        let mut store =
+            MessageStore::from_storage(Arc::new(NullStorage), "test".to_string()).unwrap();
+        let first = message("alice", "bob");
+        let second = message("alice", "carol");
+
+        store.insert(first.clone());
+        store.insert(second.clone());
+
+        assert_eq!(Some(&first), store.get_by_id(first.id()));
+        assert_eq!(2, store.get_by_author("alice").len());
+
+        assert_eq!(Some(first.clone()), store.take(first.id()));
+        assert_eq!(None, store.get_by_id(first.id()));
+        assert_eq!(vec![&second], store.get_by_author("alice"));
+
+        let third = message("alice", "dave");
+        store.insert(third.clone());
+
+        assert_eq!(
+            Some(third.id()),
+            store.resolve("alice", "1").map(Message::id)
+        );
+        assert_eq!(
+            Some(second.id()),
+            store.resolve("alice", "#2").map(Message::id)
+        );
+
+        assert_eq!(2, store.remove_user("alice").len());
+        assert!(store.get_all().is_empty());
+    }
+
+    #[test]
+    fn search_sent_and_received() {
+        let mut store =
+            MessageStore::from_storage(Arc::new(NullStorage), "test".to_string()).unwrap();
+        let sent = Message::new(
+            "sent".to_string(),
+            Activation::OnNextMessage,
+            "alice".to_string(),
+            "channel".to_string(),
+            "bob".to_string(),
+            "Order Pizza".to_string(),
+        );
+        let received = Message::new(
+            "received".to_string(),
+            Activation::OnNextMessage,
+            "carol".to_string(),
+            "channel".to_string(),
+            "alice".to_string(),
+            "pizza is here".to_string(),
+        );
+
+        store.insert(sent);
+        store.insert(received);
+        store.insert(message("alice", "dave"));
+
+        let mut found = store
+            .search("alice", "pizza")
+            .into_iter()
+            .map(Message::id)
+            .collect::<Vec<_>>();
+        found.sort_unstable();
+
+        assert_eq!(vec!["received", "sent"], found);
+        assert!(store.search("bob", "pizza").is_empty());
+    }
+
+    #[test]
+    fn pending_oldest_first() {
+        let mut store =
+            MessageStore::from_storage(Arc::new(NullStorage), "test".to_string()).unwrap();
+        let messages = (0..5)
+            .map(|_| {
+                // distinct creation times
+                std::thread::sleep(std::time::Duration::from_millis(1));
+                message("alice", "bob")
+            })
+            .collect::<Vec<_>>();
+        for message in messages.iter().rev() {
+            store.insert(message.clone());
+        }
+
+        assert_eq!(messages, store.get_pending("bob", "channel"));
+    }
+
+    #[test]
+    fn tag_index() {
+        let mut store =
+            MessageStore::from_storage(Arc::new(NullStorage), "test".to_string()).unwrap();
+        let tagged = message("alice", "bob").with_tags(["raid".to_string()].into());
+
+        store.insert(tagged.clone());
+        store.insert(message("alice", "carol"));
+
+        assert_eq!(vec![&tagged], store.get_by_tag("raid"));
+
+        store.take(tagged.id());
+        assert!(store.get_by_tag("raid").is_empty());
+    }
+
+    #[test]
+    fn remove_older_than_counts_timed_from_due_time() {
+        let mut store =
+            MessageStore::from_storage(Arc::new(NullStorage), "test".to_string()).unwrap();
+        let now = OffsetDateTime::now_utc();
+        let waiting = message("alice", "bob");
+        let due_later =
+            message("alice", "carol").with_activation(Activation::Fixed(now + Duration::days(2)));
+        let note = Message::note(
+            "note".to_string(),
+            "alice".to_string(),
+            "channel".to_string(),
+            "text".to_string(),
+        );
+
+        store.insert(waiting.clone());
+        store.insert(due_later.clone());
+        store.insert(note.clone());
+
+        assert_eq!(
+            vec![waiting],
+            store.remove_older_than(now + Duration::days(1))
+        );
+        assert_eq!(Some(&due_later), store.get_by_id(due_later.id()));
+        assert_eq!(Some(&note), store.get_by_id(note.id()));
+    }
+
+    #[test]
+    fn scheduled_pages_in_deadline_order() {
+        let mut store =
+            MessageStore::from_storage(Arc::new(NullStorage), "test".to_string()).unwrap();
+        let now = OffsetDateTime::now_utc();
+        let at = |hours| Activation::Fixed(now + Duration::hours(hours));
+        let first = message("alice", "bob").with_activation(at(1));
+        let second = message("alice", "carol").with_activation(at(2));
+        let third = message("alice", "dave").with_activation(at(3));
+        let later = message("alice", "erin").with_activation(at(48));
+
+        for message in [&third, &later, &first, &second] {
+            store.insert(message.clone());
+        }
+        store.insert(message("alice", "frank"));
+
+        let before = now + Duration::days(1);
+        let (page, cursor) = store.get_scheduled_before(before, None, 2);
+        assert_eq!(vec![&first, &second], page);
+
+        let (page, cursor) = store.get_scheduled_before(before, cursor.as_ref(), 2);
+        assert_eq!(vec![&third], page);
+        assert_eq!(None, cursor);
+
+        store.take(second.id());
+        let (page, _) = store.get_scheduled_before(before, None, 10);
+        assert_eq!(vec![&first, &third], page);
+    }
+
+    #[test]
+    fn missed_deadlines_page_in_order() {
+        let mut store =
+            MessageStore::from_storage(Arc::new(NullStorage), "test".to_string()).unwrap();
+        let now = OffsetDateTime::now_utc();
+        let by = |hours| Some(now + Duration::hours(hours));
+        let first = message("alice", "bob").with_deadline(by(-2));
+        let second = message("alice", "carol").with_deadline(by(-1));
+        let later = message("alice", "dave").with_deadline(by(1));
+
+        for message in [&later, &second, &first] {
+            store.insert(message.clone());
+        }
+        store.insert(message("alice", "erin"));
+
+        let (page, cursor) = store.get_missed_before(now, None, 1);
+        assert_eq!(vec![&first], page);
+        let (page, cursor) = store.get_missed_before(now, cursor.as_ref(), 1);
+        assert_eq!(vec![&second], page);
+        assert_eq!(None, cursor);
+
+        store.insert(first.clone().with_deadline(None));
+        let (page, _) = store.get_missed_before(now, None, 10);
+        assert_eq!(vec![&second], page);
+    }
+
+    #[test]
+    fn deadline_queries() {
+        let mut store =
+            MessageStore::from_storage(Arc::new(NullStorage), "test".to_string()).unwrap();
+        let now = OffsetDateTime::now_utc();
+        let soon = message("alice", "bob").with_activation(Activation::Fixed(now));
+        let later =
+            message("alice", "carol").with_activation(Activation::Fixed(now + Duration::hours(2)));
+
+        store.insert(later.clone());
+        store.insert(soon.clone());
+        store.insert(message("alice", "dave"));
+
+        assert_eq!(vec![&soon], store.due_before(now + Duration::hours(1)));
+        assert_eq!(
+            vec![&soon, &later],
+            store.due_before(now + Duration::days(1))
+        );
+        assert!(store.due_before(now).is_empty());
+
+        assert_eq!(Some(now), store.next_deadline(now));
+        assert_eq!(
+            Some(now + Duration::hours(2)),
+            store.next_deadline(now + Duration::seconds(1))
+        );
+        assert_eq!(None, store.next_deadline(now + Duration::days(1)));
+    }
+
+    #[test]
+    fn remove_overdue_and_addressed_to() {
+        let mut store =
+            MessageStore::from_storage(Arc::new(NullStorage), "test".to_string()).unwrap();
+        let now = OffsetDateTime::now_utc();
+        let overdue = message("alice", "bob").with_activation(Activation::Fixed(now));
+        let missed_while_down =
+            message("alice", "dave").with_activation(Activation::Fixed(now - Duration::days(1)));
+        let waiting = message("alice", "bob");
+        let upcoming =
+            message("alice", "carol").with_activation(Activation::Fixed(now + Duration::days(1)));
+
+        store.insert(overdue.clone());
+        store.insert(missed_while_down);
+        store.insert(waiting.clone());
+        store.insert(upcoming.clone());
+
+        assert_eq!(
+            vec![overdue],
+            store.remove_overdue(now - Duration::hours(1), now + Duration::hours(1))
+        );
+        assert_eq!(vec![waiting], store.remove_addressed_to("bob"));
+        let mut recipients = store.recipients();
+        recipients.sort_unstable();
+        assert_eq!(vec!["carol", "dave"], recipients);
+    }
+
+    #[test]
+    fn notes_are_never_pending() {
+        let mut store =
+            MessageStore::from_storage(Arc::new(NullStorage), "test".to_string()).unwrap();
+        let note = Message::note(
+            "note".to_string(),
+            "alice".to_string(),
+            "channel".to_string(),
+            "thank the artist".to_string(),
+        );
+
+        store.insert(note.clone());
+
+        assert!(store.get_pending("alice", "channel").is_empty());
+        assert_eq!(vec![&note], store.get_by_author("alice"));
+    }
 }
 
-#[cfg(feature = "pretty_store")]
-fn write_store(file: File, data: &[&Message]) -> Result<(), ron::Error> {
-    ron::ser::to_writer_pretty(file, &data, ron::ser::PrettyConfig::default())
+#[cfg(test)]
+mod benches {
+    extern crate test;
+
+    use test::{black_box, Bencher};
+    use time::Duration;
+
+    use super::*;
+    use crate::storage::FileFormat;
+
+    const MESSAGES: i64 = 100_000;
+
+    #[derive(Debug)]
+    struct NullStorage;
+
+    impl Storage for NullStorage {
+        fn load(&self) -> Result<Vec<Message>> {
+            Ok(Vec::new())
+        }
+
+        fn save(&self, _messages: &[&Message]) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn message(number: i64, now: OffsetDateTime) -> Message {
+        let activation = if number % 2 == 0 {
+            Activation::OnNextMessage
+        } else {
+            Activation::Fixed(now + Duration::minutes(number))
+        };
+
+        Message::new(
+            format!("id{}", number),
+            activation,
+            format!("author{}", number % 1000),
+            "channel".to_string(),
+            format!("recipient{}", number % 5000),
+            "some text long enough to look like a reminder".to_string(),
+        )
+    }
+
+    fn full_store() -> MessageStore {
+        let now = OffsetDateTime::now_utc();
+        let mut store =
+            MessageStore::from_storage(Arc::new(NullStorage), "bench".to_string()).unwrap();
+        for number in 0..MESSAGES {
+            store.insert(message(number, now));
+        }
+        store.unsaved.clear();
+
+        store
+    }
+
+    #[bench]
+    fn insert_and_take(b: &mut Bencher) {
+        let mut store = full_store();
+        let message = message(MESSAGES + 1, OffsetDateTime::now_utc());
+
+        b.iter(|| {
+            store.insert(message.clone());
+            black_box(store.take(message.id()));
+        });
+    }
+
+    #[bench]
+    fn scheduled_page(b: &mut Bencher) {
+        let store = full_store();
+        let before = OffsetDateTime::now_utc() + Duration::days(1);
+
+        b.iter(|| black_box(store.get_scheduled_before(before, None, 500)));
+    }
+
+    #[bench]
+    fn pending_of_recipient(b: &mut Bencher) {
+        let store = full_store();
+
+        b.iter(|| black_box(store.get_pending("recipient42", "channel")));
+    }
+
+    #[bench]
+    fn snapshot(b: &mut Bencher) {
+        let store = full_store();
+        let messages = store.data.values().flatten().collect::<Vec<_>>();
+
+        b.iter(|| FileFormat::Ron.write_snapshot(std::io::sink(), &messages));
+    }
 }
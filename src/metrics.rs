@@ -0,0 +1,124 @@
+//! The numbers behind `~stats` and `~admin usage` in the Prometheus text format, served as
+//! `GET /metrics` by the admin API so they can be scraped and graphed over time.
+
+use std::fmt::Write;
+
+use crate::delivery_stats::LatencySummary;
+
+/// Labels of a sample as name and value.
+pub type Labels<'a> = Vec<(&'a str, String)>;
+
+/// Collects metric families into the text format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    text: String,
+}
+
+impl Metrics {
+    /// Add the family `name` of `kind`, e.g. `gauge` or `counter`, with one sample per set of
+    /// labels.
+    pub fn family(&mut self, name: &str, kind: &str, help: &str, samples: &[(Labels, f64)]) {
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+        for (labels, value) in samples {
+            let _ = writeln!(self.text, "{}{} {}", name, format_labels(labels), value);
+        }
+    }
+
+    /// Add a gauge without labels.
+    pub fn gauge(&mut self, name: &str, help: &str, value: f64) {
+        self.family(name, "gauge", help, &[(Vec::new(), value)]);
+    }
+
+    pub fn into_text(self) -> String {
+        self.text
+    }
+}
+
+/// The median, 95th percentile and maximum of `summary` in seconds, labelled with `labels` and
+/// their quantile.
+pub fn latency_samples<'a>(
+    labels: &Labels<'a>,
+    summary: Option<LatencySummary>,
+) -> Vec<(Labels<'a>, f64)> {
+    let summary = match summary {
+        Some(summary) => summary,
+        None => return Vec::new(),
+    };
+
+    [
+        ("0.5", summary.median),
+        ("0.95", summary.p95),
+        ("1", summary.max),
+    ]
+    .into_iter()
+    .map(|(quantile, latency)| {
+        let mut labels = labels.clone();
+        labels.push(("quantile", quantile.to_string()));
+
+        (labels, latency.as_seconds_f64())
+    })
+    .collect()
+}
+
+fn format_labels(labels: &Labels) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+
+    let labels = labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+        .intersperse(",".to_string())
+        .collect::<String>();
+    format!("{{{}}}", labels)
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn text_format() {
+        let mut metrics = Metrics::default();
+        metrics.gauge("remindme_pending", "Pending reminders", 3.0);
+        metrics.family(
+            "remindme_delivery_latency_seconds",
+            "gauge",
+            "Latency",
+            &latency_samples(
+                &vec![("activation", "timed".to_string())],
+                LatencySummary::new(vec![Duration::seconds(2)]),
+            ),
+        );
+
+        assert_eq!(
+            "# HELP remindme_pending Pending reminders\n\
+             # TYPE remindme_pending gauge\n\
+             remindme_pending 3\n\
+             # HELP remindme_delivery_latency_seconds Latency\n\
+             # TYPE remindme_delivery_latency_seconds gauge\n\
+             remindme_delivery_latency_seconds{activation=\"timed\",quantile=\"0.5\"} 2\n\
+             remindme_delivery_latency_seconds{activation=\"timed\",quantile=\"0.95\"} 2\n\
+             remindme_delivery_latency_seconds{activation=\"timed\",quantile=\"1\"} 2\n",
+            metrics.into_text()
+        );
+    }
+
+    #[test]
+    fn labels_are_escaped() {
+        assert_eq!(
+            "{channel=\"a\\\"b\"}",
+            format_labels(&vec![("channel", "a\"b".to_string())])
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Why reminders written with `~tell` or saved with `~preset add` couldn't be parsed, counted for
+//! `~admin stats` and the admin API so the grammar can follow what users keep typing.
+
+use std::collections::BTreeMap;
+
+use pest::error::ErrorVariant;
+use tracing::info;
+
+use crate::message_parser::{Error, Rule};
+
+/// How many words of an input are logged at most.
+const SAMPLE_WORDS: usize = 12;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    MissingRecipient,
+    BadDuration,
+    DanglingChars,
+    UnknownAttribute,
+    InvalidValue,
+    ConflictingSchedules,
+    /// Any other syntax error.
+    Syntax,
+}
+
+impl Category {
+    pub fn of(err: &Error) -> Self {
+        match err {
+            Error::ParseRule { source, .. } => match &source.variant {
+                ErrorVariant::ParsingError { positives, .. }
+                    if positives.contains(&Rule::recipient) =>
+                {
+                    Category::MissingRecipient
+                }
+                _ => Category::Syntax,
+            },
+            Error::ParseDuration { .. } => Category::BadDuration,
+            Error::DanglingChars(_) => Category::DanglingChars,
+            Error::UnknownAttributeKey(_) => Category::UnknownAttribute,
+            Error::InvalidValue { .. } => Category::InvalidValue,
+            Error::EmptyFollowUp(_) => Category::Syntax,
+            Error::ConflictingSchedules { .. } => Category::ConflictingSchedules,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::MissingRecipient => "missing recipient",
+            Category::BadDuration => "bad duration",
+            Category::DanglingChars => "dangling chars",
+            Category::UnknownAttribute => "unknown attribute",
+            Category::InvalidValue => "invalid value",
+            Category::ConflictingSchedules => "conflicting schedules",
+            Category::Syntax => "syntax",
+        }
+    }
+}
+
+/// Parse failures since the bot started.
+#[derive(Debug, Default)]
+pub struct ParseFailures {
+    counts: BTreeMap<Category, u64>,
+}
+
+impl ParseFailures {
+    /// Count `err`, logging `input` without its free text if `log_sample` is set.
+    pub fn record(&mut self, err: &Error, input: &str, log_sample: bool) {
+        let category = Category::of(err);
+        *self.counts.entry(category).or_default() += 1;
+
+        if log_sample {
+            info!(
+                category = category.name(),
+                sample = %sample(input),
+                "Failed to parse reminder"
+            );
+        }
+    }
+
+    /// The counts, most frequent first.
+    pub fn counts(&self) -> Vec<(Category, u64)> {
+        let mut counts = self
+            .counts
+            .iter()
+            .map(|(category, count)| (*category, *count))
+            .collect::<Vec<_>>();
+        counts.sort_by(|a, b| b.1.cmp(&a.1));
+        counts
+    }
+}
+
+/// `input` with only its syntax left: attributes and words with digits are kept, mentions become
+/// `@_` and every other word `_`, so the log doesn't keep what users wrote to each other.
+fn sample(input: &str) -> String {
+    input
+        .split_whitespace()
+        .take(SAMPLE_WORDS)
+        .map(|word| {
+            if word.contains(':') || word.chars().any(|c| c.is_ascii_digit()) {
+                word
+            } else if word.starts_with('@') {
+                "@_"
+            } else {
+                "_"
+            }
+        })
+        .intersperse(" ")
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_parser::MessageDefinition;
+
+    fn category(input: &str) -> Category {
+        Category::of(&input.parse::<MessageDefinition>().unwrap_err())
+    }
+
+    #[test]
+    fn categorizes_errors() {
+        assert_eq!(Category::MissingRecipient, category("cc:foo"));
+        assert_eq!(Category::BadDuration, category("in:2x alice text"));
+        assert_eq!(Category::UnknownAttribute, category("foo:bar alice text"));
+    }
+
+    #[test]
+    fn counts_most_frequent_first() {
+        let mut failures = ParseFailures::default();
+        for input in ["cc:foo", "cc:bar", "foo:bar alice text"] {
+            let err = input.parse::<MessageDefinition>().unwrap_err();
+            failures.record(&err, input, false);
+        }
+
+        assert_eq!(
+            vec![
+                (Category::MissingRecipient, 2),
+                (Category::UnknownAttribute, 1)
+            ],
+            failures.counts()
+        );
+    }
+
+    #[test]
+    fn sample_keeps_only_syntax() {
+        assert_eq!(
+            "in:2hours @_ _ _ 5pm",
+            sample("in:2hours @alice buy milk 5pm")
+        );
+    }
+}
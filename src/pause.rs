@@ -0,0 +1,71 @@
+//! `~admin pause`: the bot stays connected but stops creating and delivering reminders, so
+//! operators can work on the store without shutting it down.
+
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Whether the bot is paused. Clones share their state.
+#[derive(Debug, Clone)]
+pub struct Pause {
+    sender: Arc<watch::Sender<bool>>,
+    receiver: watch::Receiver<bool>,
+}
+
+impl Default for Pause {
+    fn default() -> Self {
+        let (sender, receiver) = watch::channel(false);
+
+        Self {
+            sender: Arc::new(sender),
+            receiver,
+        }
+    }
+}
+
+impl Pause {
+    pub fn is_paused(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Pause or resume the bot. Returns `false` if it already was.
+    pub fn set(&self, paused: bool) -> bool {
+        if self.is_paused() == paused {
+            return false;
+        }
+
+        // `self` keeps a receiver alive, so sending can't fail
+        let _ = self.sender.send(paused);
+        true
+    }
+
+    /// Wait until the bot isn't paused.
+    pub async fn resumed(&self) {
+        let mut receiver = self.receiver.clone();
+
+        while *receiver.borrow() {
+            if receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_share_the_state() {
+        let pause = Pause::default();
+        let shared = pause.clone();
+        assert!(!shared.is_paused());
+
+        assert!(pause.set(true));
+        assert!(!pause.set(true));
+        assert!(shared.is_paused());
+
+        assert!(shared.set(false));
+        assert!(!pause.is_paused());
+    }
+}
@@ -0,0 +1,79 @@
+//! Who may run which command.
+
+use twitch_irc::message::PrivmsgMessage;
+
+use crate::config::Config;
+
+/// What a chatter is allowed to do. Roles are ordered, every role may do what the ones before it
+/// may do.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Everyone,
+    Vip,
+    Moderator,
+    Broadcaster,
+    /// The configured owner of the bot, in every channel.
+    Owner,
+}
+
+impl Role {
+    /// Derive the role of a chatter from the names of their badges in a channel.
+    pub fn from_badges<'a>(badges: impl IntoIterator<Item = &'a str>, is_owner: bool) -> Self {
+        if is_owner {
+            return Role::Owner;
+        }
+
+        badges
+            .into_iter()
+            .map(|badge| match badge {
+                "broadcaster" => Role::Broadcaster,
+                "moderator" => Role::Moderator,
+                "vip" => Role::Vip,
+                _ => Role::Everyone,
+            })
+            .max()
+            .unwrap_or(Role::Everyone)
+    }
+
+    /// The role of the sender of `privmsg` in its channel.
+    pub fn of(privmsg: &PrivmsgMessage, config: &Config) -> Self {
+        Self::from_badges(
+            privmsg.badges.iter().map(|badge| badge.name.as_str()),
+            config.is_owner(&privmsg.sender.login, &privmsg.sender.id),
+        )
+    }
+
+    /// Describe who has at least this role, e.g. `moderators`.
+    pub fn describe(self) -> &'static str {
+        match self {
+            Role::Everyone => "everyone",
+            Role::Vip => "VIPs",
+            Role::Moderator => "moderators",
+            Role::Broadcaster => "the broadcaster",
+            Role::Owner => "the bot owner",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highest_badge_wins() {
+        assert_eq!(Role::Everyone, Role::from_badges([], false));
+        assert_eq!(Role::Everyone, Role::from_badges(["subscriber"], false));
+        assert_eq!(Role::Vip, Role::from_badges(["vip", "subscriber"], false));
+        assert_eq!(
+            Role::Broadcaster,
+            Role::from_badges(["moderator", "broadcaster"], false)
+        );
+    }
+
+    #[test]
+    fn owner_outranks_everyone() {
+        assert_eq!(Role::Owner, Role::from_badges(["subscriber"], true));
+        assert!(Role::Owner > Role::Broadcaster);
+        assert!(Role::Moderator > Role::Vip);
+    }
+}
@@ -0,0 +1,98 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
+
+use eyre::Result;
+
+use crate::ron_store::RonStore;
+
+/// Reminders users saved under a name with `~preset add`, keyed by login. A preset is what would
+/// follow `~tell`, so `~preset use` runs it through the same parser.
+#[derive(Debug, Clone)]
+pub struct PresetStore {
+    data: RonStore<HashMap<String, BTreeMap<String, String>>>,
+}
+
+impl PresetStore {
+    pub fn from_path(path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            data: RonStore::from_path(path, "preset store")?,
+        })
+    }
+
+    /// Save `definition` as the preset `name` of `login`, replacing it if it exists. Returns
+    /// `false` if it was created.
+    pub fn set(&mut self, login: &str, name: &str, definition: String) -> bool {
+        self.data
+            .write()
+            .entry(login.to_string())
+            .or_default()
+            .insert(name.to_lowercase(), definition)
+            .is_some()
+    }
+
+    /// Delete the preset `name` of `login`. Returns `false` if there is no such preset.
+    pub fn remove(&mut self, login: &str, name: &str) -> bool {
+        let mut data = self.data.write();
+        let removed = data.get_mut(login).map_or(false, |presets| {
+            presets.remove(&name.to_lowercase()).is_some()
+        });
+        if data.get(login).map_or(false, BTreeMap::is_empty) {
+            data.remove(login);
+        }
+
+        removed
+    }
+
+    pub fn get(&self, login: &str, name: &str) -> Option<String> {
+        self.data
+            .read()
+            .get(login)?
+            .get(&name.to_lowercase())
+            .cloned()
+    }
+
+    /// The names of the presets of `login`, sorted.
+    pub fn names(&self, login: &str) -> Vec<String> {
+        self.data
+            .read()
+            .get(login)
+            .map(|presets| presets.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Forget the presets of `login`.
+    pub fn forget(&mut self, login: &str) {
+        self.data.write().remove(login);
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.data.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ron_store::test_path;
+
+    #[test]
+    fn presets_are_per_user() {
+        let mut presets = PresetStore::from_path(test_path("presets")).unwrap();
+        assert!(!presets.set("alice", "VodReview", "in:1d bob check VOD".to_string()));
+        assert!(presets.set("alice", "vodreview", "in:2d bob check VOD".to_string()));
+
+        assert_eq!(
+            Some("in:2d bob check VOD".to_string()),
+            presets.get("alice", "VODREVIEW")
+        );
+        assert_eq!(None, presets.get("bob", "vodreview"));
+        assert_eq!(vec!["vodreview"], presets.names("alice"));
+
+        assert!(presets.remove("alice", "vodreview"));
+        assert!(!presets.remove("alice", "vodreview"));
+        assert!(presets.names("alice").is_empty());
+        assert!(presets.data.read().is_empty());
+    }
+}
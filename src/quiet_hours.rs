@@ -0,0 +1,80 @@
+use serde::Deserialize;
+use time::{Duration, OffsetDateTime};
+
+const MINUTES_PER_DAY: i64 = 24 * 60;
+
+/// A daily window in which a channel doesn't want timed reminders to be posted, e.g.
+/// `(start: (2, 0), end: (8, 0), utc_offset_minutes: -300)` for 02:00–08:00 EST.
+#[derive(Debug, Copy, Clone, Deserialize, PartialEq, Eq)]
+pub struct QuietHours {
+    /// Hour and minute the window starts at.
+    pub start: (u8, u8),
+    /// Hour and minute the window ends at. May be before `start` if the window spans midnight.
+    pub end: (u8, u8),
+    /// Offset of the broadcaster's time zone from UTC.
+    #[serde(default)]
+    pub utc_offset_minutes: i64,
+}
+
+impl QuietHours {
+    /// Get the end of the window if `at` lies within it.
+    pub fn end_of_window(&self, at: OffsetDateTime) -> Option<OffsetDateTime> {
+        let minute = (at.unix_timestamp().div_euclid(60) + self.utc_offset_minutes)
+            .rem_euclid(MINUTES_PER_DAY);
+        let start = minute_of_day(self.start);
+        let end = minute_of_day(self.end);
+
+        let quiet = if start <= end {
+            start <= minute && minute < end
+        } else {
+            minute >= start || minute < end
+        };
+        if !quiet {
+            return None;
+        }
+
+        let remaining = (end - minute).rem_euclid(MINUTES_PER_DAY);
+        Some(at + Duration::minutes(remaining) - Duration::seconds(at.second().into()))
+    }
+}
+
+fn minute_of_day((hour, minute): (u8, u8)) -> i64 {
+    i64::from(hour) * 60 + i64::from(minute)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: i64, minute: i64) -> OffsetDateTime {
+        OffsetDateTime::UNIX_EPOCH + Duration::hours(hour) + Duration::minutes(minute)
+    }
+
+    #[test]
+    fn window_within_a_day() {
+        let quiet = QuietHours {
+            start: (2, 0),
+            end: (8, 0),
+            utc_offset_minutes: 0,
+        };
+
+        assert_eq!(None, quiet.end_of_window(at(1, 59)));
+        assert_eq!(Some(at(8, 0)), quiet.end_of_window(at(2, 0)));
+        assert_eq!(Some(at(8, 0)), quiet.end_of_window(at(7, 30)));
+        assert_eq!(None, quiet.end_of_window(at(8, 0)));
+    }
+
+    #[test]
+    fn window_across_midnight_with_offset() {
+        let quiet = QuietHours {
+            start: (22, 0),
+            end: (6, 0),
+            utc_offset_minutes: 60,
+        };
+
+        // 23:30 local
+        assert_eq!(Some(at(29, 0)), quiet.end_of_window(at(22, 30)));
+        // 12:00 local
+        assert_eq!(None, quiet.end_of_window(at(11, 0)));
+    }
+}
@@ -0,0 +1,139 @@
+//! How many messages and joins Twitch accepts from the bot's account. Bots Twitch knows or
+//! verified get higher limits, the operator declares which one the bot is in the config. Messages
+//! are only held back if they do.
+
+use std::{collections::VecDeque, time::Duration};
+
+use serde::Deserialize;
+use tokio::time::{sleep_until, Instant};
+
+/// What Twitch made of the bot's account.
+#[derive(Debug, Copy, Clone, Deserialize, PartialEq, Eq)]
+pub enum Verification {
+    /// An ordinary account.
+    Normal,
+    /// A known bot.
+    Known,
+    /// A verified bot.
+    Verified,
+}
+
+impl Verification {
+    /// Chat messages across all channels. Twitch allows ordinary accounts more in channels they
+    /// moderate, but which ones those are isn't tracked, so the lower limit is used. That's why
+    /// it only applies if the operator declared the verification.
+    pub fn messages(self) -> RateLimit {
+        let count = match self {
+            Verification::Normal => 20,
+            Verification::Known => 50,
+            Verification::Verified => 7500,
+        };
+
+        RateLimit {
+            count,
+            per: Duration::from_secs(30),
+        }
+    }
+
+    pub fn joins(self) -> RateLimit {
+        let count = match self {
+            Verification::Normal | Verification::Known => 20,
+            Verification::Verified => 2000,
+        };
+
+        RateLimit {
+            count,
+            per: Duration::from_secs(10),
+        }
+    }
+}
+
+/// At most `count` of something `per` window.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RateLimit {
+    pub count: usize,
+    pub per: Duration,
+}
+
+impl RateLimit {
+    /// The delay between two of something to stay within the limit when doing it steadily.
+    pub fn interval(self) -> Duration {
+        self.per / self.count as u32
+    }
+}
+
+/// Holds back whatever is limited by a [`RateLimit`] until it fits into the window.
+#[derive(Debug)]
+pub struct Limiter {
+    limit: RateLimit,
+    /// When the uses in the current window happened, oldest first.
+    used: VecDeque<Instant>,
+}
+
+impl Limiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            used: VecDeque::new(),
+        }
+    }
+
+    /// When the next use fits into the window, `None` if right away.
+    fn next_at(&mut self, now: Instant) -> Option<Instant> {
+        while matches!(self.used.front(), Some(used) if *used + self.limit.per <= now) {
+            self.used.pop_front();
+        }
+
+        if self.used.len() < self.limit.count {
+            None
+        } else {
+            self.used.front().map(|oldest| *oldest + self.limit.per)
+        }
+    }
+
+    /// Wait until the next use fits into the window and count it.
+    pub async fn acquire(&mut self) {
+        if let Some(at) = self.next_at(Instant::now()) {
+            sleep_until(at).await;
+            self.next_at(Instant::now());
+        }
+
+        self.used.push_back(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verified_bots_get_higher_limits() {
+        assert_eq!(
+            Duration::from_millis(500),
+            Verification::Normal.joins().interval()
+        );
+        assert_eq!(
+            Duration::from_millis(5),
+            Verification::Verified.joins().interval()
+        );
+        assert!(Verification::Known.messages().count > Verification::Normal.messages().count);
+    }
+
+    #[test]
+    fn holds_back_until_the_window_has_room() {
+        let mut limiter = Limiter::new(RateLimit {
+            count: 2,
+            per: Duration::from_secs(30),
+        });
+        let start = Instant::now();
+        limiter.used.push_back(start);
+        limiter.used.push_back(start + Duration::from_secs(10));
+
+        assert_eq!(
+            Some(start + Duration::from_secs(30)),
+            limiter.next_at(start + Duration::from_secs(20))
+        );
+        assert_eq!(None, limiter.next_at(start + Duration::from_secs(30)));
+        assert_eq!(1, limiter.used.len());
+    }
+}
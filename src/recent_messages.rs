@@ -0,0 +1,95 @@
+use std::collections::{HashMap, VecDeque};
+
+/// A ring buffer of the latest chat lines per channel.
+#[derive(Debug, Clone)]
+pub struct RecentMessages {
+    capacity: usize,
+    channels: HashMap<String, VecDeque<(String, String)>>,
+}
+
+impl RecentMessages {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            channels: HashMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, channel: &str, login: &str, text: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let lines = self.channels.entry(channel.to_string()).or_default();
+
+        if lines.len() >= self.capacity {
+            lines.pop_front();
+        }
+
+        lines.push_back((login.to_string(), text.to_string()));
+    }
+
+    /// Drop every line `login` wrote.
+    pub fn forget(&mut self, login: &str) {
+        for lines in self.channels.values_mut() {
+            lines.retain(|(author, _)| author != login);
+        }
+    }
+
+    /// Get the latest line `login` wrote in `channel`.
+    pub fn last_from(&self, channel: &str, login: &str) -> Option<&str> {
+        self.channels.get(channel).and_then(|lines| {
+            lines
+                .iter()
+                .rev()
+                .find(|(author, _)| author == login)
+                .map(|(_, text)| text.as_str())
+        })
+    }
+
+    /// Get the latest line in `channel` that was not written by `except`.
+    pub fn last_except(&self, channel: &str, except: &str) -> Option<(&str, &str)> {
+        self.channels.get(channel).and_then(|lines| {
+            lines
+                .iter()
+                .rev()
+                .find(|(author, _)| author != except)
+                .map(|(author, text)| (author.as_str(), text.as_str()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_buffer_drops_oldest() {
+        let mut recent = RecentMessages::new(2);
+
+        recent.push("channel", "alice", "first");
+        recent.push("channel", "bob", "second");
+        recent.push("channel", "bob", "third");
+
+        assert_eq!(None, recent.last_from("channel", "alice"));
+        assert_eq!(Some("third"), recent.last_from("channel", "bob"));
+        assert_eq!(
+            Some(("bob", "third")),
+            recent.last_except("channel", "alice")
+        );
+    }
+
+    #[test]
+    fn forget_drops_all_lines_of_user() {
+        let mut recent = RecentMessages::new(4);
+
+        recent.push("channel", "alice", "first");
+        recent.push("other", "alice", "second");
+        recent.push("channel", "bob", "third");
+        recent.forget("alice");
+
+        assert_eq!(None, recent.last_from("channel", "alice"));
+        assert_eq!(None, recent.last_from("other", "alice"));
+        assert_eq!(Some("third"), recent.last_from("channel", "bob"));
+    }
+}
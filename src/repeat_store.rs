@@ -0,0 +1,102 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use time::Duration;
+
+use crate::ron_store::RonStore;
+
+/// A message posted to a channel over and over while its stream is live.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RepeatingTimer {
+    pub id: String,
+    pub interval_minutes: i64,
+    pub text: String,
+}
+
+impl RepeatingTimer {
+    pub fn interval(&self) -> Duration {
+        Duration::minutes(self.interval_minutes)
+    }
+}
+
+/// The repeating timers of every channel, keyed by channel.
+#[derive(Debug, Clone)]
+pub struct RepeatStore {
+    data: RonStore<HashMap<String, Vec<RepeatingTimer>>>,
+}
+
+impl RepeatStore {
+    pub fn from_path(path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            data: RonStore::from_path(path, "repeat store")?,
+        })
+    }
+
+    pub fn add(&self, channel: &str, timer: RepeatingTimer) {
+        self.data
+            .write()
+            .entry(channel.to_string())
+            .or_default()
+            .push(timer);
+    }
+
+    /// Remove the timer `id` of `channel`. Returns `false` if there is none.
+    pub fn remove(&self, channel: &str, id: &str) -> bool {
+        let mut data = self.data.write();
+
+        let timers = match data.get_mut(channel) {
+            Some(timers) => timers,
+            None => return false,
+        };
+        let count = timers.len();
+        timers.retain(|timer| timer.id != id);
+        let removed = timers.len() < count;
+
+        if timers.is_empty() {
+            data.remove(channel);
+        }
+
+        removed
+    }
+
+    pub fn timers(&self, channel: &str) -> Vec<RepeatingTimer> {
+        self.data.read().get(channel).cloned().unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.data.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ron_store::test_path;
+
+    fn timer(id: &str) -> RepeatingTimer {
+        RepeatingTimer {
+            id: id.to_string(),
+            interval_minutes: 30,
+            text: "join the discord".to_string(),
+        }
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let repeats = RepeatStore::from_path(test_path("repeat-store")).unwrap();
+        let shared = repeats.clone();
+
+        repeats.add("channel", timer("first"));
+        repeats.add("channel", timer("second"));
+        assert_eq!(
+            vec![timer("first"), timer("second")],
+            shared.timers("channel")
+        );
+
+        assert!(!repeats.remove("other", "first"));
+        assert!(repeats.remove("channel", "first"));
+        assert!(!repeats.remove("channel", "first"));
+        assert_eq!(vec![timer("second")], shared.timers("channel"));
+    }
+}
@@ -0,0 +1,119 @@
+//! The small stores the bot keeps next to its messages, like afk statuses or settings. Each holds
+//! all of its data in memory and writes it to a RON file whenever it changes.
+
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use eyre::{eyre, Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// `T` loaded from a RON file, saved back to it with [`RonStore::save`].
+///
+/// Clones share their data, so tasks holding a clone see changes made in chat and the other way
+/// round.
+#[derive(Debug)]
+pub struct RonStore<T> {
+    path: PathBuf,
+    /// What is stored, used in errors.
+    name: &'static str,
+    data: Arc<RwLock<T>>,
+}
+
+impl<T> Clone for RonStore<T> {
+    fn clone(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+            name: self.name,
+            data: self.data.clone(),
+        }
+    }
+}
+
+impl<T: Default + DeserializeOwned + Serialize> RonStore<T> {
+    /// Load the `name` store from `path`, empty if there is no file yet.
+    pub fn from_path(path: PathBuf, name: &'static str) -> Result<Self> {
+        let data = if path.exists() {
+            if path.is_dir() {
+                return Err(eyre!("Path points to a directory"));
+            }
+
+            let file = File::open(&path).wrap_err_with(|| format!("Failed to open {}", name))?;
+            ron::de::from_reader(file)
+                .wrap_err_with(|| format!("Failed to deserialize {}", name))?
+        } else {
+            T::default()
+        };
+
+        Ok(Self {
+            path,
+            name,
+            data: Arc::new(RwLock::new(data)),
+        })
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.data.read().unwrap()
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        self.data.write().unwrap()
+    }
+
+    /// Write the data to a temporary file and move it over the old one, so a crash can't leave a
+    /// truncated file behind. The write lock keeps clones from writing at the same time.
+    pub fn save(&self) -> Result<()> {
+        let data = self.write();
+
+        let tmp = self.path.with_extension("tmp");
+        let mut writer = BufWriter::new(
+            File::create(&tmp).wrap_err_with(|| format!("Failed to create {}", self.name))?,
+        );
+        ron::ser::to_writer(&mut writer, &*data)
+            .wrap_err_with(|| format!("Failed to write {}", self.name))?;
+        writer
+            .flush()
+            .wrap_err_with(|| format!("Failed to write {}", self.name))?;
+
+        fs::rename(&tmp, &self.path).wrap_err_with(|| format!("Failed to replace {}", self.name))
+    }
+}
+
+/// A path in a fresh temporary directory for the store of the test `name`, which doesn't exist
+/// yet.
+#[cfg(test)]
+pub fn test_path(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("remindme-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+
+    directory.join("store.ron")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn saves_and_loads() {
+        let path = test_path("ron-store");
+        let store =
+            RonStore::<HashMap<String, u32>>::from_path(path.clone(), "test store").unwrap();
+        assert!(store.read().is_empty());
+
+        store.clone().write().insert("a".to_string(), 1);
+        store.save().unwrap();
+        assert!(!path.with_extension("tmp").exists());
+
+        let loaded =
+            RonStore::<HashMap<String, u32>>::from_path(path.clone(), "test store").unwrap();
+        assert_eq!(Some(&1), loaded.read().get("a"));
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
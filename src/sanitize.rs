@@ -0,0 +1,51 @@
+//! Clean up text users send before it is stored and repeated in chat.
+
+/// Characters that don't render but are added by chat clients, e.g. Chatterino appends U+E0000
+/// to bypass Twitch's duplicate message check.
+const INVISIBLE: &[char] = &[
+    '\u{034f}',  // combining grapheme joiner
+    '\u{180e}',  // mongolian vowel separator
+    '\u{200b}',  // zero width space
+    '\u{2060}',  // word joiner
+    '\u{feff}',  // zero width no-break space
+    '\u{e0000}', // unassigned tag
+    '\u{e0001}', // language tag
+];
+
+/// Remove control characters, like IRC formatting codes, and invisible characters from `text`
+/// and collapse the whitespace left behind.
+///
+/// Zero width joiners are kept since emoji sequences depend on them.
+pub fn sanitize(text: &str) -> String {
+    text.chars()
+        .filter(|c| !INVISIBLE.contains(c))
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect::<String>()
+        .split_whitespace()
+        .intersperse(" ")
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_chatterino_suffix() {
+        assert_eq!("hello", sanitize("hello \u{e0000}"));
+        assert_eq!("hello world", sanitize("hel\u{200b}lo world"));
+    }
+
+    #[test]
+    fn strip_control_codes() {
+        assert_eq!("bold text", sanitize("\u{2}bold\u{2} text\u{1}"));
+        assert_eq!("", sanitize("\u{3}\u{f}"));
+    }
+
+    #[test]
+    fn keep_emoji_sequences() {
+        let family = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}";
+
+        assert_eq!(family, sanitize(family));
+    }
+}
@@ -0,0 +1,113 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+};
+
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use time::Duration;
+
+use crate::ron_store::RonStore;
+
+/// A broadcaster who wants to be reminded ahead of the segments of their stream schedule.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ScheduleWatch {
+    pub broadcaster_id: String,
+    pub broadcaster_login: String,
+    pub lead_minutes: i64,
+    /// Segments a reminder was already created for.
+    #[serde(default)]
+    pub reminded: BTreeSet<String>,
+}
+
+impl ScheduleWatch {
+    pub fn lead(&self) -> Duration {
+        Duration::minutes(self.lead_minutes)
+    }
+}
+
+/// Schedule watches keyed by channel.
+#[derive(Debug, Clone)]
+pub struct ScheduleStore {
+    data: RonStore<HashMap<String, ScheduleWatch>>,
+}
+
+impl ScheduleStore {
+    pub fn from_path(path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            data: RonStore::from_path(path, "schedule store")?,
+        })
+    }
+
+    pub fn set(&self, channel: &str, watch: ScheduleWatch) {
+        self.data.write().insert(channel.to_string(), watch);
+    }
+
+    /// Stop watching the schedule of `channel`. Returns `false` if it wasn't watched.
+    pub fn remove(&self, channel: &str) -> bool {
+        self.data.write().remove(channel).is_some()
+    }
+
+    pub fn get(&self, channel: &str) -> Option<ScheduleWatch> {
+        self.data.read().get(channel).cloned()
+    }
+
+    pub fn all(&self) -> Vec<(String, ScheduleWatch)> {
+        self.data
+            .read()
+            .iter()
+            .map(|(channel, watch)| (channel.clone(), watch.clone()))
+            .collect()
+    }
+
+    /// Remember that a reminder was created for `segment` of `channel`, forgetting segments
+    /// that aren't `upcoming` anymore. Returns `false` if it was already reminded.
+    pub fn mark_reminded(&self, channel: &str, segment: &str, upcoming: &[String]) -> bool {
+        let mut data = self.data.write();
+        let watch = match data.get_mut(channel) {
+            Some(watch) => watch,
+            None => return false,
+        };
+
+        watch
+            .reminded
+            .retain(|reminded| upcoming.contains(reminded));
+        watch.reminded.insert(segment.to_string())
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.data.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ron_store::test_path;
+
+    #[test]
+    fn remind_each_segment_once() {
+        let schedules = ScheduleStore::from_path(test_path("schedule-store")).unwrap();
+        schedules.set(
+            "channel",
+            ScheduleWatch {
+                broadcaster_id: "1".to_string(),
+                broadcaster_login: "channel".to_string(),
+                lead_minutes: 30,
+                reminded: BTreeSet::new(),
+            },
+        );
+        let upcoming = vec!["first".to_string(), "second".to_string()];
+
+        assert!(!schedules.mark_reminded("other", "first", &upcoming));
+        assert!(schedules.mark_reminded("channel", "first", &upcoming));
+        assert!(!schedules.mark_reminded("channel", "first", &upcoming));
+
+        // segments that passed are forgotten
+        assert!(schedules.mark_reminded("channel", "second", &["second".to_string()]));
+        assert_eq!(
+            BTreeSet::from(["second".to_string()]),
+            schedules.get("channel").unwrap().reminded
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use eyre::Result;
+use time::OffsetDateTime;
+use tracing::error;
+
+use crate::ron_store::RonStore;
+
+/// How often chatters seen since the last save are written.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Remembers when each user last wrote in each channel.
+///
+/// Every chat line changes it, so it is saved every [`SAVE_INTERVAL`] by [`run`] rather than on
+/// every line.
+#[derive(Debug, Clone)]
+pub struct SeenStore {
+    data: RonStore<HashMap<String, HashMap<String, OffsetDateTime>>>,
+    /// Whether anything changed since the last save.
+    changed: Arc<AtomicBool>,
+}
+
+impl SeenStore {
+    pub fn from_path(path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            data: RonStore::from_path(path, "seen store")?,
+            changed: Arc::default(),
+        })
+    }
+
+    pub fn see(&mut self, login: &str, channel: &str, time: OffsetDateTime) {
+        self.data
+            .write()
+            .entry(login.to_string())
+            .or_default()
+            .insert(channel.to_string(), time);
+        self.changed.store(true, Ordering::SeqCst);
+    }
+
+    /// Get the channel and time `login` was last seen in.
+    pub fn last_seen(&self, login: &str) -> Option<(String, OffsetDateTime)> {
+        self.data.read().get(login).and_then(|channels| {
+            channels
+                .iter()
+                .max_by_key(|(_, time)| **time)
+                .map(|(channel, time)| (channel.clone(), *time))
+        })
+    }
+
+    /// Get everyone who wrote in `channel` at or after `since`, sorted by login.
+    pub fn seen_since(&self, channel: &str, since: OffsetDateTime) -> Vec<String> {
+        let mut logins = self
+            .data
+            .read()
+            .iter()
+            .filter(|(_, channels)| channels.get(channel).map_or(false, |time| *time >= since))
+            .map(|(login, _)| login.clone())
+            .collect::<Vec<_>>();
+        logins.sort_unstable();
+
+        logins
+    }
+
+    /// Get the seen login closest to `login` that is at most `max_distance` edits away.
+    pub fn closest(&self, login: &str, max_distance: usize) -> Option<String> {
+        self.data
+            .read()
+            .keys()
+            .map(|seen| (edit_distance(login, seen), seen))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .min()
+            .map(|(_, seen)| seen.clone())
+    }
+
+    /// Forget everything about `login`.
+    pub fn forget(&mut self, login: &str) {
+        if self.data.write().remove(login).is_some() {
+            self.changed.store(true, Ordering::SeqCst);
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.changed.store(false, Ordering::SeqCst);
+        self.data.save()
+    }
+
+    /// Save if anything changed since the last save.
+    pub fn flush(&self) -> Result<()> {
+        if !self.changed.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        self.save().map_err(|err| {
+            self.changed.store(true, Ordering::SeqCst);
+            err
+        })
+    }
+}
+
+/// Save `seen` every [`SAVE_INTERVAL`] if it changed, until the process exits.
+pub async fn run(seen: SeenStore) {
+    let mut interval = tokio::time::interval(SAVE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = seen.flush() {
+            error!("{:?}", err.wrap_err("Failed to save seen store"));
+        }
+    }
+}
+
+/// Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ron_store::test_path;
+
+    #[test]
+    fn distance() {
+        assert_eq!(0, edit_distance("forsen", "forsen"));
+        assert_eq!(1, edit_distance("forsenlo", "forsenlol"));
+        assert_eq!(1, edit_distance("forsem", "forsen"));
+        assert_eq!(3, edit_distance("", "abc"));
+    }
+
+    #[test]
+    fn closest_seen_login() {
+        let mut seen = SeenStore::from_path(test_path("seen-closest")).unwrap();
+        seen.see("forsenlol", "channel", OffsetDateTime::UNIX_EPOCH);
+        seen.see("alice", "channel", OffsetDateTime::UNIX_EPOCH);
+
+        assert_eq!(Some("forsenlol".to_string()), seen.closest("forsenlo", 2));
+        assert_eq!(None, seen.closest("bob", 2));
+    }
+
+    #[test]
+    fn recent_chatters() {
+        let mut seen = SeenStore::from_path(test_path("seen-recent")).unwrap();
+        let now = OffsetDateTime::UNIX_EPOCH + time::Duration::hours(1);
+        seen.see("alice", "channel", now);
+        seen.see("bob", "channel", now - time::Duration::minutes(45));
+        seen.see("bob", "other", now);
+        seen.see("carol", "channel", now - time::Duration::minutes(30));
+
+        assert_eq!(
+            vec!["alice", "carol"],
+            seen.seen_since("channel", now - time::Duration::minutes(30))
+        );
+        assert!(seen
+            .seen_since("unknown", OffsetDateTime::UNIX_EPOCH)
+            .is_empty());
+    }
+
+    #[test]
+    fn flushes_only_changes() {
+        let path = test_path("seen-flush");
+        let mut seen = SeenStore::from_path(path.clone()).unwrap();
+        seen.flush().unwrap();
+        assert!(!path.exists());
+
+        seen.see("alice", "channel", OffsetDateTime::UNIX_EPOCH);
+        seen.flush().unwrap();
+        let loaded = SeenStore::from_path(path).unwrap();
+        assert!(loaded.last_seen("alice").is_some());
+    }
+}
@@ -0,0 +1,123 @@
+//! Chat messages waiting to be sent. Twitch only accepts so many messages per connection, so when
+//! a burst of deliveries is sent, replies to commands skip ahead instead of waiting behind it.
+
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    sync::Mutex,
+};
+
+use tokio::sync::Notify;
+
+/// How urgent a message is, the most urgent is sent first.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Announcements, countdowns and repeating timers.
+    Announcement,
+    /// Reminders delivered to their recipients.
+    Delivery,
+    /// Replies to commands.
+    Ack,
+}
+
+#[derive(Debug)]
+struct Queued<T> {
+    priority: Priority,
+    /// Keeps messages of the same priority in order.
+    sequence: Reverse<u64>,
+    item: T,
+}
+
+impl<T> Queued<T> {
+    fn key(&self) -> (Priority, Reverse<u64>) {
+        (self.priority, self.sequence)
+    }
+}
+
+impl<T> PartialEq for Queued<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl<T> Eq for Queued<T> {}
+
+impl<T> PartialOrd for Queued<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Queued<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+#[derive(Debug)]
+pub struct SendQueue<T> {
+    /// The queued items and the sequence number of the next one.
+    items: Mutex<(BinaryHeap<Queued<T>>, u64)>,
+    notify: Notify,
+}
+
+impl<T> Default for SendQueue<T> {
+    fn default() -> Self {
+        Self {
+            items: Mutex::new((BinaryHeap::new(), 0)),
+            notify: Notify::new(),
+        }
+    }
+}
+
+impl<T> SendQueue<T> {
+    pub fn push(&self, priority: Priority, item: T) {
+        {
+            let mut items = self.items.lock().unwrap();
+            let sequence = items.1;
+            items.1 += 1;
+            items.0.push(Queued {
+                priority,
+                sequence: Reverse(sequence),
+                item,
+            });
+        }
+
+        self.notify.notify_one();
+    }
+
+    /// Wait for the most urgent item, the oldest of them if there are several.
+    pub async fn pop(&self) -> T {
+        loop {
+            if let Some(queued) = self.items.lock().unwrap().0.pop() {
+                return queued.item;
+            }
+
+            self.notify.notified().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn most_urgent_first_then_in_order() {
+        let queue = SendQueue::default();
+        queue.push(Priority::Announcement, "timer");
+        queue.push(Priority::Delivery, "first delivery");
+        queue.push(Priority::Delivery, "second delivery");
+        queue.push(Priority::Ack, "ack");
+
+        let mut popped = Vec::new();
+        for _ in 0..4 {
+            popped.push(queue.pop().await);
+        }
+
+        assert_eq!(
+            vec!["ack", "first delivery", "second delivery", "timer"],
+            popped
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+    channel_settings::{on_off, parse_bool, Error},
+    ron_store::RonStore,
+    time_zone::Zone,
+};
+
+/// Preferences of a single user.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct UserSettings {
+    /// Offset of the time zone of the user from UTC.
+    pub utc_offset_minutes: Option<i64>,
+    /// Name of the time zone of the user, e.g. `Europe/Berlin`. Takes precedence over
+    /// `utc_offset_minutes` since it knows about daylight saving time.
+    pub time_zone: Option<String>,
+    /// Deliver reminders without mentioning the user.
+    pub silent: bool,
+    /// Whisper reminders to the user instead of sending them to chat.
+    pub whisper: bool,
+    /// Only deliver reminders waiting for the user to type when they type in this channel, except
+    /// the ones written with `here:` which are only delivered where they were written.
+    pub channel: Option<String>,
+    /// Logins of authors whose reminders the user doesn't want.
+    pub ignored: BTreeSet<String>,
+    /// Who may leave reminders for the user.
+    pub accept_from: AcceptFrom,
+    /// Reminders for the user held back with `~defer`.
+    pub deferral: Option<Deferral>,
+}
+
+/// A stretch of time the user doesn't want reminders in, e.g. while on vacation.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Deferral {
+    /// When the user deferred their reminders.
+    pub since: OffsetDateTime,
+    pub until: OffsetDateTime,
+    /// Whether reminders written after `since` are held back too.
+    pub new: bool,
+}
+
+impl UserSettings {
+    /// The time zone of the user, UTC if they didn't set one.
+    pub fn zone(&self) -> Zone {
+        self.time_zone
+            .as_deref()
+            .and_then(Zone::named)
+            .or_else(|| self.utc_offset_minutes.map(Zone::Fixed))
+            .unwrap_or_default()
+    }
+
+    /// Minutes the time zone of the user is away from UTC at `at`, if they set one.
+    pub fn utc_offset_minutes_at(&self, at: OffsetDateTime) -> Option<i64> {
+        if self.time_zone.is_none() && self.utc_offset_minutes.is_none() {
+            return None;
+        }
+
+        Some(self.zone().offset_minutes_at(at))
+    }
+
+    /// When a reminder written at `created` may be delivered to the user if they deferred it at
+    /// `now`.
+    pub fn deferred_until(
+        &self,
+        created: OffsetDateTime,
+        now: OffsetDateTime,
+    ) -> Option<OffsetDateTime> {
+        self.deferral
+            .as_ref()
+            .filter(|deferral| now < deferral.until && (deferral.new || created <= deferral.since))
+            .map(|deferral| deferral.until)
+    }
+
+    /// Whether reminders waiting for the user to type may be delivered in `channel`.
+    pub fn delivers_in(&self, channel: &str) -> bool {
+        self.channel.as_deref().map_or(true, |only| only == channel)
+    }
+
+    /// Change `key` to `value`, or back to the default with `default`.
+    pub fn set(&mut self, key: Key, value: &str) -> Result<(), Error> {
+        let reset = value.eq_ignore_ascii_case("default");
+        let invalid = |expected| Error::InvalidValue {
+            key: key.name(),
+            value: value.to_string(),
+            expected,
+        };
+
+        match key {
+            Key::Silent if reset => self.silent = false,
+            Key::Silent => self.silent = parse_bool(value).ok_or_else(|| invalid("on or off"))?,
+            Key::Whisper if reset => self.whisper = false,
+            Key::Whisper => self.whisper = parse_bool(value).ok_or_else(|| invalid("on or off"))?,
+            Key::Channel if reset || value.eq_ignore_ascii_case("anywhere") => self.channel = None,
+            Key::Channel => {
+                let channel = value.trim_start_matches('#').to_lowercase();
+                if channel.is_empty()
+                    || !channel
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+                {
+                    return Err(invalid("a channel or anywhere"));
+                }
+                self.channel = Some(channel);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The value of `key`.
+    pub fn get(&self, key: Key) -> String {
+        match key {
+            Key::Silent => on_off(self.silent),
+            Key::Whisper => on_off(self.whisper),
+            Key::Channel => self
+                .channel
+                .as_ref()
+                .map_or_else(|| "anywhere".to_string(), |channel| format!("#{}", channel)),
+        }
+    }
+}
+
+/// A delivery preference that can be changed with `~settings`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Key {
+    /// Whether deliveries mention the user.
+    Silent,
+    /// Whether reminders are whispered to the user.
+    Whisper,
+    /// The only channel reminders are delivered in when the user types.
+    Channel,
+}
+
+impl Key {
+    pub const ALL: &'static [Key] = &[Key::Silent, Key::Whisper, Key::Channel];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Key::Silent => "silent",
+            Key::Whisper => "whisper",
+            Key::Channel => "channel",
+        }
+    }
+}
+
+impl FromStr for Key {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Key::ALL
+            .iter()
+            .copied()
+            .find(|key| key.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::unknown_key(s, Key::ALL.iter().map(|key| key.name())))
+    }
+}
+
+/// Who may leave reminders for a user.
+#[derive(Debug, Copy, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub enum AcceptFrom {
+    Anyone,
+    /// Only users following the recipient.
+    Followers,
+    /// Only the user themself.
+    Nobody,
+}
+
+impl Default for AcceptFrom {
+    fn default() -> Self {
+        AcceptFrom::Anyone
+    }
+}
+
+/// The settings of every user who changed one, keyed by login.
+#[derive(Debug, Clone)]
+pub struct SettingsStore {
+    data: RonStore<HashMap<String, UserSettings>>,
+}
+
+impl SettingsStore {
+    pub fn from_path(path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            data: RonStore::from_path(path, "settings store")?,
+        })
+    }
+
+    pub fn get(&self, login: &str) -> UserSettings {
+        self.data.read().get(login).cloned().unwrap_or_default()
+    }
+
+    /// Change the settings of `login`. Users left with the defaults are dropped.
+    pub fn update(&self, login: &str, f: impl FnOnce(&mut UserSettings)) {
+        let mut data = self.data.write();
+
+        let settings = data.entry(login.to_string()).or_default();
+        f(settings);
+        if *settings == UserSettings::default() {
+            data.remove(login);
+        }
+    }
+
+    /// Forget everything about `login`.
+    pub fn forget(&self, login: &str) {
+        self.data.write().remove(login);
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.data.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ron_store::test_path;
+
+    #[test]
+    fn defaults_are_dropped() {
+        let settings = SettingsStore::from_path(test_path("settings-store")).unwrap();
+
+        settings.update("alice", |settings| settings.utc_offset_minutes = Some(120));
+        assert_eq!(Some(120), settings.get("alice").utc_offset_minutes);
+
+        settings.update("alice", |settings| settings.utc_offset_minutes = None);
+        assert!(settings.data.read().is_empty());
+
+        settings.update("alice", |settings| {
+            settings.ignored.insert("bob".to_string());
+        });
+        settings.update("alice", |settings| {
+            settings.ignored.remove("bob");
+        });
+        assert!(settings.data.read().is_empty());
+    }
+
+    #[test]
+    fn delivery_preferences() {
+        let mut settings = UserSettings::default();
+        assert!(settings.delivers_in("anything"));
+
+        settings.set(Key::Channel, "#Forsen").unwrap();
+        assert_eq!("#forsen", settings.get(Key::Channel));
+        assert!(settings.delivers_in("forsen"));
+        assert!(!settings.delivers_in("other"));
+
+        settings.set(Key::Channel, "anywhere").unwrap();
+        assert!(settings.delivers_in("other"));
+
+        settings.set(Key::Whisper, "on").unwrap();
+        assert!(settings.whisper);
+        assert!(settings.set(Key::Whisper, "maybe").is_err());
+        settings.set(Key::Whisper, "default").unwrap();
+        assert_eq!(UserSettings::default(), settings);
+    }
+
+    #[test]
+    fn named_time_zone_takes_precedence() {
+        let mut settings = UserSettings {
+            utc_offset_minutes: Some(60),
+            ..UserSettings::default()
+        };
+        // 2021-07-01 00:00 UTC, summer time in Berlin
+        let summer = OffsetDateTime::from_unix_timestamp(1_625_097_600).unwrap();
+        assert_eq!(Some(60), settings.utc_offset_minutes_at(summer));
+
+        settings.time_zone = Some("Europe/Berlin".to_string());
+        assert_eq!(Some(120), settings.utc_offset_minutes_at(summer));
+
+        assert_eq!(None, UserSettings::default().utc_offset_minutes_at(summer));
+    }
+
+    #[test]
+    fn deferral_holds_reminders() {
+        let now = OffsetDateTime::UNIX_EPOCH;
+        let until = now + time::Duration::days(3);
+        let mut settings = UserSettings {
+            deferral: Some(Deferral {
+                since: now,
+                until,
+                new: false,
+            }),
+            ..UserSettings::default()
+        };
+
+        let written_before = now - time::Duration::hours(1);
+        let written_during = now + time::Duration::hours(1);
+        assert_eq!(Some(until), settings.deferred_until(written_before, now));
+        assert_eq!(None, settings.deferred_until(written_during, now));
+        assert_eq!(None, settings.deferred_until(written_before, until));
+
+        settings.deferral.as_mut().unwrap().new = true;
+        assert_eq!(Some(until), settings.deferred_until(written_during, now));
+    }
+}
@@ -0,0 +1,193 @@
+//! Full snapshots of the store written to a directory every so often, independent of how the
+//! storage saves changes, so `twitch-remindme store rollback` can return to an earlier state.
+//!
+//! Snapshots are RON files named `messages-<timestamp>.ron` after the time they were taken in UTC.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use eyre::{eyre, Context, Result};
+use serde::Deserialize;
+use time::{format_description, OffsetDateTime};
+use tracing::{error, info};
+
+use crate::{message::Message, message_store::SharedStore, storage::FileFormat};
+
+const PREFIX: &str = "messages-";
+const EXTENSION: &str = ".ron";
+
+/// How snapshots are timestamped, like `20130524T000000Z`.
+pub const TIMESTAMP_FORMAT: &str = "[year][month][day]T[hour][minute][second]Z";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SnapshotConfig {
+    pub directory: PathBuf,
+    #[serde(default = "default_interval_minutes")]
+    pub interval_minutes: u64,
+    /// How many snapshots are kept, older ones are removed.
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+}
+
+fn default_interval_minutes() -> u64 {
+    60
+}
+
+fn default_keep() -> usize {
+    48
+}
+
+/// The name of a snapshot taken at `at`.
+fn file_name(at: OffsetDateTime) -> String {
+    let format = format_description::parse(TIMESTAMP_FORMAT).expect("Invalid date format");
+    format!(
+        "{}{}{}",
+        PREFIX,
+        at.format(&format).expect("Failed to format timestamp"),
+        EXTENSION
+    )
+}
+
+/// The names of the snapshots in `directory`, oldest first.
+pub fn list(directory: &Path) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(directory).wrap_err("Failed to read snapshot directory")? {
+        let name = entry
+            .wrap_err("Failed to read snapshot directory")?
+            .file_name()
+            .to_string_lossy()
+            .into_owned();
+        if name.starts_with(PREFIX) && name.ends_with(EXTENSION) {
+            names.push(name);
+        }
+    }
+    names.sort();
+
+    Ok(names)
+}
+
+/// The name of the latest snapshot taken at or before `at`.
+fn latest_before(directory: &Path, at: OffsetDateTime) -> Result<Option<String>> {
+    let cutoff = file_name(at);
+    Ok(list(directory)?
+        .into_iter()
+        .filter(|name| *name <= cutoff)
+        .last())
+}
+
+pub fn read(directory: &Path, name: &str) -> Result<Vec<Message>> {
+    let data = fs::read_to_string(directory.join(name))
+        .wrap_err_with(|| format!("Failed to read snapshot {}", name))?;
+    FileFormat::Ron
+        .messages_from_str(&data)
+        .wrap_err_with(|| format!("Failed to deserialize snapshot {}", name))
+}
+
+/// Write a snapshot of `messages` taken at `at`, then remove all but the newest `keep`. Returns
+/// the name of the snapshot.
+fn write(config: &SnapshotConfig, messages: &[&Message], at: OffsetDateTime) -> Result<String> {
+    fs::create_dir_all(&config.directory).wrap_err("Failed to create snapshot directory")?;
+
+    let name = file_name(at);
+    let mut data = Vec::new();
+    FileFormat::Ron.write_snapshot(&mut data, messages)?;
+    // a crash while writing must not leave a truncated snapshot to roll back to
+    let tmp = config.directory.join(format!("{}.tmp", name));
+    fs::write(&tmp, data).wrap_err("Failed to write snapshot")?;
+    fs::rename(&tmp, config.directory.join(&name)).wrap_err("Failed to write snapshot")?;
+
+    let names = list(&config.directory)?;
+    for old in &names[..names.len().saturating_sub(config.keep)] {
+        fs::remove_file(config.directory.join(old))
+            .wrap_err_with(|| format!("Failed to remove snapshot {}", old))?;
+    }
+
+    Ok(name)
+}
+
+/// Take a snapshot of the store every [`SnapshotConfig::interval_minutes`], starting right away.
+pub async fn run(store: SharedStore, config: SnapshotConfig) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_minutes * 60));
+
+    loop {
+        interval.tick().await;
+
+        let written = {
+            let store = store.lock().await;
+            let messages = store.get_all().into_iter().collect::<Vec<_>>();
+            write(&config, &messages, OffsetDateTime::now_utc())
+        };
+        match written {
+            Ok(name) => info!("Wrote snapshot {}", name),
+            Err(err) => error!("{:?}", err.wrap_err("Failed to take snapshot")),
+        }
+    }
+}
+
+/// Parse the argument of `store rollback`: a snapshot name or an RFC 3339 timestamp to roll back
+/// to the latest snapshot taken at or before it.
+pub fn resolve(directory: &Path, arg: &str) -> Result<String> {
+    if arg.starts_with(PREFIX) {
+        return Ok(arg.to_string());
+    }
+
+    let at = OffsetDateTime::parse(arg, &format_description::well_known::Rfc3339)
+        .map_err(|_| eyre!("'{}' is neither a snapshot nor an RFC 3339 timestamp", arg))?;
+    latest_before(directory, at)?.ok_or_else(|| eyre!("There is no snapshot before {}", arg))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use time::Duration;
+
+    use super::*;
+    use crate::{id::IdGenerator, message::Activation};
+
+    #[test]
+    fn keeps_the_newest_and_finds_by_time() {
+        let directory = env::temp_dir().join(format!("remindme-snapshots-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        let config = SnapshotConfig {
+            directory: directory.clone(),
+            interval_minutes: default_interval_minutes(),
+            keep: 2,
+        };
+
+        let message = Message::new(
+            IdGenerator::Short.generate().unwrap(),
+            Activation::OnNextMessage,
+            "alice".to_string(),
+            "channel".to_string(),
+            "bob".to_string(),
+            "buy milk".to_string(),
+        );
+        for hour in [12, 13, 14] {
+            let at = OffsetDateTime::UNIX_EPOCH + Duration::hours(hour);
+            write(&config, &[&message], at).unwrap();
+        }
+
+        assert_eq!(
+            vec![
+                "messages-19700101T130000Z.ron".to_string(),
+                "messages-19700101T140000Z.ron".to_string()
+            ],
+            list(&directory).unwrap()
+        );
+        assert_eq!(
+            "messages-19700101T130000Z.ron",
+            resolve(&directory, "1970-01-01T13:59:00Z").unwrap()
+        );
+        assert!(resolve(&directory, "1970-01-01T12:30:00Z").is_err());
+        assert_eq!(
+            vec![message],
+            read(&directory, "messages-19700101T140000Z.ron").unwrap()
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,913 @@
+#[cfg(feature = "redis")]
+mod redis_storage;
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    fs::{self, File, Metadata, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use eyre::{eyre, Context, Result};
+use fs2::FileExt;
+use serde::{de::DeserializeOwned, Deserialize, Serialize, Serializer};
+use tracing::{error, warn};
+
+use crate::message::{
+    stored::{self, StoredMessage},
+    Message,
+};
+
+#[cfg(feature = "redis")]
+pub use self::redis_storage::RedisStorage;
+
+/// A change to the stored messages, as recorded in a journal.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation {
+    /// Insert or replace a message.
+    Insert(Message),
+    /// Remove the message with this id.
+    Remove(String),
+}
+
+/// Persists the messages of a [`MessageStore`](crate::message_store::MessageStore).
+pub trait Storage: Debug + Send + Sync {
+    fn load(&self) -> Result<Vec<Message>>;
+
+    /// Replace everything stored with `messages`.
+    fn save(&self, messages: &[&Message]) -> Result<()>;
+
+    /// Record `operations` without rewriting everything. Returns `false` if the storage has no
+    /// journal, in which case the caller has to [`save`](Storage::save) a snapshot instead.
+    fn append(&self, _operations: &[Operation]) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Whether the stored messages were changed by something else since they were last loaded or
+    /// saved. Storages shared on purpose always say no.
+    fn changed(&self) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Claim the delivery of `message` for `instance`. Returns `false` if another instance
+    /// already claimed it. Storages that can't be shared between instances always succeed.
+    fn claim(&self, _message: &Message, _instance: &str) -> Result<bool> {
+        Ok(true)
+    }
+
+    /// Give up the claim of `instance` on `message`, so any instance may deliver it again.
+    fn release(&self, _message: &Message, _instance: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum StorageConfig {
+    Ron {
+        path: PathBuf,
+        /// Compress snapshots with zstd. Needs the `zstd` feature.
+        #[serde(default)]
+        compress: bool,
+        /// How many previous snapshots are kept, as `<path>.1` being the newest.
+        #[serde(default = "default_backups")]
+        backups: usize,
+    },
+    /// Same as `Ron` but easier to consume from external scripts.
+    Json {
+        path: PathBuf,
+        /// Compress snapshots with zstd. Needs the `zstd` feature.
+        #[serde(default)]
+        compress: bool,
+        /// How many previous snapshots are kept, as `<path>.1` being the newest.
+        #[serde(default = "default_backups")]
+        backups: usize,
+    },
+    #[cfg(feature = "redis")]
+    Redis {
+        url: String,
+        #[serde(default = "default_redis_prefix")]
+        prefix: String,
+        /// Log changes made to the keyspace by other clients.
+        #[serde(default)]
+        keyspace_notifications: bool,
+    },
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig::Ron {
+            path: PathBuf::from("messages.ron"),
+            compress: false,
+            backups: default_backups(),
+        }
+    }
+}
+
+fn default_backups() -> usize {
+    3
+}
+
+#[cfg(feature = "redis")]
+fn default_redis_prefix() -> String {
+    "remindme".to_string()
+}
+
+impl StorageConfig {
+    pub fn open(&self) -> Result<Arc<dyn Storage>> {
+        match self {
+            StorageConfig::Ron {
+                path,
+                compress,
+                backups,
+            } => Ok(Arc::new(
+                FileStorage::new(path.clone(), FileFormat::Ron)
+                    .with_compression(*compress)?
+                    .with_backups(*backups)
+                    .lock()?,
+            )),
+            StorageConfig::Json {
+                path,
+                compress,
+                backups,
+            } => Ok(Arc::new(
+                FileStorage::new(path.clone(), FileFormat::Json)
+                    .with_compression(*compress)?
+                    .with_backups(*backups)
+                    .lock()?,
+            )),
+            #[cfg(feature = "redis")]
+            StorageConfig::Redis {
+                url,
+                prefix,
+                keyspace_notifications,
+            } => {
+                let storage =
+                    RedisStorage::open(url, prefix).wrap_err("Failed to connect to redis")?;
+
+                if *keyspace_notifications {
+                    storage
+                        .watch(|key| tracing::info!("Redis key {} changed", key))
+                        .wrap_err("Failed to subscribe to keyspace notifications")?;
+                }
+
+                Ok(Arc::new(storage))
+            }
+        }
+    }
+}
+
+/// An [`Operation`] as written to the journal.
+#[derive(Debug, Deserialize, Serialize)]
+enum JournalEntry {
+    Insert(StoredMessage),
+    Remove(String),
+}
+
+impl From<&Operation> for JournalEntry {
+    fn from(operation: &Operation) -> Self {
+        match operation {
+            Operation::Insert(message) => JournalEntry::Insert(message.into()),
+            Operation::Remove(id) => JournalEntry::Remove(id.clone()),
+        }
+    }
+}
+
+/// Serialization format of a [`FileStorage`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FileFormat {
+    Ron,
+    Json,
+}
+
+impl FileFormat {
+    fn serialize<T: Serialize>(self, value: &T) -> Result<String> {
+        match self {
+            FileFormat::Ron => ron::ser::to_string(value).wrap_err("Failed to serialize as RON"),
+            FileFormat::Json => {
+                serde_json::to_string(value).wrap_err("Failed to serialize as JSON")
+            }
+        }
+    }
+
+    #[cfg(feature = "pretty_store")]
+    fn write_pretty<W: Write, T: Serialize>(self, writer: W, value: &T) -> Result<()> {
+        match self {
+            FileFormat::Ron => {
+                ron::ser::to_writer_pretty(writer, value, ron::ser::PrettyConfig::default())
+                    .wrap_err("Failed to serialize as RON")
+            }
+            FileFormat::Json => {
+                serde_json::to_writer_pretty(writer, value).wrap_err("Failed to serialize as JSON")
+            }
+        }
+    }
+
+    #[cfg(not(feature = "pretty_store"))]
+    fn write_pretty<W: Write, T: Serialize>(self, writer: W, value: &T) -> Result<()> {
+        match self {
+            FileFormat::Ron => {
+                ron::ser::to_writer(writer, value).wrap_err("Failed to serialize as RON")
+            }
+            FileFormat::Json => {
+                serde_json::to_writer(writer, value).wrap_err("Failed to serialize as JSON")
+            }
+        }
+    }
+
+    /// Write `messages` as a snapshot, converting them one at a time.
+    pub(crate) fn write_snapshot<W: Write>(self, writer: W, messages: &[&Message]) -> Result<()> {
+        self.write_pretty(writer, &Snapshot(messages))
+    }
+
+    fn deserialize<T: DeserializeOwned>(self, s: &str) -> Result<T> {
+        match self {
+            FileFormat::Ron => ron::de::from_str(s).wrap_err("Failed to deserialize RON"),
+            FileFormat::Json => serde_json::from_str(s).wrap_err("Failed to deserialize JSON"),
+        }
+    }
+
+    pub(crate) fn messages_from_str(self, s: &str) -> Result<Vec<Message>> {
+        match self {
+            // RON files might predate the versioned format
+            FileFormat::Ron => stored::list_from_str(s).wrap_err("Failed to deserialize RON"),
+            FileFormat::Json => Ok(self
+                .deserialize::<Vec<StoredMessage>>(s)?
+                .into_iter()
+                .map(Message::from)
+                .collect()),
+        }
+    }
+
+    fn message_from_str(self, s: &str) -> Result<Message> {
+        match self {
+            FileFormat::Ron => stored::from_str(s).wrap_err("Failed to deserialize RON"),
+            FileFormat::Json => self.deserialize::<StoredMessage>(s).map(Message::from),
+        }
+    }
+}
+
+/// A snapshot of the store, converted to [`StoredMessage`]s one at a time while it is written
+/// instead of all at once.
+struct Snapshot<'a>(&'a [&'a Message]);
+
+impl Serialize for Snapshot<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().map(|message| StoredMessage::from(*message)))
+    }
+}
+
+/// Every zstd frame starts with these bytes.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression level of snapshots, the default of zstd.
+#[cfg(feature = "zstd")]
+const ZSTD_LEVEL: i32 = 3;
+
+/// Where entries of a snapshot that can't be parsed are moved to, next to the snapshot.
+const QUARANTINE_FILE: &str = "quarantine.ron";
+
+/// Decompress `data` if it is compressed with zstd, which is recognized by its magic bytes.
+fn decompress(data: Vec<u8>) -> Result<Vec<u8>> {
+    if !data.starts_with(&ZSTD_MAGIC) {
+        return Ok(data);
+    }
+
+    #[cfg(feature = "zstd")]
+    {
+        zstd::stream::decode_all(data.as_slice()).wrap_err("Failed to decompress storage")
+    }
+    #[cfg(not(feature = "zstd"))]
+    {
+        Err(eyre!(
+            "Storage is compressed but the zstd feature is disabled"
+        ))
+    }
+}
+
+/// Stores all messages in a single file, with changes since the last snapshot appended to a
+/// journal next to it, one operation per line.
+///
+/// Snapshots may be compressed with zstd. Compressed snapshots are recognized when loading
+/// either way, the journal is never compressed.
+///
+/// The previous snapshots can be kept as backups. If the snapshot can't be loaded, the newest
+/// backup that can is loaded instead and the broken snapshot is kept aside as `<path>.corrupt`.
+#[derive(Debug, Clone)]
+pub struct FileStorage {
+    path: PathBuf,
+    journal: PathBuf,
+    format: FileFormat,
+    compress: bool,
+    /// How many previous snapshots are kept.
+    backups: usize,
+    /// Held as long as any clone of the storage lives.
+    _lock: Option<Arc<File>>,
+    /// What the snapshot looked like when it was last loaded or saved.
+    fingerprint: Arc<Mutex<Option<Fingerprint>>>,
+}
+
+/// Modification time and size of a snapshot, to notice when someone else changed it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fingerprint {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl Fingerprint {
+    fn of(metadata: &Metadata) -> Result<Self> {
+        Ok(Self {
+            modified: metadata
+                .modified()
+                .wrap_err("Failed to read modification time")?,
+            len: metadata.len(),
+        })
+    }
+}
+
+impl FileStorage {
+    pub fn new(path: PathBuf, format: FileFormat) -> Self {
+        Self {
+            journal: path.with_extension("journal"),
+            path,
+            format,
+            compress: false,
+            backups: 0,
+            _lock: None,
+            fingerprint: Arc::default(),
+        }
+    }
+
+    /// Keep the `backups` previous snapshots.
+    pub fn with_backups(mut self, backups: usize) -> Self {
+        self.backups = backups;
+        self
+    }
+
+    /// The file next to the snapshot named like it with `suffix` appended.
+    fn sibling(&self, suffix: &str) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(suffix);
+
+        self.path.with_file_name(name)
+    }
+
+    fn backup(&self, number: usize) -> PathBuf {
+        self.sibling(&format!(".{}", number))
+    }
+
+    /// Shift the backups by one, keeping the current snapshot as the newest.
+    fn rotate_backups(&self) -> Result<()> {
+        if self.backups == 0 || !self.path.exists() {
+            return Ok(());
+        }
+
+        for number in (1..self.backups).rev() {
+            let backup = self.backup(number);
+            if backup.exists() {
+                fs::rename(&backup, self.backup(number + 1))
+                    .wrap_err("Failed to rotate backups")?;
+            }
+        }
+
+        // the snapshot is replaced by renaming over it, so a link keeps the old one around
+        let newest = self.backup(1);
+        if newest.exists() {
+            fs::remove_file(&newest).wrap_err("Failed to remove backup")?;
+        }
+        fs::hard_link(&self.path, &newest)
+            .or_else(|_| fs::copy(&self.path, &newest).map(|_| ()))
+            .wrap_err("Failed to back up storage")
+    }
+
+    /// Read the snapshot at `path`, quarantining the entries that can't be parsed.
+    fn read_snapshot(&self, path: &Path) -> Result<Vec<Message>> {
+        let data = fs::read(path).wrap_err("Failed to read storage")?;
+        let data =
+            String::from_utf8(decompress(data)?).wrap_err("Failed to decode storage as UTF-8")?;
+
+        match self.format.messages_from_str(&data) {
+            Ok(snapshot) => Ok(snapshot),
+            Err(err) => self
+                .quarantine(&data)
+                .ok_or(err)
+                .wrap_err("Failed to deserialize storage")?,
+        }
+    }
+
+    /// Load the newest backup that can be read after the snapshot failed to load with `err`,
+    /// keeping the broken snapshot aside.
+    fn restore_backup(&self, err: eyre::Report) -> Result<Vec<Message>> {
+        for number in 1..=self.backups {
+            let backup = self.backup(number);
+            if !backup.exists() {
+                continue;
+            }
+
+            match self.read_snapshot(&backup) {
+                Ok(messages) => {
+                    let corrupt = self.sibling(".corrupt");
+                    fs::rename(&self.path, &corrupt)
+                        .wrap_err("Failed to keep broken storage aside")?;
+                    error!(
+                        "{:?}",
+                        err.wrap_err(format!(
+                            "Failed to load storage, restored {} instead and moved the broken file to {}",
+                            backup.display(),
+                            corrupt.display()
+                        ))
+                    );
+
+                    return Ok(messages);
+                }
+                Err(err) => warn!(
+                    "{:?}",
+                    err.wrap_err(format!("Failed to load backup {}", backup.display()))
+                ),
+            }
+        }
+
+        Err(err)
+    }
+
+    /// Take an advisory lock on a file next to the storage, so a second instance of the bot
+    /// can't interleave its saves with ours. Fails if another process holds the lock.
+    pub fn lock(mut self) -> Result<Self> {
+        let path = self.path.with_extension("lock");
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(&path)
+            .wrap_err("Failed to open lock file")?;
+
+        match file.try_lock_exclusive() {
+            Ok(()) => {}
+            Err(err) if err.kind() == fs2::lock_contended_error().kind() => {
+                return Err(eyre!(
+                    "{} is locked by another process, is the bot already running?",
+                    self.path.display()
+                ))
+            }
+            Err(err) => return Err(err).wrap_err("Failed to lock storage"),
+        }
+
+        self._lock = Some(Arc::new(file));
+        Ok(self)
+    }
+
+    /// Compress snapshots with zstd. Fails if the `zstd` feature is disabled.
+    pub fn with_compression(mut self, compress: bool) -> Result<Self> {
+        if compress && !cfg!(feature = "zstd") {
+            return Err(eyre!("Compressing the storage needs the zstd feature"));
+        }
+
+        self.compress = compress;
+        Ok(self)
+    }
+
+    /// Write a compressed snapshot of `messages` to `file`.
+    #[cfg(feature = "zstd")]
+    fn write_compressed(&self, file: File, messages: &[&Message]) -> Result<()> {
+        let mut encoder = zstd::stream::write::Encoder::new(BufWriter::new(file), ZSTD_LEVEL)
+            .wrap_err("Failed to start compression")?;
+        self.format.write_snapshot(&mut encoder, messages)?;
+
+        encoder
+            .finish()
+            .wrap_err("Failed to finish compression")?
+            .flush()
+            .wrap_err("Failed to write storage")
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    fn write_compressed(&self, _file: File, _messages: &[&Message]) -> Result<()> {
+        Err(eyre!("Compressing the storage needs the zstd feature"))
+    }
+
+    /// Parse the entries of a snapshot that failed to load one by one, appending the ones that
+    /// can't be parsed to the quarantine file, one per line. Returns `None` if the snapshot
+    /// can't even be split into entries.
+    fn quarantine(&self, data: &str) -> Option<Result<Vec<Message>>> {
+        let entries = stored::split_list(data)?;
+
+        let mut messages = Vec::new();
+        let mut quarantined = Vec::new();
+        for entry in entries {
+            match self.format.message_from_str(entry) {
+                Ok(message) => messages.push(message),
+                Err(err) => {
+                    warn!("Quarantining unreadable message: {:?}", err);
+                    quarantined.push(entry);
+                }
+            }
+        }
+
+        Some(self.write_quarantine(&quarantined).map(|()| messages))
+    }
+
+    fn write_quarantine(&self, entries: &[&str]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let path = self.path.with_file_name(QUARANTINE_FILE);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .wrap_err("Failed to open quarantine")?;
+        for entry in entries {
+            let line = entry.lines().map(str::trim).collect::<Vec<_>>().join(" ");
+            writeln!(file, "{}", line).wrap_err("Failed to write quarantine")?;
+        }
+
+        warn!(
+            "Moved {} unreadable messages to {}",
+            entries.len(),
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Fingerprint the snapshot as it is on disk right now.
+    fn current_fingerprint(&self) -> Result<Option<Fingerprint>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+
+        let metadata = fs::metadata(&self.path).wrap_err("Failed to read storage metadata")?;
+        Fingerprint::of(&metadata).map(Some)
+    }
+
+    fn remember_fingerprint(&self) -> Result<()> {
+        *self.fingerprint.lock().unwrap() = self.current_fingerprint()?;
+
+        Ok(())
+    }
+
+    /// Move the journal out of the way after the snapshot was replaced by someone else, so the
+    /// changes it recorded against the old snapshot aren't applied to the new one.
+    fn set_journal_aside(&self) -> Result<()> {
+        if !self.journal.exists() {
+            return Ok(());
+        }
+
+        let stale = self.journal.with_extension("journal.stale");
+        fs::rename(&self.journal, &stale).wrap_err("Failed to set journal aside")?;
+        warn!(
+            "The storage was replaced externally, moved its journal to {}",
+            stale.display()
+        );
+
+        Ok(())
+    }
+
+    /// Apply the journal to `messages`. A line that can't be parsed or isn't terminated ends the
+    /// replay since it's most likely a partial write from a crash. The journal is cut off before
+    /// it, so later appends don't end up glued onto it and lost on the next replay.
+    fn replay(&self, messages: &mut HashMap<String, Message>) -> Result<()> {
+        if !self.journal.exists() {
+            return Ok(());
+        }
+
+        let file = File::open(&self.journal).wrap_err("Failed to open journal")?;
+        let mut reader = BufReader::new(file);
+        // bytes of the journal up to the end of the last complete line
+        let mut complete = 0;
+        let mut line = String::new();
+        for number in 1.. {
+            line.clear();
+            let read = reader
+                .read_line(&mut line)
+                .wrap_err("Failed to read journal")?;
+            if read == 0 {
+                return Ok(());
+            }
+            let entry = match line.strip_suffix('\n') {
+                Some(line) => self.format.deserialize(line),
+                None => Err(eyre!("Line is not terminated")),
+            };
+
+            match entry {
+                Ok(JournalEntry::Insert(message)) => {
+                    let message = Message::from(message);
+                    messages.insert(message.id().to_string(), message);
+                }
+                Ok(JournalEntry::Remove(id)) => {
+                    messages.remove(&id);
+                }
+                Err(err) => {
+                    warn!("Ignoring journal from line {} on: {:?}", number, err);
+                    break;
+                }
+            }
+            complete += read as u64;
+        }
+
+        OpenOptions::new()
+            .write(true)
+            .open(&self.journal)
+            .wrap_err("Failed to open journal")?
+            .set_len(complete)
+            .wrap_err("Failed to cut off journal")
+    }
+}
+
+impl Storage for FileStorage {
+    fn load(&self) -> Result<Vec<Message>> {
+        if self.path.is_dir() {
+            return Err(eyre!("Path points to a directory"));
+        }
+
+        // the journal only applies to the snapshot it was written after
+        let known = *self.fingerprint.lock().unwrap();
+        let edited = known.is_some() && known != self.current_fingerprint()?;
+
+        let mut messages = HashMap::new();
+        if self.path.exists() {
+            let snapshot = match self.read_snapshot(&self.path) {
+                Ok(snapshot) => snapshot,
+                Err(err) => self.restore_backup(err)?,
+            };
+
+            messages.extend(
+                snapshot
+                    .into_iter()
+                    .map(|message| (message.id().to_string(), message)),
+            );
+        }
+
+        if edited {
+            self.set_journal_aside()?;
+        } else {
+            self.replay(&mut messages)
+                .wrap_err("Failed to replay journal")?;
+        }
+        self.remember_fingerprint()?;
+
+        Ok(messages.into_values().collect())
+    }
+
+    fn save(&self, messages: &[&Message]) -> Result<()> {
+        // write to a temporary file first so a crash can't leave a truncated snapshot behind
+        let tmp = self.path.with_extension("tmp");
+        let file = File::create(&tmp).wrap_err("Failed to create storage")?;
+        if self.compress {
+            self.write_compressed(file, messages)?;
+        } else {
+            let mut writer = BufWriter::new(file);
+            self.format.write_snapshot(&mut writer, messages)?;
+            writer.flush().wrap_err("Failed to write storage")?;
+        }
+        self.rotate_backups()?;
+        fs::rename(&tmp, &self.path).wrap_err("Failed to replace storage")?;
+        self.remember_fingerprint()?;
+
+        // the snapshot contains everything in the journal now
+        File::create(&self.journal).wrap_err("Failed to truncate journal")?;
+
+        Ok(())
+    }
+
+    fn changed(&self) -> Result<bool> {
+        Ok(*self.fingerprint.lock().unwrap() != self.current_fingerprint()?)
+    }
+
+    fn append(&self, operations: &[Operation]) -> Result<bool> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.journal)
+            .wrap_err("Failed to open journal")?;
+
+        let mut lines = String::new();
+        for operation in operations {
+            lines.push_str(
+                &self
+                    .format
+                    .serialize(&JournalEntry::from(operation))
+                    .wrap_err("Failed to serialize operation")?,
+            );
+            lines.push('\n');
+        }
+
+        file.write_all(lines.as_bytes())
+            .wrap_err("Failed to write journal")?;
+        file.sync_data().wrap_err("Failed to sync journal")?;
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+    use crate::message::Activation;
+
+    fn message(id: &str) -> Message {
+        Message::new(
+            id.to_string(),
+            Activation::OnNextMessage,
+            "alice".to_string(),
+            "channel".to_string(),
+            "bob".to_string(),
+            "text".to_string(),
+        )
+    }
+
+    #[test]
+    fn journal_is_replayed_until_partial_line() {
+        let dir = env::temp_dir().join(format!("remindme-journal-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let storage = FileStorage::new(dir.join("messages.ron"), FileFormat::Ron);
+
+        storage.save(&[&message("first")]).unwrap();
+        storage
+            .append(&[
+                Operation::Insert(message("second")),
+                Operation::Remove("first".to_string()),
+            ])
+            .unwrap();
+        OpenOptions::new()
+            .append(true)
+            .open(&storage.journal)
+            .unwrap()
+            .write_all(b"Insert((id:\"thi")
+            .unwrap();
+
+        let ids = storage
+            .load()
+            .unwrap()
+            .into_iter()
+            .map(|message| message.id().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["second".to_string()], ids);
+
+        // later appends start on a line of their own
+        storage
+            .append(&[Operation::Insert(message("fourth"))])
+            .unwrap();
+        let mut ids = storage
+            .load()
+            .unwrap()
+            .into_iter()
+            .map(|message| message.id().to_string())
+            .collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(vec!["fourth".to_string(), "second".to_string()], ids);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        let dir = env::temp_dir().join(format!("remindme-json-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let storage = FileStorage::new(dir.join("messages.json"), FileFormat::Json);
+
+        storage.save(&[&message("first")]).unwrap();
+        storage
+            .append(&[Operation::Insert(message("second"))])
+            .unwrap();
+
+        let mut ids = storage
+            .load()
+            .unwrap()
+            .into_iter()
+            .map(|message| message.id().to_string())
+            .collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(vec!["first".to_string(), "second".to_string()], ids);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_entries_are_quarantined() {
+        let dir = env::temp_dir().join(format!("remindme-quarantine-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let storage = FileStorage::new(dir.join("messages.ron"), FileFormat::Ron);
+
+        storage.save(&[&message("first")]).unwrap();
+        let data = fs::read_to_string(&storage.path).unwrap();
+        let broken = format!(
+            "{},V1((id:\"broken\"))]",
+            data.trim_end().strip_suffix(']').unwrap()
+        );
+        fs::write(&storage.path, broken).unwrap();
+
+        let ids = storage
+            .load()
+            .unwrap()
+            .into_iter()
+            .map(|message| message.id().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["first".to_string()], ids);
+        assert_eq!(
+            "V1((id:\"broken\"))\n",
+            fs::read_to_string(dir.join(QUARANTINE_FILE)).unwrap()
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn broken_snapshot_falls_back_to_backup() {
+        let dir = env::temp_dir().join(format!("remindme-backup-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let storage = FileStorage::new(dir.join("messages.ron"), FileFormat::Ron).with_backups(2);
+
+        storage.save(&[&message("first")]).unwrap();
+        storage
+            .save(&[&message("first"), &message("second")])
+            .unwrap();
+        fs::write(&storage.path, "[V1((id:").unwrap();
+
+        let ids = storage
+            .load()
+            .unwrap()
+            .into_iter()
+            .map(|message| message.id().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["first".to_string()], ids);
+        assert!(dir.join("messages.ron.corrupt").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn external_changes_are_noticed() {
+        let dir = env::temp_dir().join(format!("remindme-changed-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let storage = FileStorage::new(dir.join("messages.ron"), FileFormat::Ron);
+
+        assert!(!storage.changed().unwrap());
+        storage.save(&[&message("first")]).unwrap();
+        assert!(!storage.changed().unwrap());
+
+        fs::write(&storage.path, "[]").unwrap();
+        assert!(storage.changed().unwrap());
+
+        assert!(storage.load().unwrap().is_empty());
+        assert!(!storage.changed().unwrap());
+
+        // the journal of the replaced snapshot isn't replayed over the new one
+        storage
+            .append(&[Operation::Insert(message("second"))])
+            .unwrap();
+        fs::write(&storage.path, "[ ]").unwrap();
+        assert!(storage.load().unwrap().is_empty());
+        assert!(!storage.journal.exists());
+        assert!(storage.journal.with_extension("journal.stale").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn lock_is_exclusive() {
+        let dir = env::temp_dir().join(format!("remindme-lock-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("messages.ron");
+
+        let first = FileStorage::new(path.clone(), FileFormat::Ron)
+            .lock()
+            .unwrap();
+        assert!(FileStorage::new(path.clone(), FileFormat::Ron)
+            .lock()
+            .is_err());
+
+        drop(first);
+        assert!(FileStorage::new(path, FileFormat::Ron).lock().is_ok());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn compressed_roundtrip() {
+        let dir = env::temp_dir().join(format!("remindme-zstd-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let storage = FileStorage::new(dir.join("messages.ron"), FileFormat::Ron)
+            .with_compression(true)
+            .unwrap();
+
+        storage.save(&[&message("first")]).unwrap();
+        assert!(fs::read(&storage.path).unwrap().starts_with(&ZSTD_MAGIC));
+
+        // compressed snapshots are read even if compression was turned off since
+        let storage = FileStorage::new(storage.path.clone(), FileFormat::Ron);
+        let ids = storage
+            .load()
+            .unwrap()
+            .into_iter()
+            .map(|message| message.id().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["first".to_string()], ids);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    thread,
+};
+
+use eyre::{Context, Result};
+use redis::{Client, Commands};
+use tracing::error;
+
+use crate::{
+    message::{
+        stored::{self, StoredMessage},
+        Message,
+    },
+    storage::Storage,
+};
+
+/// How long a delivery claim is kept, in seconds.
+const CLAIM_TTL: usize = 7 * 24 * 60 * 60;
+
+/// Stores every message as a hash under `<prefix>:message:<id>` and indexes them in the sets
+/// `<prefix>:ids` and `<prefix>:recipient:<login>`.
+///
+/// Saves only touch messages this instance loaded or saved before, so several instances can
+/// share one database.
+#[derive(Debug)]
+pub struct RedisStorage {
+    client: Client,
+    prefix: String,
+    /// Ids of the messages this instance knows about, mapped to their recipient.
+    known: Mutex<HashMap<String, String>>,
+}
+
+impl RedisStorage {
+    pub fn open(url: &str, prefix: &str) -> Result<Self> {
+        let client = Client::open(url).wrap_err("Invalid redis url")?;
+
+        Ok(Self {
+            client,
+            prefix: prefix.to_string(),
+            known: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn ids_key(&self) -> String {
+        format!("{}:ids", self.prefix)
+    }
+
+    fn recipients_key(&self) -> String {
+        format!("{}:recipients", self.prefix)
+    }
+
+    fn message_key(&self, id: &str) -> String {
+        format!("{}:message:{}", self.prefix, id)
+    }
+
+    fn recipient_key(&self, login: &str) -> String {
+        format!("{}:recipient:{}", self.prefix, login)
+    }
+
+    fn claim_key(&self, id: &str) -> String {
+        format!("{}:claim:{}", self.prefix, id)
+    }
+
+    /// Call `on_change` with the key whenever a key under our prefix changes.
+    ///
+    /// The server needs `notify-keyspace-events` to include `K` and the relevant event classes.
+    pub fn watch<F>(&self, on_change: F) -> Result<()>
+    where
+        F: Fn(&str) + Send + 'static,
+    {
+        let mut connection = self
+            .client
+            .get_connection()
+            .wrap_err("Failed to connect to redis")?;
+        let pattern = format!("__keyspace@*__:{}:*", self.prefix);
+
+        thread::spawn(move || {
+            let mut pubsub = connection.as_pubsub();
+
+            if let Err(err) = pubsub.psubscribe(&pattern) {
+                error!("Failed to subscribe to {}: {}", pattern, err);
+                return;
+            }
+
+            loop {
+                match pubsub.get_message() {
+                    Ok(message) => {
+                        let channel = message.get_channel_name();
+                        on_change(channel.splitn(2, ':').nth(1).unwrap_or(channel));
+                    }
+                    Err(err) => {
+                        error!("Lost keyspace notification connection: {}", err);
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+impl Storage for RedisStorage {
+    fn load(&self) -> Result<Vec<Message>> {
+        let mut connection = self
+            .client
+            .get_connection()
+            .wrap_err("Failed to connect to redis")?;
+
+        let ids: HashSet<String> = connection
+            .smembers(self.ids_key())
+            .wrap_err("Failed to read message ids")?;
+
+        let mut messages: Vec<Message> = Vec::with_capacity(ids.len());
+        for id in ids {
+            let data: Option<String> = connection
+                .hget(self.message_key(&id), "data")
+                .wrap_err_with(|| format!("Failed to read message {}", id))?;
+
+            if let Some(data) = data {
+                messages.push(
+                    stored::from_str(&data)
+                        .wrap_err_with(|| format!("Failed to deserialize message {}", id))?,
+                );
+            }
+        }
+
+        *self.known.lock().unwrap() = messages
+            .iter()
+            .map(|message| (message.id().to_string(), message.recipient().to_string()))
+            .collect();
+
+        Ok(messages)
+    }
+
+    fn save(&self, messages: &[&Message]) -> Result<()> {
+        let mut connection = self
+            .client
+            .get_connection()
+            .wrap_err("Failed to connect to redis")?;
+
+        let mut known = self.known.lock().unwrap();
+        let ids = messages
+            .iter()
+            .map(|message| message.id().to_string())
+            .collect::<HashSet<_>>();
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+
+        for (id, recipient) in known.iter().filter(|(id, _)| !ids.contains(*id)) {
+            pipe.del(self.message_key(id)).ignore();
+            pipe.srem(self.ids_key(), id).ignore();
+            pipe.srem(self.recipient_key(recipient), id).ignore();
+        }
+
+        for message in messages {
+            let data = ron::ser::to_string(&StoredMessage::from(*message))
+                .wrap_err("Failed to serialize message")?;
+
+            pipe.hset_multiple(
+                self.message_key(message.id()),
+                &[
+                    ("data", data.as_str()),
+                    ("author", message.author()),
+                    ("recipient", message.recipient()),
+                    ("channel", message.channel()),
+                ],
+            )
+            .ignore();
+            pipe.sadd(self.ids_key(), message.id()).ignore();
+            pipe.sadd(self.recipient_key(message.recipient()), message.id())
+                .ignore();
+            pipe.sadd(self.recipients_key(), message.recipient())
+                .ignore();
+        }
+
+        pipe.query::<()>(&mut connection)
+            .wrap_err("Failed to write messages")?;
+
+        *known = messages
+            .iter()
+            .map(|message| (message.id().to_string(), message.recipient().to_string()))
+            .collect();
+
+        Ok(())
+    }
+
+    fn claim(&self, message: &Message, instance: &str) -> Result<bool> {
+        let mut connection = self
+            .client
+            .get_connection()
+            .wrap_err("Failed to connect to redis")?;
+        let key = self.claim_key(message.id());
+
+        let created: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(instance)
+            .arg("NX")
+            .arg("EX")
+            .arg(CLAIM_TTL)
+            .query(&mut connection)
+            .wrap_err("Failed to claim message")?;
+        if created.is_some() {
+            return Ok(true);
+        }
+
+        // claims are re-entrant so an instance can retry its own deliveries
+        let owner: Option<String> = connection.get(&key).wrap_err("Failed to read claim")?;
+
+        Ok(owner.as_deref() == Some(instance))
+    }
+
+    fn release(&self, message: &Message, instance: &str) -> Result<()> {
+        let mut connection = self
+            .client
+            .get_connection()
+            .wrap_err("Failed to connect to redis")?;
+
+        // only delete the claim if it's still ours
+        redis::Script::new(
+            r"if redis.call('GET', KEYS[1]) == ARGV[1] then return redis.call('DEL', KEYS[1]) end return 0",
+        )
+        .key(self.claim_key(message.id()))
+        .arg(instance)
+        .invoke::<i64>(&mut connection)
+        .wrap_err("Failed to release claim")?;
+
+        Ok(())
+    }
+}
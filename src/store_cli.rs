@@ -0,0 +1,232 @@
+//! `twitch-remindme store <command>`: inspect and fix the message store while the bot is stopped,
+//! without connecting to Twitch.
+
+use std::{collections::BTreeMap, path::Path};
+
+use eyre::{eyre, Context, Result};
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    archive::Archive,
+    audit_log::{AuditKind, AuditLog},
+    config::Config,
+    format_local_timestamp,
+    message::{Activation, Kind, Message},
+    message_store::MessageStore,
+    snapshots,
+    time_zone::Zone,
+};
+
+const USAGE: &str = "Usage: twitch-remindme store list|prune [days]|remove <id>|stats|archive [login]|snapshots|rollback <snapshot or RFC 3339 time>";
+
+/// Run the store command in `args`, the arguments after `store`.
+pub fn run(config: &Config, args: &[String]) -> Result<()> {
+    let storage = config.storage.open().wrap_err("Failed to open storage")?;
+    let mut store = MessageStore::from_storage(storage, config.instance_id.clone())
+        .wrap_err("Failed to load storage")?;
+    let audit = AuditLog::new(
+        config.audit_log.clone(),
+        Duration::days(config.audit_retention_days),
+    );
+
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["list"] => list(&store),
+        ["prune"] => match config.reminder_retention_days {
+            Some(days) => prune(&mut store, &audit, days),
+            None => Err(eyre!(
+                "No reminder retention is configured, pass the days to keep: {}",
+                USAGE
+            )),
+        },
+        ["prune", days] => {
+            let days = days
+                .parse()
+                .map_err(|_| eyre!("Invalid number of days '{}'", days))?;
+            prune(&mut store, &audit, days)
+        }
+        ["remove", id] => remove(&mut store, &audit, id),
+        ["stats"] => stats(&store),
+        ["archive"] => archive(config, None),
+        ["archive", login] => archive(config, Some(&login.to_lowercase())),
+        ["snapshots"] => list_snapshots(config),
+        ["rollback", to] => rollback(config, &mut store, to),
+        _ => Err(eyre!(USAGE)),
+    }
+}
+
+fn list(store: &MessageStore) -> Result<()> {
+    let mut messages = store.get_all().into_iter().collect::<Vec<_>>();
+    messages.sort_by_key(|message| (message.created(), message.id().to_string()));
+
+    for message in messages {
+        println!(
+            "{} {} -> {} in #{} ({}): {}",
+            message.id(),
+            message.author(),
+            message.recipient(),
+            message.channel(),
+            describe_activation(message),
+            message.text()
+        );
+    }
+
+    Ok(())
+}
+
+/// Remove the messages that have been waiting for longer than `days`.
+fn prune(store: &mut MessageStore, audit: &AuditLog, days: i64) -> Result<()> {
+    let removed = store.remove_older_than(OffsetDateTime::now_utc() - Duration::days(days));
+    store.save().wrap_err("Failed to save store")?;
+
+    for message in &removed {
+        audit
+            .record(AuditKind::Expired, message)
+            .wrap_err("Failed to write audit log")?;
+    }
+    println!("Removed {} messages", removed.len());
+
+    Ok(())
+}
+
+fn remove(store: &mut MessageStore, audit: &AuditLog, id: &str) -> Result<()> {
+    let message = store
+        .take(id)
+        .ok_or_else(|| eyre!("There is no message with id {}", id))?;
+    store.save().wrap_err("Failed to save store")?;
+
+    audit
+        .record(AuditKind::Cancelled, &message)
+        .wrap_err("Failed to write audit log")?;
+    println!(
+        "Removed {} from {}: {}",
+        id,
+        message.author(),
+        message.text()
+    );
+
+    Ok(())
+}
+
+fn stats(store: &MessageStore) -> Result<()> {
+    let messages = store.get_all();
+    let mut by_activation = BTreeMap::new();
+    let mut by_channel = BTreeMap::new();
+    for message in &messages {
+        let activation = match (message.kind(), message.activation()) {
+            (Kind::Note, _) => "note",
+            (Kind::Notification, _) => "notification",
+            (_, Activation::OnNextMessage) => "on next message",
+            (_, Activation::Fixed(_)) => "timed",
+            (_, Activation::OnRaid) => "on raid",
+            (_, Activation::OnOffline) => "on offline",
+            (_, Activation::Never) => "never",
+        };
+        *by_activation.entry(activation).or_insert(0) += 1;
+        *by_channel.entry(message.channel()).or_insert(0) += 1;
+    }
+
+    println!("{} messages", messages.len());
+    for (activation, count) in by_activation {
+        println!("  {}: {}", activation, count);
+    }
+    println!("By channel:");
+    for (channel, count) in by_channel {
+        println!("  #{}: {}", channel, count);
+    }
+
+    Ok(())
+}
+
+/// List the archived reminders, only those `login` wrote or received if it is set.
+fn archive(config: &Config, login: Option<&str>) -> Result<()> {
+    let path = config
+        .archive
+        .clone()
+        .ok_or_else(|| eyre!("No archive is configured"))?;
+
+    for archived in Archive::new(path).all()? {
+        if login.map_or(true, |login| archived.involves(login)) {
+            let message = &archived.message;
+            println!(
+                "{} {} {} -> {} in #{} (written in #{} {}): {}",
+                format_local_timestamp(archived.delivered_at, None),
+                message.id(),
+                message.author(),
+                message.recipient(),
+                archived.channel,
+                message.channel(),
+                format_local_timestamp(message.created(), None),
+                message.text()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn snapshot_directory(config: &Config) -> Result<&Path> {
+    config
+        .snapshots
+        .as_ref()
+        .map(|snapshots| snapshots.directory.as_path())
+        .ok_or_else(|| eyre!("No snapshots are configured"))
+}
+
+fn list_snapshots(config: &Config) -> Result<()> {
+    for name in snapshots::list(snapshot_directory(config)?)? {
+        println!("{}", name);
+    }
+
+    Ok(())
+}
+
+/// Replace the stored messages with those of a snapshot.
+fn rollback(config: &Config, store: &mut MessageStore, to: &str) -> Result<()> {
+    let directory = snapshot_directory(config)?;
+    let name = snapshots::resolve(directory, to)?;
+    let messages = snapshots::read(directory, &name)?;
+
+    let current = store
+        .get_all()
+        .into_iter()
+        .map(|message| message.id().to_string())
+        .collect::<Vec<_>>();
+    for id in &current {
+        store.take(id);
+    }
+    let restored = messages.len();
+    for message in messages {
+        store.insert(message);
+    }
+    store.save().wrap_err("Failed to save store")?;
+    println!(
+        "Rolled back to {}, replaced {} messages with {}",
+        name,
+        current.len(),
+        restored
+    );
+
+    Ok(())
+}
+
+fn describe_activation(message: &Message) -> String {
+    match message.activation() {
+        Activation::OnNextMessage => "on next message".to_string(),
+        Activation::Fixed(at) => {
+            let offset = message
+                .time_zone()
+                .and_then(Zone::named)
+                .map(|zone| zone.offset_minutes_at(*at));
+
+            format!("due {}", format_local_timestamp(*at, offset))
+        }
+        Activation::OnRaid => "on raid".to_string(),
+        Activation::OnOffline => "on offline".to_string(),
+        Activation::Never => "never".to_string(),
+    }
+}
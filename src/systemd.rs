@@ -0,0 +1,92 @@
+//! Readiness and watchdog notifications for services with `Type=notify` and `WatchdogSec=`.
+//! Nothing is sent unless systemd passed `NOTIFY_SOCKET`.
+
+use std::{env, os::unix::net::UnixDatagram, time::Duration};
+
+use eyre::{eyre, Context, Result};
+use tracing::{debug, warn};
+
+/// Tell systemd the bot is connected and joined its channels.
+pub fn notify_ready() {
+    notify("READY=1");
+}
+
+/// Tell systemd the bot is still handling messages.
+pub fn notify_watchdog() {
+    notify("WATCHDOG=1");
+}
+
+/// How often [`notify_watchdog`] has to be called, if systemd watches this process.
+pub fn watchdog_interval() -> Option<Duration> {
+    parse_watchdog_interval(
+        env::var("WATCHDOG_USEC").ok().as_deref(),
+        env::var("WATCHDOG_PID").ok().as_deref(),
+        std::process::id(),
+    )
+}
+
+/// Half the timeout in `usec`, so a ping that is a bit late doesn't get the bot killed. The
+/// watchdog is meant for another process if `pid` names one.
+fn parse_watchdog_interval(
+    usec: Option<&str>,
+    pid: Option<&str>,
+    own_pid: u32,
+) -> Option<Duration> {
+    if let Some(pid) = pid {
+        if pid.parse::<u32>().ok()? != own_pid {
+            return None;
+        }
+    }
+
+    match usec?.parse::<u64>().ok()? {
+        0 => None,
+        usec => Some(Duration::from_micros(usec / 2)),
+    }
+}
+
+fn notify(state: &str) {
+    if let Err(err) = try_notify(state) {
+        warn!("{:?}", err.wrap_err("Failed to notify systemd"));
+    }
+}
+
+fn try_notify(state: &str) -> Result<()> {
+    let path = match env::var_os("NOTIFY_SOCKET") {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    if path.to_string_lossy().starts_with('@') {
+        return Err(eyre!("Abstract notify sockets are not supported"));
+    }
+
+    debug!("Notifying systemd: {}", state);
+    let socket = UnixDatagram::unbound().wrap_err("Failed to create socket")?;
+    socket
+        .send_to(state.as_bytes(), path)
+        .wrap_err("Failed to send notification")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watchdog_interval_is_half_the_timeout() {
+        assert_eq!(
+            Some(Duration::from_secs(15)),
+            parse_watchdog_interval(Some("30000000"), None, 42)
+        );
+        assert_eq!(
+            Some(Duration::from_secs(15)),
+            parse_watchdog_interval(Some("30000000"), Some("42"), 42)
+        );
+        assert_eq!(
+            None,
+            parse_watchdog_interval(Some("30000000"), Some("7"), 42)
+        );
+        assert_eq!(None, parse_watchdog_interval(Some("0"), None, 42));
+        assert_eq!(None, parse_watchdog_interval(None, None, 42));
+    }
+}
@@ -0,0 +1,117 @@
+use std::{env, sync::Mutex};
+
+use eyre::{Context, Result};
+#[cfg(not(feature = "error-reporting"))]
+use tracing::warn;
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+use crate::log_file::{LogFileConfig, RollingFile};
+
+/// Keeps error reporting running until dropped.
+#[must_use]
+pub struct ReportingGuard {
+    #[cfg(feature = "error-reporting")]
+    _sentry: Option<sentry::ClientInitGuard>,
+}
+
+/// Log to stdout and `log_file` if configured and, with the `otlp` feature, export spans to the
+/// collector configured by the standard `OTEL_EXPORTER_OTLP_*` environment variables.
+///
+/// With `REMINDME_LOG_FORMAT=json` every line is a JSON object carrying the fields of the spans
+/// it was logged in, like the channel, user, command and message id.
+///
+/// With the `error-reporting` feature, errors are also reported to Sentry once
+/// [`init_error_reporting`] was called.
+pub fn init(log_file: Option<&LogFileConfig>) -> Result<()> {
+    let json = env::var("REMINDME_LOG_FORMAT").map_or(false, |format| format == "json");
+    let log_file = log_file
+        .map(|config| RollingFile::open(config.clone()))
+        .transpose()
+        .wrap_err("Failed to open log file")?;
+
+    let registry = tracing_subscriber::registry()
+        .with(EnvFilter::from_default_env())
+        .with((!json).then(fmt::layer))
+        .with(json.then(|| fmt::layer().json().flatten_event(true)))
+        .with(log_file.map(|file| fmt::layer().with_ansi(false).with_writer(Mutex::new(file))));
+
+    #[cfg(feature = "otlp")]
+    let registry = registry.with(otlp::layer()?);
+
+    #[cfg(feature = "error-reporting")]
+    let registry = registry.with(sentry_tracing::layer());
+
+    registry.init();
+
+    Ok(())
+}
+
+/// Report errors and panics to the Sentry project of `dsn`.
+pub fn init_error_reporting(dsn: Option<&str>) -> ReportingGuard {
+    #[cfg(feature = "error-reporting")]
+    let guard = ReportingGuard {
+        _sentry: dsn.map(|dsn| {
+            sentry::init((
+                dsn,
+                sentry::ClientOptions {
+                    release: sentry::release_name!(),
+                    ..Default::default()
+                },
+            ))
+        }),
+    };
+
+    #[cfg(not(feature = "error-reporting"))]
+    let guard = {
+        if dsn.is_some() {
+            warn!("Ignoring sentry_dsn, the error-reporting feature is disabled");
+        }
+
+        ReportingGuard {}
+    };
+
+    guard
+}
+
+/// Flush the spans that haven't been exported yet.
+pub fn shutdown() {
+    #[cfg(feature = "otlp")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+#[cfg(feature = "otlp")]
+mod otlp {
+    use std::env;
+
+    use eyre::{Context, Result};
+    use opentelemetry::{
+        sdk::{trace, Resource},
+        KeyValue,
+    };
+    use opentelemetry_otlp::WithExportConfig;
+    use tracing::Subscriber;
+    use tracing_opentelemetry::OpenTelemetryLayer;
+    use tracing_subscriber::registry::LookupSpan;
+
+    /// Build the exporting layer if an endpoint is configured.
+    pub fn layer<S>() -> Result<Option<OpenTelemetryLayer<S, trace::Tracer>>>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        if env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT").is_none() {
+            return Ok(None);
+        }
+
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_env())
+            .with_trace_config(trace::config().with_resource(Resource::new(vec![
+                KeyValue::new("service.name", env!("CARGO_PKG_NAME")),
+                KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+            ])))
+            .install_batch(opentelemetry::runtime::Tokio)
+            .wrap_err("Failed to install OTLP exporter")?;
+
+        Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+    }
+}
@@ -0,0 +1,62 @@
+//! Placeholders like `{elapsed}` in reminder texts, expanded when the reminder is delivered.
+
+/// Replace every `{name}` in `text` that `value` knows. Unknown placeholders and unmatched braces
+/// are kept as they are.
+pub fn expand(text: &str, value: impl Fn(&str) -> Option<String>) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let name = rest[1..]
+            .find(|c: char| c == '{' || c == '}')
+            .filter(|end| rest[1 + end..].starts_with('}'))
+            .map(|end| &rest[1..1 + end]);
+        match name.and_then(|name| value(name).map(|value| (name, value))) {
+            Some((name, value)) => {
+                expanded.push_str(&value);
+                rest = &rest[name.len() + 2..];
+            }
+            None => {
+                expanded.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(name: &str) -> Option<String> {
+        match name {
+            "author" => Some("alice".to_string()),
+            "elapsed" => Some("2h 5m".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn expands_known_placeholders() {
+        assert_eq!(
+            "alice set this 2h 5m ago",
+            expand("{author} set this {elapsed} ago", value)
+        );
+        assert_eq!("alicealice", expand("{author}{author}", value));
+    }
+
+    #[test]
+    fn keeps_unknown_placeholders_and_stray_braces() {
+        assert_eq!("{unknown} stays", expand("{unknown} stays", value));
+        assert_eq!("{ {alice} }", expand("{ {{author}} }", value));
+        assert_eq!("open { brace", expand("open { brace", value));
+        assert_eq!("{{alice}", expand("{{author}", value));
+        assert_eq!("}{", expand("}{", value));
+    }
+}
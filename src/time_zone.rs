@@ -0,0 +1,121 @@
+//! Time zones of users: either a fixed offset from UTC or a named zone like `Europe/Berlin`
+//! whose offset follows the daylight saving time rules.
+
+use chrono::{NaiveDateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+use time::{Duration, OffsetDateTime};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Zone {
+    /// Minutes away from UTC.
+    Fixed(i64),
+    Named(Tz),
+}
+
+impl Default for Zone {
+    fn default() -> Self {
+        Zone::Fixed(0)
+    }
+}
+
+impl Zone {
+    /// Look up a zone of the tz database by its name, e.g. `Europe/Berlin`.
+    pub fn named(name: &str) -> Option<Self> {
+        name.parse::<Tz>().ok().map(Zone::Named)
+    }
+
+    /// The name of the zone if it is a named one.
+    pub fn name(&self) -> Option<&'static str> {
+        match self {
+            Zone::Fixed(_) => None,
+            Zone::Named(tz) => Some(tz.name()),
+        }
+    }
+
+    /// Minutes the zone is away from UTC at `at`.
+    pub fn offset_minutes_at(&self, at: OffsetDateTime) -> i64 {
+        match self {
+            Zone::Fixed(minutes) => *minutes,
+            Zone::Named(tz) => {
+                let offset = tz
+                    .offset_from_utc_datetime(&NaiveDateTime::from_timestamp(
+                        at.unix_timestamp(),
+                        0,
+                    ))
+                    .fix();
+
+                i64::from(offset.local_minus_utc()) / 60
+            }
+        }
+    }
+
+    /// What the clocks in the zone read at `at`, as a UTC time.
+    pub fn wall_clock(&self, at: OffsetDateTime) -> OffsetDateTime {
+        at + Duration::minutes(self.offset_minutes_at(at))
+    }
+
+    /// The instant at which the clocks in the zone read `wall_clock`, given as a UTC time.
+    ///
+    /// Times skipped when the clocks are set forward are moved forward by the same amount, times
+    /// that happen twice when they are set back resolve to the first time.
+    pub fn instant(&self, wall_clock: OffsetDateTime) -> OffsetDateTime {
+        let tz = match self {
+            Zone::Fixed(minutes) => return wall_clock - Duration::minutes(*minutes),
+            Zone::Named(tz) => tz,
+        };
+
+        let local = NaiveDateTime::from_timestamp(wall_clock.unix_timestamp(), 0);
+        let resolved = tz
+            .from_local_datetime(&local)
+            .earliest()
+            .map(|at| at.timestamp())
+            .or_else(|| {
+                // clocks are set forward by an hour almost everywhere
+                tz.from_local_datetime(&(local + chrono::Duration::hours(1)))
+                    .earliest()
+                    .map(|at| at.timestamp())
+            });
+
+        match resolved.and_then(|at| OffsetDateTime::from_unix_timestamp(at).ok()) {
+            Some(at) => at,
+            None => wall_clock - Duration::minutes(self.offset_minutes_at(wall_clock)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: i64) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(timestamp).unwrap()
+    }
+
+    #[test]
+    fn named_zones() {
+        assert_eq!(
+            Some("Europe/Berlin"),
+            Zone::named("Europe/Berlin").unwrap().name()
+        );
+        assert_eq!(None, Zone::named("Mars/Olympus_Mons"));
+    }
+
+    #[test]
+    fn offset_follows_daylight_saving_time() {
+        let berlin = Zone::named("Europe/Berlin").unwrap();
+
+        // 2021-03-27 12:00 and 2021-03-28 07:00 UTC
+        assert_eq!(60, berlin.offset_minutes_at(at(1_616_846_400)));
+        assert_eq!(120, berlin.offset_minutes_at(at(1_616_914_800)));
+    }
+
+    #[test]
+    fn instant_skips_the_gap() {
+        let berlin = Zone::named("Europe/Berlin").unwrap();
+        let wall_clock = |timestamp| at(timestamp) + Duration::hours(1);
+
+        // 2021-03-28 02:30 doesn't exist in Berlin, 03:30 is 01:30 UTC
+        assert_eq!(at(1_616_895_000), berlin.instant(wall_clock(1_616_895_000)));
+        assert_eq!(at(0), Zone::Fixed(60).instant(wall_clock(0)));
+    }
+}
@@ -0,0 +1,71 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+/// Ids of the messages that have an active delivery timer or are being delivered, so every
+/// message is handled by at most one task. Clones share their state.
+#[derive(Debug, Clone, Default)]
+pub struct Timers {
+    active: Arc<Mutex<HashSet<String>>>,
+    /// The active ones whose message is being sent right now.
+    sending: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Timers {
+    /// Register a timer for `id`. Returns `false` if one is already active.
+    pub fn start(&self, id: &str) -> bool {
+        self.active.lock().unwrap().insert(id.to_string())
+    }
+
+    pub fn finish(&self, id: &str) {
+        self.active.lock().unwrap().remove(id);
+        self.sending.lock().unwrap().remove(id);
+    }
+
+    /// Mark the message with `id` as being sent until its timer is finished.
+    pub fn sending(&self, id: &str) {
+        self.sending.lock().unwrap().insert(id.to_string());
+    }
+
+    pub fn is_sending(&self, id: &str) -> bool {
+        self.sending.lock().unwrap().contains(id)
+    }
+
+    /// Number of active timers.
+    pub fn len(&self) -> usize {
+        self.active.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.active.lock().unwrap().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_timer_per_id() {
+        let timers = Timers::default();
+        let shared = timers.clone();
+
+        assert!(timers.start("id"));
+        assert!(!shared.start("id"));
+
+        shared.finish("id");
+        assert!(timers.start("id"));
+    }
+
+    #[test]
+    fn finishing_ends_sending() {
+        let timers = Timers::default();
+        timers.start("id");
+        timers.sending("id");
+        assert!(timers.is_sending("id"));
+
+        timers.finish("id");
+        assert!(!timers.is_sending("id"));
+    }
+}
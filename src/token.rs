@@ -0,0 +1,216 @@
+//! Checks at startup that `TWITCH_TOKEN` belongs to `TWITCH_LOGIN` and has the scopes the bot
+//! needs. Otherwise Twitch only answers with a vague login failure, or the bot connects and its
+//! whispers and announcements are silently dropped.
+//!
+//! Only what surely can't work stops the bot: a token Twitch rejects, one of another account, or
+//! one missing a scope the config relies on. If Twitch can't be asked, the bot starts anyway.
+
+use eyre::{Context, Result};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::{config::Config, connections::ReadIdentity, proxy::Proxy};
+
+const VALIDATE_URL: &str = "https://id.twitch.tv/oauth2/validate";
+
+/// Scopes every instance needs and what for.
+const REQUIRED_SCOPES: &[(&str, &str)] = &[("chat:edit", "sending chat messages")];
+
+/// Scopes of features users may or may not use and what for. Without them only those fail.
+const OPTIONAL_SCOPES: &[(&str, &str)] = &[
+    ("user:manage:whispers", "whispering reminders"),
+    ("moderator:manage:announcements", "sending announcements"),
+];
+
+/// What Twitch knows about a token.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenInfo {
+    pub login: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum TokenError {
+    #[error("The token is invalid or expired")]
+    Invalid,
+
+    #[error("The token belongs to {actual}, not to {expected}")]
+    WrongLogin { expected: String, actual: String },
+
+    #[error("The token is missing the scopes {}", format_missing(.0))]
+    MissingScopes(Vec<(&'static str, &'static str)>),
+}
+
+fn format_missing(missing: &[(&str, &str)]) -> String {
+    missing
+        .iter()
+        .map(|(scope, purpose)| format!("{} (for {})", scope, purpose))
+        .intersperse(", ".to_string())
+        .collect()
+}
+
+/// The scopes the bot needs with `config` and what for.
+pub fn required_scopes(config: &Config) -> Vec<(&'static str, &'static str)> {
+    let mut scopes = REQUIRED_SCOPES.to_vec();
+    if config.irc.read_identity == ReadIdentity::Sender {
+        scopes.push(("chat:read", "reading chat"));
+    }
+    if config.whisper_commands {
+        scopes.push(("user:manage:whispers", "replying to whispered commands"));
+    }
+    if !config.presence_channels.is_empty() {
+        scopes.push((
+            "moderator:read:chatters",
+            "polling the chatters of presence_channels",
+        ));
+    }
+
+    scopes
+}
+
+/// Check that `token` belongs to `login` and has all of `required`, warning about missing
+/// [`OPTIONAL_SCOPES`]. Fails only if Twitch said so, errors asking it are logged.
+pub async fn verify(
+    token: &str,
+    login: &str,
+    required: &[(&'static str, &'static str)],
+    proxy: Option<&Proxy>,
+) -> Result<()> {
+    let info = match validate(token, proxy).await {
+        Ok(info) => info,
+        Err(err) if err.downcast_ref::<TokenError>().is_some() => return Err(err),
+        Err(err) => {
+            warn!(
+                "{:?}",
+                err.wrap_err("Failed to validate token, assuming it's fine")
+            );
+            return Ok(());
+        }
+    };
+
+    info.check(login, required)?;
+    if let Err(err) = info.check(login, OPTIONAL_SCOPES) {
+        warn!("{}, those features won't work", err);
+    }
+
+    Ok(())
+}
+
+/// Ask Twitch who `token` belongs to and what it may do. `token` may have its `oauth:` prefix.
+pub async fn validate(token: &str, proxy: Option<&Proxy>) -> Result<TokenInfo> {
+    let mut http = reqwest::Client::builder();
+    if let Some(proxy) = proxy {
+        http = http.proxy(proxy.http_proxy().wrap_err("Failed to set up proxy")?);
+    }
+
+    let response = http
+        .build()
+        .wrap_err("Failed to build HTTP client")?
+        .get(VALIDATE_URL)
+        .header(
+            "Authorization",
+            format!("OAuth {}", token.trim_start_matches("oauth:")),
+        )
+        .send()
+        .await
+        .wrap_err("Failed to send request")?;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        return Err(TokenError::Invalid.into());
+    }
+
+    response
+        .error_for_status()
+        .wrap_err("Request failed")?
+        .json()
+        .await
+        .wrap_err("Failed to deserialize response")
+}
+
+impl TokenInfo {
+    /// Check that the token belongs to `login` and has all of `required`.
+    pub fn check(
+        &self,
+        login: &str,
+        required: &[(&'static str, &'static str)],
+    ) -> Result<(), TokenError> {
+        if !self.login.eq_ignore_ascii_case(login) {
+            return Err(TokenError::WrongLogin {
+                expected: login.to_string(),
+                actual: self.login.clone(),
+            });
+        }
+
+        let missing = required
+            .iter()
+            .copied()
+            .filter(|(scope, _)| !self.scopes.iter().any(|granted| granted == scope))
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(TokenError::MissingScopes(missing))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(login: &str, scopes: &[&str]) -> TokenInfo {
+        TokenInfo {
+            login: login.to_string(),
+            scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn checks_login_and_scopes() {
+        let all = REQUIRED_SCOPES
+            .iter()
+            .map(|(scope, _)| *scope)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            Ok(()),
+            info("RemindMeBot", &all).check("remindmebot", REQUIRED_SCOPES)
+        );
+
+        assert_eq!(
+            Err(TokenError::WrongLogin {
+                expected: "remindmebot".to_string(),
+                actual: "someoneelse".to_string()
+            }),
+            info("someoneelse", &all).check("remindmebot", REQUIRED_SCOPES)
+        );
+
+        let error = info(
+            "remindmebot",
+            &["user:manage:whispers", "moderator:manage:announcements"],
+        )
+        .check("remindmebot", REQUIRED_SCOPES)
+        .unwrap_err();
+        assert_eq!(
+            "The token is missing the scopes chat:edit (for sending chat messages)",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn only_requires_scopes_the_config_uses() {
+        let scopes = |config: &Config| {
+            required_scopes(config)
+                .into_iter()
+                .map(|(scope, _)| scope)
+                .collect::<Vec<_>>()
+        };
+
+        let mut config = Config::default();
+        assert_eq!(vec!["chat:edit", "chat:read"], scopes(&config));
+
+        config.irc.read_identity = ReadIdentity::Anonymous;
+        config.whisper_commands = true;
+        assert_eq!(vec!["chat:edit", "user:manage:whispers"], scopes(&config));
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use time::{Duration, OffsetDateTime};
+
+use crate::message::Message;
+
+/// The reminders each user removed most recently, so `~undo` can bring them back for a while.
+#[derive(Debug, Clone)]
+pub struct UndoBuffer {
+    ttl: Duration,
+    data: HashMap<String, (OffsetDateTime, Vec<Message>)>,
+}
+
+impl UndoBuffer {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            data: HashMap::new(),
+        }
+    }
+
+    /// Remember `messages` as the latest removal of `login`, replacing the previous one. An empty
+    /// list leaves nothing to undo.
+    pub fn push(&mut self, login: &str, messages: Vec<Message>) {
+        let now = OffsetDateTime::now_utc();
+        let ttl = self.ttl;
+        self.data.retain(|_, (at, _)| now - *at < ttl);
+
+        if messages.is_empty() {
+            self.data.remove(login);
+        } else {
+            self.data.insert(login.to_string(), (now, messages));
+        }
+    }
+
+    /// Add `messages` to the latest removal of `login` if it didn't expire yet, and keep it for
+    /// longer.
+    pub fn append(&mut self, login: &str, messages: Vec<Message>) {
+        if messages.is_empty() {
+            return;
+        }
+
+        let mut all = self.pop(login).unwrap_or_default();
+        all.extend(messages);
+        self.push(login, all);
+    }
+
+    /// Whether the message with `id` is part of the latest removal of `login`.
+    pub fn contains(&self, login: &str, id: &str) -> bool {
+        self.data.get(login).map_or(false, |(at, messages)| {
+            OffsetDateTime::now_utc() - *at < self.ttl
+                && messages.iter().any(|message| message.id() == id)
+        })
+    }
+
+    /// Take the latest removal of `login` unless it expired.
+    pub fn pop(&mut self, login: &str) -> Option<Vec<Message>> {
+        let (at, messages) = self.data.remove(login)?;
+
+        (OffsetDateTime::now_utc() - at < self.ttl).then(|| messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Activation;
+
+    fn message() -> Message {
+        message_with_id("id")
+    }
+
+    fn message_with_id(id: &str) -> Message {
+        Message::new(
+            id.to_string(),
+            Activation::OnNextMessage,
+            "alice".to_string(),
+            "channel".to_string(),
+            "bob".to_string(),
+            "text".to_string(),
+        )
+    }
+
+    #[test]
+    fn pop_once() {
+        let mut undo = UndoBuffer::new(Duration::minutes(5));
+        undo.push("alice", vec![message()]);
+
+        assert_eq!(None, undo.pop("bob"));
+        assert_eq!(Some(vec![message()]), undo.pop("alice"));
+        assert_eq!(None, undo.pop("alice"));
+    }
+
+    #[test]
+    fn append_extends_the_latest_removal() {
+        let mut undo = UndoBuffer::new(Duration::minutes(5));
+        undo.push("alice", vec![message_with_id("a")]);
+        undo.append("alice", vec![message_with_id("b")]);
+
+        assert!(undo.contains("alice", "a"));
+        assert!(undo.contains("alice", "b"));
+        assert!(!undo.contains("bob", "a"));
+        assert_eq!(2, undo.pop("alice").unwrap().len());
+    }
+
+    #[test]
+    fn expired_entries_are_dropped() {
+        let mut undo = UndoBuffer::new(Duration::ZERO);
+        undo.push("alice", vec![message()]);
+
+        assert_eq!(None, undo.pop("alice"));
+    }
+}
@@ -0,0 +1,196 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::ron_store::RonStore;
+
+/// A user waiting for a keyword to come up in chat.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Watch {
+    pub login: String,
+    /// Lowercase, matched anywhere in a chat message.
+    pub keyword: String,
+}
+
+/// Parse the keyword of `~watchword`: a quoted phrase or a single word, optionally followed by
+/// `in this channel`.
+pub fn parse_keyword(args: &str) -> Option<String> {
+    let (keyword, rest) = match args.strip_prefix('"') {
+        Some(quoted) => {
+            let end = quoted.find('"')?;
+            (&quoted[..end], &quoted[end + 1..])
+        }
+        None => args.split_once(' ').unwrap_or((args, "")),
+    };
+
+    let (keyword, rest) = (keyword.trim(), rest.trim());
+    if keyword.is_empty() || !(rest.is_empty() || rest.eq_ignore_ascii_case("in this channel")) {
+        return None;
+    }
+
+    Some(keyword.to_lowercase())
+}
+
+/// Keywords users want to be pinged about the next time they are said, keyed by channel.
+#[derive(Debug, Clone)]
+pub struct WatchStore {
+    data: RonStore<HashMap<String, Vec<Watch>>>,
+}
+
+impl WatchStore {
+    pub fn from_path(path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            data: RonStore::from_path(path, "watch store")?,
+        })
+    }
+
+    /// Watch for `keyword` in `channel` for `login`. Returns `false` if they already do.
+    pub fn add(&self, channel: &str, login: &str, keyword: &str) -> bool {
+        self.put(
+            channel,
+            Watch {
+                login: login.to_string(),
+                keyword: keyword.to_lowercase(),
+            },
+        )
+    }
+
+    /// Put `watch` of `channel` back, e.g. after pinging about it failed. Returns `false` if it
+    /// is already there.
+    pub fn put(&self, channel: &str, watch: Watch) -> bool {
+        let mut data = self.data.write();
+        let watches = data.entry(channel.to_string()).or_default();
+        if watches.contains(&watch) {
+            return false;
+        }
+        watches.push(watch);
+
+        true
+    }
+
+    /// Stop watching for `keyword` in `channel` for `login`. Returns `false` if they didn't.
+    pub fn remove(&self, channel: &str, login: &str, keyword: &str) -> bool {
+        let keyword = keyword.to_lowercase();
+        let mut data = self.data.write();
+        let watches = match data.get_mut(channel) {
+            Some(watches) => watches,
+            None => return false,
+        };
+
+        let len = watches.len();
+        watches.retain(|watch| watch.login != login || watch.keyword != keyword);
+        let removed = watches.len() != len;
+        if watches.is_empty() {
+            data.remove(channel);
+        }
+
+        removed
+    }
+
+    /// The channels and keywords `login` watches, sorted.
+    pub fn of(&self, login: &str) -> Vec<(String, String)> {
+        let mut watches = self
+            .data
+            .read()
+            .iter()
+            .flat_map(|(channel, watches)| {
+                watches
+                    .iter()
+                    .filter(|watch| watch.login == login)
+                    .map(move |watch| (channel.clone(), watch.keyword.clone()))
+            })
+            .collect::<Vec<_>>();
+        watches.sort_unstable();
+
+        watches
+    }
+
+    /// Remove and return the watches of `channel` whose keyword `text` contains, ignoring case.
+    /// Nobody is pinged about their own messages.
+    pub fn take_matches(&self, channel: &str, sender: &str, text: &str) -> Vec<Watch> {
+        let text = text.to_lowercase();
+        let mut data = self.data.write();
+        let watches = match data.get_mut(channel) {
+            Some(watches) => watches,
+            None => return Vec::new(),
+        };
+
+        let (matches, rest): (Vec<Watch>, Vec<Watch>) = std::mem::take(watches)
+            .into_iter()
+            .partition(|watch| watch.login != sender && text.contains(&watch.keyword));
+        *watches = rest;
+        if watches.is_empty() {
+            data.remove(channel);
+        }
+
+        matches
+    }
+
+    /// Forget every watch of `login`.
+    pub fn forget(&self, login: &str) {
+        let mut data = self.data.write();
+        for watches in data.values_mut() {
+            watches.retain(|watch| watch.login != login);
+        }
+        data.retain(|_, watches| !watches.is_empty());
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.data.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ron_store::test_path;
+
+    #[test]
+    fn parse_keywords() {
+        assert_eq!(
+            Some("drops".to_string()),
+            parse_keyword("\"Drops\" in this channel")
+        );
+        assert_eq!(
+            Some("free drops".to_string()),
+            parse_keyword("\"free drops\"")
+        );
+        assert_eq!(Some("drops".to_string()), parse_keyword("drops"));
+        assert_eq!(None, parse_keyword("drops elsewhere"));
+        assert_eq!(None, parse_keyword("\"unterminated"));
+        assert_eq!(None, parse_keyword("\"\""));
+    }
+
+    #[test]
+    fn matches_once_and_not_the_watcher() {
+        let watches = WatchStore::from_path(test_path("watch-store")).unwrap();
+        assert!(watches.add("channel", "alice", "Drops"));
+        assert!(!watches.add("channel", "alice", "drops"));
+        assert!(watches.add("other", "alice", "drops"));
+        assert_eq!(
+            vec![
+                ("channel".to_string(), "drops".to_string()),
+                ("other".to_string(), "drops".to_string())
+            ],
+            watches.of("alice")
+        );
+
+        assert!(watches
+            .take_matches("channel", "alice", "drops are on")
+            .is_empty());
+        assert_eq!(
+            vec![Watch {
+                login: "alice".to_string(),
+                keyword: "drops".to_string()
+            }],
+            watches.take_matches("channel", "bob", "DROPS ARE ON")
+        );
+        assert!(watches
+            .take_matches("channel", "bob", "drops are on")
+            .is_empty());
+
+        assert!(watches.remove("other", "alice", "DROPS"));
+        assert!(watches.of("alice").is_empty());
+    }
+}